@@ -4,7 +4,7 @@ use chrono::{DateTime, Utc};
 use deadpool_postgres::Pool;
 use drogue_client::{
     registry::{self, v1::DeviceSpecAuthentication, v1::Password, v1::PreSharedKey},
-    Dialect, Translator,
+    Translator,
 };
 use drogue_cloud_database_common::{
     error::ServiceError,
@@ -14,8 +14,8 @@ use drogue_cloud_database_common::{
 };
 use drogue_cloud_service_api::{
     auth::device::authn::{
-        self, AuthenticationRequest, AuthorizeGatewayRequest, GatewayOutcome, Outcome,
-        PreSharedKeyOutcome, PreSharedKeyRequest,
+        self, strip_credentials, validate_app, AuthenticationRequest, AuthorizeGatewayRequest,
+        GatewayOutcome, Outcome, PreSharedKeyOutcome, PreSharedKeyRequest,
     },
     health::{HealthCheckError, HealthChecked},
     webapp as actix_web,
@@ -320,44 +320,6 @@ impl AuthenticationService for PostgresAuthenticationService {
     }
 }
 
-/// Strip the credentials from the device information, so that we do not leak them.
-fn strip_credentials(mut device: registry::v1::Device) -> registry::v1::Device {
-    // FIXME: we need to do a better job here, maybe add a "secrets" section instead
-    device
-        .spec
-        .remove(registry::v1::DeviceSpecAuthentication::key());
-    device
-        .spec
-        .remove(registry::v1::DeviceSpecCredentials::key());
-    device
-}
-
-/// Validate if an application is "ok" to be used for authentication.
-fn validate_app(app: &registry::v1::Application) -> bool {
-    if app.metadata.deletion_timestamp.is_some() {
-        log::debug!("Application is about being deleted");
-        return false;
-    }
-
-    match app.section::<registry::v1::DeviceSpecCore>() {
-        // found "core", decoded successfully -> check
-        Some(Ok(core)) => {
-            if core.disabled {
-                return false;
-            }
-        }
-        // found "core", but could not decode -> fail
-        Some(Err(_)) => {
-            return false;
-        }
-        // no "core" section
-        _ => {}
-    };
-
-    // done
-    true
-}
-
 fn locate_psk(device: &registry::v1::Device) -> Option<PreSharedKey> {
     if device.metadata.deletion_timestamp.is_some() {
         log::debug!("Device is about to being deleted");
@@ -13,6 +13,13 @@ impl CoapEndpointError {
             EndpointError::ConfigurationError { .. } => ResponseType::InternalServerError,
             EndpointError::AuthenticationServiceError { .. } => ResponseType::ServiceUnavailable,
             EndpointError::AuthenticationError { .. } => ResponseType::Forbidden,
+            EndpointError::InvalidSignature { .. } => ResponseType::Unauthorized,
+            EndpointError::PayloadTooLarge { .. } => ResponseType::RequestEntityTooLarge,
+            EndpointError::TimestampSkewed { .. } => ResponseType::BadRequest,
+            EndpointError::RateLimited { .. } => ResponseType::ServiceUnavailable,
+            EndpointError::DeadlineExceeded { .. } => ResponseType::GatewayTimeout,
+            EndpointError::Backpressure { .. } => ResponseType::ServiceUnavailable,
+            EndpointError::Maintenance { .. } => ResponseType::ServiceUnavailable,
         }
     }
 }
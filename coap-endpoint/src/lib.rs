@@ -18,7 +18,7 @@ use drogue_cloud_endpoint_common::{
     sink::KafkaSink,
 };
 use drogue_cloud_service_api::auth::device::authn::PreSharedKeyOutcome;
-use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use drogue_cloud_service_api::kafka::{KafkaClientConfig, TopicNaming};
 use drogue_cloud_service_common::{
     app::{Startup, StartupExt},
     defaults,
@@ -82,6 +82,10 @@ pub struct Config {
 
     #[serde(default)]
     pub key_file: Option<String>,
+
+    /// The naming of the topics, must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
 
 #[derive(Clone, Debug)]
@@ -103,7 +107,8 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         KafkaSink::from_config(
             config.kafka_downstream_config,
             config.check_kafka_topic_ready,
-        )?,
+        )?
+        .with_topic_naming(config.topic_naming.clone()),
         config.instance,
         config.endpoint_pool,
     )?;
@@ -121,6 +126,7 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         commands,
         config.kafka_command_config,
         config.command_source_kafka,
+        &config.topic_naming,
     )?;
 
     let server = UdpSocket::bind(&addr).await?;
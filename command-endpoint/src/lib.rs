@@ -8,7 +8,7 @@ use drogue_cloud_endpoint_common::{
 };
 use drogue_cloud_service_api::{
     health::HealthChecked,
-    kafka::KafkaClientConfig,
+    kafka::{KafkaClientConfig, TopicNaming},
     webapp::{self as actix_web, web::ServiceConfig},
 };
 use drogue_cloud_service_common::{
@@ -49,6 +49,10 @@ pub struct Config {
 
     #[serde(default)]
     pub http: HttpConfig,
+
+    /// The naming of the topics, must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
 
 #[derive(Debug)]
@@ -68,7 +72,8 @@ pub async fn configurator(
 )> {
     let sender = UpstreamSender::new(
         config.instance,
-        KafkaSink::from_config(config.command_kafka_sink, config.check_kafka_topic_ready)?,
+        KafkaSink::from_config(config.command_kafka_sink, config.check_kafka_topic_ready)?
+            .with_topic_naming(config.topic_naming),
         config.endpoint_pool,
     )?;
 
@@ -10,7 +10,7 @@ use drogue_client::{
 };
 use drogue_cloud_service_api::{
     endpoints::{Endpoints, HttpEndpoint, MqttEndpoint},
-    kafka::{KafkaConfigExt, KafkaEventType},
+    kafka::{KafkaConfigExt, KafkaEventType, TopicNaming},
 };
 use java_properties::PropertiesWriter;
 use monaco::{
@@ -198,7 +198,18 @@ impl IntegrationDetails<'_> {
     fn kafka_info(&self) -> Option<KafkaInfo> {
         let bootstrap = self.endpoints.kafka_bootstrap_servers.as_ref().cloned();
 
-        let topic = self.application.kafka_topic(KafkaEventType::Events);
+        // the topic name is reported by the operator, which may use a custom naming
+        let topic = self
+            .application
+            .status
+            .get("kafka")
+            .and_then(|kafka| kafka.get("topicName"))
+            .and_then(|name| name.as_str())
+            .map(|name| Ok(name.to_string()))
+            .unwrap_or_else(|| {
+                self.application
+                    .kafka_topic(&TopicNaming::default(), KafkaEventType::Events)
+            });
 
         let user = self
             .application
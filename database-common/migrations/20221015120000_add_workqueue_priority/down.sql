@@ -0,0 +1,2 @@
+ALTER TABLE WORKQUEUE
+    DROP COLUMN PRIORITY;
@@ -0,0 +1,2 @@
+ALTER TABLE WORKQUEUE
+    ADD COLUMN PRIORITY BIGINT NOT NULL DEFAULT 0;
@@ -2,7 +2,7 @@ use crate::EventController;
 use async_trait::async_trait;
 use drogue_client::{error::ClientError, registry};
 use drogue_cloud_operator_common::controller::{
-    base::{Key, ResourceOperations},
+    base::{reconcile_priority, Key, ResourceOperations},
     reconciler::ReconcileError,
 };
 use futures::try_join;
@@ -69,4 +69,8 @@ impl ResourceOperations<ApplicationAndDeviceKey, ApplicationAndDevice, ()> for E
     fn ref_output(_input: &ApplicationAndDevice) -> &() {
         &()
     }
+
+    fn priority(input: &ApplicationAndDevice) -> Option<i64> {
+        reconcile_priority(&input.application)
+    }
 }
@@ -12,7 +12,7 @@ use drogue_cloud_registry_events::{
     stream::{KafkaEventStream, KafkaStreamConfig},
     Event,
 };
-use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use drogue_cloud_service_api::kafka::{KafkaClientConfig, TopicNaming};
 use drogue_cloud_service_common::{
     app::{Startup, StartupExt},
     client::ClientConfig,
@@ -20,7 +20,6 @@ use drogue_cloud_service_common::{
 };
 use serde::Deserialize;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
@@ -42,6 +41,10 @@ pub struct Config {
     pub kafka_downstream_config: KafkaClientConfig,
     #[serde(default)]
     pub endpoint_pool: ExternalClientPoolConfig,
+
+    /// The naming of the topics, must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
 
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
@@ -51,7 +54,8 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         KafkaSink::from_config(
             config.kafka_downstream_config,
             config.check_kafka_topic_ready,
-        )?,
+        )?
+        .with_topic_naming(config.topic_naming.clone()),
         config.instance,
         config.endpoint_pool,
     )?;
@@ -62,11 +66,11 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     // event source
 
-    let controller = Arc::new(Mutex::new(BaseController::new(
+    let controller = Arc::new(BaseController::new(
         config.work_queue,
         "mgmt-events",
         EventController::new(config.controller, registry, sender),
-    )?));
+    )?);
 
     // event source - device registry
 
@@ -85,6 +89,7 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
             _ => None,
         }));
     let registry = KafkaEventStream::new(config.kafka_source)?;
+    startup.check(registry.health());
     let registry = registry.run(registry_dispatcher);
 
     // run
@@ -9,7 +9,7 @@ use drogue_cloud_endpoint_common::{
     sink::KafkaSink,
 };
 use drogue_cloud_service_api::{
-    kafka::KafkaClientConfig,
+    kafka::{KafkaClientConfig, TopicNaming},
     webapp::{self as actix_web},
 };
 use drogue_cloud_service_common::{
@@ -40,6 +40,10 @@ pub struct Config {
 
     #[serde(default)]
     pub http: HttpConfig,
+
+    /// The naming of the topics, must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
 
 #[macro_export]
@@ -83,7 +87,8 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         KafkaSink::from_config(
             config.kafka_downstream_config,
             config.check_kafka_topic_ready,
-        )?,
+        )?
+        .with_topic_naming(config.topic_naming.clone()),
         config.instance,
         config.endpoint_pool,
     )?;
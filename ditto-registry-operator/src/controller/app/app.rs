@@ -18,7 +18,9 @@ use drogue_cloud_operator_common::controller::reconciler::{
     progress::{self, OperationOutcome, ProgressOperation},
     ReconcileError,
 };
-use drogue_cloud_service_api::kafka::{KafkaClientConfig, KafkaConfigExt, KafkaEventType};
+use drogue_cloud_service_api::kafka::{
+    KafkaClientConfig, KafkaConfigExt, KafkaEventType, TopicNaming,
+};
 use indexmap::IndexMap;
 use std::time::Duration;
 use tracing::instrument;
@@ -87,6 +89,7 @@ impl<'a> CreateApplication<'a> {
             &ctx,
             spec.and_then(|spec| spec.ingress.as_ref()),
             &self.config.kafka,
+            &self.config.topic_naming,
         )?)
         .await?;
 
@@ -257,10 +260,11 @@ fn inbound_connection_definition(
     ctx: &ConstructContext,
     ingress: Option<&Ingress>,
     default_config: &KafkaClientConfig,
+    naming: &TopicNaming,
 ) -> Result<Connection, ReconcileError> {
     let topic = ctx
         .app
-        .kafka_topic(KafkaEventType::Events)
+        .kafka_topic(naming, KafkaEventType::Events)
         .map_err(|_| ReconcileError::permanent("This should be infallible"))?;
     let id = ConnectionType::Inbound.connection_id(&ctx.app);
     let group_id = format!("ditto-{}", id);
@@ -3,7 +3,7 @@ pub mod device;
 
 use crate::ditto::data::EntityId;
 use drogue_client::registry::v1::Application;
-use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use drogue_cloud_service_api::kafka::{KafkaClientConfig, TopicNaming};
 use drogue_cloud_service_common::auth::openid::TokenConfig;
 use serde::Deserialize;
 use url::Url;
@@ -13,6 +13,9 @@ pub struct ControllerConfig {
     pub ditto_devops: DittoDevops,
     pub ditto_admin: TokenConfig,
     pub kafka: KafkaClientConfig,
+    /// The naming of the topics, must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
 
 #[derive(Clone, Debug, Deserialize)]
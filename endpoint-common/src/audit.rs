@@ -0,0 +1,311 @@
+//! Audit logging of authentication decisions.
+//!
+//! The audit log is separate from the request access log. It records every decision taken when
+//! authenticating a device. Denied decisions are always logged as warnings, even if the audit log
+//! is disabled. Granted ones are only logged if it is enabled, and may be sampled.
+
+use crate::auth::{AuthResult, AuthValue, Username};
+use chrono::{DateTime, Utc};
+use drogue_cloud_service_api::{auth::device::authn::Outcome, webapp::HttpRequest};
+use http::header;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<AuditLevel> for log::Level {
+    fn from(level: AuditLevel) -> Self {
+        match level {
+            AuditLevel::Error => log::Level::Error,
+            AuditLevel::Warn => log::Level::Warn,
+            AuditLevel::Info => log::Level::Info,
+            AuditLevel::Debug => log::Level::Debug,
+            AuditLevel::Trace => log::Level::Trace,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditConfig {
+    /// Enable the audit log of granted decisions.
+    ///
+    /// Denied decisions are always logged.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// The log target to emit audit records to.
+    #[serde(default = "default_target")]
+    pub target: String,
+
+    /// The log level to emit granted decisions with, denied ones are logged as warnings.
+    #[serde(default)]
+    pub level: AuditLevel,
+
+    /// The ratio of granted decisions to log, from `0.0` (none) to `1.0` (all).
+    #[serde(default = "default_granted_sample_rate")]
+    pub granted_sample_rate: f64,
+}
+
+fn default_target() -> String {
+    "audit".into()
+}
+
+const fn default_granted_sample_rate() -> f64 {
+    1.0
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target: default_target(),
+            level: Default::default(),
+            granted_sample_rate: default_granted_sample_rate(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditOutcome {
+    Granted,
+    Denied,
+}
+
+/// A single authentication decision.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_ip: Option<String>,
+    /// The authenticated principal, or the attempted username.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub principal: Option<String>,
+    pub route: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    pub outcome: AuditOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl AuditRecord {
+    /// Start a new record from an HTTP request.
+    ///
+    /// This will only extract the username from the authorization header, never the password or
+    /// token.
+    pub fn from_http(req: &HttpRequest, outcome: AuditOutcome) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            source_ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+            principal: req.headers().get(header::AUTHORIZATION).and_then(|header| {
+                match AuthValue::from(header) {
+                    AuthValue::Basic { username, .. } => Some(match username {
+                        Username::Scoped { scope, device } => format!("{device}@{scope}"),
+                        Username::NonScoped(username) => username,
+                    }),
+                    _ => None,
+                }
+            }),
+            route: req
+                .match_pattern()
+                .unwrap_or_else(|| req.path().to_string()),
+            application: None,
+            device: None,
+            outcome,
+            reason: None,
+        }
+    }
+
+    pub fn application<S: Into<String>>(mut self, application: Option<S>) -> Self {
+        self.application = application.map(Into::into);
+        self
+    }
+
+    pub fn device<S: Into<String>>(mut self, device: Option<S>) -> Self {
+        self.device = device.map(Into::into);
+        self
+    }
+
+    pub fn reason<S: Into<String>>(mut self, reason: S) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+/// Emit audit records, according to the [`AuditConfig`].
+#[derive(Clone, Debug, Default)]
+pub struct AuditLogger {
+    config: AuditConfig,
+}
+
+impl AuditLogger {
+    pub fn new(config: AuditConfig) -> Self {
+        Self { config }
+    }
+
+    /// Get the level to log a record with the outcome, [`None`] if it should not be logged.
+    fn level(&self, outcome: AuditOutcome) -> Option<log::Level> {
+        let sampled = match (outcome, self.config.enabled) {
+            (AuditOutcome::Denied, _) => return Some(log::Level::Warn),
+            (AuditOutcome::Granted, false) => false,
+            (AuditOutcome::Granted, true) => match self.config.granted_sample_rate {
+                rate if rate >= 1.0 => true,
+                rate if rate <= 0.0 => false,
+                rate => rand::random::<f64>() < rate,
+            },
+        };
+
+        sampled.then(|| self.config.level.into())
+    }
+
+    /// Log the outcome of authenticating an HTTP request.
+    ///
+    /// The application and device are the ones claimed by the request, they will be replaced by
+    /// the authenticated ones when access was granted.
+    pub fn log_http(
+        &self,
+        req: &HttpRequest,
+        application: Option<&str>,
+        device: Option<&str>,
+        result: &AuthResult<Outcome>,
+    ) {
+        let record = match result {
+            Ok(Outcome::Pass {
+                application,
+                device,
+                ..
+            }) => AuditRecord::from_http(req, AuditOutcome::Granted)
+                .application(Some(application.metadata.name.as_str()))
+                .device(Some(device.metadata.name.as_str())),
+            Ok(Outcome::Fail) => AuditRecord::from_http(req, AuditOutcome::Denied)
+                .application(application)
+                .device(device)
+                .reason("Authentication failed"),
+            Err(err) => AuditRecord::from_http(req, AuditOutcome::Denied)
+                .application(application)
+                .device(device)
+                .reason(format!("Authentication service error: {err}")),
+        };
+
+        self.log(&record);
+    }
+
+    /// Log an audit record.
+    pub fn log(&self, record: &AuditRecord) {
+        let level = match self.level(record.outcome) {
+            Some(level) => level,
+            None => return,
+        };
+
+        match serde_json::to_string(record) {
+            Ok(record) => log::log!(target: self.config.target.as_str(), level, "{}", record),
+            Err(err) => log::warn!("Failed to serialize audit record: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_cloud_service_api::webapp::test::TestRequest;
+    use serde_json::json;
+
+    fn request() -> HttpRequest {
+        TestRequest::post()
+            .uri("/v1/telemetry")
+            .peer_addr("192.168.1.2:12345".parse().unwrap())
+            .insert_header((
+                header::AUTHORIZATION,
+                format!("Basic {}", base64::encode("device1@app1:very-secret")),
+            ))
+            .to_http_request()
+    }
+
+    #[test]
+    fn test_record_granted() {
+        let record = AuditRecord::from_http(&request(), AuditOutcome::Granted)
+            .application(Some("app1"))
+            .device(Some("device1"));
+
+        let mut json = serde_json::to_value(&record).unwrap();
+        json.as_object_mut().unwrap().remove("timestamp");
+
+        assert_eq!(
+            json,
+            json!({
+                "sourceIp": "192.168.1.2",
+                "principal": "device1@app1",
+                "route": "/v1/telemetry",
+                "application": "app1",
+                "device": "device1",
+                "outcome": "granted",
+            })
+        );
+    }
+
+    #[test]
+    fn test_record_denied() {
+        let record = AuditRecord::from_http(&request(), AuditOutcome::Denied)
+            .application(Some("app1"))
+            .device(None::<String>)
+            .reason("Authentication failed");
+
+        let mut json = serde_json::to_value(&record).unwrap();
+        json.as_object_mut().unwrap().remove("timestamp");
+
+        assert_eq!(
+            json,
+            json!({
+                "sourceIp": "192.168.1.2",
+                "principal": "device1@app1",
+                "route": "/v1/telemetry",
+                "application": "app1",
+                "outcome": "denied",
+                "reason": "Authentication failed",
+            })
+        );
+
+        // the password must never be part of the record
+        assert!(!serde_json::to_string(&record)
+            .unwrap()
+            .contains("very-secret"));
+    }
+
+    #[test]
+    fn test_sampling() {
+        let logger = AuditLogger::new(AuditConfig {
+            enabled: true,
+            granted_sample_rate: 0.0,
+            ..Default::default()
+        });
+        assert_eq!(logger.level(AuditOutcome::Denied), Some(log::Level::Warn));
+        assert_eq!(logger.level(AuditOutcome::Granted), None);
+
+        let logger = AuditLogger::new(AuditConfig {
+            enabled: true,
+            level: AuditLevel::Debug,
+            ..Default::default()
+        });
+        assert_eq!(logger.level(AuditOutcome::Denied), Some(log::Level::Warn));
+        assert_eq!(logger.level(AuditOutcome::Granted), Some(log::Level::Debug));
+    }
+
+    #[test]
+    fn test_denied_when_disabled() {
+        let logger = AuditLogger::new(Default::default());
+        assert_eq!(logger.level(AuditOutcome::Denied), Some(log::Level::Warn));
+        assert_eq!(logger.level(AuditOutcome::Granted), None);
+    }
+}
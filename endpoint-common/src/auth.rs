@@ -22,7 +22,7 @@ use std::fmt::Debug;
 use tracing::instrument;
 use x509_parser::prelude::X509Certificate;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AuthConfig {
     /// Disable authenticating towards the authentication service.
     #[serde(default)]
@@ -5,10 +5,10 @@ use drogue_cloud_event_common::stream::{
 };
 use drogue_cloud_service_api::{
     health::{HealthCheckError, HealthChecked},
-    kafka::{KafkaClientConfig, KafkaConfig},
+    kafka::{KafkaClientConfig, KafkaConfig, TopicNaming},
 };
 use futures::StreamExt;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
     convert::TryFrom,
     sync::{
@@ -18,29 +18,44 @@ use std::{
 };
 use tokio::task::JoinHandle;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct KafkaCommandSourceConfig {
-    pub topic: String,
+    /// The topic to consume commands from, derived from the topic naming by default.
+    #[serde(default)]
+    pub topic: Option<String>,
     pub consumer_group: String,
 }
 
+impl KafkaCommandSourceConfig {
+    /// The topic to consume commands from.
+    pub fn topic(&self, naming: &TopicNaming) -> String {
+        self.topic
+            .clone()
+            .unwrap_or_else(|| naming.commands_topic())
+    }
+}
+
 pub struct KafkaCommandSource {
     handle: JoinHandle<()>,
     alive: Arc<AtomicBool>,
 }
 
 impl KafkaCommandSource {
+    /// Create a new command source.
+    ///
+    /// The naming must match the naming of the sink, publishing the commands.
     pub fn new<D>(
         dispatcher: D,
         kafka_client: KafkaClientConfig,
         config: KafkaCommandSourceConfig,
+        naming: &TopicNaming,
     ) -> Result<Self, EventStreamError>
     where
         D: CommandDispatcher + Send + Sync + 'static,
     {
         let mut source = EventStream::<AutoAck>::new(EventStreamConfig {
             kafka: KafkaConfig {
-                topic: config.topic,
+                topic: config.topic(naming),
                 client: kafka_client,
             },
             consumer_group: Some(config.consumer_group),
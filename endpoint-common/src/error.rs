@@ -1,9 +1,12 @@
+use crate::sender::{BackpressureSource, RateLimitScope};
 use drogue_client::error::ClientError;
 use drogue_cloud_service_api::webapp::{
-    error::PayloadError, http::StatusCode, HttpResponse, ResponseError,
+    error::PayloadError,
+    http::{header, StatusCode},
+    HttpResponse, ResponseError,
 };
 use serde::{Deserialize, Serialize};
-use std::fmt::Formatter;
+use std::{fmt::Formatter, time::Duration};
 
 #[derive(Debug, thiserror::Error)]
 pub enum EndpointError {
@@ -19,6 +22,39 @@ pub enum EndpointError {
     /// The authentication process successfully evaluated that the access is denied.
     #[error("Authentication failed")]
     AuthenticationError,
+    /// The signature of the payload is missing or invalid.
+    #[error("Invalid signature: {}", details)]
+    InvalidSignature { details: String },
+    /// The payload exceeds the size limit of its channel.
+    #[error("Payload too large: {}", details)]
+    PayloadTooLarge { details: String },
+    /// The device provided timestamp deviates too much from the server time.
+    #[error("Timestamp skewed: {}", details)]
+    TimestampSkewed { details: String },
+    /// The rate limit of the device, or of its tenant, was exceeded.
+    #[error("Rate limit of the {} exceeded", scope)]
+    RateLimited {
+        scope: RateLimitScope,
+        /// Seconds until the next event would be accepted.
+        retry_after: u64,
+    },
+    /// The processing of the request exceeded its deadline.
+    #[error("Processing exceeded the deadline of {:?}", timeout)]
+    DeadlineExceeded { timeout: Duration },
+    /// The request was rejected due to backpressure, reported in a standardized way.
+    #[error("Service overloaded ({}), retry after {} seconds", source, retry_after)]
+    Backpressure {
+        source: BackpressureSource,
+        /// Seconds the client should wait before retrying.
+        retry_after: u64,
+    },
+    /// The endpoint is in maintenance mode.
+    #[error("{}", message)]
+    Maintenance {
+        message: String,
+        /// Seconds the client should wait before retrying.
+        retry_after: u64,
+    },
 }
 
 impl EndpointError {
@@ -29,6 +65,13 @@ impl EndpointError {
             EndpointError::ConfigurationError { .. } => "ConfigurationError",
             EndpointError::AuthenticationServiceError { .. } => "AuthenticationServiceError",
             EndpointError::AuthenticationError { .. } => "AuthenticationError",
+            EndpointError::InvalidSignature { .. } => "InvalidSignature",
+            EndpointError::PayloadTooLarge { .. } => "PayloadTooLarge",
+            EndpointError::TimestampSkewed { .. } => "TimestampSkewed",
+            EndpointError::RateLimited { .. } => "RateLimited",
+            EndpointError::DeadlineExceeded { .. } => "DeadlineExceeded",
+            EndpointError::Backpressure { .. } => "Backpressure",
+            EndpointError::Maintenance { .. } => "Maintenance",
         }
     }
 }
@@ -48,6 +91,11 @@ pub struct ErrorResponse {
     pub message: String,
 }
 
+/// Header indicating which rate limit was exceeded.
+pub const HEADER_RATE_LIMIT_SCOPE: &str = "X-RateLimit-Scope";
+/// Header indicating the source of the backpressure.
+pub const HEADER_BACKPRESSURE_SOURCE: &str = "X-Backpressure-Source";
+
 #[derive(Debug)]
 pub struct HttpEndpointError(pub EndpointError);
 
@@ -65,6 +113,13 @@ impl ResponseError for HttpEndpointError {
             EndpointError::ConfigurationError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             EndpointError::AuthenticationServiceError { .. } => StatusCode::SERVICE_UNAVAILABLE,
             EndpointError::AuthenticationError { .. } => StatusCode::FORBIDDEN,
+            EndpointError::InvalidSignature { .. } => StatusCode::UNAUTHORIZED,
+            EndpointError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            EndpointError::TimestampSkewed { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            EndpointError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            EndpointError::DeadlineExceeded { .. } => StatusCode::GATEWAY_TIMEOUT,
+            EndpointError::Backpressure { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            EndpointError::Maintenance { .. } => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 
@@ -75,7 +130,27 @@ impl ResponseError for HttpEndpointError {
             message: self.to_string(),
             error: self.0.name().into(),
         };
-        HttpResponse::build(status_code).json(error_response)
+        let mut response = HttpResponse::build(status_code);
+        match &self.0 {
+            EndpointError::RateLimited { scope, retry_after } => {
+                response
+                    .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                    .insert_header((HEADER_RATE_LIMIT_SCOPE, scope.as_str()));
+            }
+            EndpointError::Backpressure {
+                source,
+                retry_after,
+            } => {
+                response
+                    .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                    .insert_header((HEADER_BACKPRESSURE_SOURCE, source.as_str()));
+            }
+            EndpointError::Maintenance { retry_after, .. } => {
+                response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+            }
+            _ => {}
+        }
+        response.json(error_response)
     }
 }
 
@@ -92,3 +167,80 @@ impl From<EndpointError> for HttpEndpointError {
         HttpEndpointError(err)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sender::{BackpressureConfig, RetryAfterStrategy};
+
+    #[test]
+    fn test_rate_limited_response() {
+        let response = HttpEndpointError(EndpointError::RateLimited {
+            scope: RateLimitScope::Tenant,
+            retry_after: 2,
+        })
+        .error_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "2");
+        assert_eq!(
+            response.headers().get(HEADER_RATE_LIMIT_SCOPE).unwrap(),
+            "tenant"
+        );
+    }
+
+    #[test]
+    fn test_deadline_exceeded_response() {
+        let response = HttpEndpointError(EndpointError::DeadlineExceeded {
+            timeout: Duration::from_secs(1),
+        })
+        .error_response();
+
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[test]
+    fn test_backpressure_response() {
+        let config = BackpressureConfig {
+            enabled: true,
+            retry_after: RetryAfterStrategy::Fixed { seconds: 3 },
+        };
+
+        let errors = [
+            config.apply(EndpointError::RateLimited {
+                scope: RateLimitScope::Device,
+                retry_after: 1,
+            }),
+            config.error(BackpressureSource::Concurrency, None),
+            config.apply(EndpointError::DeadlineExceeded {
+                timeout: Duration::from_secs(1),
+            }),
+        ];
+
+        for (err, source) in errors
+            .into_iter()
+            .zip(["rate-limit", "concurrency", "deadline"])
+        {
+            let response = HttpEndpointError(err).error_response();
+
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "3");
+            assert_eq!(
+                response.headers().get(HEADER_BACKPRESSURE_SOURCE).unwrap(),
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_maintenance_response() {
+        let response = HttpEndpointError(EndpointError::Maintenance {
+            message: "Down for maintenance".into(),
+            retry_after: 60,
+        })
+        .error_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "60");
+    }
+}
@@ -1,7 +1,9 @@
+pub mod audit;
 pub mod auth;
 pub mod command;
 pub mod error;
 pub mod psk;
+pub mod sampling;
 pub mod sender;
 pub mod sink;
 pub mod x509;
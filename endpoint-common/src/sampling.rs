@@ -0,0 +1,182 @@
+//! Head-based sampling of request traces.
+//!
+//! The decision is taken once, when a request enters the endpoint. Requests which are part of a
+//! trace (having a `traceparent` header) follow the decision of the caller. For all other
+//! requests, the configured rate is used.
+
+use drogue_cloud_service_api::webapp::HttpRequest;
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tracing::Span;
+
+/// The W3C trace context header.
+pub const HEADER_TRACEPARENT: &str = "traceparent";
+
+/// The rate of requests to sample.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SampleRate {
+    /// Sample a ratio of the requests, from `0.0` (none) to `1.0` (all).
+    Probability(f64),
+    /// Sample every n-th request.
+    OneIn(u64),
+}
+
+impl Default for SampleRate {
+    fn default() -> Self {
+        Self::Probability(1.0)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct TraceSamplingConfig {
+    /// The rate of requests to trace, which are not part of a trace already.
+    ///
+    /// By default, all requests get traced.
+    #[serde(default)]
+    pub rate: SampleRate,
+}
+
+/// Evaluates if a request should be traced.
+#[derive(Clone, Debug, Default)]
+pub struct TraceSampler {
+    rate: SampleRate,
+    counter: Arc<AtomicU64>,
+}
+
+impl TraceSampler {
+    pub fn new(config: TraceSamplingConfig) -> Self {
+        Self {
+            rate: config.rate,
+            counter: Default::default(),
+        }
+    }
+
+    /// Check if a request should be traced, respecting the decision of the caller.
+    pub fn should_sample(&self, traceparent: Option<&str>) -> bool {
+        if let Some(sampled) = traceparent.and_then(parent_sampled) {
+            return sampled;
+        }
+
+        match self.rate {
+            SampleRate::Probability(rate) if rate >= 1.0 => true,
+            SampleRate::Probability(rate) if rate <= 0.0 => false,
+            SampleRate::Probability(rate) => rand::random::<f64>() < rate,
+            SampleRate::OneIn(0) => false,
+            SampleRate::OneIn(n) => self.counter.fetch_add(1, Ordering::Relaxed) % n == 0,
+        }
+    }
+
+    /// Create the span of an HTTP request, or a disabled span if the request isn't sampled.
+    pub fn span<F>(&self, req: &HttpRequest, f: F) -> Span
+    where
+        F: FnOnce() -> Span,
+    {
+        let traceparent = req
+            .headers()
+            .get(HEADER_TRACEPARENT)
+            .and_then(|value| value.to_str().ok());
+
+        match self.should_sample(traceparent) {
+            true => f(),
+            false => Span::none(),
+        }
+    }
+}
+
+/// Extract the sampled flag from a `traceparent` header value.
+///
+/// Returns [`None`] if the value is invalid, in which case the request isn't considered part of a
+/// trace.
+fn parent_sampled(traceparent: &str) -> Option<bool> {
+    let hex = |value: &str, len: usize| {
+        value.len() == len && value.chars().all(|c| c.is_ascii_hexdigit())
+    };
+
+    let parts = traceparent.trim().split('-').collect::<Vec<_>>();
+    match parts.as_slice() {
+        [version, trace_id, parent_id, flags, ..]
+            if hex(version, 2)
+                && *version != "ff"
+                && (*version != "00" || parts.len() == 4)
+                && hex(trace_id, 32)
+                && hex(parent_id, 16)
+                && hex(flags, 2)
+                && trace_id.chars().any(|c| c != '0')
+                && parent_id.chars().any(|c| c != '0') =>
+        {
+            u8::from_str_radix(flags, 16)
+                .ok()
+                .map(|flags| flags & 0x01 == 0x01)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLED: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+    const NOT_SAMPLED: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00";
+
+    fn sampler(rate: SampleRate) -> TraceSampler {
+        TraceSampler::new(TraceSamplingConfig { rate })
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parent_sampled(SAMPLED), Some(true));
+        assert_eq!(parent_sampled(NOT_SAMPLED), Some(false));
+
+        assert_eq!(parent_sampled(""), None);
+        assert_eq!(parent_sampled("00-1234-5678-01"), None);
+        // invalid version
+        assert_eq!(
+            parent_sampled("ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            None
+        );
+        // invalid trace id
+        assert_eq!(
+            parent_sampled("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sampled_parent_kept() {
+        let sampler = sampler(SampleRate::Probability(0.0));
+
+        for _ in 0..100 {
+            assert!(sampler.should_sample(Some(SAMPLED)));
+            assert!(!sampler.should_sample(Some(NOT_SAMPLED)));
+        }
+
+        // the local sampler governs the others
+        assert!(!sampler.should_sample(None));
+        assert!(!sampler.should_sample(Some("invalid")));
+    }
+
+    #[test]
+    fn test_one_in() {
+        let sampler = sampler(SampleRate::OneIn(10));
+
+        let sampled = (0..100).filter(|_| sampler.should_sample(None)).count();
+        assert_eq!(sampled, 10);
+
+        // parents don't count towards the local rate
+        for _ in 0..5 {
+            assert!(sampler.should_sample(Some(SAMPLED)));
+        }
+        assert!(sampler.should_sample(None));
+    }
+
+    #[test]
+    fn test_default() {
+        let sampler = TraceSampler::default();
+        assert!(sampler.should_sample(None));
+    }
+}
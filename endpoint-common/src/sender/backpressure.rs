@@ -0,0 +1,199 @@
+use crate::error::EndpointError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Display, Formatter},
+    time::Duration,
+};
+
+/// The mechanism which pushed back on a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackpressureSource {
+    /// The rate limit of the device or its tenant was exceeded.
+    RateLimit,
+    /// The in-flight events of the device, or of all devices, reached their limit.
+    Concurrency,
+    /// The processing of the request exceeded its deadline.
+    Deadline,
+}
+
+impl BackpressureSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::RateLimit => "rate-limit",
+            Self::Concurrency => "concurrency",
+            Self::Deadline => "deadline",
+        }
+    }
+}
+
+impl Display for BackpressureSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How to compute the `Retry-After` value of a backpressure response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RetryAfterStrategy {
+    /// Always use the same number of seconds.
+    Fixed { seconds: u64 },
+    /// Use the estimate of the source, limited to a range of seconds.
+    ///
+    /// The rate limit estimates the time until the bucket is refilled, the deadline uses its
+    /// timeout. Sources without an estimate use the minimum.
+    Adaptive { min: u64, max: u64 },
+}
+
+impl Default for RetryAfterStrategy {
+    fn default() -> Self {
+        Self::Fixed { seconds: 1 }
+    }
+}
+
+impl RetryAfterStrategy {
+    /// The number of seconds a client should wait, given the estimate of the source.
+    pub fn retry_after(&self, estimate: Option<u64>) -> u64 {
+        match *self {
+            Self::Fixed { seconds } => seconds,
+            Self::Adaptive { min, max } => estimate.unwrap_or(min).clamp(min, max.max(min)),
+        }
+    }
+}
+
+/// Reporting all kinds of backpressure the same way to clients.
+///
+/// When enabled, requests rejected due to backpressure are answered with a
+/// `503 Service Unavailable`, carrying a `Retry-After` header. This way, devices only need to
+/// implement a single backoff strategy.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BackpressureConfig {
+    /// Use the standardized backpressure response.
+    ///
+    /// Also, requests get rejected instead of waiting for an in-flight slot.
+    #[serde(default)]
+    pub enabled: bool,
+    /// How to compute the `Retry-After` value.
+    #[serde(default)]
+    pub retry_after: RetryAfterStrategy,
+}
+
+impl BackpressureConfig {
+    /// Create the backpressure error of a source.
+    pub fn error(&self, source: BackpressureSource, estimate: Option<u64>) -> EndpointError {
+        EndpointError::Backpressure {
+            source,
+            retry_after: self.retry_after.retry_after(estimate),
+        }
+    }
+
+    /// Convert an error, caused by backpressure, into the standardized error.
+    ///
+    /// Other errors, or all errors if not enabled, are returned unchanged.
+    pub fn apply(&self, err: EndpointError) -> EndpointError {
+        if !self.enabled {
+            return err;
+        }
+
+        match err {
+            EndpointError::RateLimited { retry_after, .. } => {
+                self.error(BackpressureSource::RateLimit, Some(retry_after))
+            }
+            EndpointError::DeadlineExceeded { timeout } => {
+                self.error(BackpressureSource::Deadline, Some(round_up(timeout)))
+            }
+            err => err,
+        }
+    }
+}
+
+/// The number of seconds of a duration, rounded up.
+fn round_up(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sender::RateLimitScope;
+
+    fn config(retry_after: RetryAfterStrategy) -> BackpressureConfig {
+        BackpressureConfig {
+            enabled: true,
+            retry_after,
+        }
+    }
+
+    fn retry_after(err: EndpointError) -> Option<(BackpressureSource, u64)> {
+        match err {
+            EndpointError::Backpressure {
+                source,
+                retry_after,
+            } => Some((source, retry_after)),
+            _ => None,
+        }
+    }
+
+    fn rate_limited() -> EndpointError {
+        EndpointError::RateLimited {
+            scope: RateLimitScope::Device,
+            retry_after: 30,
+        }
+    }
+
+    #[test]
+    fn test_disabled() {
+        let err = BackpressureConfig::default().apply(rate_limited());
+        assert!(matches!(err, EndpointError::RateLimited { .. }));
+    }
+
+    #[test]
+    fn test_fixed() {
+        let config = config(RetryAfterStrategy::Fixed { seconds: 5 });
+
+        assert_eq!(
+            retry_after(config.apply(rate_limited())),
+            Some((BackpressureSource::RateLimit, 5))
+        );
+        assert_eq!(
+            retry_after(config.apply(EndpointError::DeadlineExceeded {
+                timeout: Duration::from_millis(200)
+            })),
+            Some((BackpressureSource::Deadline, 5))
+        );
+        assert_eq!(
+            retry_after(config.error(BackpressureSource::Concurrency, None)),
+            Some((BackpressureSource::Concurrency, 5))
+        );
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let config = config(RetryAfterStrategy::Adaptive { min: 1, max: 10 });
+
+        // limited to the maximum
+        assert_eq!(
+            retry_after(config.apply(rate_limited())),
+            Some((BackpressureSource::RateLimit, 10))
+        );
+        // rounded up
+        assert_eq!(
+            retry_after(config.apply(EndpointError::DeadlineExceeded {
+                timeout: Duration::from_millis(2500)
+            })),
+            Some((BackpressureSource::Deadline, 3))
+        );
+        // no estimate
+        assert_eq!(
+            retry_after(config.error(BackpressureSource::Concurrency, None)),
+            Some((BackpressureSource::Concurrency, 1))
+        );
+    }
+
+    #[test]
+    fn test_other_errors() {
+        let config = config(Default::default());
+        let err = config.apply(EndpointError::AuthenticationError);
+        assert!(matches!(err, EndpointError::AuthenticationError));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::error::EndpointError;
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+tokio::task_local! {
+    static COMMITTED: Arc<AtomicBool>;
+}
+
+/// Mark the current request as committed, as its event is about to be handed over to the sink.
+///
+/// From this point on, the request can't be cancelled without risking a partially sent event.
+/// Outside of a request with a deadline, this has no effect.
+pub(crate) fn commit() {
+    let _ = COMMITTED.try_with(|committed| committed.store(true, Ordering::Release));
+}
+
+/// The maximum processing time of a request.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DeadlineConfig {
+    /// The time after which the processing of a request gets cancelled.
+    ///
+    /// The deadline only applies until the event gets sent downstream. Once the event is handed
+    /// over to the sink, the request is completed regularly. By default, there is no deadline.
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+impl DeadlineConfig {
+    /// Run the processing of a request, cancelling it if it exceeds the deadline before sending
+    /// its event.
+    pub async fn run<F, T>(&self, f: F) -> Result<T, EndpointError>
+    where
+        F: Future<Output = T>,
+    {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return Ok(f.await),
+        };
+
+        let committed = Arc::new(AtomicBool::new(false));
+        let f = COMMITTED.scope(committed.clone(), f);
+        tokio::pin!(f);
+
+        tokio::select! {
+            result = &mut f => Ok(result),
+            _ = tokio::time::sleep(timeout) => {
+                match committed.load(Ordering::Acquire) {
+                    // already sending, let it complete
+                    true => Ok(f.await),
+                    // dropping the future cancels the processing
+                    false => Err(EndpointError::DeadlineExceeded { timeout }),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+    use tokio::time::sleep;
+
+    fn config(timeout: Option<Duration>) -> DeadlineConfig {
+        DeadlineConfig { timeout }
+    }
+
+    #[tokio::test]
+    async fn test_no_deadline() {
+        let result = config(None)
+            .run(async {
+                sleep(Duration::from_millis(50)).await;
+                42
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn test_within_deadline() {
+        let result = config(Some(Duration::from_secs(5))).run(async { 42 }).await;
+
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn test_slow_stage_cancelled() {
+        let sent = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+
+        let result = config(Some(Duration::from_millis(50)))
+            .run({
+                let sent = sent.clone();
+                async move {
+                    // slow processing before sending
+                    sleep(Duration::from_secs(5)).await;
+                    commit();
+                    sent.store(true, Ordering::Release);
+                }
+            })
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(EndpointError::DeadlineExceeded { .. })
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!sent.load(Ordering::Acquire));
+    }
+
+    #[tokio::test]
+    async fn test_committed_completes() {
+        let result = config(Some(Duration::from_millis(50)))
+            .run(async {
+                commit();
+                // slow send
+                sleep(Duration::from_millis(200)).await;
+                42
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), 42);
+    }
+}
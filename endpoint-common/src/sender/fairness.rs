@@ -0,0 +1,240 @@
+use lazy_static::lazy_static;
+use prometheus::{register_histogram, register_int_gauge, Histogram, IntGauge};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+lazy_static! {
+    pub static ref DOWNSTREAM_ACTIVE_DEVICES: IntGauge = register_int_gauge!(
+        "drogue_downstream_active_devices",
+        "Devices with in-flight downstream events"
+    )
+    .unwrap();
+    pub static ref DOWNSTREAM_DEVICE_IN_FLIGHT: Histogram = register_histogram!(
+        "drogue_downstream_device_in_flight",
+        "In-flight downstream events of a device, when sending an event",
+        vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0]
+    )
+    .unwrap();
+}
+
+/// Configuration of the per-device fairness.
+///
+/// Every device can only occupy a limited number of the overall in-flight events. This way, a
+/// single chatty device can't starve all other devices.
+///
+/// If no per-device limit is configured, there are no limits at all.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FairnessConfig {
+    /// The maximum number of in-flight events of a single device.
+    #[serde(default)]
+    pub per_device_limit: Option<usize>,
+    /// The maximum number of in-flight events of all devices.
+    #[serde(default = "default_total_limit")]
+    pub total_limit: usize,
+}
+
+const fn default_total_limit() -> usize {
+    1024
+}
+
+impl Default for FairnessConfig {
+    fn default() -> Self {
+        Self {
+            per_device_limit: None,
+            total_limit: default_total_limit(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Slots {
+    per_device: usize,
+    total: Arc<Semaphore>,
+    devices: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+/// The in-flight slots of the devices.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceSlots {
+    slots: Option<Arc<Slots>>,
+}
+
+/// A slot of a device, which must be held until the event was sent.
+pub struct DevicePermit {
+    slots: Arc<Slots>,
+    device: String,
+    device_permit: Option<OwnedSemaphorePermit>,
+    _total_permit: OwnedSemaphorePermit,
+}
+
+impl Drop for DevicePermit {
+    fn drop(&mut self) {
+        // release the permit first, so that we can check if the device is idle
+        self.device_permit.take();
+
+        let mut devices = self.slots.devices.lock().unwrap();
+        // nobody else is holding or waiting for a permit of this device
+        if let Some(semaphore) = devices.get(&self.device) {
+            if Arc::strong_count(semaphore) == 1 {
+                devices.remove(&self.device);
+                DOWNSTREAM_ACTIVE_DEVICES.dec();
+            }
+        }
+    }
+}
+
+impl DeviceSlots {
+    pub fn new(config: FairnessConfig) -> Self {
+        let slots = config.per_device_limit.map(|per_device| {
+            Arc::new(Slots {
+                per_device,
+                total: Arc::new(Semaphore::new(config.total_limit)),
+                devices: Default::default(),
+            })
+        });
+
+        Self { slots }
+    }
+
+    /// Acquire a slot for a device.
+    ///
+    /// The slot of the device is acquired before the overall slot, so that a device waiting for
+    /// its own slots doesn't occupy any of the overall slots. If there are no limits, [`None`] is
+    /// returned right away.
+    pub async fn acquire(&self, device: &str) -> Option<DevicePermit> {
+        let slots = self.slots.as_ref()?;
+
+        let semaphore = {
+            let mut devices = slots.devices.lock().unwrap();
+            devices
+                .entry(device.to_string())
+                .or_insert_with(|| {
+                    DOWNSTREAM_ACTIVE_DEVICES.inc();
+                    Arc::new(Semaphore::new(slots.per_device))
+                })
+                .clone()
+        };
+
+        DOWNSTREAM_DEVICE_IN_FLIGHT
+            .observe((slots.per_device - semaphore.available_permits()) as f64);
+
+        // we never close the semaphores, so this can't fail
+        let device_permit = semaphore.acquire_owned().await.ok()?;
+        let total_permit = slots.total.clone().acquire_owned().await.ok()?;
+
+        Some(DevicePermit {
+            slots: slots.clone(),
+            device: device.to_string(),
+            device_permit: Some(device_permit),
+            _total_permit: total_permit,
+        })
+    }
+
+    /// Check if the device, or all devices, have no free slots, so that acquiring one would wait.
+    pub fn is_exhausted(&self, device: &str) -> bool {
+        let slots = match &self.slots {
+            Some(slots) => slots,
+            None => return false,
+        };
+
+        let device_exhausted = slots
+            .devices
+            .lock()
+            .unwrap()
+            .get(device)
+            .map_or(false, |semaphore| semaphore.available_permits() == 0);
+
+        device_exhausted || slots.total.available_permits() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn slots(per_device: usize, total: usize) -> DeviceSlots {
+        DeviceSlots::new(FairnessConfig {
+            per_device_limit: Some(per_device),
+            total_limit: total,
+        })
+    }
+
+    async fn try_acquire(slots: &DeviceSlots, device: &str) -> Option<DevicePermit> {
+        timeout(Duration::from_millis(50), slots.acquire(device))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[tokio::test]
+    async fn test_unlimited() {
+        assert!(DeviceSlots::default().acquire("device1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_skewed_load() {
+        let slots = slots(2, 6);
+
+        // the chatty device only gets its share
+
+        let mut chatty = vec![];
+        for _ in 0..10 {
+            if let Some(permit) = try_acquire(&slots, "chatty").await {
+                chatty.push(permit);
+            }
+        }
+        assert_eq!(chatty.len(), 2);
+
+        // the other devices still get their slots
+
+        let quiet1 = [
+            try_acquire(&slots, "quiet1").await,
+            try_acquire(&slots, "quiet1").await,
+        ];
+        let quiet2 = [
+            try_acquire(&slots, "quiet2").await,
+            try_acquire(&slots, "quiet2").await,
+        ];
+        assert!(quiet1.iter().chain(&quiet2).all(Option::is_some));
+
+        // the overall limit is reached
+
+        assert!(try_acquire(&slots, "quiet3").await.is_none());
+
+        // releasing slots of the chatty device, makes room for others
+
+        chatty.clear();
+        assert!(try_acquire(&slots, "quiet3").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup() {
+        let slots = slots(2, 6);
+
+        let permit = slots.acquire("device1").await.unwrap();
+        let inner = slots.slots.as_ref().unwrap();
+        assert_eq!(inner.devices.lock().unwrap().len(), 1);
+
+        drop(permit);
+        assert!(inner.devices.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_exhausted() {
+        let slots = slots(1, 2);
+        assert!(!DeviceSlots::default().is_exhausted("device1"));
+
+        let _permit1 = slots.acquire("device1").await.unwrap();
+        assert!(slots.is_exhausted("device1"));
+        assert!(!slots.is_exhausted("device2"));
+
+        let _permit2 = slots.acquire("device2").await.unwrap();
+        assert!(slots.is_exhausted("device3"));
+    }
+}
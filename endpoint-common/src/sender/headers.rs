@@ -0,0 +1,179 @@
+use cloudevents::Event;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A violated header limit.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum HeaderLimitError {
+    #[error("Header key '{key}' exceeds the maximum length of {max} bytes")]
+    KeyLength { key: String, max: usize },
+    #[error("Value of header '{key}' exceeds the maximum size of {max} bytes")]
+    ValueSize { key: String, max: usize },
+    #[error("Headers exceed the maximum total size of {max} bytes ({size} bytes)")]
+    TotalSize { size: usize, max: usize },
+}
+
+/// Limits of the Kafka record headers, derived from the event attributes and extensions.
+///
+/// Kafka limits the size of a record, including its headers (`message.max.bytes`, 1 MiB by
+/// default). Checking the headers before producing, rejects events with an excessive amount of
+/// properties with a clear error, rather than failing to produce them.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HeaderLimitsConfig {
+    /// The maximum length of a header key.
+    #[serde(default = "default_max_key_length")]
+    pub max_key_length: usize,
+    /// The maximum size of a single header value.
+    #[serde(default = "default_max_value_size")]
+    pub max_value_size: usize,
+    /// The maximum size of all header keys and values.
+    #[serde(default = "default_max_total_size")]
+    pub max_total_size: usize,
+}
+
+const fn default_max_key_length() -> usize {
+    256
+}
+
+const fn default_max_value_size() -> usize {
+    16 * 1024
+}
+
+const fn default_max_total_size() -> usize {
+    64 * 1024
+}
+
+impl Default for HeaderLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_key_length: default_max_key_length(),
+            max_value_size: default_max_value_size(),
+            max_total_size: default_max_total_size(),
+        }
+    }
+}
+
+/// The name of the Kafka header, carrying an attribute or extension.
+fn header_key(name: &str) -> String {
+    match name {
+        "datacontenttype" => "content-type".to_string(),
+        name => format!("ce_{name}"),
+    }
+}
+
+impl HeaderLimitsConfig {
+    /// Check the headers, which would be produced for the event.
+    pub fn check(&self, event: &Event) -> Result<(), HeaderLimitError> {
+        let mut size = 0;
+
+        for (name, value) in event.iter() {
+            let key = header_key(name);
+            if key.len() > self.max_key_length {
+                return Err(HeaderLimitError::KeyLength {
+                    key,
+                    max: self.max_key_length,
+                });
+            }
+
+            let value = value.to_string();
+            if value.len() > self.max_value_size {
+                return Err(HeaderLimitError::ValueSize {
+                    key,
+                    max: self.max_value_size,
+                });
+            }
+
+            size += key.len() + value.len();
+        }
+
+        if size > self.max_total_size {
+            return Err(HeaderLimitError::TotalSize {
+                size,
+                max: self.max_total_size,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    fn event(extensions: &[(&str, String)]) -> Event {
+        let mut event = EventBuilderV10::new()
+            .id("1")
+            .ty("io.drogue.event.v1")
+            .source("drogue://app1/device1");
+        for (name, value) in extensions {
+            event = event.extension(name, value.clone());
+        }
+        event.build().unwrap()
+    }
+
+    #[test]
+    fn test_within_limits() {
+        let config = HeaderLimitsConfig::default();
+        let event = event(&[("device", "device1".into()), ("property", "a".repeat(1024))]);
+
+        assert_eq!(config.check(&event), Ok(()));
+    }
+
+    #[test]
+    fn test_key_length() {
+        let config = HeaderLimitsConfig {
+            max_key_length: 16,
+            ..Default::default()
+        };
+        let event = event(&[("averyverylongpropertyname", "value".into())]);
+
+        assert_eq!(
+            config.check(&event),
+            Err(HeaderLimitError::KeyLength {
+                key: "ce_averyverylongpropertyname".into(),
+                max: 16
+            })
+        );
+    }
+
+    #[test]
+    fn test_value_size() {
+        let config = HeaderLimitsConfig {
+            max_value_size: 1024,
+            ..Default::default()
+        };
+        let event = event(&[("property", "a".repeat(1025))]);
+
+        assert_eq!(
+            config.check(&event),
+            Err(HeaderLimitError::ValueSize {
+                key: "ce_property".into(),
+                max: 1024
+            })
+        );
+    }
+
+    #[test]
+    fn test_total_size() {
+        let config = HeaderLimitsConfig {
+            max_total_size: 4096,
+            ..Default::default()
+        };
+
+        // each header is within its limits, but not all of them
+        let extensions = (0..10)
+            .map(|i| (format!("property{i}"), "a".repeat(512)))
+            .collect::<Vec<_>>();
+        let extensions = extensions
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect::<Vec<_>>();
+
+        assert!(matches!(
+            config.check(&event(&extensions)),
+            Err(HeaderLimitError::TotalSize { max: 4096, .. })
+        ));
+    }
+}
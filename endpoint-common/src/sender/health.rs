@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use drogue_cloud_service_api::health::{HealthCheckError, HealthChecked};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Tying the liveness of the endpoint to the downstream connection.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LivenessConfig {
+    /// Report the endpoint as not alive, once sending downstream continuously failed for this
+    /// duration.
+    ///
+    /// This will let Kubernetes restart the pod. By default, the liveness doesn't depend on the
+    /// downstream connection.
+    #[serde(default, with = "humantime_serde")]
+    pub failure_threshold: Option<Duration>,
+}
+
+/// Tracks the duration of continuous downstream failures.
+///
+/// Any successful send resets the tracking.
+#[derive(Clone, Debug)]
+pub struct DownstreamHealth {
+    threshold: Duration,
+    failing_since: Arc<Mutex<Option<Instant>>>,
+}
+
+impl DownstreamHealth {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            failing_since: Default::default(),
+        }
+    }
+
+    /// Record a successful send.
+    pub fn success(&self) {
+        self.failing_since.lock().unwrap().take();
+    }
+
+    /// Record a failed send.
+    pub fn failure(&self) {
+        self.failure_at(Instant::now());
+    }
+
+    /// Check if sending downstream currently fails, regardless of the threshold.
+    pub fn is_failing(&self) -> bool {
+        self.failing_since.lock().unwrap().is_some()
+    }
+
+    fn failure_at(&self, now: Instant) {
+        self.failing_since.lock().unwrap().get_or_insert(now);
+    }
+
+    fn check_at(&self, now: Instant) -> Result<(), HealthCheckError> {
+        match *self.failing_since.lock().unwrap() {
+            Some(since) if now.saturating_duration_since(since) >= self.threshold => {
+                HealthCheckError::nok(format!(
+                    "Downstream continuously failing for {:?}",
+                    now.saturating_duration_since(since)
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl HealthChecked for DownstreamHealth {
+    async fn is_alive(&self) -> Result<(), HealthCheckError> {
+        self.check_at(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_threshold() {
+        let health = DownstreamHealth::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(health.check_at(start).is_ok());
+
+        health.failure_at(start);
+        // later failures must not reset the start
+        health.failure_at(start + Duration::from_secs(30));
+
+        assert!(health.check_at(start + Duration::from_secs(59)).is_ok());
+        assert!(health.check_at(start + Duration::from_secs(60)).is_err());
+    }
+
+    #[test]
+    fn test_reset() {
+        let health = DownstreamHealth::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        health.failure_at(start);
+        health.success();
+
+        assert!(health.check_at(start + Duration::from_secs(120)).is_ok());
+
+        // the next failure starts over
+
+        health.failure_at(start + Duration::from_secs(120));
+        assert!(health.check_at(start + Duration::from_secs(150)).is_ok());
+        assert!(health.check_at(start + Duration::from_secs(180)).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use super::{key::validate_client_value, ordering::override_properties};
+use crate::error::EndpointError;
+use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use serde::{Deserialize, Serialize};
+
+/// The extension attribute carrying the idempotence key of an event.
+pub const EXT_IDEMPOTENCE_KEY: &str = "idempotencekey";
+
+const IDEMPOTENT_PROPERTIES: &[(&str, &str)] = &[("enable.idempotence", "true"), ("acks", "all")];
+
+/// Preventing duplicates when publishing is retried.
+///
+/// The idempotent producer prevents duplicates when the producer retries internally. Retries
+/// across producer sessions, e.g. by the device after a timeout, can only be detected by
+/// consumers. For this, the event carries an idempotence key, which stays the same for all
+/// attempts.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IdempotenceConfig {
+    /// Use an idempotent producer, and add the idempotence key to events.
+    ///
+    /// The idempotent producer must be applied to the configuration of the sink, using
+    /// [`IdempotenceConfig::apply`].
+    #[serde(default)]
+    pub enabled: bool,
+    /// Allow the client to provide the idempotence key, instead of using the event ID.
+    ///
+    /// For HTTP, this is the value of the `Idempotency-Key` header.
+    #[serde(default)]
+    pub client_key: bool,
+    /// The maximum length of an idempotence key provided by the client.
+    #[serde(default = "default_max_length")]
+    pub max_length: usize,
+}
+
+const fn default_max_length() -> usize {
+    256
+}
+
+impl Default for IdempotenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            client_key: false,
+            max_length: default_max_length(),
+        }
+    }
+}
+
+impl IdempotenceConfig {
+    /// Apply the idempotent producer to the configuration of the producer.
+    ///
+    /// This overrides conflicting properties of the configuration.
+    pub fn apply(&self, config: KafkaClientConfig) -> KafkaClientConfig {
+        match self.enabled {
+            true => override_properties(config, IDEMPOTENT_PROPERTIES, "idempotence"),
+            false => config,
+        }
+    }
+
+    /// Validate an idempotence key provided by the client.
+    ///
+    /// This returns [`None`] if providing the key is not allowed, or the client didn't provide one.
+    /// In this case, the ID of the event is used.
+    pub fn client_key(&self, key: Option<&str>) -> Result<Option<String>, EndpointError> {
+        match key {
+            Some(key) if self.enabled && self.client_key => {
+                validate_client_value("Idempotence key", key, self.max_length).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Evaluate the idempotence key of an event.
+    ///
+    /// Returns [`None`] if not enabled.
+    pub fn key(&self, client_key: Option<String>, id: &str) -> Option<String> {
+        self.enabled
+            .then(|| client_key.unwrap_or_else(|| id.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn enabled(client_key: bool) -> IdempotenceConfig {
+        IdempotenceConfig {
+            enabled: true,
+            client_key,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_producer() {
+        let config = KafkaClientConfig {
+            bootstrap_servers: "localhost:9092".into(),
+            properties: [("acks".to_string(), "1".to_string())].into(),
+        };
+
+        assert_eq!(IdempotenceConfig::default().apply(config.clone()), config);
+
+        let config = enabled(false).apply(config);
+        assert_eq!(
+            config
+                .properties
+                .get("enable.idempotence")
+                .map(String::as_str),
+            Some("true")
+        );
+        assert_eq!(
+            config.properties.get("acks").map(String::as_str),
+            Some("all")
+        );
+    }
+
+    #[test]
+    fn test_client_key() {
+        assert_eq!(enabled(false).client_key(Some("key1")).unwrap(), None);
+        assert_eq!(
+            enabled(true).client_key(Some("key1")).unwrap().as_deref(),
+            Some("key1")
+        );
+        assert!(matches!(
+            enabled(true).client_key(Some("")),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(IdempotenceConfig::default().key(None, "id1"), None);
+        assert_eq!(enabled(false).key(None, "id1").as_deref(), Some("id1"));
+        assert_eq!(
+            enabled(true).key(Some("key1".into()), "id1").as_deref(),
+            Some("key1")
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::error::EndpointError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Configuration of how the key for the downstream (Kafka) record is derived.
+///
+/// By default, the key is derived from the application and sending device. This allows to use
+/// a field of the (JSON) payload instead, co-partitioning related devices.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KeyConfig {
+    /// Name of the payload field to use as the record key.
+    ///
+    /// This can either be the name of a top-level field, or a JSON pointer (starting with a `/`).
+    #[serde(default)]
+    pub payload_field: Option<String>,
+    /// The maximum length of a key extracted from the payload.
+    ///
+    /// If the extracted key exceeds this length, the default key will be used.
+    #[serde(default = "default_max_key_length")]
+    pub max_length: usize,
+    /// Allow the client to provide the key, overriding the payload field.
+    ///
+    /// For HTTP, this is the value of the `X-Message-Key` header.
+    #[serde(default)]
+    pub client_key: bool,
+}
+
+const fn default_max_key_length() -> usize {
+    256
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        Self {
+            payload_field: None,
+            max_length: default_max_key_length(),
+            client_key: false,
+        }
+    }
+}
+
+impl KeyConfig {
+    /// Extract the key from a payload.
+    ///
+    /// This returns [`None`] if no field is configured, the payload isn't JSON, or the field is
+    /// missing, empty or not a scalar value. In this case, the caller must use the default key.
+    pub fn extract(&self, payload: &[u8]) -> Option<String> {
+        let field = self.payload_field.as_deref()?;
+
+        let json = serde_json::from_slice::<Value>(payload).ok()?;
+        let value = payload_field(&json, field)?;
+
+        let key = match value {
+            Value::String(value) => value.clone(),
+            Value::Number(value) => value.to_string(),
+            _ => return None,
+        };
+
+        if key.is_empty() {
+            return None;
+        }
+
+        if key.len() > self.max_length {
+            log::debug!(
+                "Key from payload field '{}' exceeds maximum length ({} > {})",
+                field,
+                key.len(),
+                self.max_length
+            );
+            return None;
+        }
+
+        Some(key)
+    }
+
+    /// Validate a key provided by the client.
+    ///
+    /// This returns [`None`] if providing the key is not allowed, or the client didn't provide one.
+    /// In this case, the caller must use the configured strategy. Empty keys, keys exceeding the
+    /// maximum length, or containing control characters get rejected.
+    pub fn client_key(&self, key: Option<&str>) -> Result<Option<String>, EndpointError> {
+        match key {
+            Some(key) if self.client_key => {
+                validate_client_value("Message key", key, self.max_length).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Look up a field of a JSON payload.
+///
+/// The field can either be the name of a top-level field, or a JSON pointer (starting with a `/`).
+pub(crate) fn payload_field<'v>(json: &'v Value, field: &str) -> Option<&'v Value> {
+    match field.starts_with('/') {
+        true => json.pointer(field),
+        false => json.get(field),
+    }
+}
+
+/// Validate a value provided by the client, used as a key.
+///
+/// Empty values, values exceeding the maximum length, or containing control characters get
+/// rejected.
+pub(super) fn validate_client_value(
+    name: &str,
+    value: &str,
+    max_length: usize,
+) -> Result<String, EndpointError> {
+    if value.is_empty() {
+        return Err(EndpointError::InvalidRequest {
+            details: format!("{name} must not be empty"),
+        });
+    }
+
+    if value.len() > max_length {
+        return Err(EndpointError::InvalidRequest {
+            details: format!(
+                "{name} exceeds maximum length ({} > {max_length})",
+                value.len()
+            ),
+        });
+    }
+
+    if value.chars().any(char::is_control) {
+        return Err(EndpointError::InvalidRequest {
+            details: format!("{name} must not contain control characters"),
+        });
+    }
+
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(field: &str) -> KeyConfig {
+        KeyConfig {
+            payload_field: Some(field.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_not_configured() {
+        assert_eq!(KeyConfig::default().extract(br#"{"site": "a"}"#), None);
+    }
+
+    #[test]
+    fn test_field_present() {
+        assert_eq!(
+            config("site").extract(br#"{"site": "site-1", "temp": 42}"#),
+            Some("site-1".into())
+        );
+        assert_eq!(
+            config("site").extract(br#"{"site": 123, "temp": 42}"#),
+            Some("123".into())
+        );
+    }
+
+    #[test]
+    fn test_pointer() {
+        assert_eq!(
+            config("/location/site").extract(br#"{"location": {"site": "site-1"}}"#),
+            Some("site-1".into())
+        );
+    }
+
+    #[test]
+    fn test_field_absent() {
+        assert_eq!(config("site").extract(br#"{"temp": 42}"#), None);
+        assert_eq!(config("site").extract(br#"{"site": ""}"#), None);
+        assert_eq!(config("site").extract(br#"{"site": {"id": 1}}"#), None);
+    }
+
+    #[test]
+    fn test_non_json() {
+        assert_eq!(config("site").extract(b"site=1"), None);
+        assert_eq!(config("site").extract(&[0xFF, 0x00, 0x01]), None);
+    }
+
+    #[test]
+    fn test_too_long() {
+        let config = KeyConfig {
+            payload_field: Some("site".into()),
+            max_length: 4,
+            ..Default::default()
+        };
+        assert_eq!(config.extract(br#"{"site": "1234"}"#), Some("1234".into()));
+        assert_eq!(config.extract(br#"{"site": "12345"}"#), None);
+    }
+
+    #[test]
+    fn test_client_key() {
+        let config = KeyConfig {
+            max_length: 4,
+            client_key: true,
+            ..Default::default()
+        };
+
+        assert_eq!(config.client_key(None).unwrap(), None);
+        assert_eq!(
+            config.client_key(Some("1234")).unwrap(),
+            Some("1234".into())
+        );
+        assert!(matches!(
+            config.client_key(Some("")),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+        assert!(matches!(
+            config.client_key(Some("12345")),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+        assert!(matches!(
+            config.client_key(Some("1\n2")),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+    }
+
+    #[test]
+    fn test_client_key_disabled() {
+        assert_eq!(KeyConfig::default().client_key(Some("1234")).unwrap(), None);
+    }
+}
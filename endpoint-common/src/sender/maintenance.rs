@@ -0,0 +1,162 @@
+use crate::error::EndpointError;
+use async_trait::async_trait;
+use drogue_cloud_service_api::health::{HealthCheckError, HealthChecked};
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Rejecting publish requests during a planned maintenance.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MaintenanceConfig {
+    /// Start the endpoint in maintenance mode.
+    #[serde(default)]
+    pub active: bool,
+    /// The message returned to clients while in maintenance mode.
+    #[serde(default = "default_message")]
+    pub message: String,
+    /// The number of seconds clients should wait before retrying.
+    #[serde(default = "default_retry_after")]
+    pub retry_after: u64,
+}
+
+fn default_message() -> String {
+    "The endpoint is down for maintenance".into()
+}
+
+const fn default_retry_after() -> u64 {
+    60
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            active: false,
+            message: default_message(),
+            retry_after: default_retry_after(),
+        }
+    }
+}
+
+/// The maintenance mode of the endpoint, which can be toggled at runtime.
+///
+/// While active, publish requests are rejected and the endpoint reports as not ready, so that
+/// load balancers drain the traffic. The endpoint is still reported as alive.
+#[derive(Clone, Debug)]
+pub struct Maintenance {
+    config: Arc<MaintenanceConfig>,
+    active: Arc<AtomicBool>,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl Maintenance {
+    pub fn new(config: MaintenanceConfig) -> Self {
+        Self {
+            active: Arc::new(AtomicBool::new(config.active)),
+            config: Arc::new(config),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Enter or exit the maintenance mode.
+    pub fn set_active(&self, active: bool) {
+        if self.active.swap(active, Ordering::Relaxed) != active {
+            match active {
+                true => log::warn!("Entering maintenance mode, rejecting publish requests"),
+                false => log::warn!("Exiting maintenance mode, accepting publish requests"),
+            }
+        }
+    }
+
+    /// Toggle the maintenance mode, returning the new state.
+    pub fn toggle(&self) -> bool {
+        let active = !self.is_active();
+        self.set_active(active);
+        active
+    }
+
+    /// Reject the request, if the maintenance mode is active.
+    pub fn check(&self) -> Result<(), EndpointError> {
+        match self.is_active() {
+            true => Err(EndpointError::Maintenance {
+                message: self.config.message.clone(),
+                retry_after: self.config.retry_after,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// Toggle the maintenance mode whenever the process receives a `SIGUSR1`.
+    #[cfg(unix)]
+    pub async fn listen(self) -> anyhow::Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut signals = signal(SignalKind::user_defined1())?;
+        while signals.recv().await.is_some() {
+            self.toggle();
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HealthChecked for Maintenance {
+    async fn is_ready(&self) -> Result<(), HealthCheckError> {
+        match self.is_active() {
+            true => HealthCheckError::nok("Maintenance mode is active"),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reject() {
+        let maintenance = Maintenance::new(MaintenanceConfig {
+            retry_after: 30,
+            ..Default::default()
+        });
+
+        assert!(maintenance.check().is_ok());
+
+        assert!(maintenance.toggle());
+        assert!(matches!(
+            maintenance.check(),
+            Err(EndpointError::Maintenance {
+                retry_after: 30,
+                ..
+            })
+        ));
+
+        // clones share the state
+        maintenance.clone().set_active(false);
+        assert!(maintenance.check().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let maintenance = Maintenance::new(MaintenanceConfig {
+            active: true,
+            ..Default::default()
+        });
+
+        assert!(maintenance.is_ready().await.is_err());
+        assert!(maintenance.is_alive().await.is_ok());
+
+        maintenance.set_active(false);
+        assert!(maintenance.is_ready().await.is_ok());
+    }
+}
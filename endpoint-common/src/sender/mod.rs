@@ -1,22 +1,61 @@
+mod backpressure;
+mod deadline;
+mod fairness;
+mod headers;
+mod health;
+mod idempotence;
+mod key;
+mod maintenance;
+mod ordering;
+mod pacing;
+mod priority;
 mod process;
+mod rate_limit;
+mod redaction;
+mod retry;
+mod sample;
+mod schema;
+mod sensitivity;
+mod shutdown;
+mod timestamp;
 
+pub use backpressure::*;
+pub use deadline::DeadlineConfig;
+pub use fairness::*;
+pub use headers::*;
+pub use health::*;
+pub use idempotence::*;
+pub use key::*;
+pub use maintenance::*;
+pub use ordering::*;
+pub use pacing::*;
+pub use priority::*;
 pub use process::ExternalClientPoolConfig;
+pub use rate_limit::*;
+pub use redaction::*;
+pub use retry::*;
+pub use sample::*;
+pub use schema::*;
+pub use sensitivity::*;
+pub use shutdown::*;
+pub use timestamp::*;
 
 use crate::{
+    error::EndpointError,
     sender::process::{ExternalClientPool, Outcome},
-    sink::{Sink, SinkError, SinkTarget},
+    sink::{KafkaSinkError, Sink, SinkError, SinkTarget},
     EXT_PARTITIONKEY,
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use cloudevents::{event::Data, Event, EventBuilder, EventBuilderV10};
+use cloudevents::{event::Data, AttributesReader, Event, EventBuilder, EventBuilderV10};
 use drogue_client::{
     meta::v1::{NonScopedMetadata, ScopedMetadata},
     registry,
 };
 use drogue_cloud_service_api::{
-    webapp::HttpResponse, EXT_APPLICATION_UID, EXT_DEVICE_UID, EXT_INSTANCE, EXT_SENDER,
-    EXT_SENDER_UID,
+    webapp::HttpResponse, EXT_APPLICATION, EXT_APPLICATION_UID, EXT_DEVICE, EXT_DEVICE_UID,
+    EXT_INSTANCE, EXT_SENDER, EXT_SENDER_UID,
 };
 use drogue_cloud_service_common::{Id, IdInjector};
 use lazy_static::lazy_static;
@@ -27,6 +66,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tracing::instrument;
 
@@ -179,14 +219,46 @@ impl IntoPublishId for NonScopedMetadata {
     }
 }
 
+/// Extensions identifying the origin of an event, which are always set by the endpoint.
+const IDENTITY_EXTENSIONS: &[&str] = &[
+    EXT_APPLICATION,
+    EXT_APPLICATION_UID,
+    EXT_DEVICE,
+    EXT_DEVICE_UID,
+    EXT_SENDER,
+    EXT_SENDER_UID,
+    EXT_INSTANCE,
+    EXT_PARTITIONKEY,
+];
+
+/// Check if an extension is owned by the endpoint, and so must not be provided by a client.
+pub fn is_reserved_extension(name: &str) -> bool {
+    IDENTITY_EXTENSIONS.contains(&name)
+        || [
+            EXT_SENSITIVITY,
+            EXT_IDEMPOTENCE_KEY,
+            EXT_DEVICE_TIME,
+            EXT_TIME_SKEW,
+            EXT_SCHEMA_VERSION,
+            "dataschema",
+        ]
+        .contains(&name)
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct PublishOptions {
+    /// The ID of the event, generated if missing.
+    pub id: Option<String>,
     pub time: Option<DateTime<Utc>>,
     pub topic: Option<String>,
     pub data_schema: Option<String>,
     pub content_type: Option<String>,
     pub extensions: HashMap<String, String>,
     pub r#type: Option<String>,
+    /// The record key, overriding the configured key strategy.
+    pub key: Option<String>,
+    /// The idempotence key, overriding the event ID.
+    pub idempotence_key: Option<String>,
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -229,12 +301,85 @@ impl UpstreamSender {
     }
 }
 
+/// Additional configuration of the [`DownstreamSender`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DownstreamSenderConfig {
+    /// How to derive the record key.
+    #[serde(default)]
+    pub key: KeyConfig,
+    /// How to check device provided timestamps.
+    #[serde(default)]
+    pub timestamp: TimestampConfig,
+    /// How to handle device provided schema versions.
+    #[serde(default)]
+    pub schema_version: SchemaVersionConfig,
+    /// How to assign events to admission lanes.
+    #[serde(default)]
+    pub priority: PriorityConfig,
+    /// How to share the in-flight events between devices.
+    #[serde(default)]
+    pub fairness: FairnessConfig,
+    /// The ordering guarantee of the producer.
+    ///
+    /// This must be applied to the configuration of the sink, using
+    /// [`OrderingGuarantee::apply`].
+    #[serde(default)]
+    pub ordering: OrderingGuarantee,
+    /// The publish rate limits of devices and tenants.
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// How to tag events of sensitive channels.
+    #[serde(default)]
+    pub sensitivity: SensitivityConfig,
+    /// Tying the liveness to the downstream connection.
+    #[serde(default)]
+    pub liveness: LivenessConfig,
+    /// Limits of the record headers.
+    #[serde(default)]
+    pub headers: HeaderLimitsConfig,
+    /// How to report backpressure to clients.
+    #[serde(default)]
+    pub backpressure: BackpressureConfig,
+    /// Rejecting publish requests during a planned maintenance.
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// Draining in-flight events, when the endpoint shuts down.
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    /// Preventing duplicates when publishing is retried.
+    ///
+    /// The idempotent producer must be applied to the configuration of the sink, using
+    /// [`IdempotenceConfig::apply`].
+    #[serde(default)]
+    pub idempotence: IdempotenceConfig,
+    /// Retrying events, which failed to be sent due to a transient failure.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Copying a sample of the published events to a topic.
+    #[serde(default)]
+    pub sample: Option<PayloadSampleConfig>,
+    /// Redacting fields of JSON payloads, before forwarding them.
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    /// Suggesting devices an interval to publish in.
+    #[serde(default)]
+    pub pacing: PacingConfig,
+}
+
 /// A sender delivering events downstream, from the device to the cloud.
 #[derive(Debug, Clone)]
 pub struct DownstreamSender {
     sink: Arc<dyn Sink>,
     instance: String,
     pool: ExternalClientPool,
+    config: DownstreamSenderConfig,
+    lanes: AdmissionLanes,
+    slots: DeviceSlots,
+    limiter: RateLimiter,
+    health: Option<DownstreamHealth>,
+    maintenance: Maintenance,
+    in_flight: InFlight,
+    sampler: Option<PayloadSampler>,
 }
 
 impl DownstreamSender {
@@ -247,8 +392,174 @@ impl DownstreamSender {
             sink: Arc::new(sink),
             instance,
             pool: ExternalClientPool::new(config),
+            config: Default::default(),
+            lanes: Default::default(),
+            slots: Default::default(),
+            limiter: Default::default(),
+            health: None,
+            maintenance: Default::default(),
+            in_flight: Default::default(),
+            sampler: None,
         })
     }
+
+    /// Apply the additional sender configuration.
+    pub fn with_config(mut self, config: DownstreamSenderConfig) -> Self {
+        self.lanes = AdmissionLanes::new(config.priority.clone());
+        self.slots = DeviceSlots::new(config.fairness.clone());
+        self.limiter = RateLimiter::new(config.rate_limit.clone());
+        self.health = config.liveness.failure_threshold.map(DownstreamHealth::new);
+        self.maintenance = Maintenance::new(config.maintenance.clone());
+        self.sampler = config.sample.clone().map(PayloadSampler::new);
+        if config.retry.is_enabled() && config.ordering == OrderingGuarantee::Strict {
+            log::warn!("Retrying events is disabled, due to the strict ordering guarantee");
+        }
+        self.config = config;
+        self
+    }
+
+    /// Check the device timestamp of the payload, according to the [`TimestampConfig`].
+    ///
+    /// This may modify the publish options, or reject the payload.
+    pub fn check_timestamp(
+        &self,
+        options: &mut PublishOptions,
+        payload: &[u8],
+    ) -> Result<(), EndpointError> {
+        self.config.timestamp.apply(options, payload, Utc::now())
+    }
+
+    /// Check the schema version, provided by the device, according to the [`SchemaVersionConfig`].
+    pub fn check_schema_version(
+        &self,
+        options: &mut PublishOptions,
+        version: Option<&str>,
+    ) -> Result<(), EndpointError> {
+        self.config.schema_version.apply(options, version)
+    }
+
+    /// Check the rate limits of the device and its tenant, according to the [`RateLimitConfig`].
+    ///
+    /// The tenant is the application the device was authenticated for, which may select a tier
+    /// of tenant limits.
+    pub fn check_rate_limit(
+        &self,
+        application: &registry::v1::Application,
+        device: &PublishId,
+    ) -> Result<(), EndpointError> {
+        self.limiter
+            .check_tier(
+                &application.metadata.name,
+                tier_of(application),
+                &device.name,
+            )
+            .map_err(|err| self.backpressure(err))
+    }
+
+    /// Check if the in-flight events of the device, or of the channel's lane, are at their limit.
+    ///
+    /// This only rejects requests if the [`BackpressureConfig`] is enabled, otherwise the request
+    /// waits for a free slot when sending. As the slots are acquired later on, this is only a
+    /// best effort check.
+    pub fn check_capacity(
+        &self,
+        application: &registry::v1::Application,
+        device: &PublishId,
+        channel: &str,
+    ) -> Result<(), EndpointError> {
+        if !self.config.backpressure.enabled {
+            return Ok(());
+        }
+
+        let slot = format!("{}/{}", application.metadata.name, device.name);
+        if self.slots.is_exhausted(&slot) || self.lanes.is_exhausted(channel) {
+            return Err(self
+                .config
+                .backpressure
+                .error(BackpressureSource::Concurrency, None));
+        }
+
+        Ok(())
+    }
+
+    /// Convert an error, caused by backpressure, according to the [`BackpressureConfig`].
+    pub fn backpressure(&self, err: EndpointError) -> EndpointError {
+        self.config.backpressure.apply(err)
+    }
+
+    /// Reject the request, if the endpoint is in maintenance mode.
+    pub fn check_maintenance(&self) -> Result<(), EndpointError> {
+        self.maintenance.check()
+    }
+
+    /// The maintenance mode of the endpoint.
+    ///
+    /// This is shared between all clones of the sender.
+    pub fn maintenance(&self) -> Maintenance {
+        self.maintenance.clone()
+    }
+
+    /// The rate limiter, shared between all clones of the sender.
+    pub fn rate_limiter(&self) -> RateLimiter {
+        self.limiter.clone()
+    }
+
+    /// The graceful shutdown of the endpoint, draining the in-flight events of all clones of the
+    /// sender.
+    pub fn shutdown(&self) -> Shutdown {
+        Shutdown::new(
+            self.config.shutdown.clone(),
+            self.maintenance.clone(),
+            self.in_flight.clone(),
+        )
+    }
+
+    /// The health check, tracking continuous downstream failures.
+    ///
+    /// Returns [`None`] if the liveness doesn't depend on the downstream connection.
+    pub fn health(&self) -> Option<DownstreamHealth> {
+        self.health.clone()
+    }
+
+    /// Validate a record key provided by the client, according to the [`KeyConfig`].
+    pub fn client_key(&self, key: Option<&str>) -> Result<Option<String>, EndpointError> {
+        self.config.key.client_key(key)
+    }
+
+    /// The interval to suggest to a device for publishing, according to the [`PacingConfig`].
+    ///
+    /// Returns [`None`] if not enabled, or there is no reason to slow down.
+    pub fn suggested_interval(
+        &self,
+        application: &registry::v1::Application,
+        device: &PublishId,
+    ) -> Option<Duration> {
+        if !self.config.pacing.enabled {
+            return None;
+        }
+
+        let headroom = self.limiter.headroom(
+            &application.metadata.name,
+            tier_of(application),
+            &device.name,
+        );
+        let failing = self
+            .health
+            .as_ref()
+            .map(DownstreamHealth::is_failing)
+            .unwrap_or_default();
+
+        self.config.pacing.suggest(&headroom, failing)
+    }
+
+    /// Validate an idempotence key provided by the client, according to the
+    /// [`IdempotenceConfig`].
+    pub fn client_idempotence_key(
+        &self,
+        key: Option<&str>,
+    ) -> Result<Option<String>, EndpointError> {
+        self.config.idempotence.client_key(key)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -261,6 +572,53 @@ pub enum PublishError {
     Event(#[source] cloudevents::event::EventBuilderError),
     #[error("Process error")]
     Processor(#[from] process::Error),
+    #[error("Header limit exceeded")]
+    Headers(#[from] HeaderLimitError),
+}
+
+impl DownstreamSender {
+    /// Send an event to the sink, retrying transient failures according to the [`RetryConfig`].
+    async fn send_retrying(
+        &self,
+        app: &registry::v1::Application,
+        event: Event,
+    ) -> Result<PublishOutcome, SinkError> {
+        // with strict ordering, a retried event could overtake the events sent in the meantime
+        let retry = &self.config.retry;
+        if !retry.is_enabled() || self.config.ordering == OrderingGuarantee::Strict {
+            return self.sink.publish(SinkTarget::Events(app), event).await;
+        }
+
+        let started = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .sink
+                .publish(SinkTarget::Events(app), event.clone())
+                .await;
+
+            if let Err(err) = &result {
+                if err.is_retryable() {
+                    if let Some(delay) = retry.delay(attempt, started.elapsed()) {
+                        tracing::debug!(attempt, ?delay, "Retrying to send event: {err}");
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                }
+            }
+
+            if attempt > 1 {
+                tracing::info!(
+                    retries = attempt - 1,
+                    success = result.is_ok(),
+                    "Sent event with retries"
+                );
+            }
+
+            return result;
+        }
+    }
 }
 
 #[async_trait]
@@ -278,12 +636,95 @@ impl Publisher for DownstreamSender {
         Direction::Downstream
     }
 
+    fn payload_key(&self, payload: &[u8]) -> Option<String> {
+        self.config.key.extract(payload)
+    }
+
+    fn sensitivity(&self, channel: &str) -> Option<String> {
+        self.config
+            .sensitivity
+            .sensitivity(channel)
+            .map(ToString::to_string)
+    }
+
+    fn check_headers(&self, event: &Event) -> Result<(), HeaderLimitError> {
+        self.config.headers.check(event)
+    }
+
+    fn idempotence_key(&self, client_key: Option<String>, id: &str) -> Option<String> {
+        self.config.idempotence.key(client_key, id)
+    }
+
+    fn redact(&self, channel: &str, data: Data) -> Redacted {
+        self.config.redaction.redact(channel, data)
+    }
+
+    async fn send_unredacted(
+        &self,
+        app: &registry::v1::Application,
+        event: Event,
+    ) -> Result<PublishOutcome, SinkError> {
+        match &self.config.redaction.restricted_topic {
+            Some(topic) => {
+                self.sink
+                    .publish(SinkTarget::Topic(app, topic), event)
+                    .await
+            }
+            None => Ok(PublishOutcome::Accepted),
+        }
+    }
+
     async fn send(
         &self,
         app: &registry::v1::Application,
         event: Event,
     ) -> Result<PublishOutcome, SinkError> {
-        self.sink.publish(SinkTarget::Events(app), event).await
+        let _in_flight = self.in_flight.enter();
+        // hold the permits of the device and the lane until the event is sent
+        let device = format!(
+            "{}/{}",
+            app.metadata.name,
+            event
+                .extension(EXT_DEVICE)
+                .map(|device| device.to_string())
+                .unwrap_or_default()
+        );
+        let _device_permit = self.slots.acquire(&device).await;
+        let _permit = self
+            .lanes
+            .acquire(event.subject().unwrap_or_default())
+            .await;
+        // from here on, the request must not be cancelled anymore
+        deadline::commit();
+        let sample = self
+            .sampler
+            .as_ref()
+            .and_then(|sampler| sampler.sample(app, &event));
+        let result = self.send_retrying(app, event).await;
+
+        // only copy events, which got accepted
+        if let (Some(sample), Ok(PublishOutcome::Accepted)) = (sample, &result) {
+            sample.spawn(self.sink.clone());
+        }
+
+        if let Some(health) = &self.health {
+            match &result {
+                Ok(PublishOutcome::Accepted | PublishOutcome::Rejected) => health.success(),
+                // a topic not being ready is a problem of the application
+                Err(SinkError::Transport(err))
+                    if !matches!(
+                        err.downcast_ref::<KafkaSinkError>(),
+                        Some(KafkaSinkError::NotReady)
+                    ) =>
+                {
+                    health.failure()
+                }
+                // neither proves nor disproves a working connection
+                Ok(PublishOutcome::QueueFull) | Err(_) => {}
+            }
+        }
+
+        result
     }
 }
 
@@ -319,6 +760,46 @@ pub trait Publisher {
 
     fn direction() -> Direction;
 
+    /// Derive the record key from the payload.
+    ///
+    /// Returning [`None`] will use the default key, derived from the application and sender.
+    fn payload_key(&self, _payload: &[u8]) -> Option<String> {
+        None
+    }
+
+    /// Evaluate the sensitivity of a channel.
+    ///
+    /// Returning [`None`] will not tag the event.
+    fn sensitivity(&self, _channel: &str) -> Option<String> {
+        None
+    }
+
+    /// Check the headers of the final event, before sending it.
+    fn check_headers(&self, _event: &Event) -> Result<(), HeaderLimitError> {
+        Ok(())
+    }
+
+    /// Evaluate the idempotence key of an event.
+    ///
+    /// Returning [`None`] will not add the key to the event.
+    fn idempotence_key(&self, _client_key: Option<String>, _id: &str) -> Option<String> {
+        None
+    }
+
+    /// Redact the payload of a channel, before forwarding it.
+    fn redact(&self, _channel: &str, data: Data) -> Redacted {
+        Redacted::Unchanged(data)
+    }
+
+    /// Send the original version of a redacted event.
+    async fn send_unredacted(
+        &self,
+        _app: &registry::v1::Application,
+        _event: Event,
+    ) -> Result<PublishOutcome, SinkError> {
+        Ok(PublishOutcome::Accepted)
+    }
+
     async fn send(
         &self,
         app: &registry::v1::Application,
@@ -352,10 +833,20 @@ pub trait Publisher {
         let device_enc = utf8_percent_encode(&publish.device.name, NON_ALPHANUMERIC);
         let sender_enc = utf8_percent_encode(&publish.sender.name, NON_ALPHANUMERIC);
 
-        let key = format!("{}/{}", app_enc, sender_enc);
+        let key = publish
+            .options
+            .key
+            .or_else(|| self.payload_key(body.as_ref()))
+            .unwrap_or_else(|| format!("{}/{}", app_enc, sender_enc));
+
+        let id = publish
+            .options
+            .id
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let idempotence_key = self.idempotence_key(publish.options.idempotence_key, &id);
 
         let mut event = EventBuilderV10::new()
-            .id(uuid::Uuid::new_v4().to_string())
+            .id(id)
             .ty(publish
                 .options
                 .r#type
@@ -389,39 +880,66 @@ pub trait Publisher {
         }
 
         for (k, v) in publish.options.extensions {
+            // the identity of the event can't be overridden
+            if IDENTITY_EXTENSIONS.contains(&k.as_str()) {
+                log::debug!("Ignoring extension '{k}', overriding the identity of the event");
+                continue;
+            }
             event = event.extension(&k, v);
         }
 
+        // set last, so that it can't be overridden by the client
+        if let Some(sensitivity) = self.sensitivity(&publish.channel) {
+            event = event.extension(EXT_SENSITIVITY, sensitivity);
+        }
+        if let Some(idempotence_key) = idempotence_key {
+            event = event.extension(EXT_IDEMPOTENCE_KEY, idempotence_key);
+        }
+
         log::debug!("Content-Type: {:?}", publish.options.content_type);
         log::debug!("Payload size: {} bytes", body.as_ref().len());
 
-        let event = match publish.options.content_type {
+        let (content_type, data) = match publish.options.content_type {
             // if the content type "is JSON", we do an extra check if the content type is indeed JSON
             Some(t) if is_json(&t) => {
                 // try decoding as JSON
                 match serde_json::from_slice::<Value>(body.as_ref()) {
                     // ok -> pass along
-                    Ok(v) => event.data(mime::APPLICATION_JSON.to_string(), Data::Json(v)),
+                    Ok(v) => (mime::APPLICATION_JSON.to_string(), Data::Json(v)),
                     // not ok -> reject
                     Err(_) => return Ok(PublishOutcome::Rejected),
                 }
             }
             // pass through content type
-            Some(t) => event.data(t, Vec::from(body.as_ref())),
+            Some(t) => (t, Data::Binary(Vec::from(body.as_ref()))),
             // no content type, try JSON, then fall back to "bytes"
             None => {
                 // try decoding as JSON
                 match serde_json::from_slice::<Value>(body.as_ref()) {
-                    Ok(v) => event.data(mime::APPLICATION_JSON.to_string(), Data::Json(v)),
-                    Err(_) => event.data(
+                    Ok(v) => (mime::APPLICATION_JSON.to_string(), Data::Json(v)),
+                    Err(_) => (
                         mime::APPLICATION_OCTET_STREAM.to_string(),
-                        Vec::from(body.as_ref()),
+                        Data::Binary(Vec::from(body.as_ref())),
                     ),
                 }
             }
         };
 
-        let event = event.build().map_err(PublishError::Event)?;
+        let (data, original) = match self.redact(&publish.channel, data) {
+            Redacted::Unchanged(data) => (data, None),
+            Redacted::Redacted { data, original } => (data, original),
+            Redacted::Rejected => return Ok(PublishOutcome::Rejected),
+        };
+
+        let event = event
+            .data(content_type.clone(), data)
+            .build()
+            .map_err(PublishError::Event)?;
+        let unredacted = original.map(|original| {
+            let mut unredacted = event.clone();
+            unredacted.set_data(content_type, original);
+            unredacted
+        });
 
         // handle publish steps
 
@@ -435,6 +953,17 @@ pub trait Publisher {
             }
             Outcome::Accepted(event) => {
                 // event was accepted, send it
+                self.check_headers(&event)?;
+                // send the original first, so that a failure doesn't lose it
+                if let Some(unredacted) = unredacted {
+                    match self
+                        .send_unredacted(publish.application, unredacted)
+                        .await?
+                    {
+                        PublishOutcome::Accepted => {}
+                        outcome => return Ok(outcome),
+                    }
+                }
                 Ok(self.send(publish.application, event).await?)
             }
             Outcome::Dropped => {
@@ -487,3 +1016,556 @@ pub(crate) fn is_json(content_type: &str) -> bool {
         || content_type.starts_with("text/json")
         || content_type.ends_with("+json")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sampling::SampleRate;
+    use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+    use std::sync::Mutex;
+    use tokio::sync::mpsc;
+
+    #[derive(Clone, Debug, Default)]
+    struct MockSink {
+        events: Arc<Mutex<Vec<Event>>>,
+        samples: Arc<Mutex<Vec<(String, Event)>>>,
+        /// Notified for every event sent to a topic.
+        sampled: Option<mpsc::UnboundedSender<()>>,
+        delay: Option<Duration>,
+        /// Errors to fail with, before accepting events, taken from the end.
+        failures: Arc<Mutex<Vec<SinkError>>>,
+    }
+
+    #[async_trait]
+    impl Sink for MockSink {
+        #[allow(clippy::needless_lifetimes)]
+        async fn publish<'a>(
+            &self,
+            target: SinkTarget<'a>,
+            event: Event,
+        ) -> Result<PublishOutcome, SinkError> {
+            if let Some(delay) = self.delay {
+                tokio::time::sleep(delay).await;
+            }
+            if let Some(err) = self.failures.lock().unwrap().pop() {
+                return Err(err);
+            }
+            match target {
+                SinkTarget::Topic(_, topic) => {
+                    self.samples.lock().unwrap().push((topic.into(), event));
+                    if let Some(sampled) = &self.sampled {
+                        let _ = sampled.send(());
+                    }
+                }
+                _ => self.events.lock().unwrap().push(event),
+            }
+            Ok(PublishOutcome::Accepted)
+        }
+    }
+
+    /// Publish a single event, returning the event sent to the sink.
+    async fn publish_event(
+        config: DownstreamSenderConfig,
+        channel: &str,
+        key: Option<&str>,
+        extensions: HashMap<String, String>,
+    ) -> Event {
+        let sink = MockSink::default();
+        let sender = DownstreamSender::new(sink.clone(), "test".into(), Default::default())
+            .unwrap()
+            .with_config(config);
+
+        let application = registry::v1::Application::default();
+        let publish = Publish {
+            application: &application,
+            device: "device1".to_string().into_id(),
+            sender: "device1".to_string().into_id(),
+            channel: channel.into(),
+            options: PublishOptions {
+                key: sender.client_key(key).unwrap(),
+                extensions,
+                ..Default::default()
+            },
+        };
+
+        sender
+            .publish(publish, br#"{"site": "site-1"}"#)
+            .await
+            .unwrap();
+
+        let mut events = sink.events.lock().unwrap();
+        events.remove(0)
+    }
+
+    async fn publish_key(key: Option<&str>) -> Option<String> {
+        let config = DownstreamSenderConfig {
+            key: KeyConfig {
+                payload_field: Some("site".into()),
+                client_key: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        publish_event(config, "telemetry", key, Default::default())
+            .await
+            .extension(EXT_PARTITIONKEY)
+            .map(|key| key.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_client_key_override() {
+        assert_eq!(publish_key(Some("custom")).await.as_deref(), Some("custom"));
+    }
+
+    #[tokio::test]
+    async fn test_client_key_absent() {
+        assert_eq!(publish_key(None).await.as_deref(), Some("site-1"));
+    }
+
+    fn sensitivity_config() -> DownstreamSenderConfig {
+        DownstreamSenderConfig {
+            sensitivity: SensitivityConfig {
+                channels: vec![SensitivityRule {
+                    channel: "patient/*".into(),
+                    sensitivity: "high".into(),
+                }],
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sensitive_channel() {
+        let event = publish_event(
+            sensitivity_config(),
+            "patient/vitals",
+            None,
+            Default::default(),
+        )
+        .await;
+        assert_eq!(
+            event.extension(EXT_SENSITIVITY).map(|s| s.to_string()),
+            Some("high".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_sensitive_channel() {
+        let event =
+            publish_event(sensitivity_config(), "telemetry", None, Default::default()).await;
+        assert!(event.extension(EXT_SENSITIVITY).is_none());
+    }
+
+    /// Publish the same message, as a retry would, returning the idempotence key.
+    async fn publish_idempotent(id: Option<&str>, client_key: Option<&str>) -> Option<String> {
+        let sink = MockSink::default();
+        let sender = DownstreamSender::new(sink.clone(), "test".into(), Default::default())
+            .unwrap()
+            .with_config(DownstreamSenderConfig {
+                idempotence: IdempotenceConfig {
+                    enabled: true,
+                    client_key: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+
+        let application = registry::v1::Application::default();
+        let publish = Publish {
+            application: &application,
+            device: "device1".to_string().into_id(),
+            sender: "device1".to_string().into_id(),
+            channel: "telemetry".into(),
+            options: PublishOptions {
+                id: id.map(Into::into),
+                idempotence_key: sender.client_idempotence_key(client_key).unwrap(),
+                ..Default::default()
+            },
+        };
+
+        sender.publish(publish, b"{}").await.unwrap();
+
+        let event = sink.events.lock().unwrap().remove(0);
+        event
+            .extension(EXT_IDEMPOTENCE_KEY)
+            .map(|key| key.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_idempotence_key_stable() {
+        // the client retries with the same key, but a new message ID
+        let first = publish_idempotent(None, Some("attempt")).await;
+        let retry = publish_idempotent(None, Some("attempt")).await;
+        assert_eq!(first.as_deref(), Some("attempt"));
+        assert_eq!(first, retry);
+
+        // without a client key, the message ID is used
+        assert_eq!(
+            publish_idempotent(Some("msg1"), None).await.as_deref(),
+            Some("msg1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_idempotence_disabled() {
+        let event = publish_event(Default::default(), "telemetry", None, Default::default()).await;
+        assert!(event.extension(EXT_IDEMPOTENCE_KEY).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sensitivity_not_overridden() {
+        let extensions = HashMap::from([(EXT_SENSITIVITY.to_string(), "none".to_string())]);
+        let event = publish_event(sensitivity_config(), "patient/vitals", None, extensions).await;
+        assert_eq!(
+            event.extension(EXT_SENSITIVITY).map(|s| s.to_string()),
+            Some("high".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_identity_not_overridden() {
+        let extensions = HashMap::from([
+            (EXT_APPLICATION.to_string(), "other-app".to_string()),
+            (EXT_DEVICE.to_string(), "other-device".to_string()),
+            (EXT_SENDER.to_string(), "other-device".to_string()),
+            ("site".to_string(), "site1".to_string()),
+        ]);
+        let event = publish_event(Default::default(), "telemetry", None, extensions).await;
+        assert_eq!(
+            event.extension(EXT_DEVICE).map(|s| s.to_string()),
+            Some("device1".into())
+        );
+        assert_eq!(
+            event.extension(EXT_SENDER).map(|s| s.to_string()),
+            Some("device1".into())
+        );
+        assert_ne!(
+            event.extension(EXT_APPLICATION).map(|s| s.to_string()),
+            Some("other-app".into())
+        );
+        assert_eq!(
+            event.extension("site").map(|s| s.to_string()),
+            Some("site1".into())
+        );
+    }
+
+    /// Publish a number of events, returning the sink after the expected copies were sent.
+    async fn publish_sampled(rate: SampleRate, count: usize, expected: usize) -> MockSink {
+        tokio::time::pause();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let sink = MockSink {
+            sampled: Some(tx),
+            ..Default::default()
+        };
+        let sender = DownstreamSender::new(sink.clone(), "test".into(), Default::default())
+            .unwrap()
+            .with_config(DownstreamSenderConfig {
+                sample: Some(PayloadSampleConfig {
+                    topic: "samples".into(),
+                    rate,
+                    max_payload_size: 1024,
+                    max_in_flight: count,
+                    include_sensitive: false,
+                    opt_in: false,
+                }),
+                ..Default::default()
+            });
+
+        let application = registry::v1::Application::default();
+        for _ in 0..count {
+            let publish = Publish {
+                application: &application,
+                device: "device1".to_string().into_id(),
+                sender: "device1".to_string().into_id(),
+                channel: "telemetry".into(),
+                options: Default::default(),
+            };
+            sender.publish(publish, b"{}").await.unwrap();
+        }
+
+        // wait for the copies, sent in the background
+        for _ in 0..expected {
+            rx.recv().await.unwrap();
+        }
+        // and no more, the paused clock only advances once all tasks are idle
+        assert!(tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .is_err());
+
+        sink
+    }
+
+    #[tokio::test]
+    async fn test_sampled_copied() {
+        let sink = publish_sampled(SampleRate::OneIn(2), 4, 2).await;
+
+        assert_eq!(sink.events.lock().unwrap().len(), 4);
+        let samples = sink.samples.lock().unwrap();
+        assert_eq!(samples.len(), 2);
+        assert!(samples.iter().all(|(topic, _)| topic == "samples"));
+    }
+
+    #[tokio::test]
+    async fn test_not_sampled() {
+        let sink = publish_sampled(SampleRate::Probability(0.0), 4, 0).await;
+
+        assert_eq!(sink.events.lock().unwrap().len(), 4);
+        assert!(sink.samples.lock().unwrap().is_empty());
+    }
+
+    fn redaction_config(restricted_topic: Option<&str>) -> DownstreamSenderConfig {
+        DownstreamSenderConfig {
+            redaction: RedactionConfig {
+                rules: vec![RedactionRule {
+                    channel: "location".into(),
+                    remove: vec!["/site".into()],
+                    mask: vec![],
+                }],
+                restricted_topic: restricted_topic.map(Into::into),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redacted() {
+        let event =
+            publish_event(redaction_config(None), "location", None, Default::default()).await;
+        assert_eq!(event.data(), Some(&Data::Json(serde_json::json!({}))));
+
+        let event = publish_event(
+            redaction_config(None),
+            "telemetry",
+            None,
+            Default::default(),
+        )
+        .await;
+        assert_eq!(
+            event.data(),
+            Some(&Data::Json(serde_json::json!({"site": "site-1"})))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unredacted_restricted() {
+        let sink = MockSink::default();
+        let sender = DownstreamSender::new(sink.clone(), "test".into(), Default::default())
+            .unwrap()
+            .with_config(redaction_config(Some("restricted")));
+
+        let application = registry::v1::Application::default();
+        let publish = Publish {
+            application: &application,
+            device: "device1".to_string().into_id(),
+            sender: "device1".to_string().into_id(),
+            channel: "location".into(),
+            options: Default::default(),
+        };
+        sender
+            .publish(publish, br#"{"site": "site-1"}"#)
+            .await
+            .unwrap();
+
+        let event = sink.events.lock().unwrap().remove(0);
+        let (topic, unredacted) = sink.samples.lock().unwrap().remove(0);
+        assert_eq!(event.data(), Some(&Data::Json(serde_json::json!({}))));
+        assert_eq!(topic, "restricted");
+        assert_eq!(
+            unredacted.data(),
+            Some(&Data::Json(serde_json::json!({"site": "site-1"})))
+        );
+        assert_eq!(unredacted.id(), event.id());
+    }
+
+    #[test]
+    fn test_suggested_interval() {
+        let config = DownstreamSenderConfig {
+            rate_limit: RateLimitConfig {
+                device: Some(RateLimit { rate: 1, burst: 4 }),
+                ..Default::default()
+            },
+            pacing: PacingConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sender = DownstreamSender::new(MockSink::default(), "test".into(), Default::default())
+            .unwrap()
+            .with_config(config);
+
+        let application = registry::v1::Application::default();
+        let device = "device1".to_string().into_id();
+
+        assert_eq!(sender.suggested_interval(&application, &device), None);
+
+        // enough headroom left
+        sender.check_rate_limit(&application, &device).unwrap();
+        assert_eq!(sender.suggested_interval(&application, &device), None);
+
+        // running low, slow down to the rate of the limit
+        sender.check_rate_limit(&application, &device).unwrap();
+        sender.check_rate_limit(&application, &device).unwrap();
+        assert_eq!(
+            sender.suggested_interval(&application, &device),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains() {
+        tokio::time::pause();
+
+        let sink = MockSink {
+            delay: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let sender = DownstreamSender::new(sink.clone(), "test".into(), Default::default())
+            .unwrap()
+            .with_config(Default::default());
+        let shutdown = sender.shutdown();
+
+        let application = registry::v1::Application::default();
+        let publish = Publish {
+            application: &application,
+            device: "device1".to_string().into_id(),
+            sender: "device1".to_string().into_id(),
+            channel: "state".into(),
+            options: Default::default(),
+        };
+
+        let (outcome, drained) = tokio::join!(sender.publish(publish, b"slow"), async {
+            // shut down while the event is still being sent
+            tokio::time::advance(Duration::from_millis(50)).await;
+            assert!(sink.events.lock().unwrap().is_empty());
+            shutdown.drain().await
+        });
+
+        assert!(matches!(outcome, Ok(PublishOutcome::Accepted)));
+        assert!(drained);
+        assert_eq!(sink.events.lock().unwrap().len(), 1);
+        assert!(matches!(
+            sender.check_maintenance(),
+            Err(EndpointError::Maintenance { .. })
+        ));
+    }
+
+    fn kafka_error(code: RDKafkaErrorCode) -> SinkError {
+        SinkError::Transport(Box::new(KafkaError::MessageProduction(code)))
+    }
+
+    async fn publish_failing(
+        failures: Vec<SinkError>,
+    ) -> (Result<PublishOutcome, PublishError>, MockSink) {
+        publish_failing_with(
+            failures,
+            DownstreamSenderConfig {
+                retry: RetryConfig {
+                    max_attempts: 3,
+                    base_delay: Duration::from_millis(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    async fn publish_failing_with(
+        failures: Vec<SinkError>,
+        config: DownstreamSenderConfig,
+    ) -> (Result<PublishOutcome, PublishError>, MockSink) {
+        let sink = MockSink {
+            failures: Arc::new(Mutex::new(failures)),
+            ..Default::default()
+        };
+        let sender = DownstreamSender::new(sink.clone(), "test".into(), Default::default())
+            .unwrap()
+            .with_config(config);
+
+        let application = registry::v1::Application::default();
+        let publish = Publish {
+            application: &application,
+            device: "device1".to_string().into_id(),
+            sender: "device1".to_string().into_id(),
+            channel: "state".into(),
+            options: Default::default(),
+        };
+
+        (sender.publish(publish, b"{}").await, sink)
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient() {
+        let (outcome, sink) = publish_failing(vec![
+            kafka_error(RDKafkaErrorCode::NotLeaderForPartition),
+            kafka_error(RDKafkaErrorCode::LeaderNotAvailable),
+        ])
+        .await;
+
+        assert!(matches!(outcome, Ok(PublishOutcome::Accepted)));
+        assert_eq!(sink.events.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_bounded() {
+        let (outcome, sink) = publish_failing(vec![
+            kafka_error(RDKafkaErrorCode::LeaderNotAvailable),
+            kafka_error(RDKafkaErrorCode::LeaderNotAvailable),
+            kafka_error(RDKafkaErrorCode::LeaderNotAvailable),
+        ])
+        .await;
+
+        assert!(matches!(outcome, Err(PublishError::Sink(_))));
+        assert!(sink.events.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_permanent() {
+        let (outcome, sink) = publish_failing(vec![
+            kafka_error(RDKafkaErrorCode::LeaderNotAvailable),
+            kafka_error(RDKafkaErrorCode::MessageSizeTooLarge),
+        ])
+        .await;
+
+        // fails fast, the transient failure is never reached
+        assert!(matches!(outcome, Err(PublishError::Sink(_))));
+        assert_eq!(sink.failures.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_by_default() {
+        let (outcome, sink) = publish_failing_with(
+            vec![kafka_error(RDKafkaErrorCode::LeaderNotAvailable)],
+            Default::default(),
+        )
+        .await;
+
+        assert!(matches!(outcome, Err(PublishError::Sink(_))));
+        assert!(sink.failures.lock().unwrap().is_empty());
+        assert!(sink.events.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_strict_ordering() {
+        let (outcome, sink) = publish_failing_with(
+            vec![kafka_error(RDKafkaErrorCode::LeaderNotAvailable)],
+            DownstreamSenderConfig {
+                retry: RetryConfig {
+                    max_attempts: 3,
+                    base_delay: Duration::from_millis(1),
+                    ..Default::default()
+                },
+                ordering: OrderingGuarantee::Strict,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        assert!(matches!(outcome, Err(PublishError::Sink(_))));
+        assert!(sink.events.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use serde::{Deserialize, Serialize};
+
+/// The ordering guarantee of the downstream producer.
+///
+/// Kafka only keeps the order of records inside a partition. Records of one device only end up in
+/// the same partition if they share the same record key, which is the default. When deriving the
+/// key from the payload (see [`super::KeyConfig`]), the order is kept per payload key instead.
+///
+/// Even then, the producer may re-order records when retrying a failed request, while other
+/// requests are in flight. The strict mode prevents this, at the cost of throughput.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OrderingGuarantee {
+    /// Use the producer settings as configured, optimized for throughput.
+    #[default]
+    Throughput,
+    /// Keep the order of records per partition, using an idempotent producer with a single
+    /// in-flight request per connection.
+    Strict,
+}
+
+const STRICT_PROPERTIES: &[(&str, &str)] = &[
+    ("enable.idempotence", "true"),
+    ("max.in.flight.requests.per.connection", "1"),
+    ("acks", "all"),
+];
+
+impl OrderingGuarantee {
+    /// Apply the ordering guarantee to the configuration of the producer.
+    ///
+    /// In strict mode, this overrides conflicting properties of the configuration.
+    pub fn apply(&self, config: KafkaClientConfig) -> KafkaClientConfig {
+        match self {
+            Self::Throughput => config,
+            Self::Strict => override_properties(config, STRICT_PROPERTIES, "strict ordering"),
+        }
+    }
+}
+
+/// Set properties of the producer, overriding conflicting ones.
+pub(super) fn override_properties(
+    mut config: KafkaClientConfig,
+    properties: &[(&str, &str)],
+    reason: &str,
+) -> KafkaClientConfig {
+    for (key, value) in properties {
+        // properties may use underscores instead of dots
+        let existing = config
+            .properties
+            .keys()
+            .filter(|k| k.replace('_', ".") == *key)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for k in existing {
+            if let Some(v) = config.properties.remove(&k) {
+                if v != *value {
+                    log::warn!(
+                        "Overriding producer property '{}' ({} -> {}) for {}",
+                        k,
+                        v,
+                        value,
+                        reason
+                    );
+                }
+            }
+        }
+
+        config.properties.insert(key.to_string(), value.to_string());
+    }
+
+    config
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(properties: &[(&str, &str)]) -> KafkaClientConfig {
+        KafkaClientConfig {
+            bootstrap_servers: "localhost:9092".into(),
+            properties: properties
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_throughput() {
+        let original = config(&[("linger_ms", "5")]);
+        assert_eq!(
+            OrderingGuarantee::Throughput.apply(original.clone()),
+            original
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let config = OrderingGuarantee::Strict.apply(config(&[
+            ("linger_ms", "5"),
+            ("max_in_flight_requests_per_connection", "5"),
+            ("acks", "1"),
+        ]));
+
+        assert_eq!(
+            config,
+            self::config(&[
+                ("linger_ms", "5"),
+                ("enable.idempotence", "true"),
+                ("max.in.flight.requests.per.connection", "1"),
+                ("acks", "all"),
+            ])
+        );
+    }
+}
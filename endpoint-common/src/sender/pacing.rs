@@ -0,0 +1,164 @@
+use super::Headroom;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Suggesting devices an interval to publish in, before they get rate limited.
+///
+/// The interval is derived from the headroom of the rate limits of the device and its tenant,
+/// and from the state of the downstream connection. If there is no reason to slow down, no
+/// interval is suggested.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PacingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Suggest an interval once less than this fraction of the burst of a limit is left.
+    #[serde(default = "default_headroom")]
+    pub headroom: f64,
+    /// The interval to suggest while sending downstream fails.
+    ///
+    /// This requires the liveness to be tied to the downstream connection, which tracks the
+    /// failures.
+    #[serde(default = "default_failing_interval", with = "humantime_serde")]
+    pub failing_interval: Duration,
+    /// The maximum interval to suggest.
+    #[serde(default = "default_max_interval", with = "humantime_serde")]
+    pub max_interval: Duration,
+}
+
+const fn default_headroom() -> f64 {
+    0.5
+}
+
+const fn default_failing_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+const fn default_max_interval() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            headroom: default_headroom(),
+            failing_interval: default_failing_interval(),
+            max_interval: default_max_interval(),
+        }
+    }
+}
+
+impl PacingConfig {
+    /// Evaluate the interval to suggest, the longest one of all reasons to slow down.
+    pub fn suggest(&self, headroom: &[Headroom], failing: bool) -> Option<Duration> {
+        if !self.enabled {
+            return None;
+        }
+
+        let limited = headroom
+            .iter()
+            .filter_map(|headroom| self.limited(headroom))
+            .max();
+        let failing = failing.then_some(self.failing_interval);
+
+        limited
+            .max(failing)
+            .map(|interval| interval.min(self.max_interval))
+    }
+
+    /// The interval of a limit running low, which is the one the limit can sustain.
+    ///
+    /// As tokens are only taken if available, this is never shorter than the time until the next
+    /// token is available.
+    fn limited(&self, headroom: &Headroom) -> Option<Duration> {
+        let Headroom { limit, tokens } = *headroom;
+
+        if limit.burst > 0 && tokens / limit.burst as f64 >= self.headroom {
+            return None;
+        }
+        if limit.rate == 0 {
+            // never refilled
+            return Some(self.max_interval);
+        }
+
+        Some(Duration::from_secs_f64(1.0 / limit.rate as f64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sender::RateLimit;
+
+    fn enabled() -> PacingConfig {
+        PacingConfig {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn headroom(rate: u32, burst: u32, tokens: f64) -> Headroom {
+        Headroom {
+            limit: RateLimit { rate, burst },
+            tokens,
+        }
+    }
+
+    #[test]
+    fn test_disabled() {
+        let config = PacingConfig::default();
+        assert_eq!(config.suggest(&[headroom(1, 10, 0.0)], true), None);
+    }
+
+    #[test]
+    fn test_enough_headroom() {
+        let config = enabled();
+
+        assert_eq!(config.suggest(&[], false), None);
+        assert_eq!(config.suggest(&[headroom(1, 10, 5.0)], false), None);
+    }
+
+    #[test]
+    fn test_low_headroom() {
+        let config = enabled();
+
+        // the rate the limit can sustain
+        assert_eq!(
+            config.suggest(&[headroom(2, 10, 4.0)], false),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            config.suggest(&[headroom(2, 10, 0.0)], false),
+            Some(Duration::from_millis(500))
+        );
+        // the most restrictive limit wins
+        assert_eq!(
+            config.suggest(&[headroom(10, 10, 1.0), headroom(1, 10, 1.0)], false),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn test_failing() {
+        let config = enabled();
+
+        assert_eq!(
+            config.suggest(&[headroom(1, 10, 0.0)], true),
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn test_max_interval() {
+        let config = PacingConfig {
+            max_interval: Duration::from_secs(30),
+            ..enabled()
+        };
+
+        assert_eq!(config.suggest(&[], true), Some(Duration::from_secs(30)));
+        assert_eq!(
+            config.suggest(&[headroom(0, 10, 0.0)], false),
+            Some(Duration::from_secs(30))
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+lazy_static! {
+    pub static ref DOWNSTREAM_LANE_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "drogue_downstream_lane_events",
+        "Downstream events by lane",
+        &["lane"],
+    )
+    .unwrap();
+}
+
+/// The priority of a published event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Priority {
+    /// Bulk traffic, using the shared lane.
+    Normal,
+    /// Traffic using the reserved lane, not competing with bulk traffic for admission.
+    High,
+}
+
+impl Priority {
+    pub fn lane(&self) -> &'static str {
+        match self {
+            Self::Normal => "shared",
+            Self::High => "reserved",
+        }
+    }
+}
+
+/// Configuration of the downstream lanes.
+///
+/// Events of high priority channels are admitted through a reserved lane, with its own limit of
+/// in-flight events. This way, they don't wait for bulk traffic to free up a slot in the case of
+/// congestion.
+///
+/// The lanes only control admission. Both lanes share the same producer, so once admitted, high
+/// priority events are queued and sent along with bulk traffic, and still get delayed by a slow
+/// Kafka cluster.
+///
+/// If no high priority channels are configured, there are no lanes and no limits.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PriorityConfig {
+    /// The channels of high priority.
+    ///
+    /// A channel matches if it is equal to an entry, or if the entry ends with a `*` and the channel
+    /// starts with the part before the `*`.
+    #[serde(default)]
+    pub high_priority_channels: Vec<String>,
+    /// The maximum number of in-flight events of the shared lane.
+    #[serde(default = "default_shared_capacity")]
+    pub shared_capacity: usize,
+    /// The maximum number of in-flight events of the reserved lane.
+    #[serde(default = "default_reserved_capacity")]
+    pub reserved_capacity: usize,
+}
+
+const fn default_shared_capacity() -> usize {
+    1024
+}
+
+const fn default_reserved_capacity() -> usize {
+    128
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        Self {
+            high_priority_channels: vec![],
+            shared_capacity: default_shared_capacity(),
+            reserved_capacity: default_reserved_capacity(),
+        }
+    }
+}
+
+impl PriorityConfig {
+    /// Evaluate the priority of a channel.
+    pub fn priority(&self, channel: &str) -> Priority {
+        let high = self
+            .high_priority_channels
+            .iter()
+            .any(|pattern| channel_matches(pattern, channel));
+
+        match high {
+            true => Priority::High,
+            false => Priority::Normal,
+        }
+    }
+}
+
+/// Check if a channel matches a pattern.
+///
+/// A channel matches if it is equal to the pattern, or if the pattern ends with a `*` and the
+/// channel starts with the part before the `*`.
+pub(crate) fn channel_matches(pattern: &str, channel: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => channel.starts_with(prefix),
+        None => channel == pattern,
+    }
+}
+
+#[derive(Clone, Debug)]
+struct LaneSemaphores {
+    shared: Arc<Semaphore>,
+    reserved: Arc<Semaphore>,
+}
+
+/// The downstream admission lanes, limiting the in-flight events per priority.
+///
+/// This is admission control only, the events of all lanes are sent using the same producer.
+#[derive(Clone, Debug, Default)]
+pub struct AdmissionLanes {
+    config: PriorityConfig,
+    semaphores: Option<LaneSemaphores>,
+}
+
+impl AdmissionLanes {
+    pub fn new(config: PriorityConfig) -> Self {
+        let semaphores = match config.high_priority_channels.is_empty() {
+            true => None,
+            false => Some(LaneSemaphores {
+                shared: Arc::new(Semaphore::new(config.shared_capacity)),
+                reserved: Arc::new(Semaphore::new(config.reserved_capacity)),
+            }),
+        };
+
+        Self { config, semaphores }
+    }
+
+    /// Acquire a slot in the lane of the channel.
+    ///
+    /// The returned permit must be held until the event was sent. If there are no lanes,
+    /// [`None`] is returned right away.
+    pub async fn acquire(&self, channel: &str) -> Option<OwnedSemaphorePermit> {
+        let semaphores = self.semaphores.as_ref()?;
+
+        let priority = self.config.priority(channel);
+        DOWNSTREAM_LANE_COUNTER
+            .with_label_values(&[priority.lane()])
+            .inc();
+
+        let semaphore = match priority {
+            Priority::Normal => &semaphores.shared,
+            Priority::High => &semaphores.reserved,
+        };
+
+        // we never close the semaphore, so this can't fail
+        semaphore.clone().acquire_owned().await.ok()
+    }
+
+    /// Check if the lane of the channel has no free slots, so that acquiring one would wait.
+    pub fn is_exhausted(&self, channel: &str) -> bool {
+        let semaphores = match &self.semaphores {
+            Some(semaphores) => semaphores,
+            None => return false,
+        };
+
+        let semaphore = match self.config.priority(channel) {
+            Priority::Normal => &semaphores.shared,
+            Priority::High => &semaphores.reserved,
+        };
+
+        semaphore.available_permits() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(channels: &[&str]) -> PriorityConfig {
+        PriorityConfig {
+            high_priority_channels: channels.iter().map(ToString::to_string).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_priority() {
+        let config = PriorityConfig::default();
+        assert_eq!(config.priority("alarm"), Priority::Normal);
+        assert_eq!(config.priority("telemetry"), Priority::Normal);
+    }
+
+    #[test]
+    fn test_exact_match() {
+        let config = config(&["alarm"]);
+        assert_eq!(config.priority("alarm"), Priority::High);
+        assert_eq!(config.priority("alarms"), Priority::Normal);
+        assert_eq!(config.priority("telemetry"), Priority::Normal);
+    }
+
+    #[test]
+    fn test_prefix_match() {
+        let config = config(&["alarm/*", "emergency"]);
+        assert_eq!(config.priority("alarm/fire"), Priority::High);
+        assert_eq!(config.priority("alarm/"), Priority::High);
+        assert_eq!(config.priority("alarm"), Priority::Normal);
+        assert_eq!(config.priority("emergency"), Priority::High);
+        assert_eq!(config.priority("telemetry"), Priority::Normal);
+    }
+
+    #[tokio::test]
+    async fn test_lanes() {
+        let lanes = AdmissionLanes::new(PriorityConfig {
+            high_priority_channels: vec!["alarm".into()],
+            shared_capacity: 1,
+            reserved_capacity: 1,
+        });
+
+        // occupy the shared lane
+
+        let _bulk = lanes.acquire("telemetry").await.unwrap();
+
+        // the reserved lane must still be available
+
+        let alarm = tokio::time::timeout(std::time::Duration::from_secs(1), lanes.acquire("alarm"))
+            .await
+            .expect("Reserved lane must not be blocked by the shared lane");
+        assert!(alarm.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_no_lanes() {
+        let lanes = AdmissionLanes::new(Default::default());
+        assert!(lanes.acquire("telemetry").await.is_none());
+    }
+}
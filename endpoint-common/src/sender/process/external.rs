@@ -10,7 +10,7 @@ use drogue_cloud_service_common::reqwest::to_method;
 use http::{header::HeaderName, HeaderMap, HeaderValue, Method};
 use lru::LruCache;
 use reqwest::{Certificate, Url};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::num::NonZeroUsize;
 use std::{sync::Arc, time::Duration};
 use thiserror::Error;
@@ -28,7 +28,7 @@ pub enum ExternalError {
     CloudEvent(#[from] cloudevents::message::Error),
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ExternalClientPoolConfig {
     pub capacity: NonZeroUsize,
 }
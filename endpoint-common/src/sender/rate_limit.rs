@@ -0,0 +1,644 @@
+use crate::error::EndpointError;
+use drogue_client::registry;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Annotation of the application, selecting one of the configured tiers of tenant limits.
+pub const ANNOTATION_RATE_LIMIT_TIER: &str = "drogue.io/rate-limit-tier";
+
+/// The limit which was exceeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateLimitScope {
+    Device,
+    Tenant,
+}
+
+impl RateLimitScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Device => "device",
+            Self::Tenant => "tenant",
+        }
+    }
+}
+
+impl Display for RateLimitScope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A token bucket limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RateLimit {
+    /// The number of events per second.
+    pub rate: u32,
+    /// The number of events which may be sent at once, after being idle.
+    pub burst: u32,
+}
+
+/// Configuration of the publish rate limits.
+///
+/// The limits of a device and of its tenant (the application) are both enforced. If no limit is
+/// configured, publishing isn't limited at all.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    /// The limit of a single device.
+    #[serde(default)]
+    pub device: Option<RateLimit>,
+    /// The default limit of a tenant, shared by all of its devices.
+    #[serde(default)]
+    pub tenant: Option<RateLimit>,
+    /// Limits of specific tenants, overriding the default.
+    #[serde(default)]
+    pub tenants: HashMap<String, RateLimit>,
+    /// Tiers of tenant limits, selected by the application.
+    ///
+    /// The limits of specific tenants take precedence over the tier.
+    #[serde(default)]
+    pub tiers: HashMap<String, RateLimit>,
+    /// The maximum number of tracked devices, as well as tenants.
+    ///
+    /// The least recently used buckets get evicted first, starting over with a full bucket.
+    #[serde(default = "default_max_buckets")]
+    pub max_buckets: NonZeroUsize,
+    /// The interval of evicting the buckets of idle devices and tenants.
+    #[serde(default = "default_eviction_interval", with = "humantime_serde")]
+    pub eviction_interval: Duration,
+}
+
+const fn default_max_buckets() -> NonZeroUsize {
+    unsafe { NonZeroUsize::new_unchecked(10_000) }
+}
+
+const fn default_eviction_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            device: None,
+            tenant: None,
+            tenants: Default::default(),
+            tiers: Default::default(),
+            max_buckets: default_max_buckets(),
+            eviction_interval: default_eviction_interval(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    fn tenant_limit(&self, tenant: &str, tier: Option<&str>) -> Option<RateLimit> {
+        self.tenants
+            .get(tenant)
+            .or_else(|| tier.and_then(|tier| self.tiers.get(tier)))
+            .copied()
+            .or(self.tenant)
+    }
+}
+
+/// The tier of tenant limits, selected by the application.
+pub fn tier_of(application: &registry::v1::Application) -> Option<&str> {
+    application
+        .metadata
+        .annotations
+        .get(ANNOTATION_RATE_LIMIT_TIER)
+        .map(String::as_str)
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last: Instant,
+    /// The limit last applied to the bucket.
+    limit: RateLimit,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            last: now,
+            limit,
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate as f64).min(limit.burst as f64);
+        self.last = now;
+        self.limit = limit;
+    }
+
+    /// A full bucket is the same as no bucket at all.
+    fn is_full(&self, now: Instant) -> bool {
+        self.peek(self.limit, now) >= self.limit.burst as f64
+    }
+
+    /// The tokens there would be after refilling, without changing the bucket.
+    fn peek(&self, limit: RateLimit, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        (self.tokens + elapsed * limit.rate as f64).min(limit.burst as f64)
+    }
+
+    /// The number of seconds until the next token is available, zero if one is available now.
+    fn retry_after(&self, limit: RateLimit) -> u64 {
+        if self.tokens >= 1.0 {
+            return 0;
+        }
+        // saturates for a zero rate
+        ((1.0 - self.tokens) / limit.rate as f64).ceil() as u64
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    config: RateLimitConfig,
+    devices: Mutex<LruCache<String, TokenBucket>>,
+    tenants: Mutex<LruCache<String, TokenBucket>>,
+}
+
+/// Check a bucket, creating it if necessary.
+fn check_bucket(
+    buckets: &mut LruCache<String, TokenBucket>,
+    key: &str,
+    limit: RateLimit,
+    now: Instant,
+) -> u64 {
+    match buckets.get_mut(key) {
+        Some(bucket) => {
+            bucket.refill(limit, now);
+            bucket.retry_after(limit)
+        }
+        None => {
+            let bucket = TokenBucket::new(limit, now);
+            let retry_after = bucket.retry_after(limit);
+            buckets.put(key.to_string(), bucket);
+            retry_after
+        }
+    }
+}
+
+fn take_token(buckets: &mut LruCache<String, TokenBucket>, key: &str) {
+    if let Some(bucket) = buckets.get_mut(key) {
+        bucket.tokens -= 1.0;
+    }
+}
+
+/// Remove the full buckets, returning the number of removed buckets.
+fn evict_full(buckets: &mut LruCache<String, TokenBucket>, now: Instant) -> usize {
+    let full = buckets
+        .iter()
+        .filter(|(_, bucket)| bucket.is_full(now))
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+    for key in &full {
+        buckets.pop(key);
+    }
+    full.len()
+}
+
+/// The tokens left in the bucket of a limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Headroom {
+    pub limit: RateLimit,
+    pub tokens: f64,
+}
+
+/// Enforces the [`RateLimitConfig`].
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter {
+    buckets: Option<Arc<Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        if config.device.is_none() && config.tenant.is_none() && config.tenants.is_empty() {
+            return Self::default();
+        }
+
+        Self {
+            buckets: Some(Arc::new(Buckets {
+                devices: Mutex::new(LruCache::new(config.max_buckets)),
+                tenants: Mutex::new(LruCache::new(config.max_buckets)),
+                config,
+            })),
+        }
+    }
+
+    /// Check the limits of a device and its tenant, for publishing a single event.
+    ///
+    /// A token is only taken if both limits allow it. Otherwise, the exceeded limit is reported,
+    /// checking the device first.
+    pub fn check(&self, tenant: &str, device: &str) -> Result<(), EndpointError> {
+        self.check_at(tenant, None, device, Instant::now())
+    }
+
+    /// Check the limits of a device and its tenant, using the limit of the tenant's tier.
+    pub fn check_tier(
+        &self,
+        tenant: &str,
+        tier: Option<&str>,
+        device: &str,
+    ) -> Result<(), EndpointError> {
+        self.check_at(tenant, tier, device, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        tenant: &str,
+        tier: Option<&str>,
+        device: &str,
+        now: Instant,
+    ) -> Result<(), EndpointError> {
+        let buckets = match &self.buckets {
+            Some(buckets) => buckets,
+            None => return Ok(()),
+        };
+
+        let device_key = format!("{tenant}/{device}");
+        let device_limit = buckets.config.device;
+        let tenant_limit = buckets.config.tenant_limit(tenant, tier);
+
+        let mut devices = buckets.devices.lock().unwrap();
+        let mut tenants = buckets.tenants.lock().unwrap();
+
+        for (scope, limit, cache, key) in [
+            (
+                RateLimitScope::Device,
+                device_limit,
+                &mut *devices,
+                device_key.as_str(),
+            ),
+            (RateLimitScope::Tenant, tenant_limit, &mut *tenants, tenant),
+        ] {
+            if let Some(limit) = limit {
+                let retry_after = check_bucket(cache, key, limit, now);
+                if retry_after > 0 {
+                    return Err(EndpointError::RateLimited { scope, retry_after });
+                }
+            }
+        }
+
+        if device_limit.is_some() {
+            take_token(&mut devices, &device_key);
+        }
+        if tenant_limit.is_some() {
+            take_token(&mut tenants, tenant);
+        }
+
+        Ok(())
+    }
+
+    /// Get the headroom of the limits of a device and its tenant.
+    ///
+    /// This neither takes a token, nor changes the order of eviction. Limits without a bucket
+    /// yet are not reported, as their bucket would be full.
+    pub fn headroom(&self, tenant: &str, tier: Option<&str>, device: &str) -> Vec<Headroom> {
+        self.headroom_at(tenant, tier, device, Instant::now())
+    }
+
+    fn headroom_at(
+        &self,
+        tenant: &str,
+        tier: Option<&str>,
+        device: &str,
+        now: Instant,
+    ) -> Vec<Headroom> {
+        let buckets = match &self.buckets {
+            Some(buckets) => buckets,
+            None => return vec![],
+        };
+
+        let device_key = format!("{tenant}/{device}");
+        let devices = buckets.devices.lock().unwrap();
+        let tenants = buckets.tenants.lock().unwrap();
+
+        [
+            (buckets.config.device, devices.peek(&device_key)),
+            (
+                buckets.config.tenant_limit(tenant, tier),
+                tenants.peek(tenant),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(limit, bucket)| {
+            let limit = limit?;
+            Some(Headroom {
+                limit,
+                tokens: bucket?.peek(limit, now),
+            })
+        })
+        .collect()
+    }
+
+    /// Evict the buckets of idle devices and tenants, which are full again.
+    ///
+    /// Returns the number of evicted buckets.
+    pub fn evict_idle(&self) -> usize {
+        self.evict_idle_at(Instant::now())
+    }
+
+    fn evict_idle_at(&self, now: Instant) -> usize {
+        match &self.buckets {
+            Some(buckets) => {
+                evict_full(&mut buckets.devices.lock().unwrap(), now)
+                    + evict_full(&mut buckets.tenants.lock().unwrap(), now)
+            }
+            None => 0,
+        }
+    }
+
+    /// Periodically evict the buckets of idle devices and tenants, never completing.
+    pub async fn run_eviction(self) -> anyhow::Result<()> {
+        let interval = match &self.buckets {
+            Some(buckets) => buckets.config.eviction_interval,
+            // nothing to evict, but completing would stop the endpoint
+            None => std::future::pending().await,
+        };
+
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+        loop {
+            ticker.tick().await;
+            let evicted = self.evict_idle();
+            log::debug!("Evicted {} idle rate limit buckets", evicted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter(device: Option<RateLimit>, tenant: Option<RateLimit>) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            device,
+            tenant,
+            tenants: HashMap::from([(
+                "premium".to_string(),
+                RateLimit {
+                    rate: 100,
+                    burst: 100,
+                },
+            )]),
+            ..Default::default()
+        })
+    }
+
+    fn scope(result: Result<(), EndpointError>) -> Option<RateLimitScope> {
+        match result {
+            Ok(()) => None,
+            Err(EndpointError::RateLimited { scope, .. }) => Some(scope),
+            Err(err) => panic!("Unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = RateLimiter::new(Default::default());
+        for _ in 0..1000 {
+            assert!(limiter.check("app1", "device1").is_ok());
+        }
+    }
+
+    #[test]
+    fn test_device_limit() {
+        let limiter = limiter(Some(RateLimit { rate: 1, burst: 2 }), None);
+        let now = Instant::now();
+
+        assert_eq!(scope(limiter.check_at("app1", None, "device1", now)), None);
+        assert_eq!(scope(limiter.check_at("app1", None, "device1", now)), None);
+        assert_eq!(
+            scope(limiter.check_at("app1", None, "device1", now)),
+            Some(RateLimitScope::Device)
+        );
+
+        // other devices of the same tenant are not affected
+        assert_eq!(scope(limiter.check_at("app1", None, "device2", now)), None);
+
+        // refilled
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            scope(limiter.check_at("app1", None, "device1", later)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tenant_limit() {
+        let limiter = limiter(
+            Some(RateLimit { rate: 1, burst: 2 }),
+            Some(RateLimit { rate: 1, burst: 3 }),
+        );
+        let now = Instant::now();
+
+        // each device stays within its own limit, but the tenant is exhausted
+        assert_eq!(scope(limiter.check_at("app1", None, "device1", now)), None);
+        assert_eq!(scope(limiter.check_at("app1", None, "device1", now)), None);
+        assert_eq!(scope(limiter.check_at("app1", None, "device2", now)), None);
+        assert_eq!(
+            scope(limiter.check_at("app1", None, "device2", now)),
+            Some(RateLimitScope::Tenant)
+        );
+
+        // other tenants are not affected
+        assert_eq!(scope(limiter.check_at("app2", None, "device1", now)), None);
+
+        // a rejected event doesn't use a token of the device
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            scope(limiter.check_at("app1", None, "device2", later)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_which_limit() {
+        let limiter = limiter(
+            Some(RateLimit { rate: 1, burst: 1 }),
+            Some(RateLimit { rate: 1, burst: 1 }),
+        );
+        let now = Instant::now();
+
+        assert_eq!(scope(limiter.check_at("app1", None, "device1", now)), None);
+        // both are exhausted, the device is reported first
+        assert_eq!(
+            scope(limiter.check_at("app1", None, "device1", now)),
+            Some(RateLimitScope::Device)
+        );
+        assert_eq!(
+            scope(limiter.check_at("app1", None, "device2", now)),
+            Some(RateLimitScope::Tenant)
+        );
+    }
+
+    #[test]
+    fn test_tenant_override() {
+        let limiter = limiter(None, Some(RateLimit { rate: 1, burst: 1 }));
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            assert_eq!(
+                scope(limiter.check_at("premium", None, "device1", now)),
+                None
+            );
+        }
+        assert_eq!(
+            scope(limiter.check_at("premium", None, "device1", now)),
+            Some(RateLimitScope::Tenant)
+        );
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let limiter = limiter(None, Some(RateLimit { rate: 1, burst: 1 }));
+        let now = Instant::now();
+
+        assert!(limiter.check_at("app1", None, "device1", now).is_ok());
+        assert!(matches!(
+            limiter.check_at("app1", None, "device1", now),
+            Err(EndpointError::RateLimited {
+                scope: RateLimitScope::Tenant,
+                retry_after: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_bounded() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            device: Some(RateLimit { rate: 1, burst: 1 }),
+            max_buckets: NonZeroUsize::new(2).unwrap(),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        for device in ["device1", "device2", "device3"] {
+            assert!(limiter.check_at("app1", None, device, now).is_ok());
+        }
+
+        let buckets = limiter.buckets.as_ref().unwrap();
+        assert_eq!(buckets.devices.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_headroom() {
+        let device = RateLimit { rate: 1, burst: 4 };
+        let tenant = RateLimit { rate: 2, burst: 10 };
+        let limiter = limiter(Some(device), Some(tenant));
+        let now = Instant::now();
+
+        assert!(limiter.headroom_at("app1", None, "device1", now).is_empty());
+
+        for _ in 0..3 {
+            assert!(limiter.check_at("app1", None, "device1", now).is_ok());
+        }
+        assert_eq!(
+            limiter.headroom_at("app1", None, "device1", now),
+            vec![
+                Headroom {
+                    limit: device,
+                    tokens: 1.0
+                },
+                Headroom {
+                    limit: tenant,
+                    tokens: 7.0
+                }
+            ]
+        );
+
+        // refilled, without taking a token
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            limiter.headroom_at("app1", None, "device1", later)[0].tokens,
+            2.0
+        );
+        assert_eq!(
+            limiter.headroom_at("app1", None, "device1", later)[0].tokens,
+            2.0
+        );
+    }
+
+    #[test]
+    fn test_tier() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            tenant: Some(RateLimit { rate: 1, burst: 1 }),
+            tenants: HashMap::from([("app2".to_string(), RateLimit { rate: 1, burst: 1 })]),
+            tiers: HashMap::from([("gold".to_string(), RateLimit { rate: 1, burst: 3 })]),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(
+                scope(limiter.check_at("app1", Some("gold"), "device1", now)),
+                None
+            );
+        }
+        assert_eq!(
+            scope(limiter.check_at("app1", Some("gold"), "device1", now)),
+            Some(RateLimitScope::Tenant)
+        );
+
+        // unknown tiers get the default
+        assert_eq!(
+            scope(limiter.check_at("app3", Some("platinum"), "device1", now)),
+            None
+        );
+        assert_eq!(
+            scope(limiter.check_at("app3", Some("platinum"), "device1", now)),
+            Some(RateLimitScope::Tenant)
+        );
+
+        // the limit of a specific tenant takes precedence
+        assert_eq!(
+            scope(limiter.check_at("app2", Some("gold"), "device1", now)),
+            None
+        );
+        assert_eq!(
+            scope(limiter.check_at("app2", Some("gold"), "device1", now)),
+            Some(RateLimitScope::Tenant)
+        );
+    }
+
+    #[test]
+    fn test_evict_idle() {
+        let limiter = limiter(
+            Some(RateLimit { rate: 1, burst: 2 }),
+            Some(RateLimit {
+                rate: 10,
+                burst: 10,
+            }),
+        );
+        let now = Instant::now();
+
+        assert!(limiter.check_at("app1", None, "device1", now).is_ok());
+        assert!(limiter.check_at("app1", None, "device1", now).is_ok());
+        assert!(limiter.check_at("app1", None, "device2", now).is_ok());
+
+        // the tenant is refilled, device2 is refilled, device1 isn't yet
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.evict_idle_at(later), 2);
+
+        let buckets = limiter.buckets.as_ref().unwrap();
+        assert_eq!(buckets.devices.lock().unwrap().len(), 1);
+        assert!(buckets.tenants.lock().unwrap().is_empty());
+
+        // an evicted bucket starts over with a full bucket, as if it was kept
+        assert!(limiter.check_at("app1", None, "device2", later).is_ok());
+        assert!(limiter.check_at("app1", None, "device2", later).is_ok());
+        assert_eq!(
+            scope(limiter.check_at("app1", None, "device2", later)),
+            Some(RateLimitScope::Device)
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use super::channel_matches;
+use cloudevents::Data;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How to handle payloads of redacted channels, which are not JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NonJsonPolicy {
+    /// Forward the payload as it is.
+    #[default]
+    PassThrough,
+    /// Reject the payload, as it can't be redacted.
+    Reject,
+}
+
+/// The fields to redact from the payloads of a channel.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RedactionRule {
+    /// The channel pattern, matching like the ones of the [`super::SensitivityConfig`].
+    pub channel: String,
+    /// The fields to remove, as JSON pointers (e.g. `/location/lat`).
+    #[serde(default)]
+    pub remove: Vec<String>,
+    /// The fields to replace with the mask value, as JSON pointers.
+    #[serde(default)]
+    pub mask: Vec<String>,
+}
+
+/// Redacting fields of JSON payloads, before forwarding them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RedactionConfig {
+    /// The rules, evaluated in order. The first matching rule wins.
+    #[serde(default)]
+    pub rules: Vec<RedactionRule>,
+    /// The value replacing masked fields.
+    #[serde(default = "default_mask_value")]
+    pub mask_value: String,
+    /// How to handle payloads of redacted channels, which are not JSON.
+    #[serde(default)]
+    pub non_json: NonJsonPolicy,
+    /// Additionally send the un-redacted payload to this topic.
+    ///
+    /// Access to this topic should be restricted. The topic must already exist.
+    #[serde(default)]
+    pub restricted_topic: Option<String>,
+}
+
+fn default_mask_value() -> String {
+    "***".into()
+}
+
+/// The outcome of redacting a payload.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Redacted {
+    /// Nothing to redact.
+    Unchanged(Data),
+    /// The payload was redacted, keeping the original if it should be forwarded as well.
+    Redacted { data: Data, original: Option<Data> },
+    /// The payload must not be forwarded.
+    Rejected,
+}
+
+impl RedactionConfig {
+    /// Redact the payload of a channel.
+    pub fn redact(&self, channel: &str, data: Data) -> Redacted {
+        let rule = match self
+            .rules
+            .iter()
+            .find(|rule| channel_matches(&rule.channel, channel))
+        {
+            Some(rule) => rule,
+            None => return Redacted::Unchanged(data),
+        };
+
+        let mut value = match data {
+            Data::Json(value) => value,
+            data => {
+                return match self.non_json {
+                    NonJsonPolicy::PassThrough => Redacted::Unchanged(data),
+                    NonJsonPolicy::Reject => Redacted::Rejected,
+                }
+            }
+        };
+
+        let original = self.restricted_topic.as_ref().map(|_| value.clone());
+
+        let mut changed = false;
+        for pointer in &rule.remove {
+            changed |= remove(&mut value, pointer);
+        }
+        for pointer in &rule.mask {
+            if let Some(field) = value.pointer_mut(pointer) {
+                *field = Value::String(self.mask_value.clone());
+                changed = true;
+            }
+        }
+
+        match changed {
+            true => Redacted::Redacted {
+                data: Data::Json(value),
+                original: original.map(Data::Json),
+            },
+            false => Redacted::Unchanged(Data::Json(value)),
+        }
+    }
+}
+
+/// Remove the field referenced by a JSON pointer, returning `true` if it was present.
+fn remove(value: &mut Value, pointer: &str) -> bool {
+    let (parent, key) = match pointer.rsplit_once('/') {
+        Some(split) => split,
+        None => return false,
+    };
+    let key = key.replace("~1", "/").replace("~0", "~");
+
+    match value.pointer_mut(parent) {
+        Some(Value::Object(fields)) => fields.remove(&key).is_some(),
+        Some(Value::Array(items)) => match key.parse::<usize>() {
+            Ok(index) if index < items.len() => {
+                items.remove(index);
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn config(remove: &[&str], mask: &[&str]) -> RedactionConfig {
+        RedactionConfig {
+            rules: vec![RedactionRule {
+                channel: "location/*".into(),
+                remove: remove.iter().map(ToString::to_string).collect(),
+                mask: mask.iter().map(ToString::to_string).collect(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn payload() -> Data {
+        Data::Json(json!({"gps": {"lat": 48.1, "lon": 11.6}, "device": "d1", "speed": 12}))
+    }
+
+    #[test]
+    fn test_remove() {
+        let config = config(&["/gps/lat", "/gps/lon", "/missing"], &[]);
+
+        assert_eq!(
+            config.redact("location/car", payload()),
+            Redacted::Redacted {
+                data: Data::Json(json!({"gps": {}, "device": "d1", "speed": 12})),
+                original: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_mask() {
+        let config = config(&[], &["/gps"]);
+
+        assert_eq!(
+            config.redact("location/car", payload()),
+            Redacted::Redacted {
+                data: Data::Json(json!({"gps": "***", "device": "d1", "speed": 12})),
+                original: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_other_channel() {
+        let config = config(&["/gps"], &[]);
+
+        assert_eq!(
+            config.redact("telemetry", payload()),
+            Redacted::Unchanged(payload())
+        );
+        // nothing to redact in the payload
+        assert_eq!(
+            config.redact("location/car", Data::Json(json!({"speed": 12}))),
+            Redacted::Unchanged(Data::Json(json!({"speed": 12})))
+        );
+    }
+
+    #[test]
+    fn test_non_json() {
+        let mut config = config(&["/gps"], &[]);
+        let binary = Data::Binary(b"48.1,11.6".to_vec());
+
+        assert_eq!(
+            config.redact("location/car", binary.clone()),
+            Redacted::Unchanged(binary.clone())
+        );
+
+        config.non_json = NonJsonPolicy::Reject;
+        assert_eq!(
+            config.redact("location/car", binary.clone()),
+            Redacted::Rejected
+        );
+        // only affects redacted channels
+        assert_eq!(
+            config.redact("telemetry", binary.clone()),
+            Redacted::Unchanged(binary)
+        );
+    }
+
+    #[test]
+    fn test_keep_original() {
+        let mut config = config(&["/gps"], &[]);
+        config.restricted_topic = Some("restricted".into());
+
+        assert_eq!(
+            config.redact("location/car", payload()),
+            Redacted::Redacted {
+                data: Data::Json(json!({"device": "d1", "speed": 12})),
+                original: Some(payload()),
+            }
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Retrying events, which failed to be sent due to a transient failure.
+///
+/// Retrying is disabled by default, and never used with the strict ordering guarantee, as a
+/// retried event may overtake events sent in the meantime.
+///
+/// The delay between two attempts doubles with each attempt. Retrying stops once the next attempt
+/// would exceed the maximum time, so that the device still gets a timely response.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RetryConfig {
+    /// The maximum number of attempts to send an event, the default of `1` disables retrying.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// The delay before the first retry.
+    #[serde(default = "default_base_delay", with = "humantime_serde")]
+    pub base_delay: Duration,
+    /// The maximum time of all attempts, including the delays.
+    #[serde(default = "default_max_elapsed", with = "humantime_serde")]
+    pub max_elapsed: Duration,
+}
+
+const fn default_max_attempts() -> u32 {
+    1
+}
+
+const fn default_base_delay() -> Duration {
+    Duration::from_millis(100)
+}
+
+const fn default_max_elapsed() -> Duration {
+    Duration::from_secs(2)
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            base_delay: default_base_delay(),
+            max_elapsed: default_max_elapsed(),
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_attempts > 1
+    }
+
+    /// The delay before the next attempt, after `attempt` attempts failed within `elapsed`.
+    ///
+    /// Returns [`None`] if there should be no further attempt.
+    pub fn delay(&self, attempt: u32, elapsed: Duration) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        // leave some time for the attempt itself
+        match elapsed + delay < self.max_elapsed {
+            true => Some(delay),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let config = RetryConfig {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(100),
+            max_elapsed: Duration::from_secs(10),
+        };
+
+        assert_eq!(
+            config.delay(1, Duration::ZERO),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            config.delay(2, Duration::ZERO),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            config.delay(3, Duration::ZERO),
+            Some(Duration::from_millis(400))
+        );
+        assert_eq!(config.delay(4, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn test_max_elapsed() {
+        let config = RetryConfig {
+            max_attempts: 3,
+            max_elapsed: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        assert!(config.delay(1, Duration::from_millis(500)).is_some());
+        assert_eq!(config.delay(1, Duration::from_millis(950)), None);
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert!(!RetryConfig::default().is_enabled());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let config = RetryConfig {
+            max_attempts: 1,
+            ..Default::default()
+        };
+
+        assert!(!config.is_enabled());
+        assert_eq!(config.delay(1, Duration::ZERO), None);
+    }
+}
@@ -0,0 +1,266 @@
+use super::EXT_SENSITIVITY;
+use crate::{
+    sampling::SampleRate,
+    sink::{Sink, SinkTarget},
+};
+use cloudevents::{AttributesReader, Data, Event};
+use drogue_client::registry;
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Annotation of the application, opting in to copying samples of its events.
+pub const ANNOTATION_SAMPLE_PAYLOADS: &str = "drogue.io/sample-payloads";
+
+/// Copying a sample of the published events to a topic, for inspecting the payloads.
+///
+/// The copy is sent in the background, after the event was accepted. It doesn't delay the
+/// response to the client, and failing to send it is only logged.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PayloadSampleConfig {
+    /// The topic to copy the sampled events to.
+    ///
+    /// The topic must already exist, it is not created by the endpoint.
+    pub topic: String,
+    /// The rate of events to copy.
+    #[serde(default = "default_rate")]
+    pub rate: SampleRate,
+    /// Events with a larger payload are never copied.
+    #[serde(default = "default_max_payload_size")]
+    pub max_payload_size: usize,
+    /// The maximum number of copies being sent at the same time.
+    ///
+    /// Further events are not copied, until a copy was sent.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    /// Also copy events of sensitive channels, which carry the `sensitivity` extension.
+    #[serde(default)]
+    pub include_sensitive: bool,
+    /// Only copy events of applications, which opted in using the `drogue.io/sample-payloads`
+    /// annotation.
+    #[serde(default)]
+    pub opt_in: bool,
+}
+
+const fn default_rate() -> SampleRate {
+    SampleRate::OneIn(1000)
+}
+
+const fn default_max_payload_size() -> usize {
+    64 * 1024
+}
+
+const fn default_max_in_flight() -> usize {
+    10
+}
+
+/// Evaluates if an event should be copied to the sample topic.
+#[derive(Clone, Debug)]
+pub struct PayloadSampler {
+    config: Arc<PayloadSampleConfig>,
+    counter: Arc<AtomicU64>,
+    in_flight: Arc<Semaphore>,
+}
+
+impl PayloadSampler {
+    pub fn new(config: PayloadSampleConfig) -> Self {
+        Self {
+            in_flight: Arc::new(Semaphore::new(config.max_in_flight)),
+            counter: Default::default(),
+            config: Arc::new(config),
+        }
+    }
+
+    /// Check if the event should be copied, reserving a slot for sending the copy.
+    pub fn sample(&self, app: &registry::v1::Application, event: &Event) -> Option<Sample> {
+        if !self.is_eligible(app, event)
+            || data_len(event) > self.config.max_payload_size
+            || !self.should_sample()
+        {
+            return None;
+        }
+
+        let permit = match self.in_flight.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                log::debug!("Too many samples in flight, skipping event {}", event.id());
+                return None;
+            }
+        };
+
+        Some(Sample {
+            topic: self.config.topic.clone(),
+            app: app.clone(),
+            event: event.clone(),
+            permit,
+        })
+    }
+
+    /// Check if the event may be copied at all, independent of the rate.
+    fn is_eligible(&self, app: &registry::v1::Application, event: &Event) -> bool {
+        if !self.config.include_sensitive && event.extension(EXT_SENSITIVITY).is_some() {
+            return false;
+        }
+
+        !self.config.opt_in
+            || app
+                .metadata
+                .annotations
+                .get(ANNOTATION_SAMPLE_PAYLOADS)
+                .map(String::as_str)
+                == Some("true")
+    }
+
+    fn should_sample(&self) -> bool {
+        match self.config.rate {
+            SampleRate::Probability(rate) if rate >= 1.0 => true,
+            SampleRate::Probability(rate) if rate <= 0.0 => false,
+            SampleRate::Probability(rate) => rand::random::<f64>() < rate,
+            SampleRate::OneIn(0) => false,
+            SampleRate::OneIn(n) => self.counter.fetch_add(1, Ordering::Relaxed) % n == 0,
+        }
+    }
+}
+
+/// A copy of an event, to be sent to the sample topic.
+pub struct Sample {
+    topic: String,
+    app: registry::v1::Application,
+    event: Event,
+    permit: OwnedSemaphorePermit,
+}
+
+impl Sample {
+    /// Send the copy in the background.
+    pub fn spawn(self, sink: Arc<dyn Sink>) {
+        let Self {
+            topic,
+            app,
+            event,
+            permit,
+        } = self;
+
+        tokio::spawn(async move {
+            let id = event.id().to_string();
+            if let Err(err) = sink.publish(SinkTarget::Topic(&app, &topic), event).await {
+                log::info!("Failed to copy event {id} to sample topic '{topic}': {err}");
+            }
+            drop(permit);
+        });
+    }
+}
+
+fn data_len(event: &Event) -> usize {
+    match event.data() {
+        Some(Data::Binary(data)) => data.len(),
+        Some(Data::String(data)) => data.len(),
+        Some(Data::Json(data)) => data.to_string().len(),
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    fn config(rate: SampleRate) -> PayloadSampleConfig {
+        PayloadSampleConfig {
+            topic: "samples".into(),
+            rate,
+            max_payload_size: 16,
+            max_in_flight: 2,
+            include_sensitive: false,
+            opt_in: false,
+        }
+    }
+
+    fn sampler(rate: SampleRate) -> PayloadSampler {
+        PayloadSampler::new(config(rate))
+    }
+
+    fn event(payload: &[u8]) -> Event {
+        EventBuilderV10::new()
+            .id("id1")
+            .ty("type")
+            .source("drogue://app1/device1")
+            .data("application/octet-stream", payload.to_vec())
+            .build()
+            .unwrap()
+    }
+
+    fn sampled(sampler: &PayloadSampler, payload: &[u8]) -> bool {
+        sampler
+            .sample(&Default::default(), &event(payload))
+            .is_some()
+    }
+
+    #[test]
+    fn test_rate() {
+        assert!(sampled(&sampler(SampleRate::Probability(1.0)), b"{}"));
+        assert!(!sampled(&sampler(SampleRate::Probability(0.0)), b"{}"));
+        assert!(!sampled(&sampler(SampleRate::OneIn(0)), b"{}"));
+
+        let sampler = sampler(SampleRate::OneIn(10));
+        let count = (0..100).filter(|_| sampled(&sampler, b"{}")).count();
+        assert_eq!(count, 10);
+    }
+
+    #[test]
+    fn test_payload_size() {
+        let sampler = sampler(SampleRate::Probability(1.0));
+
+        assert!(sampled(&sampler, &[0u8; 16]));
+        assert!(!sampled(&sampler, &[0u8; 17]));
+    }
+
+    #[test]
+    fn test_sensitive() {
+        let mut sensitive = event(b"{}");
+        sensitive.set_extension(EXT_SENSITIVITY, "high");
+        let app = Default::default();
+
+        let sampler = sampler(SampleRate::Probability(1.0));
+        assert!(sampler.sample(&app, &sensitive).is_none());
+
+        let sampler = PayloadSampler::new(PayloadSampleConfig {
+            include_sensitive: true,
+            ..config(SampleRate::Probability(1.0))
+        });
+        assert!(sampler.sample(&app, &sensitive).is_some());
+    }
+
+    #[test]
+    fn test_opt_in() {
+        let sampler = PayloadSampler::new(PayloadSampleConfig {
+            opt_in: true,
+            ..config(SampleRate::Probability(1.0))
+        });
+
+        let mut app = registry::v1::Application::default();
+        assert!(sampler.sample(&app, &event(b"{}")).is_none());
+
+        app.metadata
+            .annotations
+            .insert(ANNOTATION_SAMPLE_PAYLOADS.into(), "true".into());
+        assert!(sampler.sample(&app, &event(b"{}")).is_some());
+    }
+
+    #[test]
+    fn test_in_flight() {
+        let sampler = sampler(SampleRate::Probability(1.0));
+        let app = Default::default();
+
+        let first = sampler.sample(&app, &event(b"{}"));
+        let second = sampler.sample(&app, &event(b"{}"));
+        assert!(first.is_some() && second.is_some());
+
+        // bounded, until a copy was sent
+        assert!(sampler.sample(&app, &event(b"{}")).is_none());
+        drop(first);
+        assert!(sampler.sample(&app, &event(b"{}")).is_some());
+    }
+}
@@ -0,0 +1,193 @@
+use super::PublishOptions;
+use crate::error::EndpointError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Extension carrying the payload schema version, provided by the device.
+pub const EXT_SCHEMA_VERSION: &str = "schemaversion";
+
+/// How to handle schema versions which are not registered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UnknownVersionPolicy {
+    /// Pass on the version, without validating it.
+    #[default]
+    PassThrough,
+    /// Reject the message.
+    Reject,
+}
+
+/// A registered schema version.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SchemaVersion {
+    /// The data schema of this version.
+    ///
+    /// If the device doesn't provide a data schema, this one is used. If it provides a different
+    /// one, the message is rejected.
+    #[serde(default)]
+    pub data_schema: Option<String>,
+}
+
+impl SchemaVersion {
+    /// Check the data schema of the publish options against this version.
+    fn check(&self, options: &mut PublishOptions, version: &str) -> Result<(), EndpointError> {
+        let data_schema = match &self.data_schema {
+            Some(data_schema) => data_schema,
+            None => return Ok(()),
+        };
+
+        match &options.data_schema {
+            Some(provided) if provided != data_schema => Err(EndpointError::InvalidRequest {
+                details: format!(
+                    "Data schema '{provided}' doesn't match schema version '{version}'"
+                ),
+            }),
+            Some(_) => Ok(()),
+            None => {
+                options.data_schema = Some(data_schema.clone());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Configuration of the payload schema versions.
+///
+/// Devices may announce the version of their payload schema, for example, when different firmware
+/// generations send different payloads. The version is passed on downstream, so that consumers
+/// can handle the generations accordingly.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SchemaVersionConfig {
+    /// The registered versions.
+    #[serde(default)]
+    pub versions: HashMap<String, SchemaVersion>,
+    /// What to do with versions which are not registered.
+    #[serde(default)]
+    pub unknown: UnknownVersionPolicy,
+}
+
+impl SchemaVersionConfig {
+    /// Check the schema version, provided by the device, and apply it to the publish options.
+    ///
+    /// Messages without a version are passed on unchanged.
+    pub fn apply(
+        &self,
+        options: &mut PublishOptions,
+        version: Option<&str>,
+    ) -> Result<(), EndpointError> {
+        let version = match version {
+            Some(version) => version,
+            None => return Ok(()),
+        };
+
+        match (self.versions.get(version), self.unknown) {
+            (Some(registered), _) => registered.check(options, version)?,
+            (None, UnknownVersionPolicy::PassThrough) => {}
+            (None, UnknownVersionPolicy::Reject) => {
+                return Err(EndpointError::InvalidRequest {
+                    details: format!("Unknown schema version '{version}'"),
+                })
+            }
+        }
+
+        options
+            .extensions
+            .insert(EXT_SCHEMA_VERSION.into(), version.to_string());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(unknown: UnknownVersionPolicy) -> SchemaVersionConfig {
+        SchemaVersionConfig {
+            versions: HashMap::from([
+                (
+                    "2".to_string(),
+                    SchemaVersion {
+                        data_schema: Some("urn:sensor:v2".into()),
+                    },
+                ),
+                ("3".to_string(), SchemaVersion::default()),
+            ]),
+            unknown,
+        }
+    }
+
+    fn apply(
+        config: &SchemaVersionConfig,
+        data_schema: Option<&str>,
+        version: Option<&str>,
+    ) -> Result<PublishOptions, EndpointError> {
+        let mut options = PublishOptions {
+            data_schema: data_schema.map(String::from),
+            ..Default::default()
+        };
+        config.apply(&mut options, version)?;
+        Ok(options)
+    }
+
+    #[test]
+    fn test_absent() {
+        let options = apply(&config(UnknownVersionPolicy::Reject), None, None).unwrap();
+        assert!(options.extensions.is_empty());
+        assert_eq!(options.data_schema, None);
+    }
+
+    #[test]
+    fn test_known() {
+        let config = config(UnknownVersionPolicy::Reject);
+
+        let options = apply(&config, None, Some("2")).unwrap();
+        assert_eq!(
+            options
+                .extensions
+                .get(EXT_SCHEMA_VERSION)
+                .map(String::as_str),
+            Some("2")
+        );
+        assert_eq!(options.data_schema.as_deref(), Some("urn:sensor:v2"));
+
+        let options = apply(&config, Some("urn:sensor:v2"), Some("2")).unwrap();
+        assert_eq!(options.data_schema.as_deref(), Some("urn:sensor:v2"));
+
+        let options = apply(&config, Some("urn:other"), Some("3")).unwrap();
+        assert_eq!(options.data_schema.as_deref(), Some("urn:other"));
+    }
+
+    #[test]
+    fn test_known_mismatch() {
+        assert!(matches!(
+            apply(
+                &config(UnknownVersionPolicy::PassThrough),
+                Some("urn:sensor:v1"),
+                Some("2")
+            ),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_pass_through() {
+        let options = apply(&config(UnknownVersionPolicy::PassThrough), None, Some("9")).unwrap();
+        assert_eq!(
+            options
+                .extensions
+                .get(EXT_SCHEMA_VERSION)
+                .map(String::as_str),
+            Some("9")
+        );
+        assert_eq!(options.data_schema, None);
+    }
+
+    #[test]
+    fn test_unknown_reject() {
+        assert!(matches!(
+            apply(&config(UnknownVersionPolicy::Reject), None, Some("9")),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+    }
+}
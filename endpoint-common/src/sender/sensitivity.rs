@@ -0,0 +1,78 @@
+use super::channel_matches;
+use serde::{Deserialize, Serialize};
+
+/// The extension attribute carrying the sensitivity of an event.
+pub const EXT_SENSITIVITY: &str = "sensitivity";
+
+/// A mapping of channels to a sensitivity level.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SensitivityRule {
+    /// The channel pattern.
+    ///
+    /// A channel matches if it is equal to the pattern, or if the pattern ends with a `*` and the
+    /// channel starts with the part before the `*`.
+    pub channel: String,
+    /// The sensitivity level, e.g. `high`.
+    pub sensitivity: String,
+}
+
+/// Tagging events of sensitive channels.
+///
+/// The endpoint doesn't encrypt anything itself, but attaches the sensitivity level to the event,
+/// so that consumers can decide on encryption and retention.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SensitivityConfig {
+    /// The rules, evaluated in order. The first matching rule wins.
+    #[serde(default)]
+    pub channels: Vec<SensitivityRule>,
+}
+
+impl SensitivityConfig {
+    /// Evaluate the sensitivity of a channel.
+    ///
+    /// Returns [`None`] if the channel doesn't match any rule.
+    pub fn sensitivity(&self, channel: &str) -> Option<&str> {
+        self.channels
+            .iter()
+            .find(|rule| channel_matches(&rule.channel, channel))
+            .map(|rule| rule.sensitivity.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rule(channel: &str, sensitivity: &str) -> SensitivityRule {
+        SensitivityRule {
+            channel: channel.into(),
+            sensitivity: sensitivity.into(),
+        }
+    }
+
+    #[test]
+    fn test_default() {
+        assert_eq!(SensitivityConfig::default().sensitivity("patient"), None);
+    }
+
+    #[test]
+    fn test_match() {
+        let config = SensitivityConfig {
+            channels: vec![rule("patient/*", "high"), rule("location", "medium")],
+        };
+
+        assert_eq!(config.sensitivity("patient/vitals"), Some("high"));
+        assert_eq!(config.sensitivity("location"), Some("medium"));
+        assert_eq!(config.sensitivity("telemetry"), None);
+    }
+
+    #[test]
+    fn test_first_wins() {
+        let config = SensitivityConfig {
+            channels: vec![rule("patient", "high"), rule("*", "low")],
+        };
+
+        assert_eq!(config.sensitivity("patient"), Some("high"));
+        assert_eq!(config.sensitivity("telemetry"), Some("low"));
+    }
+}
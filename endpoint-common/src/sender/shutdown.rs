@@ -0,0 +1,178 @@
+use crate::sender::Maintenance;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+/// Draining in-flight events, when the endpoint shuts down.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ShutdownConfig {
+    /// The maximum time to wait for in-flight events to be sent, once a shutdown was requested.
+    ///
+    /// This should be shorter than the termination grace period of the pod.
+    #[serde(default = "default_drain_timeout", with = "humantime_serde")]
+    pub drain_timeout: Duration,
+}
+
+const fn default_drain_timeout() -> Duration {
+    Duration::from_secs(20)
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout: default_drain_timeout(),
+        }
+    }
+}
+
+/// The events currently being sent.
+#[derive(Clone, Debug, Default)]
+pub struct InFlight(Arc<InFlightState>);
+
+#[derive(Debug, Default)]
+struct InFlightState {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+/// Marks an event as in-flight, until dropped.
+pub struct InFlightGuard(InFlight);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0 .0.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0 .0.idle.notify_waiters();
+        }
+    }
+}
+
+impl InFlight {
+    /// Mark an event as in-flight, until the guard is dropped.
+    pub fn enter(&self) -> InFlightGuard {
+        self.0.count.fetch_add(1, Ordering::AcqRel);
+        InFlightGuard(self.clone())
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.count.load(Ordering::Acquire)
+    }
+
+    /// Wait until there are no events in-flight.
+    pub async fn drained(&self) {
+        loop {
+            // register before checking, so that we don't miss the notification
+            let idle = self.0.idle.notified();
+            if self.count() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+/// The graceful shutdown of the endpoint.
+///
+/// Once a shutdown was requested, the endpoint enters the maintenance mode. It rejects new
+/// publish requests and reports as not ready, while the in-flight events are still sent.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    config: ShutdownConfig,
+    maintenance: Maintenance,
+    in_flight: InFlight,
+}
+
+impl Shutdown {
+    pub fn new(config: ShutdownConfig, maintenance: Maintenance, in_flight: InFlight) -> Self {
+        Self {
+            config,
+            maintenance,
+            in_flight,
+        }
+    }
+
+    /// Stop accepting events, and wait for the in-flight events, up to the drain timeout.
+    ///
+    /// Returns `false` if the drain timeout expired before all events were sent.
+    pub async fn drain(&self) -> bool {
+        self.maintenance.set_active(true);
+
+        log::info!(
+            "Shutting down, draining {} in-flight events",
+            self.in_flight.count()
+        );
+
+        match tokio::time::timeout(self.config.drain_timeout, self.in_flight.drained()).await {
+            Ok(()) => {
+                log::info!("Drained all in-flight events");
+                true
+            }
+            Err(_) => {
+                log::warn!(
+                    "Drain timeout expired, dropping {} in-flight events",
+                    self.in_flight.count()
+                );
+                false
+            }
+        }
+    }
+
+    /// Drain the in-flight events once the process receives a `SIGTERM`, completing afterwards.
+    #[cfg(unix)]
+    pub async fn listen(self) -> anyhow::Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut signals = signal(SignalKind::terminate())?;
+        signals.recv().await;
+        self.drain().await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drained() {
+        let in_flight = InFlight::default();
+        in_flight.drained().await;
+
+        let guard = in_flight.enter();
+        let second = in_flight.enter();
+        assert_eq!(in_flight.count(), 2);
+        drop(second);
+
+        let release = async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(guard);
+        };
+        tokio::join!(in_flight.drained(), release);
+
+        assert_eq!(in_flight.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout() {
+        let maintenance = Maintenance::default();
+        let in_flight = InFlight::default();
+        let shutdown = Shutdown::new(
+            ShutdownConfig {
+                drain_timeout: Duration::from_millis(50),
+            },
+            maintenance.clone(),
+            in_flight.clone(),
+        );
+
+        let _guard = in_flight.enter();
+        assert!(!shutdown.drain().await);
+        // new requests get rejected
+        assert!(maintenance.check().is_err());
+    }
+}
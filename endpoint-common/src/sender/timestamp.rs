@@ -0,0 +1,247 @@
+use super::{key::payload_field, PublishOptions};
+use crate::error::EndpointError;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Extension carrying the original device timestamp, when it was replaced.
+pub const EXT_DEVICE_TIME: &str = "devicetime";
+/// Extension carrying the skew (device time minus server time) in milliseconds.
+pub const EXT_TIME_SKEW: &str = "timeskew";
+
+/// How to handle device timestamps which deviate from the server time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SkewPolicy {
+    /// Reject the message.
+    Reject,
+    /// Use the server time as event time, and keep the device time as an extension.
+    Correct,
+    /// Keep the message as it is, but add the skew as an extension.
+    #[default]
+    Tag,
+}
+
+/// The unit of numeric timestamps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EpochUnit {
+    #[default]
+    Seconds,
+    Milliseconds,
+}
+
+/// Configuration of how device provided timestamps are checked.
+///
+/// Devices may report the time of the measurement as part of their (JSON) payload. If the clock
+/// of the device is off, this value can be misleading. When a payload field is configured, its
+/// value is compared to the time of the server, and handled according to the [`SkewPolicy`] when
+/// it exceeds the threshold.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TimestampConfig {
+    /// Name of the payload field carrying the device timestamp.
+    ///
+    /// This can either be the name of a top-level field, or a JSON pointer (starting with a `/`).
+    /// The value may either be an RFC 3339 string, or a number of seconds (or milliseconds)
+    /// since the epoch.
+    #[serde(default)]
+    pub payload_field: Option<String>,
+    /// The unit of numeric timestamps.
+    #[serde(default)]
+    pub epoch_unit: EpochUnit,
+    /// The maximum accepted difference between device and server time.
+    #[serde(default = "default_threshold", with = "humantime_serde")]
+    pub threshold: Duration,
+    /// What to do when the threshold is exceeded.
+    #[serde(default)]
+    pub policy: SkewPolicy,
+}
+
+const fn default_threshold() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        Self {
+            payload_field: None,
+            epoch_unit: Default::default(),
+            threshold: default_threshold(),
+            policy: Default::default(),
+        }
+    }
+}
+
+impl TimestampConfig {
+    /// Extract the device timestamp from a payload.
+    ///
+    /// This returns [`None`] if no field is configured, the payload isn't JSON, or the field is
+    /// missing or can't be parsed as a timestamp.
+    pub fn extract(&self, payload: &[u8]) -> Option<DateTime<Utc>> {
+        let field = self.payload_field.as_deref()?;
+
+        let json = serde_json::from_slice::<Value>(payload).ok()?;
+
+        match payload_field(&json, field)? {
+            Value::String(value) => DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|time| time.with_timezone(&Utc)),
+            Value::Number(value) => {
+                let value = value.as_i64()?;
+                match self.epoch_unit {
+                    EpochUnit::Seconds => Utc.timestamp_opt(value, 0).single(),
+                    EpochUnit::Milliseconds => Utc.timestamp_millis_opt(value).single(),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Check the device timestamp of a payload, and apply the policy to the publish options.
+    ///
+    /// Payloads without a (valid) device timestamp, or with a timestamp within the threshold, are
+    /// passed on unchanged.
+    pub fn apply(
+        &self,
+        options: &mut PublishOptions,
+        payload: &[u8],
+        now: DateTime<Utc>,
+    ) -> Result<(), EndpointError> {
+        let device_time = match self.extract(payload) {
+            Some(device_time) => device_time,
+            None => return Ok(()),
+        };
+
+        let skew = device_time - now;
+        if u128::from(skew.num_milliseconds().unsigned_abs()) <= self.threshold.as_millis() {
+            return Ok(());
+        }
+
+        log::debug!(
+            "Device timestamp skewed: {} ms (policy: {:?})",
+            skew.num_milliseconds(),
+            self.policy
+        );
+
+        match self.policy {
+            SkewPolicy::Reject => {
+                return Err(EndpointError::TimestampSkewed {
+                    details: format!(
+                        "Device timestamp {} deviates from server time by {} ms",
+                        device_time.to_rfc3339(),
+                        skew.num_milliseconds()
+                    ),
+                })
+            }
+            SkewPolicy::Correct => {
+                options.time = Some(now);
+                options
+                    .extensions
+                    .insert(EXT_DEVICE_TIME.into(), device_time.to_rfc3339());
+            }
+            SkewPolicy::Tag => {
+                options
+                    .extensions
+                    .insert(EXT_TIME_SKEW.into(), skew.num_milliseconds().to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(policy: SkewPolicy) -> TimestampConfig {
+        TimestampConfig {
+            payload_field: Some("ts".into()),
+            threshold: Duration::from_secs(60),
+            policy,
+            ..Default::default()
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2022-10-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    const IN_THRESHOLD: &[u8] = br#"{"ts": "2022-10-01T12:00:30Z"}"#;
+    const OUT_OF_THRESHOLD: &[u8] = br#"{"ts": "2022-10-01T11:50:00Z"}"#;
+
+    fn apply(config: &TimestampConfig, payload: &[u8]) -> Result<PublishOptions, EndpointError> {
+        let mut options = PublishOptions::default();
+        config.apply(&mut options, payload, now())?;
+        Ok(options)
+    }
+
+    #[test]
+    fn test_extract() {
+        let mut config = config(SkewPolicy::Tag);
+        assert_eq!(
+            config.extract(IN_THRESHOLD),
+            Some(now() + chrono::Duration::seconds(30))
+        );
+        assert_eq!(config.extract(br#"{"ts": 1664625600}"#), Some(now()));
+        assert_eq!(config.extract(br#"{"ts": true}"#), None);
+        assert_eq!(config.extract(br#"{"ts": "yesterday"}"#), None);
+        assert_eq!(config.extract(b"ts=1664625600"), None);
+
+        config.epoch_unit = EpochUnit::Milliseconds;
+        assert_eq!(config.extract(br#"{"ts": 1664625600000}"#), Some(now()));
+
+        config.payload_field = Some("/meta/ts".into());
+        assert_eq!(
+            config.extract(br#"{"meta": {"ts": 1664625600000}}"#),
+            Some(now())
+        );
+    }
+
+    #[test]
+    fn test_not_configured() {
+        let options = apply(&TimestampConfig::default(), OUT_OF_THRESHOLD).unwrap();
+        assert_eq!(options.time, None);
+        assert!(options.extensions.is_empty());
+    }
+
+    #[test]
+    fn test_in_threshold() {
+        for policy in [SkewPolicy::Reject, SkewPolicy::Correct, SkewPolicy::Tag] {
+            let options = apply(&config(policy), IN_THRESHOLD).unwrap();
+            assert_eq!(options.time, None, "Policy: {policy:?}");
+            assert!(options.extensions.is_empty(), "Policy: {policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_reject() {
+        assert!(matches!(
+            apply(&config(SkewPolicy::Reject), OUT_OF_THRESHOLD),
+            Err(EndpointError::TimestampSkewed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_correct() {
+        let options = apply(&config(SkewPolicy::Correct), OUT_OF_THRESHOLD).unwrap();
+        assert_eq!(options.time, Some(now()));
+        assert_eq!(
+            options.extensions.get(EXT_DEVICE_TIME).map(String::as_str),
+            Some("2022-10-01T11:50:00+00:00")
+        );
+    }
+
+    #[test]
+    fn test_tag() {
+        let options = apply(&config(SkewPolicy::Tag), OUT_OF_THRESHOLD).unwrap();
+        assert_eq!(options.time, None);
+        assert_eq!(
+            options.extensions.get(EXT_TIME_SKEW).map(String::as_str),
+            Some("-600000")
+        );
+    }
+}
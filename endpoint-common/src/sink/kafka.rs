@@ -7,15 +7,21 @@ use cloudevents::{
     AttributesReader,
 };
 use drogue_client::{core, registry, Translator};
-use drogue_cloud_service_api::kafka::{KafkaClientConfig, KafkaConfigExt, KafkaEventType};
+use drogue_cloud_service_api::{
+    health::{HealthCheckError, HealthChecked},
+    kafka::{KafkaClientConfig, KafkaConfigExt, KafkaEventType, TopicNaming},
+};
 use drogue_cloud_service_common::config::ConfigFromEnv;
 use futures::channel::oneshot;
 use rdkafka::{
+    admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
+    client::DefaultClientContext,
     error::{KafkaError, RDKafkaErrorCode},
-    producer::{FutureProducer, FutureRecord},
+    producer::{BaseProducer, FutureProducer, FutureRecord, Producer},
     ClientConfig,
 };
-use std::fmt::Formatter;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Formatter, time::Duration};
 use thiserror::Error;
 use tracing::instrument;
 
@@ -29,16 +35,102 @@ pub enum KafkaSinkError {
     Canceled,
 }
 
+impl SinkError {
+    /// Check if a failed send might succeed, when being retried.
+    ///
+    /// This is only the case for transient failures of the Kafka cluster, like a leader election.
+    ///
+    /// Timeouts are not retried, as the record might have been written nevertheless, and sending
+    /// it again would create a duplicate.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Transport(err) => match err.downcast_ref::<KafkaError>() {
+                Some(err) => matches!(
+                    err.rdkafka_error_code(),
+                    Some(
+                        RDKafkaErrorCode::AllBrokersDown
+                            | RDKafkaErrorCode::BrokerTransportFailure
+                            | RDKafkaErrorCode::BrokerNotAvailable
+                            | RDKafkaErrorCode::LeaderNotAvailable
+                            | RDKafkaErrorCode::NotLeaderForPartition
+                            | RDKafkaErrorCode::NetworkException
+                            | RDKafkaErrorCode::NotEnoughReplicas
+                            | RDKafkaErrorCode::NotEnoughReplicasAfterAppend
+                    )
+                ),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Reporting the endpoint as not ready, while the Kafka cluster can't be reached.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KafkaReadinessConfig {
+    /// Check the connection to the Kafka cluster, when the readiness gets checked.
+    #[serde(default = "default_readiness_enabled")]
+    pub enabled: bool,
+    /// The maximum time to wait for the cluster to respond.
+    #[serde(default = "default_readiness_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+const fn default_readiness_enabled() -> bool {
+    true
+}
+
+const fn default_readiness_timeout() -> Duration {
+    Duration::from_secs(2)
+}
+
+impl Default for KafkaReadinessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_readiness_enabled(),
+            timeout: default_readiness_timeout(),
+        }
+    }
+}
+
+/// Checks the connection of the producer to the Kafka cluster, by requesting the cluster ID.
+#[derive(Clone)]
+pub struct KafkaReadiness {
+    producer: FutureProducer,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl HealthChecked for KafkaReadiness {
+    async fn is_ready(&self) -> Result<(), HealthCheckError> {
+        let producer = self.producer.clone();
+        let timeout = self.timeout;
+        // the request blocks until the cluster responds, or the timeout expires
+        let cluster_id =
+            tokio::task::spawn_blocking(move || producer.client().fetch_cluster_id(timeout)).await;
+
+        match cluster_id {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => HealthCheckError::nok("Unable to reach the Kafka cluster"),
+            Err(err) => HealthCheckError::nok(format!("Failed to check the Kafka cluster: {err}")),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct KafkaSink {
     internal_producer: FutureProducer,
     check_ready: bool,
+    routing: RoutingConfig,
+    naming: TopicNaming,
 }
 
 impl Debug for KafkaSink {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("KafkaSink")
             .field("check_ready", &self.check_ready)
+            .field("routing", &self.routing)
+            .field("naming", &self.naming)
             .finish()
     }
 }
@@ -57,6 +149,30 @@ impl KafkaSink {
         Ok(Self {
             internal_producer: kafka_config.create()?,
             check_ready,
+            routing: Default::default(),
+            naming: Default::default(),
+        })
+    }
+
+    /// Name the topics of applications, must match the naming of the topic operator.
+    pub fn with_topic_naming(mut self, naming: TopicNaming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Route events to topics, based on their channel.
+    pub fn with_routing(mut self, routing: RoutingConfig) -> Self {
+        self.routing = routing;
+        self
+    }
+
+    /// The readiness check of the connection to the Kafka cluster.
+    ///
+    /// Returns [`None`] if the readiness doesn't depend on the connection.
+    pub fn readiness(&self, config: &KafkaReadinessConfig) -> Option<KafkaReadiness> {
+        config.enabled.then(|| KafkaReadiness {
+            producer: self.internal_producer.clone(),
+            timeout: config.timeout,
         })
     }
 
@@ -142,6 +258,109 @@ impl KafkaSink {
     }
 }
 
+/// Create the routed topics, which don't exist yet.
+///
+/// The settings of the routes are validated against the cluster first. Existing topics are not
+/// changed, but a warning is logged if they have fewer partitions than configured.
+pub async fn create_routed_topics(
+    config: KafkaClientConfig,
+    routing: &RoutingConfig,
+) -> anyhow::Result<()> {
+    let config: ClientConfig = config.into();
+
+    let (brokers, existing) = {
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || -> Result<_, KafkaError> {
+            let producer: BaseProducer = config.create()?;
+            let metadata = producer
+                .client()
+                .fetch_metadata(None, Duration::from_secs(10))?;
+            let existing = metadata
+                .topics()
+                .iter()
+                .map(|topic| (topic.name().to_string(), topic.partitions().len()))
+                .collect::<BTreeMap<_, _>>();
+            Ok((metadata.brokers().len(), existing))
+        })
+        .await??
+    };
+
+    let topics = routing.topics(brokers)?;
+    let missing = missing_topics(&topics, &existing);
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let admin: AdminClient<DefaultClientContext> = config.create()?;
+    for result in admin
+        .create_topics(&missing, &AdminOptions::default())
+        .await?
+    {
+        match result {
+            Ok(topic) => log::info!("Created routed topic: {topic}"),
+            Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {}
+            Err((topic, code)) => {
+                anyhow::bail!("Failed to create routed topic '{topic}': {code}")
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Evaluate the routed topics which need to be created.
+fn missing_topics<'t>(
+    topics: &'t [RoutedTopic],
+    existing: &BTreeMap<String, usize>,
+) -> Vec<NewTopic<'t>> {
+    topics
+        .iter()
+        .filter(|topic| match existing.get(&topic.topic) {
+            Some(&actual) => {
+                if let Some(partitions) = topic.partitions {
+                    if (partitions as usize) > actual {
+                        log::warn!(
+                            "Routed topic '{}' has {actual} partitions, but {partitions} are configured",
+                            topic.topic
+                        );
+                    }
+                }
+                false
+            }
+            None => true,
+        })
+        .map(|topic| {
+            // -1 uses the default of the cluster
+            NewTopic::new(
+                &topic.topic,
+                topic.partitions.unwrap_or(-1),
+                TopicReplication::Fixed(topic.replicas.unwrap_or(-1)),
+            )
+        })
+        .collect()
+}
+
+impl KafkaSink {
+    /// The topic to publish an event to.
+    fn topic(&self, target: &SinkTarget<'_>, event: &Event) -> Result<String, SinkError> {
+        match target {
+            SinkTarget::Commands(app) => app.kafka_topic(&self.naming, KafkaEventType::Commands),
+            SinkTarget::Topic(_, topic) => Ok(topic.to_string()),
+            SinkTarget::Events(app) => {
+                match self
+                    .routing
+                    .route(event.subject().unwrap_or_default())
+                    .map_err(|err| SinkError::Target(Box::new(err)))?
+                {
+                    Some(topic) => Ok(topic.to_string()),
+                    None => app.kafka_topic(&self.naming, KafkaEventType::Events),
+                }
+            }
+        }
+        .map_err(|err| SinkError::Target(Box::new(err)))
+    }
+}
+
 #[async_trait]
 impl Sink for KafkaSink {
     #[allow(clippy::needless_lifetimes)]
@@ -155,16 +374,13 @@ impl Sink for KafkaSink {
         target: SinkTarget<'a>,
         event: Event,
     ) -> Result<PublishOutcome, SinkError> {
-        if !self.check_ready(&target) {
+        // the readiness of the application's topics doesn't matter for an explicit topic
+        if !matches!(target, SinkTarget::Topic(..)) && !self.check_ready(&target) {
             log::debug!("Kafka topic is not ready yet");
             return Err(SinkError::Transport(Box::new(KafkaSinkError::NotReady)));
         }
 
-        let topic = match target {
-            SinkTarget::Commands(app) => app.kafka_topic(KafkaEventType::Commands),
-            SinkTarget::Events(app) => app.kafka_topic(KafkaEventType::Events),
-        }
-        .map_err(|err| SinkError::Target(Box::new(err)))?;
+        let topic = self.topic(&target, &event)?;
 
         let key = match event.extension(crate::EXT_PARTITIONKEY) {
             Some(ExtensionValue::String(key)) => key,
@@ -184,6 +400,8 @@ impl Sink for KafkaSink {
 mod test {
 
     use super::*;
+    use crate::command::KafkaCommandSourceConfig;
+    use cloudevents::{EventBuilder, EventBuilderV10};
     use drogue_client::core::v1::Conditions;
 
     #[test]
@@ -200,4 +418,122 @@ mod test {
 
         assert!(KafkaSink::is_ready(&app));
     }
+
+    #[test]
+    fn test_topic_naming() {
+        let naming = TopicNaming {
+            prefix: "staging-".into(),
+            template: None,
+        };
+        let sink = KafkaSink::from_config(Default::default(), false)
+            .unwrap()
+            .with_topic_naming(naming.clone());
+        let mut app = registry::v1::Application::default();
+        app.metadata.name = "app1".into();
+        let event = EventBuilderV10::new()
+            .id("1")
+            .source("drogue://app1/device1")
+            .ty("io.drogue.event.v1")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            sink.topic(&SinkTarget::Events(&app), &event).unwrap(),
+            "staging-events-app1"
+        );
+        assert_eq!(
+            sink.topic(&SinkTarget::Commands(&app), &event).unwrap(),
+            "staging-iot-commands"
+        );
+        // the command sources consume the topic the commands are published to
+        let source = KafkaCommandSourceConfig {
+            topic: None,
+            consumer_group: "endpoint".into(),
+        };
+        assert_eq!(source.topic(&naming), "staging-iot-commands");
+        assert_eq!(
+            sink.topic(&SinkTarget::Topic(&app, "copies"), &event)
+                .unwrap(),
+            "copies"
+        );
+    }
+
+    #[test]
+    fn test_retryable() {
+        let transport = |err: KafkaError| SinkError::Transport(Box::new(err)).is_retryable();
+
+        assert!(transport(KafkaError::MessageProduction(
+            RDKafkaErrorCode::LeaderNotAvailable
+        )));
+        // might have been written
+        assert!(!transport(KafkaError::MessageProduction(
+            RDKafkaErrorCode::MessageTimedOut
+        )));
+        assert!(!transport(KafkaError::MessageProduction(
+            RDKafkaErrorCode::RequestTimedOut
+        )));
+        assert!(!transport(KafkaError::MessageProduction(
+            RDKafkaErrorCode::MessageSizeTooLarge
+        )));
+        assert!(!transport(KafkaError::MessageProduction(
+            RDKafkaErrorCode::TopicAuthorizationFailed
+        )));
+        assert!(!SinkError::Transport(Box::new(KafkaSinkError::NotReady)).is_retryable());
+    }
+
+    #[test]
+    fn test_readiness_config() {
+        let config: KafkaReadinessConfig = serde_json::from_str(r#"{"timeout": "5s"}"#).unwrap();
+
+        assert_eq!(
+            config,
+            KafkaReadinessConfig {
+                enabled: true,
+                timeout: Duration::from_secs(5),
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_topics() {
+        let routing = RoutingConfig {
+            routes: vec![
+                Route {
+                    channel: "telemetry".into(),
+                    topic: "high-volume".into(),
+                    partitions: Some(24),
+                    replicas: Some(3),
+                },
+                Route {
+                    channel: "state".into(),
+                    topic: "low-volume".into(),
+                    partitions: Some(1),
+                    replicas: None,
+                },
+                Route {
+                    channel: "logs".into(),
+                    topic: "existing".into(),
+                    partitions: Some(6),
+                    replicas: None,
+                },
+            ],
+            create_topics: true,
+            ..Default::default()
+        };
+        let topics = routing.topics(3).unwrap();
+        let existing = BTreeMap::from([("existing".to_string(), 3)]);
+
+        let missing = missing_topics(&topics, &existing)
+            .into_iter()
+            .map(|topic| {
+                let replicas = match topic.replication {
+                    TopicReplication::Fixed(replicas) => replicas,
+                    TopicReplication::Variable(_) => unreachable!(),
+                };
+                (topic.name, topic.num_partitions, replicas)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(missing, vec![("high-volume", 24, 3), ("low-volume", 1, -1)]);
+    }
 }
@@ -1,8 +1,10 @@
 mod http;
 mod kafka;
+mod route;
 
 pub use self::http::HttpSink;
 pub use kafka::*;
+pub use route::*;
 
 use crate::sender::PublishOutcome;
 use async_trait::async_trait;
@@ -15,6 +17,8 @@ use thiserror::Error;
 pub enum SinkTarget<'a> {
     Events(&'a registry::v1::Application),
     Commands(&'a registry::v1::Application),
+    /// An explicit topic, outside of the application, e.g. for copies of events.
+    Topic(&'a registry::v1::Application, &'a str),
 }
 
 impl<'a> Deref for SinkTarget<'a> {
@@ -24,6 +28,7 @@ impl<'a> Deref for SinkTarget<'a> {
         match self {
             SinkTarget::Commands(app) => app,
             SinkTarget::Events(app) => app,
+            SinkTarget::Topic(app, _) => app,
         }
     }
 }
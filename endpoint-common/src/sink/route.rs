@@ -0,0 +1,306 @@
+use crate::sender::channel_matches;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter, IntCounter};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+lazy_static! {
+    pub static ref ROUTING_FALLBACK_COUNTER: IntCounter = register_int_counter!(
+        "drogue_routing_fallback_events",
+        "Events sent to the fallback topic, as no route matched their channel"
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Error)]
+pub enum RoutingError {
+    #[error("No route for channel '{channel}'")]
+    Unroutable { channel: String },
+    #[error("Conflicting settings of routed topic '{topic}'")]
+    ConflictingSettings { topic: String },
+    #[error("Invalid settings of routed topic '{topic}': {reason}")]
+    InvalidSettings { topic: String, reason: String },
+}
+
+/// A route, sending the events of matching channels to a topic.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Route {
+    /// The channel to match.
+    ///
+    /// A channel matches if it is equal to this value, or if this value ends with a `*` and the
+    /// channel starts with the part before the `*`.
+    pub channel: String,
+    /// The topic to send the events to.
+    pub topic: String,
+    /// The number of partitions, when creating the topic.
+    ///
+    /// Uses the default of the cluster if missing.
+    #[serde(default)]
+    pub partitions: Option<i32>,
+    /// The number of replicas, when creating the topic.
+    ///
+    /// Uses the default of the cluster if missing.
+    #[serde(default)]
+    pub replicas: Option<i32>,
+}
+
+/// A topic, used by the routing, and the settings to create it with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoutedTopic {
+    pub topic: String,
+    pub partitions: Option<i32>,
+    pub replicas: Option<i32>,
+}
+
+impl RoutedTopic {
+    /// Merge the settings of another route to the same topic.
+    ///
+    /// Routes may omit the settings, but must not declare different ones.
+    fn merge(&mut self, route: &Route) -> Result<(), RoutingError> {
+        fn merge(current: &mut Option<i32>, other: Option<i32>) -> Result<(), ()> {
+            match (*current, other) {
+                (Some(current), Some(other)) if current != other => Err(()),
+                (None, other) => {
+                    *current = other;
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        }
+
+        merge(&mut self.partitions, route.partitions)
+            .and_then(|_| merge(&mut self.replicas, route.replicas))
+            .map_err(|_| RoutingError::ConflictingSettings {
+                topic: self.topic.clone(),
+            })
+    }
+
+    /// Validate the settings against the constraints of the cluster.
+    fn validate(&self, brokers: usize) -> Result<(), RoutingError> {
+        let invalid = |reason: String| {
+            Err(RoutingError::InvalidSettings {
+                topic: self.topic.clone(),
+                reason,
+            })
+        };
+
+        match (self.partitions, self.replicas) {
+            (Some(partitions), _) if partitions < 1 => {
+                invalid(format!("partitions must be at least 1, was {partitions}"))
+            }
+            (_, Some(replicas)) if replicas < 1 => {
+                invalid(format!("replicas must be at least 1, was {replicas}"))
+            }
+            (_, Some(replicas)) if replicas as usize > brokers => invalid(format!(
+                "replicas ({replicas}) exceed the number of brokers ({brokers})"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Channel based routing of events to topics.
+///
+/// If no routes are configured, events are sent to the topic of their application. Otherwise,
+/// the first route matching the channel of the event is used. Events of channels not matching any
+/// route are sent to the fallback topic, or rejected if no fallback topic is configured.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RoutingConfig {
+    /// The routes to evaluate, in order.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    /// The topic for events of channels not matching any route.
+    #[serde(default)]
+    pub fallback_topic: Option<String>,
+    /// Create missing routed topics on startup, using the settings of their routes.
+    #[serde(default)]
+    pub create_topics: bool,
+}
+
+impl RoutingConfig {
+    /// Find the topic of a channel.
+    ///
+    /// Returns [`None`] if routing is not enabled, in which case the default topic must be used.
+    pub fn route(&self, channel: &str) -> Result<Option<&str>, RoutingError> {
+        if self.routes.is_empty() {
+            return Ok(None);
+        }
+
+        if let Some(route) = self
+            .routes
+            .iter()
+            .find(|route| channel_matches(&route.channel, channel))
+        {
+            return Ok(Some(&route.topic));
+        }
+
+        match &self.fallback_topic {
+            Some(topic) => {
+                log::debug!("No route for channel '{channel}', using fallback topic");
+                ROUTING_FALLBACK_COUNTER.inc();
+                Ok(Some(topic))
+            }
+            None => Err(RoutingError::Unroutable {
+                channel: channel.to_string(),
+            }),
+        }
+    }
+
+    /// Collect the routed topics, including the fallback topic.
+    ///
+    /// The settings are validated against the number of brokers of the cluster.
+    pub fn topics(&self, brokers: usize) -> Result<Vec<RoutedTopic>, RoutingError> {
+        let mut topics = Vec::<RoutedTopic>::new();
+
+        let fallback = self.fallback_topic.iter().map(|topic| Route {
+            topic: topic.clone(),
+            ..Default::default()
+        });
+
+        for route in self.routes.iter().cloned().chain(fallback) {
+            match topics.iter_mut().find(|topic| topic.topic == route.topic) {
+                Some(topic) => topic.merge(&route)?,
+                None => topics.push(RoutedTopic {
+                    topic: route.topic,
+                    partitions: route.partitions,
+                    replicas: route.replicas,
+                }),
+            }
+        }
+
+        for topic in &topics {
+            topic.validate(brokers)?;
+        }
+
+        Ok(topics)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(fallback_topic: Option<&str>) -> RoutingConfig {
+        RoutingConfig {
+            routes: vec![
+                Route {
+                    channel: "alarm/*".into(),
+                    topic: "alarms".into(),
+                    ..Default::default()
+                },
+                Route {
+                    channel: "telemetry".into(),
+                    topic: "telemetry".into(),
+                    ..Default::default()
+                },
+            ],
+            fallback_topic: fallback_topic.map(Into::into),
+            ..Default::default()
+        }
+    }
+
+    fn route(channel: &str, topic: &str, partitions: Option<i32>, replicas: Option<i32>) -> Route {
+        Route {
+            channel: channel.into(),
+            topic: topic.into(),
+            partitions,
+            replicas,
+        }
+    }
+
+    #[test]
+    fn test_disabled() {
+        assert_eq!(RoutingConfig::default().route("foo").unwrap(), None);
+    }
+
+    #[test]
+    fn test_routed() {
+        let config = config(Some("unroutable"));
+        assert_eq!(config.route("alarm/fire").unwrap(), Some("alarms"));
+        assert_eq!(config.route("telemetry").unwrap(), Some("telemetry"));
+    }
+
+    #[test]
+    fn test_fallback() {
+        let config = config(Some("unroutable"));
+
+        let before = ROUTING_FALLBACK_COUNTER.get();
+        assert_eq!(config.route("state").unwrap(), Some("unroutable"));
+        assert!(ROUTING_FALLBACK_COUNTER.get() > before);
+    }
+
+    #[test]
+    fn test_no_fallback() {
+        assert!(matches!(
+            config(None).route("state"),
+            Err(RoutingError::Unroutable { channel }) if channel == "state"
+        ));
+    }
+
+    #[test]
+    fn test_topics() {
+        let config = RoutingConfig {
+            routes: vec![
+                route("telemetry/*", "telemetry", Some(12), Some(3)),
+                route("state", "state", Some(1), None),
+                // same topic, without settings
+                route("telemetry", "telemetry", None, None),
+            ],
+            fallback_topic: Some("unroutable".into()),
+            create_topics: true,
+        };
+
+        assert_eq!(
+            config.topics(3).unwrap(),
+            vec![
+                RoutedTopic {
+                    topic: "telemetry".into(),
+                    partitions: Some(12),
+                    replicas: Some(3),
+                },
+                RoutedTopic {
+                    topic: "state".into(),
+                    partitions: Some(1),
+                    replicas: None,
+                },
+                RoutedTopic {
+                    topic: "unroutable".into(),
+                    partitions: None,
+                    replicas: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_topics_conflicting() {
+        let config = RoutingConfig {
+            routes: vec![
+                route("a", "telemetry", Some(12), None),
+                route("b", "telemetry", Some(6), None),
+            ],
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            config.topics(3),
+            Err(RoutingError::ConflictingSettings { topic }) if topic == "telemetry"
+        ));
+    }
+
+    #[test]
+    fn test_topics_invalid() {
+        let config = |partitions, replicas| RoutingConfig {
+            routes: vec![route("a", "telemetry", partitions, replicas)],
+            ..Default::default()
+        };
+
+        assert!(config(Some(3), Some(3)).topics(3).is_ok());
+        for (partitions, replicas) in [(Some(0), None), (None, Some(0)), (None, Some(4))] {
+            assert!(matches!(
+                config(partitions, replicas).topics(3),
+                Err(RoutingError::InvalidSettings { .. })
+            ));
+        }
+    }
+}
@@ -333,6 +333,11 @@ impl<'s, T> Handle<'s, T> {
             msg: self.msg,
         })
     }
+
+    /// The Kafka message, the event was read from.
+    pub fn message(&self) -> &BorrowedMessage<'s> {
+        &self.msg
+    }
 }
 
 impl<T> Deref for Handle<'_, T> {
@@ -377,10 +382,15 @@ impl Stream for EventStream<'_, AutoAck> {
     }
 }
 
-impl<'s> Stream for EventStream<'s, CustomAck> {
-    type Item = Result<Handle<'s, Event>, EventStreamError>;
-
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+impl<'s> EventStream<'s, CustomAck> {
+    /// Poll the next message, keeping it when it can't be converted into an event.
+    ///
+    /// This allows to acknowledge messages which are not a valid cloud event, instead of failing
+    /// on them over and over again.
+    pub fn poll_next_message(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Handle<'s, Result<Event, EventStreamError>>, EventStreamError>>> {
         let next = self.upstream.poll_next_unpin(cx);
 
         match next {
@@ -394,8 +404,10 @@ impl<'s> Stream for EventStream<'s, CustomAck> {
                         msg.partition(),
                         msg.offset()
                     );
-                    let event = msg.to_event()?;
-                    let event = fixup_data_type(event);
+                    let event = msg
+                        .to_event()
+                        .map(fixup_data_type)
+                        .map_err(EventStreamError::from);
 
                     let event = Handle { event, msg };
                     Poll::Ready(Some(Ok(event)))
@@ -405,6 +417,15 @@ impl<'s> Stream for EventStream<'s, CustomAck> {
     }
 }
 
+impl<'s> Stream for EventStream<'s, CustomAck> {
+    type Item = Result<Handle<'s, Event>, EventStreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_next_message(cx)
+            .map(|next| next.map(|handle| handle?.try_map(|event| event)))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,450 @@
+use drogue_client::registry;
+use drogue_cloud_endpoint_common::sender::{RateLimit, RateLimitConfig, RateLimiter};
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+lazy_static! {
+    pub static ref ACK_WEBHOOK_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "drogue_ack_webhook",
+        "Acknowledgements sent to webhooks",
+        &["outcome"],
+    )
+    .unwrap();
+}
+
+/// Annotation of the application, overriding the webhook URL of its devices.
+pub const ANNOTATION_ACK_WEBHOOK: &str = "drogue.io/ack-webhook";
+
+/// Notifying a webhook, once the message of a device was accepted downstream.
+///
+/// The webhook is called best-effort, after the message was accepted. Failing to call it never
+/// fails or delays the publish request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AckWebhookConfig {
+    /// The default URL of the webhook.
+    ///
+    /// Applications can override this, using the `drogue.io/ack-webhook` annotation, if enabled. If
+    /// neither is set, no acknowledgements are sent.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Allow applications to override the URL of the webhook.
+    ///
+    /// The URL of the annotation must start with one of the `allowed_urls`, otherwise it is
+    /// ignored.
+    #[serde(default)]
+    pub allow_override: bool,
+    /// The URL prefixes, which applications may use for their webhook.
+    ///
+    /// A URL is allowed, if scheme, host and port match, and its path starts with the path of
+    /// the prefix.
+    #[serde(default)]
+    pub allowed_urls: Vec<String>,
+    /// The timeout of a webhook call.
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+    /// The maximum rate of webhook calls per device.
+    ///
+    /// Acknowledgements exceeding the rate are dropped.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+}
+
+const fn default_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl Default for AckWebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            allow_override: false,
+            allowed_urls: vec![],
+            timeout: default_timeout(),
+            rate_limit: None,
+        }
+    }
+}
+
+/// The acknowledgement, posted to the webhook.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Ack {
+    /// The ID of the message.
+    pub id: String,
+    pub application: String,
+    pub device: String,
+    pub channel: String,
+    /// The outcome of publishing the message.
+    pub outcome: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct AckWebhook {
+    config: AckWebhookConfig,
+    client: reqwest::Client,
+    limiter: RateLimiter,
+}
+
+impl Default for AckWebhook {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl AckWebhook {
+    pub fn new(config: AckWebhookConfig) -> Self {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            device: config.rate_limit,
+            ..Default::default()
+        });
+
+        // never follow redirects, which could lead to a URL we didn't allow
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to create the webhook client");
+
+        Self {
+            config,
+            client,
+            limiter,
+        }
+    }
+
+    /// The webhook URL for the devices of an application.
+    fn url<'a>(&'a self, application: &'a registry::v1::Application) -> Option<&'a str> {
+        match application.metadata.annotations.get(ANNOTATION_ACK_WEBHOOK) {
+            Some(url) if self.is_allowed(url) => Some(url),
+            Some(url) => {
+                log::debug!(
+                    "Ignoring webhook URL of application '{}': {}",
+                    application.metadata.name,
+                    url
+                );
+                self.config.url.as_deref()
+            }
+            None => self.config.url.as_deref(),
+        }
+    }
+
+    /// Check if an application may use the URL for its webhook.
+    fn is_allowed(&self, url: &str) -> bool {
+        if !self.config.allow_override {
+            return false;
+        }
+
+        let url = match reqwest::Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+
+        self.config
+            .allowed_urls
+            .iter()
+            .filter_map(|prefix| reqwest::Url::parse(prefix).ok())
+            .any(|prefix| {
+                url.scheme() == prefix.scheme()
+                    && url.host_str() == prefix.host_str()
+                    && url.port_or_known_default() == prefix.port_or_known_default()
+                    && url.path().starts_with(prefix.path())
+            })
+    }
+
+    /// Notify the webhook of an accepted message, without waiting for the outcome.
+    ///
+    /// Returns the handle of the call, or [`None`] if no call is made.
+    pub fn notify(
+        &self,
+        application: &registry::v1::Application,
+        device: &str,
+        channel: &str,
+        id: &str,
+    ) -> Option<JoinHandle<bool>> {
+        let url = self.url(application)?.to_string();
+
+        if self
+            .limiter
+            .check(&application.metadata.name, device)
+            .is_err()
+        {
+            ACK_WEBHOOK_COUNTER
+                .with_label_values(&["RateLimited"])
+                .inc();
+            return None;
+        }
+
+        let ack = Ack {
+            id: id.to_string(),
+            application: application.metadata.name.clone(),
+            device: device.to_string(),
+            channel: channel.to_string(),
+            outcome: "Accepted".to_string(),
+        };
+
+        let request = self
+            .client
+            .post(url)
+            .timeout(self.config.timeout)
+            .json(&ack);
+
+        Some(tokio::spawn(async move {
+            let result = request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) => {
+                    ACK_WEBHOOK_COUNTER.with_label_values(&["Sent"]).inc();
+                    true
+                }
+                Err(err) => {
+                    log::info!("Failed to send acknowledgement of '{}': {}", ack.id, err);
+                    ACK_WEBHOOK_COUNTER.with_label_values(&["Failed"]).inc();
+                    false
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_client::meta::v1::NonScopedMetadata;
+    use drogue_cloud_service_api::webapp::{web, App, HttpResponse, HttpServer};
+    use std::{
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    fn application(annotations: &[(&str, &str)]) -> registry::v1::Application {
+        registry::v1::Application {
+            metadata: NonScopedMetadata {
+                name: "app1".into(),
+                annotations: annotations
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn free_addr() -> std::net::SocketAddr {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_url() {
+        let webhook = AckWebhook::new(AckWebhookConfig {
+            url: Some("http://default".into()),
+            allow_override: true,
+            allowed_urls: vec!["https://hooks.example.com/acks/".into()],
+            ..Default::default()
+        });
+
+        assert_eq!(webhook.url(&application(&[])), Some("http://default"));
+        assert_eq!(
+            webhook.url(&application(&[(
+                ANNOTATION_ACK_WEBHOOK,
+                "https://hooks.example.com/acks/app1"
+            )])),
+            Some("https://hooks.example.com/acks/app1")
+        );
+        assert_eq!(AckWebhook::default().url(&application(&[])), None);
+    }
+
+    #[test]
+    fn test_url_not_allowed() {
+        let webhook = AckWebhook::new(AckWebhookConfig {
+            url: Some("http://default".into()),
+            allow_override: true,
+            allowed_urls: vec!["https://hooks.example.com/acks/".into()],
+            ..Default::default()
+        });
+
+        for url in [
+            "http://169.254.169.254/latest/meta-data",
+            "http://hooks.example.com/acks/app1",
+            "https://hooks.example.com.evil.com/acks/app1",
+            "https://hooks.example.com:8443/acks/app1",
+            "https://hooks.example.com/other",
+            "not a url",
+        ] {
+            assert_eq!(
+                webhook.url(&application(&[(ANNOTATION_ACK_WEBHOOK, url)])),
+                Some("http://default"),
+                "{url}"
+            );
+        }
+
+        // not enabled, even if allowed
+        let webhook = AckWebhook::new(AckWebhookConfig {
+            allowed_urls: vec!["https://hooks.example.com/acks/".into()],
+            ..Default::default()
+        });
+        assert_eq!(
+            webhook.url(&application(&[(
+                ANNOTATION_ACK_WEBHOOK,
+                "https://hooks.example.com/acks/app1"
+            )])),
+            None
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_fire() {
+        let addr = free_addr();
+        let received = Arc::new(Mutex::new(Vec::<Ack>::new()));
+
+        let server = HttpServer::new({
+            let received = received.clone();
+            move || {
+                let received = received.clone();
+                App::new().route(
+                    "/ack",
+                    web::post().to(move |ack: web::Json<Ack>| {
+                        received.lock().unwrap().push(ack.into_inner());
+                        async { HttpResponse::NoContent() }
+                    }),
+                )
+            }
+        })
+        .bind(addr)
+        .unwrap()
+        .run();
+        let handle = server.handle();
+        actix_rt::spawn(server);
+
+        let webhook = AckWebhook::new(AckWebhookConfig {
+            url: Some(format!("http://{addr}/ack")),
+            ..Default::default()
+        });
+        let sent = webhook
+            .notify(&application(&[]), "device1", "temperature", "msg1")
+            .unwrap()
+            .await
+            .unwrap();
+
+        assert!(sent);
+        assert_eq!(
+            received.lock().unwrap().as_slice(),
+            &[Ack {
+                id: "msg1".into(),
+                application: "app1".into(),
+                device: "device1".into(),
+                channel: "temperature".into(),
+                outcome: "Accepted".into(),
+            }]
+        );
+
+        handle.stop(true).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_no_redirect() {
+        let addr = free_addr();
+        let redirected = Arc::new(Mutex::new(false));
+
+        let server = HttpServer::new({
+            let redirected = redirected.clone();
+            move || {
+                let redirected = redirected.clone();
+                App::new()
+                    .route(
+                        "/ack",
+                        web::post().to(|| async {
+                            HttpResponse::TemporaryRedirect()
+                                .insert_header(("Location", "/internal"))
+                                .finish()
+                        }),
+                    )
+                    .route(
+                        "/internal",
+                        web::post().to(move || {
+                            *redirected.lock().unwrap() = true;
+                            async { HttpResponse::NoContent() }
+                        }),
+                    )
+            }
+        })
+        .bind(addr)
+        .unwrap()
+        .run();
+        let handle = server.handle();
+        actix_rt::spawn(server);
+
+        let webhook = AckWebhook::new(AckWebhookConfig {
+            url: Some(format!("http://{addr}/ack")),
+            ..Default::default()
+        });
+        let sent = webhook
+            .notify(&application(&[]), "device1", "temperature", "msg1")
+            .unwrap()
+            .await
+            .unwrap();
+
+        // the redirect is not followed, and counts as a failure
+        assert!(!sent);
+        assert!(!*redirected.lock().unwrap());
+
+        handle.stop(true).await;
+    }
+
+    #[tokio::test]
+    async fn test_failure_does_not_block() {
+        // a server accepting connections, but never responding
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _server = tokio::spawn(async move {
+            let mut connections = vec![];
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+
+        let webhook = AckWebhook::new(AckWebhookConfig {
+            url: Some(format!("http://{addr}/ack")),
+            timeout: Duration::from_millis(200),
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        let call = webhook
+            .notify(&application(&[]), "device1", "temperature", "msg1")
+            .unwrap();
+        // returns right away
+        assert!(start.elapsed() < Duration::from_millis(200));
+
+        // the call fails in the background
+        assert!(!call.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited() {
+        let webhook = AckWebhook::new(AckWebhookConfig {
+            // nothing listens here, failing right away
+            url: Some(format!("http://{}/ack", free_addr())),
+            rate_limit: Some(RateLimit { rate: 1, burst: 1 }),
+            ..Default::default()
+        });
+        let app = application(&[]);
+
+        assert!(webhook
+            .notify(&app, "device1", "temperature", "1")
+            .is_some());
+        assert!(webhook
+            .notify(&app, "device1", "temperature", "2")
+            .is_none());
+        // other devices have their own limit
+        assert!(webhook
+            .notify(&app, "device2", "temperature", "3")
+            .is_some());
+    }
+}
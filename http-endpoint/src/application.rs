@@ -0,0 +1,262 @@
+use async_trait::async_trait;
+use drogue_client::{error::ClientError, registry};
+use drogue_cloud_endpoint_common::error::EndpointError;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::{
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// How to handle publishing for applications which are not known to the registry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ApplicationNotFoundMode {
+    /// Don't check, and forward to the downstream topic.
+    #[default]
+    Permissive,
+    /// Look up the application, and reject the request if it doesn't exist.
+    ///
+    /// The request is rejected like a failed authentication, so that callers can't probe for
+    /// the names of existing applications.
+    Verify,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApplicationCheckConfig {
+    #[serde(default)]
+    pub mode: ApplicationNotFoundMode,
+
+    #[serde(default = "default_cache_size")]
+    pub cache_size: NonZeroUsize,
+
+    /// How long to remember an application which was found.
+    #[serde(default = "default_found_ttl", with = "humantime_serde")]
+    pub found_ttl: Duration,
+
+    /// How long to remember an application which was not found.
+    #[serde(default = "default_not_found_ttl", with = "humantime_serde")]
+    pub not_found_ttl: Duration,
+}
+
+const fn default_cache_size() -> NonZeroUsize {
+    unsafe { NonZeroUsize::new_unchecked(1024) }
+}
+
+const fn default_found_ttl() -> Duration {
+    Duration::from_secs(60)
+}
+
+const fn default_not_found_ttl() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl Default for ApplicationCheckConfig {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            cache_size: default_cache_size(),
+            found_ttl: default_found_ttl(),
+            not_found_ttl: default_not_found_ttl(),
+        }
+    }
+}
+
+/// Look up if an application exists.
+#[async_trait]
+pub trait ApplicationLookup: Send + Sync {
+    async fn exists(&self, application: &str) -> Result<bool, ClientError>;
+}
+
+#[async_trait]
+impl ApplicationLookup for registry::v1::Client {
+    async fn exists(&self, application: &str) -> Result<bool, ClientError> {
+        Ok(self.get_app(application).await?.is_some())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CacheEntry {
+    exists: bool,
+    expires: Instant,
+}
+
+/// Verify that applications exist, before publishing for them.
+#[derive(Clone)]
+pub struct ApplicationVerifier {
+    config: ApplicationCheckConfig,
+    lookup: Option<Arc<dyn ApplicationLookup>>,
+    cache: Arc<Mutex<LruCache<String, CacheEntry>>>,
+}
+
+impl ApplicationVerifier {
+    /// Create a new verifier.
+    ///
+    /// In "verify" mode, a lookup is required.
+    pub fn new(
+        config: ApplicationCheckConfig,
+        lookup: Option<Arc<dyn ApplicationLookup>>,
+    ) -> anyhow::Result<Self> {
+        if config.mode == ApplicationNotFoundMode::Verify && lookup.is_none() {
+            anyhow::bail!("Verifying applications requires access to the registry");
+        }
+
+        let cache = Arc::new(Mutex::new(LruCache::new(config.cache_size)));
+
+        Ok(Self {
+            config,
+            lookup,
+            cache,
+        })
+    }
+
+    /// Check if publishing for the application is allowed.
+    ///
+    /// Unknown applications fail with [`EndpointError::AuthenticationError`], as this runs before
+    /// the device is authenticated.
+    pub async fn verify(&self, application: &str) -> Result<(), EndpointError> {
+        let lookup = match (self.config.mode, &self.lookup) {
+            (ApplicationNotFoundMode::Verify, Some(lookup)) => lookup,
+            _ => return Ok(()),
+        };
+
+        match self.exists(lookup.as_ref(), application).await? {
+            true => Ok(()),
+            false => {
+                log::debug!(
+                    "Rejecting publish for unknown application '{}'",
+                    application
+                );
+                Err(EndpointError::AuthenticationError)
+            }
+        }
+    }
+
+    async fn exists(
+        &self,
+        lookup: &dyn ApplicationLookup,
+        application: &str,
+    ) -> Result<bool, EndpointError> {
+        let now = Instant::now();
+
+        if let Some(entry) = self.cache.lock().await.get(application) {
+            if entry.expires > now {
+                return Ok(entry.exists);
+            }
+        }
+
+        // we don't hold the lock while looking up, so concurrent requests might look up
+        // the same application, which is ok
+
+        let exists = lookup.exists(application).await.map_err(|err| {
+            log::info!("Failed to look up application '{}': {}", application, err);
+            EndpointError::from(err)
+        })?;
+
+        let ttl = match exists {
+            true => self.config.found_ttl,
+            false => self.config.not_found_ttl,
+        };
+
+        self.cache.lock().await.put(
+            application.to_string(),
+            CacheEntry {
+                exists,
+                expires: now + ttl,
+            },
+        );
+
+        Ok(exists)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct MockLookup {
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ApplicationLookup for MockLookup {
+        async fn exists(&self, application: &str) -> Result<bool, ClientError> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            Ok(application == "known")
+        }
+    }
+
+    fn verifier(mode: ApplicationNotFoundMode) -> (ApplicationVerifier, Arc<MockLookup>) {
+        let lookup = Arc::new(MockLookup::default());
+        let verifier = ApplicationVerifier::new(
+            ApplicationCheckConfig {
+                mode,
+                ..Default::default()
+            },
+            Some(lookup.clone()),
+        )
+        .unwrap();
+        (verifier, lookup)
+    }
+
+    #[tokio::test]
+    async fn test_permissive() {
+        let (verifier, lookup) = verifier(ApplicationNotFoundMode::Permissive);
+
+        assert!(verifier.verify("known").await.is_ok());
+        assert!(verifier.verify("unknown").await.is_ok());
+        assert_eq!(lookup.lookups.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let (verifier, lookup) = verifier(ApplicationNotFoundMode::Verify);
+
+        assert!(verifier.verify("known").await.is_ok());
+        assert!(matches!(
+            verifier.verify("unknown").await,
+            Err(EndpointError::AuthenticationError)
+        ));
+
+        // both outcomes must be cached
+
+        assert!(verifier.verify("known").await.is_ok());
+        assert!(verifier.verify("unknown").await.is_err());
+        assert_eq!(lookup.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_not_found_expires() {
+        let lookup = Arc::new(MockLookup::default());
+        let verifier = ApplicationVerifier::new(
+            ApplicationCheckConfig {
+                mode: ApplicationNotFoundMode::Verify,
+                not_found_ttl: Duration::ZERO,
+                ..Default::default()
+            },
+            Some(lookup.clone()),
+        )
+        .unwrap();
+
+        assert!(verifier.verify("unknown").await.is_err());
+        assert!(verifier.verify("unknown").await.is_err());
+        assert_eq!(lookup.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_verify_requires_lookup() {
+        assert!(ApplicationVerifier::new(Default::default(), None).is_ok());
+        assert!(ApplicationVerifier::new(
+            ApplicationCheckConfig {
+                mode: ApplicationNotFoundMode::Verify,
+                ..Default::default()
+            },
+            None
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,410 @@
+//! Publishing multiple messages with a single request.
+
+use crate::{
+    ack::AckWebhook,
+    downstream::HttpCommandSender,
+    payload::PayloadConfig,
+    response::ResponseConfig,
+    telemetry::{PublishAuthenticator, PublishOptions},
+};
+use drogue_cloud_endpoint_common::{
+    error::{EndpointError, HttpEndpointError},
+    psk::VerifiedIdentity,
+    sampling::TraceSampler,
+    sender::{self, DeadlineConfig, DownstreamSender, PublishIdPair},
+    x509::ClientCertificateChain,
+};
+use drogue_cloud_service_api::webapp::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+
+/// The content type of newline delimited JSON.
+const CONTENT_TYPE_NDJSON: &str = "application/x-ndjson";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BatchConfig {
+    /// Enable the batch publish route.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// The maximum number of messages in a batch.
+    #[serde(default = "default_max_messages")]
+    pub max_messages: usize,
+}
+
+const fn default_max_messages() -> usize {
+    100
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_messages: default_max_messages(),
+        }
+    }
+}
+
+/// A message of a batch.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchMessage {
+    /// The channel to publish to, defaults to the channel of the request.
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// The content type of the payload.
+    ///
+    /// Defaults to `application/json`, unless the payload is a string.
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// The payload, strings are published as they are, other values as JSON.
+    pub payload: serde_json::Value,
+}
+
+impl BatchMessage {
+    /// The content type and body to publish.
+    fn into_body(self) -> (Option<String>, Vec<u8>) {
+        match self.payload {
+            serde_json::Value::String(payload) => (self.content_type, payload.into_bytes()),
+            payload => (
+                self.content_type
+                    .or_else(|| Some("application/json".to_string())),
+                // serializing a value can't fail
+                serde_json::to_vec(&payload).unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+/// The outcome of publishing a message of a batch.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResult {
+    /// The position of the message in the batch.
+    pub index: usize,
+    /// The status code, as if the message was published on its own.
+    pub status: u16,
+    /// The ID of the message, if it was accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The reason of rejecting the message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The response to a batch publish request.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResponse {
+    pub accepted: usize,
+    pub failed: usize,
+    pub results: Vec<BatchResult>,
+}
+
+impl BatchResponse {
+    fn push(&mut self, result: BatchResult) {
+        if result.error.is_none() {
+            self.accepted += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.results.push(result);
+    }
+}
+
+/// Parse the messages of a batch, either a JSON array or newline delimited JSON.
+fn parse(
+    config: &BatchConfig,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<Vec<BatchMessage>, EndpointError> {
+    let ndjson = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|mime| mime.trim().eq_ignore_ascii_case(CONTENT_TYPE_NDJSON))
+        .unwrap_or_default();
+
+    let messages = if ndjson {
+        body.split(|b| *b == b'\n')
+            .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+            .map(serde_json::from_slice)
+            .collect::<Result<Vec<BatchMessage>, _>>()
+    } else {
+        serde_json::from_slice(body)
+    }
+    .map_err(|err| EndpointError::InvalidFormat {
+        source: Box::new(err),
+    })?;
+
+    if messages.len() > config.max_messages {
+        return Err(EndpointError::InvalidRequest {
+            details: format!(
+                "Batch exceeds the maximum of {} messages",
+                config.max_messages
+            ),
+        });
+    }
+
+    Ok(messages)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn publish(
+    downstream: web::Data<DownstreamSender>,
+    authenticator: web::Data<PublishAuthenticator>,
+    response: web::Data<ResponseConfig>,
+    ack: web::Data<AckWebhook>,
+    config: web::Data<BatchConfig>,
+    sampler: web::Data<TraceSampler>,
+    deadline: web::Data<DeadlineConfig>,
+    payload: web::Data<PayloadConfig>,
+    channel: web::Path<String>,
+    web::Query(opts): web::Query<PublishOptions>,
+    req: HttpRequest,
+    body: web::Bytes,
+    certs: Option<ClientCertificateChain>,
+    verified_identity: Option<VerifiedIdentity>,
+) -> Result<HttpResponse, HttpEndpointError> {
+    let span = sampler.span(
+        &req,
+        || tracing::info_span!("publish_batch", %channel, ?opts),
+    );
+    let backpressure = downstream.clone();
+    let request = publish_batch(
+        downstream,
+        authenticator,
+        response,
+        ack,
+        config,
+        payload,
+        channel.into_inner(),
+        opts,
+        req,
+        body,
+        certs,
+        verified_identity,
+    );
+
+    deadline
+        .run(request)
+        .instrument(span)
+        .await
+        .map_err(|err| backpressure.backpressure(err))?
+}
+
+/// Publish a batch of messages.
+///
+/// The device is authenticated once for the whole batch, while each message is checked and
+/// published on its own. A rejected message doesn't abort the remaining ones.
+#[allow(clippy::too_many_arguments)]
+async fn publish_batch(
+    downstream: web::Data<DownstreamSender>,
+    authenticator: web::Data<PublishAuthenticator>,
+    response: web::Data<ResponseConfig>,
+    ack: web::Data<AckWebhook>,
+    config: web::Data<BatchConfig>,
+    payload: web::Data<PayloadConfig>,
+    channel: String,
+    opts: PublishOptions,
+    req: HttpRequest,
+    body: web::Bytes,
+    certs: Option<ClientCertificateChain>,
+    verified_identity: Option<VerifiedIdentity>,
+) -> Result<HttpResponse, HttpEndpointError> {
+    log::debug!("Publish batch to '{}'", channel);
+
+    downstream.check_maintenance()?;
+
+    let (application, authenticated, PublishIdPair { device, sender }) = authenticator
+        .authenticate(&opts, &req, certs, verified_identity)
+        .await?;
+
+    authenticator
+        .signature
+        .verify(&application, &authenticated, &req, &channel, &body)?;
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let messages = parse(&config, content_type, &body)?;
+
+    let mut result = BatchResponse::default();
+
+    for (index, message) in messages.into_iter().enumerate() {
+        let channel = message.channel.clone().unwrap_or_else(|| channel.clone());
+        let (content_type, body) = message.into_body();
+
+        let mut options = sender::PublishOptions {
+            id: Some(uuid::Uuid::new_v4().to_string()),
+            data_schema: opts.common.data_schema.clone(),
+            content_type,
+            ..Default::default()
+        };
+
+        let checked = downstream
+            .check_rate_limit(&application, &device)
+            .and_then(|_| downstream.check_capacity(&application, &device, &channel))
+            .and_then(|_| payload.check(&channel, body.len()))
+            .and_then(|_| downstream.check_timestamp(&mut options, &body));
+        if let Err(err) = checked {
+            let error = err.to_string();
+            result.push(BatchResult {
+                index,
+                status: HttpEndpointError(err).status_code().as_u16(),
+                id: None,
+                error: Some(error),
+            });
+            continue;
+        }
+
+        let id = options.id.clone();
+        let publish = sender::Publish {
+            channel,
+            application: &application,
+            device: device.clone(),
+            sender: sender.clone(),
+            options,
+        };
+
+        let status = downstream
+            .publish_http(publish, &response, &ack, body)
+            .await
+            .status();
+
+        result.push(if status.is_success() {
+            BatchResult {
+                index,
+                status: status.as_u16(),
+                id,
+                error: None,
+            }
+        } else {
+            BatchResult {
+                index,
+                status: status.as_u16(),
+                id: None,
+                error: Some(
+                    status
+                        .canonical_reason()
+                        .unwrap_or("Failed to publish")
+                        .to_string(),
+                ),
+            }
+        });
+    }
+
+    Ok(HttpResponse::build(StatusCode::MULTI_STATUS).json(result))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_array() {
+        let body = json!([
+            {"payload": {"temp": 42}},
+            {"channel": "log", "contentType": "text/plain", "payload": "started"},
+        ])
+        .to_string();
+
+        let messages = parse(
+            &Default::default(),
+            Some("application/json"),
+            body.as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].clone().into_body(),
+            (
+                Some("application/json".to_string()),
+                br#"{"temp":42}"#.to_vec()
+            )
+        );
+        assert_eq!(messages[1].channel.as_deref(), Some("log"));
+        assert_eq!(
+            messages[1].clone().into_body(),
+            (Some("text/plain".to_string()), b"started".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_parse_ndjson() {
+        let body = "{\"payload\":{\"temp\":42}}\n\n{\"channel\":\"log\",\"payload\":\"started\"}\n";
+
+        let messages = parse(
+            &Default::default(),
+            Some("application/x-ndjson; charset=utf-8"),
+            body.as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].channel.as_deref(), Some("log"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(matches!(
+            parse(&Default::default(), None, br#"{"payload":42}"#),
+            Err(EndpointError::InvalidFormat { .. })
+        ));
+        assert!(matches!(
+            parse(
+                &Default::default(),
+                Some(CONTENT_TYPE_NDJSON),
+                b"{\"payload\":42}\n[]"
+            ),
+            Err(EndpointError::InvalidFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_limit() {
+        let config = BatchConfig {
+            enabled: true,
+            max_messages: 1,
+        };
+        let body = json!([{"payload": 1}, {"payload": 2}]).to_string();
+
+        assert!(matches!(
+            parse(&config, None, body.as_bytes()),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+    }
+
+    #[test]
+    fn test_response() {
+        let mut response = BatchResponse::default();
+        response.push(BatchResult {
+            index: 0,
+            status: 202,
+            id: Some("msg1".into()),
+            error: None,
+        });
+        response.push(BatchResult {
+            index: 1,
+            status: 429,
+            id: None,
+            error: Some("Rate limit exceeded".into()),
+        });
+
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({
+                "accepted": 1,
+                "failed": 1,
+                "results": [
+                    {"index": 0, "status": 202, "id": "msg1"},
+                    {"index": 1, "status": 429, "error": "Rate limit exceeded"},
+                ]
+            })
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use async_trait::async_trait;
+use drogue_client::{error::ClientError, registry};
+use drogue_cloud_endpoint_common::psk::Identity;
+use drogue_cloud_service_api::auth::device::authn;
+use drogue_cloud_service_common::auth::openid;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The credentials accepted in the `Authorization` header.
+///
+/// This doesn't affect client certificates or TLS-PSK, which are accepted in all modes but [`AuthMode::Cert`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuthMode {
+    /// Only accept basic credentials, verified by the authentication service.
+    #[default]
+    Basic,
+    /// Only accept bearer tokens, issued by an OpenID Connect provider.
+    Bearer,
+    /// Accept basic credentials as well as bearer tokens.
+    Both,
+    /// Only accept client certificates, ignoring the `Authorization` header and TLS-PSK.
+    Cert,
+}
+
+impl AuthMode {
+    pub fn accepts_basic(&self) -> bool {
+        matches!(self, Self::Basic | Self::Both)
+    }
+
+    pub fn accepts_bearer(&self) -> bool {
+        matches!(self, Self::Bearer | Self::Both)
+    }
+
+    pub fn requires_certificate(&self) -> bool {
+        matches!(self, Self::Cert)
+    }
+}
+
+/// Validate a bearer token.
+#[async_trait]
+pub trait TokenValidator: Send + Sync {
+    /// Validate the signature, expiration, and audience of the token, returning its subject.
+    async fn validate(&self, token: &str) -> anyhow::Result<String>;
+}
+
+#[async_trait]
+impl TokenValidator for openid::Authenticator {
+    async fn validate(&self, token: &str) -> anyhow::Result<String> {
+        let token = self.validate_token(token).await?;
+        Ok(token.standard_claims().sub.clone())
+    }
+}
+
+/// Look up the application and device of an authenticated identity.
+#[async_trait]
+pub trait DeviceLookup: Send + Sync {
+    async fn lookup(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<Option<(registry::v1::Application, registry::v1::Device)>, ClientError>;
+}
+
+#[async_trait]
+impl DeviceLookup for registry::v1::Client {
+    async fn lookup(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<Option<(registry::v1::Application, registry::v1::Device)>, ClientError> {
+        let app = match self.get_app(application).await? {
+            Some(app) => app,
+            None => return Ok(None),
+        };
+        Ok(self
+            .get_device(application, device)
+            .await?
+            .map(|device| (app, device)))
+    }
+}
+
+/// Authenticate devices by bearer tokens.
+#[derive(Clone)]
+pub struct BearerAuthenticator {
+    mode: AuthMode,
+    validator: Option<Arc<dyn TokenValidator>>,
+    lookup: Option<Arc<dyn DeviceLookup>>,
+}
+
+impl BearerAuthenticator {
+    /// Create a new authenticator.
+    ///
+    /// Accepting bearer tokens requires a validator and a lookup.
+    pub fn new(
+        mode: AuthMode,
+        validator: Option<Arc<dyn TokenValidator>>,
+        lookup: Option<Arc<dyn DeviceLookup>>,
+    ) -> anyhow::Result<Self> {
+        if mode.accepts_bearer() && (validator.is_none() || lookup.is_none()) {
+            anyhow::bail!(
+                "Accepting bearer tokens requires an OpenID Connect configuration and access to the registry"
+            );
+        }
+
+        Ok(Self {
+            mode,
+            validator,
+            lookup,
+        })
+    }
+
+    pub fn mode(&self) -> AuthMode {
+        self.mode
+    }
+
+    /// Authenticate a device by a bearer token.
+    ///
+    /// The subject of the token must be the device, in the form of `<device>@<application>`. The
+    /// application and device are checked like the authentication service does: the application
+    /// must not be disabled, and neither must be pending deletion. Like with the authentication
+    /// service, the credentials of the device are removed from the outcome.
+    ///
+    /// Tokens are issued for a single device, so gateways can't publish on behalf of other devices.
+    pub async fn authenticate(&self, token: &str) -> Result<authn::Outcome, ClientError> {
+        let (validator, lookup) = match (self.mode.accepts_bearer(), &self.validator, &self.lookup)
+        {
+            (true, Some(validator), Some(lookup)) => (validator, lookup),
+            _ => return Ok(authn::Outcome::Fail),
+        };
+
+        let subject = match validator.validate(token).await {
+            Ok(subject) => subject,
+            Err(err) => {
+                log::debug!("Rejected bearer token: {}", err);
+                return Ok(authn::Outcome::Fail);
+            }
+        };
+
+        let identity = match Identity::parse(&subject) {
+            Ok(identity) => identity,
+            Err(_) => {
+                log::debug!("Invalid subject of bearer token: {}", subject);
+                return Ok(authn::Outcome::Fail);
+            }
+        };
+
+        Ok(
+            match lookup
+                .lookup(identity.application(), identity.device())
+                .await?
+            {
+                Some((application, device)) if validate(&application, &device) => {
+                    authn::Outcome::Pass {
+                        application,
+                        device: authn::strip_credentials(device),
+                        r#as: None,
+                    }
+                }
+                _ => authn::Outcome::Fail,
+            },
+        )
+    }
+}
+
+/// Validate if an application and device are "ok" to be used for authentication.
+fn validate(application: &registry::v1::Application, device: &registry::v1::Device) -> bool {
+    if !authn::validate_app(application) {
+        return false;
+    }
+
+    if device.metadata.deletion_timestamp.is_some() {
+        log::debug!("Device is about to being deleted");
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+
+    struct MockValidator;
+
+    #[async_trait]
+    impl TokenValidator for MockValidator {
+        async fn validate(&self, token: &str) -> anyhow::Result<String> {
+            match token.strip_prefix("valid:") {
+                Some(subject) => Ok(subject.into()),
+                None => anyhow::bail!("Invalid signature"),
+            }
+        }
+    }
+
+    struct MockLookup;
+
+    #[async_trait]
+    impl DeviceLookup for MockLookup {
+        async fn lookup(
+            &self,
+            application: &str,
+            device: &str,
+        ) -> Result<Option<(registry::v1::Application, registry::v1::Device)>, ClientError>
+        {
+            let mut app = registry::v1::Application::default();
+            app.metadata.name = application.into();
+            let mut dev = registry::v1::Device::default();
+            dev.metadata.application = application.into();
+            dev.metadata.name = device.into();
+
+            match (application, device) {
+                ("app1", "device1") => {
+                    dev.spec.insert(
+                        "credentials".into(),
+                        serde_json::json!({ "credentials": [{ "pass": "foo" }] }),
+                    );
+                }
+                ("app1", "deleted") => dev.metadata.deletion_timestamp = Some(Utc::now()),
+                ("disabled", "device1") => {
+                    app.spec
+                        .insert("core".into(), serde_json::json!({ "disabled": true }));
+                }
+                ("deleted", "device1") => app.metadata.deletion_timestamp = Some(Utc::now()),
+                _ => return Ok(None),
+            }
+
+            Ok(Some((app, dev)))
+        }
+    }
+
+    fn authenticator(mode: AuthMode) -> BearerAuthenticator {
+        BearerAuthenticator::new(
+            mode,
+            Some(Arc::new(MockValidator)),
+            Some(Arc::new(MockLookup)),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_strip_credentials() {
+        let auth = authenticator(AuthMode::Bearer);
+
+        match auth.authenticate("valid:device1@app1").await.unwrap() {
+            authn::Outcome::Pass { device, .. } => {
+                assert!(!device.spec.contains_key("credentials"));
+                assert!(!device.spec.contains_key("authentication"));
+            }
+            authn::Outcome::Fail => panic!("Must pass"),
+        }
+    }
+
+    fn passed(outcome: Result<authn::Outcome, ClientError>) -> Option<String> {
+        match outcome.unwrap() {
+            authn::Outcome::Pass { device, .. } => Some(device.metadata.name),
+            authn::Outcome::Fail => None,
+        }
+    }
+
+    #[test]
+    fn test_mode() {
+        assert!(AuthMode::default().accepts_basic());
+        assert!(!AuthMode::default().accepts_bearer());
+        assert!(!AuthMode::Bearer.accepts_basic());
+        assert!(AuthMode::Both.accepts_basic() && AuthMode::Both.accepts_bearer());
+        assert!(!AuthMode::Cert.accepts_basic() && !AuthMode::Cert.accepts_bearer());
+        assert!(AuthMode::Cert.requires_certificate());
+        assert!(!AuthMode::Both.requires_certificate());
+    }
+
+    #[test]
+    fn test_requires_config() {
+        assert!(BearerAuthenticator::new(AuthMode::Basic, None, None).is_ok());
+        assert!(BearerAuthenticator::new(AuthMode::Both, None, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let auth = authenticator(AuthMode::Bearer);
+
+        assert_eq!(
+            passed(auth.authenticate("valid:device1@app1").await),
+            Some("device1".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reject() {
+        let auth = authenticator(AuthMode::Both);
+
+        // invalid token
+        assert_eq!(passed(auth.authenticate("device1@app1").await), None);
+        // not scoped to an application
+        assert_eq!(passed(auth.authenticate("valid:device1").await), None);
+        // unknown device
+        assert_eq!(passed(auth.authenticate("valid:device2@app1").await), None);
+        // pending deletion
+        assert_eq!(passed(auth.authenticate("valid:deleted@app1").await), None);
+        assert_eq!(
+            passed(auth.authenticate("valid:device1@deleted").await),
+            None
+        );
+        // disabled application
+        assert_eq!(
+            passed(auth.authenticate("valid:device1@disabled").await),
+            None
+        );
+    }
+}
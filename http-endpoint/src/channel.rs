@@ -0,0 +1,111 @@
+//! Hierarchical channels, like `sensors/room1/temp`.
+
+use drogue_cloud_endpoint_common::error::EndpointError;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ChannelConfig {
+    /// Take the full path after `/v1/`, as the channel.
+    ///
+    /// By default, only the first segment is the channel, and the remaining path is the topic of
+    /// the event.
+    #[serde(default)]
+    pub folders: bool,
+}
+
+impl ChannelConfig {
+    /// The channel and topic of a request path, with a `suffix` following the first segment.
+    pub fn split(
+        &self,
+        channel: String,
+        suffix: String,
+    ) -> Result<(String, Option<String>), EndpointError> {
+        if !self.folders {
+            return Ok((channel, Some(suffix)));
+        }
+
+        let channel = format!("{channel}/{suffix}");
+        validate(&channel)?;
+        Ok((channel, None))
+    }
+}
+
+/// Validate a hierarchical channel.
+///
+/// Each segment must be non-empty, and may only consist of ASCII letters, digits, `-`, `_`, and
+/// `.`, but must not be `.` or `..`.
+pub fn validate(channel: &str) -> Result<(), EndpointError> {
+    let invalid = |details: &str| EndpointError::InvalidRequest {
+        details: format!("Invalid channel '{channel}': {details}"),
+    };
+
+    if channel.is_empty() {
+        return Err(invalid("must not be empty"));
+    }
+
+    for segment in channel.split('/') {
+        match segment {
+            "" => return Err(invalid("must not contain empty segments")),
+            "." | ".." => return Err(invalid("must not contain relative segments")),
+            segment
+                if !segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) =>
+            {
+                return Err(invalid(
+                    "may only contain ASCII letters, digits, '-', '_', and '.'",
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        for channel in ["telemetry", "sensors/room1/temp", "a.b/c-d/e_f"] {
+            assert!(validate(channel).is_ok(), "{channel}");
+        }
+
+        for channel in [
+            "",
+            "sensors/",
+            "sensors//temp",
+            "/sensors",
+            "sensors/../temp",
+            "sensors/room 1",
+            "sensors/räume",
+            "sensors/+/temp",
+        ] {
+            assert!(
+                matches!(validate(channel), Err(EndpointError::InvalidRequest { .. })),
+                "{channel}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        let config = ChannelConfig::default();
+        assert_eq!(
+            config.split("sensors".into(), "room1/temp".into()).unwrap(),
+            ("sensors".into(), Some("room1/temp".into()))
+        );
+
+        let config = ChannelConfig { folders: true };
+        assert_eq!(
+            config.split("sensors".into(), "room1/temp".into()).unwrap(),
+            ("sensors/room1/temp".into(), None)
+        );
+        assert!(matches!(
+            config.split("sensors".into(), "".into()),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+    }
+}
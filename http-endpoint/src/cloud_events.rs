@@ -0,0 +1,600 @@
+//! Accepting events using the CloudEvents HTTP binding.
+
+use crate::{
+    ack::AckWebhook,
+    downstream::HttpCommandSender,
+    payload::PayloadConfig,
+    response::ResponseConfig,
+    telemetry::{PublishAuthenticator, PublishOptions},
+};
+use cloudevents::{event::Data, AttributesReader, Event};
+use drogue_cloud_endpoint_common::{
+    command::Commands,
+    error::{EndpointError, HttpEndpointError},
+    psk::VerifiedIdentity,
+    sampling::TraceSampler,
+    sender::{self, DeadlineConfig, DownstreamSender, PublishIdPair},
+    x509::ClientCertificateChain,
+};
+use drogue_cloud_service_api::webapp::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::Instrument;
+
+/// Extension carrying the source of the original event.
+pub const EXT_CE_SOURCE: &str = "cesource";
+
+/// The attribute of a CloudEvent to derive a value from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AttributeMapping {
+    /// Don't derive the value from the event.
+    None,
+    /// Use the last segment of the path of the `source` attribute.
+    Source,
+    /// Use the `subject` attribute.
+    Subject,
+    /// Use the `type` attribute.
+    Type,
+}
+
+impl AttributeMapping {
+    fn eval(&self, event: &Event) -> Option<String> {
+        let value = match self {
+            Self::None => None,
+            Self::Source => event
+                .source()
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .map(ToString::to_string),
+            Self::Subject => event.subject().map(ToString::to_string),
+            Self::Type => Some(event.ty().to_string()),
+        };
+        value.filter(|value| !value.is_empty())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CloudEventsConfig {
+    /// Enable the CloudEvents publish route.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// The attribute to derive the device from.
+    ///
+    /// If a device is derived, the authenticated device publishes on its behalf, which
+    /// requires the authenticated device to be a gateway for it.
+    #[serde(default = "default_device_mapping")]
+    pub device: AttributeMapping,
+
+    /// The attribute to derive the channel from.
+    #[serde(default = "default_channel_mapping")]
+    pub channel: AttributeMapping,
+
+    /// The channel to use, if none could be derived.
+    #[serde(default = "default_channel")]
+    pub default_channel: String,
+
+    /// Unwrap CloudEvents in structured mode, published on the standard endpoint.
+    ///
+    /// The channel is derived from the event, falling back to the channel of the request path.
+    #[serde(default)]
+    pub structured_publish: bool,
+}
+
+const fn default_device_mapping() -> AttributeMapping {
+    AttributeMapping::None
+}
+
+const fn default_channel_mapping() -> AttributeMapping {
+    AttributeMapping::Subject
+}
+
+fn default_channel() -> String {
+    "telemetry".into()
+}
+
+impl Default for CloudEventsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: default_device_mapping(),
+            channel: default_channel_mapping(),
+            default_channel: default_channel(),
+            structured_publish: false,
+        }
+    }
+}
+
+/// The content type of a CloudEvent in structured mode, using the JSON format.
+const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json";
+
+/// A CloudEvent, mapped to a publish request.
+#[derive(Clone, Debug)]
+pub struct MappedEvent {
+    pub channel: String,
+    pub device: Option<String>,
+    pub options: sender::PublishOptions,
+    pub body: Vec<u8>,
+}
+
+impl CloudEventsConfig {
+    /// Map a CloudEvent to the information required for publishing.
+    fn map(&self, event: Event) -> Result<MappedEvent, EndpointError> {
+        self.map_with_default(event, &self.default_channel)
+    }
+
+    /// Unwrap a CloudEvent in structured mode, published on the standard endpoint.
+    ///
+    /// Returns [`None`] if the payload is not a structured CloudEvent, or unwrapping is disabled.
+    pub fn unwrap_structured(
+        &self,
+        channel: &str,
+        content_type: Option<&str>,
+        body: &[u8],
+    ) -> Result<Option<MappedEvent>, EndpointError> {
+        if !self.structured_publish || !content_type.map(is_structured).unwrap_or_default() {
+            return Ok(None);
+        }
+
+        let event: Event =
+            serde_json::from_slice(body).map_err(|err| EndpointError::InvalidRequest {
+                details: format!("Invalid CloudEvent: {err}"),
+            })?;
+
+        self.map_with_default(event, channel).map(Some)
+    }
+
+    fn map_with_default(
+        &self,
+        mut event: Event,
+        default_channel: &str,
+    ) -> Result<MappedEvent, EndpointError> {
+        let channel = self
+            .channel
+            .eval(&event)
+            .unwrap_or_else(|| default_channel.to_string());
+        let device = self.device.eval(&event);
+
+        // extensions of the endpoint, like the identity of the device, can't be provided
+        let mut extensions = event
+            .iter_extensions()
+            .filter(|(k, _)| {
+                let reserved = sender::is_reserved_extension(k) || *k == EXT_CE_SOURCE;
+                if reserved {
+                    log::debug!("Dropping reserved extension '{k}' of CloudEvent");
+                }
+                !reserved
+            })
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        extensions.insert(EXT_CE_SOURCE.into(), event.source().to_string());
+
+        let id = event.id().to_string();
+        let r#type = event.ty().to_string();
+        let time = event.time().cloned();
+
+        let (content_type, data_schema, data) = event.take_data();
+
+        let body = match data {
+            Some(Data::Binary(data)) => data,
+            Some(Data::String(data)) => data.into_bytes(),
+            Some(Data::Json(data)) => {
+                serde_json::to_vec(&data).map_err(|err| EndpointError::InvalidFormat {
+                    source: Box::new(err),
+                })?
+            }
+            None => vec![],
+        };
+
+        Ok(MappedEvent {
+            channel,
+            device,
+            options: sender::PublishOptions {
+                id: Some(id),
+                time,
+                data_schema: data_schema.map(|s| s.to_string()),
+                content_type,
+                extensions,
+                r#type: Some(r#type),
+                ..Default::default()
+            },
+            body,
+        })
+    }
+}
+
+fn is_structured(content_type: &str) -> bool {
+    content_type
+        .parse::<mime::Mime>()
+        .map(|mime| mime.essence_str() == STRUCTURED_CONTENT_TYPE)
+        .unwrap_or_default()
+}
+
+/// Parse a CloudEvent from an HTTP request, supporting both binary and structured mode.
+///
+/// The body is taken from the [`web::Bytes`] extractor, so that its size is limited by the
+/// payload config.
+fn parse(req: &HttpRequest, body: &web::Bytes) -> Result<Event, EndpointError> {
+    cloudevents::binding::actix::to_event(req, body.clone()).map_err(|err| {
+        EndpointError::InvalidRequest {
+            details: format!("Invalid CloudEvent: {err}"),
+        }
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn publish(
+    downstream: web::Data<DownstreamSender>,
+    authenticator: web::Data<PublishAuthenticator>,
+    response: web::Data<ResponseConfig>,
+    ack: web::Data<AckWebhook>,
+    config: web::Data<CloudEventsConfig>,
+    limits: web::Data<PayloadConfig>,
+    commands: web::Data<Commands>,
+    sampler: web::Data<TraceSampler>,
+    deadline: web::Data<DeadlineConfig>,
+    web::Query(opts): web::Query<PublishOptions>,
+    req: HttpRequest,
+    body: web::Bytes,
+    certs: Option<ClientCertificateChain>,
+    verified_identity: Option<VerifiedIdentity>,
+) -> Result<HttpResponse, HttpEndpointError> {
+    let span = sampler.span(&req, || tracing::info_span!("publish_cloud_event", ?opts));
+    let backpressure = downstream.clone();
+    let request = publish_event(
+        downstream,
+        authenticator,
+        response,
+        ack,
+        config,
+        limits,
+        commands,
+        opts,
+        req,
+        body,
+        certs,
+        verified_identity,
+    );
+
+    deadline
+        .run(request)
+        .instrument(span)
+        .await
+        .map_err(|err| backpressure.backpressure(err))?
+}
+
+/// Publish a CloudEvent.
+///
+/// The span of the request is created by the caller, only if the request is sampled.
+#[allow(clippy::too_many_arguments)]
+async fn publish_event(
+    downstream: web::Data<DownstreamSender>,
+    authenticator: web::Data<PublishAuthenticator>,
+    response: web::Data<ResponseConfig>,
+    ack: web::Data<AckWebhook>,
+    config: web::Data<CloudEventsConfig>,
+    limits: web::Data<PayloadConfig>,
+    commands: web::Data<Commands>,
+    mut opts: PublishOptions,
+    req: HttpRequest,
+    body: web::Bytes,
+    certs: Option<ClientCertificateChain>,
+    verified_identity: Option<VerifiedIdentity>,
+) -> Result<HttpResponse, HttpEndpointError> {
+    downstream.check_maintenance()?;
+
+    let event = config.map(parse(&req, &body)?)?;
+
+    log::debug!("Publish CloudEvent to '{}'", event.channel);
+
+    if let Some(device) = event.device {
+        opts.r#as = Some(device);
+    }
+
+    let (application, authenticated, PublishIdPair { device, sender }) = authenticator
+        .authenticate(&opts, &req, certs, verified_identity)
+        .await?;
+
+    // the signature covers the raw request, and the channel derived from the event
+    authenticator
+        .signature
+        .verify(&application, &authenticated, &req, &event.channel, &body)?;
+
+    downstream.check_rate_limit(&application, &device)?;
+    downstream.check_capacity(&application, &device, &event.channel)?;
+    limits.check(&event.channel, event.body.len())?;
+
+    let mut options = event.options;
+    downstream.check_timestamp(&mut options, &event.body)?;
+
+    let publish = sender::Publish {
+        channel: event.channel,
+        application: &application,
+        device,
+        sender,
+        options,
+    };
+
+    downstream
+        .publish_and_await(
+            publish,
+            &response,
+            &ack,
+            commands,
+            opts.ct,
+            event.body.into(),
+        )
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_cloud_service_api::webapp::{test::TestRequest, FromRequest};
+    use serde_json::json;
+
+    async fn parse_request(request: TestRequest) -> Result<Event, EndpointError> {
+        let (req, mut payload) = request.to_http_parts();
+        let body = web::Bytes::from_request(&req, &mut payload).await.unwrap();
+        parse(&req, &body)
+    }
+
+    fn config() -> CloudEventsConfig {
+        CloudEventsConfig {
+            enabled: true,
+            device: AttributeMapping::Source,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_binary() {
+        let event = parse_request(
+            TestRequest::post()
+                .insert_header(("ce-specversion", "1.0"))
+                .insert_header(("ce-id", "event1"))
+                .insert_header(("ce-source", "/sensors/device1"))
+                .insert_header(("ce-type", "io.example.temperature"))
+                .insert_header(("ce-subject", "temperature"))
+                .insert_header(("ce-site", "site1"))
+                .insert_header(("content-type", "application/json"))
+                .set_payload(r#"{"temp":42}"#),
+        )
+        .await
+        .unwrap();
+
+        let event = config().map(event).unwrap();
+
+        assert_eq!(event.channel, "temperature");
+        assert_eq!(event.device.as_deref(), Some("device1"));
+        assert_eq!(event.options.id.as_deref(), Some("event1"));
+        assert_eq!(
+            event.options.r#type.as_deref(),
+            Some("io.example.temperature")
+        );
+        assert_eq!(
+            event.options.content_type.as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(
+            event.options.extensions.get("site").map(String::as_str),
+            Some("site1")
+        );
+        assert_eq!(
+            event
+                .options
+                .extensions
+                .get(EXT_CE_SOURCE)
+                .map(String::as_str),
+            Some("/sensors/device1")
+        );
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&event.body).unwrap(),
+            json!({"temp": 42})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_structured() {
+        let event = parse_request(
+            TestRequest::post()
+                .insert_header(("content-type", "application/cloudevents+json"))
+                .set_payload(
+                    json!({
+                        "specversion": "1.0",
+                        "id": "event2",
+                        "source": "/sensors/device2/",
+                        "type": "io.example.humidity",
+                        "datacontenttype": "application/json",
+                        "data": {"hum": 50},
+                    })
+                    .to_string(),
+                ),
+        )
+        .await
+        .unwrap();
+
+        let event = config().map(event).unwrap();
+
+        // no subject, use the default channel
+        assert_eq!(event.channel, "telemetry");
+        assert_eq!(event.device.as_deref(), Some("device2"));
+        assert_eq!(event.options.id.as_deref(), Some("event2"));
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&event.body).unwrap(),
+            json!({"hum": 50})
+        );
+    }
+
+    #[test]
+    fn test_unwrap_structured() {
+        let config = CloudEventsConfig {
+            structured_publish: true,
+            ..Default::default()
+        };
+        let body = json!({
+            "specversion": "1.0",
+            "id": "event3",
+            "source": "/sensors/device3",
+            "type": "io.example.temperature",
+            "subject": "temperature",
+            "datacontenttype": "application/json",
+            "data": {"temp": 21},
+        })
+        .to_string();
+
+        let event = config
+            .unwrap_structured(
+                "telemetry",
+                Some("application/cloudevents+json; charset=utf-8"),
+                body.as_bytes(),
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(event.channel, "temperature");
+        assert_eq!(
+            event.options.content_type.as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&event.body).unwrap(),
+            json!({"temp": 21})
+        );
+
+        // other content types are published as they are
+        assert!(config
+            .unwrap_structured("telemetry", Some("application/json"), body.as_bytes())
+            .unwrap()
+            .is_none());
+        // disabled
+        assert!(CloudEventsConfig::default()
+            .unwrap_structured(
+                "telemetry",
+                Some("application/cloudevents+json"),
+                body.as_bytes()
+            )
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_unwrap_structured_default_channel() {
+        let config = CloudEventsConfig {
+            structured_publish: true,
+            ..Default::default()
+        };
+        let body = json!({
+            "specversion": "1.0",
+            "id": "event4",
+            "source": "/sensors/device4",
+            "type": "io.example.temperature",
+        })
+        .to_string();
+
+        let event = config
+            .unwrap_structured("state", Some(STRUCTURED_CONTENT_TYPE), body.as_bytes())
+            .unwrap()
+            .unwrap();
+
+        // no subject, use the channel of the request
+        assert_eq!(event.channel, "state");
+        assert!(event.body.is_empty());
+
+        let result = config.unwrap_structured(
+            "state",
+            Some(STRUCTURED_CONTENT_TYPE),
+            br#"{"specversion": "1.0"}"#,
+        );
+        assert!(matches!(result, Err(EndpointError::InvalidRequest { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_reserved_extensions() {
+        let event = parse_request(
+            TestRequest::post()
+                .insert_header(("ce-specversion", "1.0"))
+                .insert_header(("ce-id", "event1"))
+                .insert_header(("ce-source", "/sensors/device1"))
+                .insert_header(("ce-type", "io.example.temperature"))
+                .insert_header(("ce-application", "other-app"))
+                .insert_header(("ce-device", "other-device"))
+                .insert_header(("ce-partitionkey", "other-key"))
+                .insert_header(("ce-cesource", "/spoofed"))
+                .insert_header(("ce-site", "site1"))
+                .set_payload("42"),
+        )
+        .await
+        .unwrap();
+
+        let event = config().map(event).unwrap();
+        let extensions = &event.options.extensions;
+
+        assert!(!extensions.contains_key("application"));
+        assert!(!extensions.contains_key("device"));
+        assert!(!extensions.contains_key("partitionkey"));
+        assert_eq!(
+            extensions.get(EXT_CE_SOURCE).map(String::as_str),
+            Some("/sensors/device1")
+        );
+        assert_eq!(extensions.get("site").map(String::as_str), Some("site1"));
+    }
+
+    #[tokio::test]
+    async fn test_device_not_mapped() {
+        let event = parse_request(
+            TestRequest::post()
+                .insert_header(("ce-specversion", "1.0"))
+                .insert_header(("ce-id", "event1"))
+                .insert_header(("ce-source", "/sensors/device1"))
+                .insert_header(("ce-type", "io.example.temperature"))
+                .set_payload("42"),
+        )
+        .await
+        .unwrap();
+
+        let event = CloudEventsConfig::default().map(event).unwrap();
+
+        assert_eq!(event.device, None);
+        assert_eq!(event.body, b"42");
+    }
+
+    #[tokio::test]
+    async fn test_malformed() {
+        // missing required attributes
+        let result = parse_request(
+            TestRequest::post()
+                .insert_header(("ce-specversion", "1.0"))
+                .insert_header(("ce-id", "event1"))
+                .set_payload("42"),
+        )
+        .await;
+        assert!(matches!(result, Err(EndpointError::InvalidRequest { .. })));
+
+        // broken structured event
+        let result = parse_request(
+            TestRequest::post()
+                .insert_header(("content-type", "application/cloudevents+json"))
+                .set_payload("{ \"specversion\": "),
+        )
+        .await;
+        assert!(matches!(result, Err(EndpointError::InvalidRequest { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_payload_limit() {
+        let (req, mut payload) = TestRequest::post()
+            .app_data(web::PayloadConfig::new(16))
+            .insert_header(("ce-specversion", "1.0"))
+            .insert_header(("ce-id", "event1"))
+            .insert_header(("ce-source", "/sensors/device1"))
+            .insert_header(("ce-type", "io.example.temperature"))
+            .set_payload("x".repeat(17))
+            .to_http_parts();
+
+        // the body is rejected before being parsed
+        assert!(web::Bytes::from_request(&req, &mut payload).await.is_err());
+    }
+}
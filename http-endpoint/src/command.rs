@@ -7,17 +7,19 @@ use drogue_cloud_endpoint_common::{
     command::{CommandFilter, Commands, Subscription},
     error::HttpEndpointError,
 };
-use drogue_cloud_service_api::webapp::{http, web, HttpResponse};
+use drogue_cloud_service_api::webapp::{web, HttpResponse};
 use std::time::Duration;
 use tracing::instrument;
 
 const HEADER_COMMAND: &str = "command";
 
-#[instrument(skip(commands))]
+/// Wait for a command, responding with `accepted` if none was received.
+#[instrument(skip(commands, accepted))]
 pub async fn wait_for_command(
     commands: web::Data<Commands>,
     filter: CommandFilter,
     ttd: Option<u64>,
+    accepted: HttpResponse,
 ) -> Result<HttpResponse, HttpEndpointError> {
     match ttd {
         Some(ttd) if ttd > 0 => {
@@ -34,10 +36,10 @@ pub async fn wait_for_command(
                 }
                 _ => {
                     commands.unsubscribe(handle).await;
-                    Ok(HttpResponse::build(http::StatusCode::ACCEPTED).finish())
+                    Ok(accepted)
                 }
             }
         }
-        _ => Ok(HttpResponse::build(http::StatusCode::ACCEPTED).finish()),
+        _ => Ok(accepted),
     }
 }
@@ -0,0 +1,127 @@
+//! Inferring the content type of payloads, which the device didn't provide.
+
+use serde::{Deserialize, Serialize};
+
+const CONTENT_TYPE_JSON: &str = "application/json";
+const CONTENT_TYPE_CBOR: &str = "application/cbor";
+const CONTENT_TYPE_BINARY: &str = "application/octet-stream";
+
+/// The self-described CBOR tag (RFC 8949, section 3.4.6).
+const CBOR_SELF_DESCRIBED: [u8; 3] = [0xd9, 0xd9, 0xf7];
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ContentTypeConfig {
+    /// The content type of payloads, published without a `Content-Type` header.
+    #[serde(default)]
+    pub default: Option<String>,
+    /// Detect the content type of payloads, published without a `Content-Type` header.
+    ///
+    /// Payloads which are neither JSON nor CBOR fall back to the default content type, or to
+    /// `application/octet-stream` if there is no default.
+    #[serde(default)]
+    pub sniff: bool,
+}
+
+impl ContentTypeConfig {
+    /// The content type of a payload, preferring the one provided by the device.
+    pub fn infer(&self, content_type: Option<String>, body: &[u8]) -> Option<String> {
+        if content_type.is_some() {
+            return content_type;
+        }
+
+        if self.sniff {
+            let sniffed = sniff(body)
+                .map(ToString::to_string)
+                .or_else(|| self.default.clone())
+                .unwrap_or_else(|| CONTENT_TYPE_BINARY.to_string());
+            return Some(sniffed);
+        }
+
+        self.default.clone()
+    }
+}
+
+/// Detect JSON and CBOR payloads, [`None`] if the payload is neither.
+///
+/// JSON payloads must be a valid object or array. CBOR payloads must either start with the
+/// self-described CBOR tag, or with the head of a map or an array. Those bytes are never the start
+/// of a UTF-8 text.
+fn sniff(body: &[u8]) -> Option<&'static str> {
+    match body.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{' | b'[') => {
+            if serde_json::from_slice::<serde::de::IgnoredAny>(body).is_ok() {
+                Some(CONTENT_TYPE_JSON)
+            } else {
+                None
+            }
+        }
+        _ if body.starts_with(&CBOR_SELF_DESCRIBED) => Some(CONTENT_TYPE_CBOR),
+        _ => match body.first() {
+            // major type 4 (array) and 5 (map)
+            Some(0x80..=0xbf) => Some(CONTENT_TYPE_CBOR),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(br#" {"temp": 42}"#), Some(CONTENT_TYPE_JSON));
+        assert_eq!(sniff(b"[1, 2]"), Some(CONTENT_TYPE_JSON));
+        // {"temp": 42}
+        assert_eq!(
+            sniff(&[0xa1, 0x64, b't', b'e', b'm', b'p', 0x18, 0x2a]),
+            Some(CONTENT_TYPE_CBOR)
+        );
+        assert_eq!(sniff(&[0xd9, 0xd9, 0xf7, 0x01]), Some(CONTENT_TYPE_CBOR));
+
+        assert_eq!(sniff(b"{not json"), None);
+        assert_eq!(sniff(b"42"), None);
+        assert_eq!(sniff(b"hello"), None);
+        assert_eq!(sniff(&[0x00, 0x01]), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn test_infer() {
+        let config = ContentTypeConfig::default();
+        assert_eq!(config.infer(None, b"{}"), None);
+        assert_eq!(
+            config.infer(Some("text/plain".into()), b"{}").as_deref(),
+            Some("text/plain")
+        );
+
+        let config = ContentTypeConfig {
+            default: Some("text/plain".into()),
+            sniff: false,
+        };
+        assert_eq!(config.infer(None, b"{}").as_deref(), Some("text/plain"));
+
+        let config = ContentTypeConfig {
+            default: None,
+            sniff: true,
+        };
+        assert_eq!(
+            config.infer(None, b"{}").as_deref(),
+            Some(CONTENT_TYPE_JSON)
+        );
+        assert_eq!(
+            config.infer(None, b"hello").as_deref(),
+            Some(CONTENT_TYPE_BINARY)
+        );
+        assert_eq!(
+            config.infer(Some("text/plain".into()), b"{}").as_deref(),
+            Some("text/plain")
+        );
+
+        let config = ContentTypeConfig {
+            default: Some("text/plain".into()),
+            sniff: true,
+        };
+        assert_eq!(config.infer(None, b"hello").as_deref(), Some("text/plain"));
+    }
+}
@@ -1,12 +1,28 @@
-use crate::command::wait_for_command;
+use crate::{
+    ack::AckWebhook, command::wait_for_command, metrics::DOWNSTREAM_PUBLISH_DURATION,
+    response::ResponseConfig,
+};
 use async_trait::async_trait;
-use drogue_client::error::ErrorInformation;
+use drogue_client::{error::ErrorInformation, registry};
 use drogue_cloud_endpoint_common::{
     command::{CommandFilter, Commands},
     error::HttpEndpointError,
-    sender::{DownstreamSender, Publish, PublishOutcome, Publisher, DOWNSTREAM_EVENTS_COUNTER},
+    sender::{
+        DownstreamSender, Publish, PublishError, PublishOutcome, Publisher,
+        DOWNSTREAM_EVENTS_COUNTER,
+    },
+};
+use drogue_cloud_service_api::webapp::{
+    http::header::{HeaderName, HeaderValue},
+    web, HttpResponse,
 };
-use drogue_cloud_service_api::webapp::{web, HttpResponse};
+use futures::Future;
+use std::time::{Duration, Instant};
+
+/// Header suggesting the device an interval to publish in, in seconds.
+const HEADER_SUGGESTED_INTERVAL: &str = "x-suggested-interval";
+/// Header carrying the ID of an accepted message, which is also the ID of the CloudEvent.
+const HEADER_EVENT_ID: &str = "ce-id";
 
 #[async_trait]
 pub trait HttpCommandSender {
@@ -14,12 +30,26 @@ pub trait HttpCommandSender {
     async fn publish_and_await<'a, B>(
         &self,
         publish: Publish<'a>,
+        response: &ResponseConfig,
+        ack: &AckWebhook,
         commands: web::Data<Commands>,
         ttd: Option<u64>,
         body: B,
     ) -> Result<HttpResponse, HttpEndpointError>
     where
         B: AsRef<[u8]> + Send + Sync;
+
+    /// Publish, without waiting for a command.
+    #[allow(clippy::needless_lifetimes)]
+    async fn publish_http<'a, B>(
+        &self,
+        publish: Publish<'a>,
+        response: &ResponseConfig,
+        ack: &AckWebhook,
+        body: B,
+    ) -> HttpResponse
+    where
+        B: AsRef<[u8]> + Send + Sync;
 }
 
 #[async_trait]
@@ -27,7 +57,9 @@ impl HttpCommandSender for DownstreamSender {
     #[allow(clippy::needless_lifetimes)]
     async fn publish_and_await<'a, B>(
         &self,
-        publish: Publish<'a>,
+        mut publish: Publish<'a>,
+        response: &ResponseConfig,
+        ack: &AckWebhook,
         commands: web::Data<Commands>,
         ttd: Option<u64>,
         body: B,
@@ -40,41 +72,177 @@ impl HttpCommandSender for DownstreamSender {
             &publish.sender.name,
             &publish.device.name,
         );
-        match self.publish(publish, body).await {
-            // ok, and accepted
-            Ok(PublishOutcome::Accepted) => {
-                DOWNSTREAM_EVENTS_COUNTER
-                    .with_label_values(&["http", "Accepted"])
-                    .inc();
-                wait_for_command(commands, filter, ttd).await
+        let id = ensure_id(&mut publish);
+        let (application, device, channel) = ack_target(&publish);
+        let device_id = publish.device.clone();
+        let result = outcome(timed(self.publish(publish, body)).await);
+        let interval = self.suggested_interval(application, &device_id);
+        match result {
+            Ok(()) => {
+                ack.notify(application, &device, &channel, &id);
+                wait_for_command(commands, filter, ttd, response.accepted(&id))
+                    .await
+                    .map(|response| with_event_id(response, &id))
+                    .map(|response| with_suggested_interval(response, interval))
             }
+            Err(response) => Ok(with_suggested_interval(response, interval)),
+        }
+    }
 
-            // ok, but rejected
-            Ok(PublishOutcome::Rejected) => {
-                DOWNSTREAM_EVENTS_COUNTER
-                    .with_label_values(&["http", "Rejected"])
-                    .inc();
-                Ok(HttpResponse::build(http::StatusCode::NOT_ACCEPTABLE).finish())
+    #[allow(clippy::needless_lifetimes)]
+    async fn publish_http<'a, B>(
+        &self,
+        mut publish: Publish<'a>,
+        response: &ResponseConfig,
+        ack: &AckWebhook,
+        body: B,
+    ) -> HttpResponse
+    where
+        B: AsRef<[u8]> + Send + Sync,
+    {
+        let id = ensure_id(&mut publish);
+        let (application, device, channel) = ack_target(&publish);
+        let device_id = publish.device.clone();
+        let result = outcome(timed(self.publish(publish, body)).await);
+        let interval = self.suggested_interval(application, &device_id);
+        let response = match result {
+            Ok(()) => {
+                ack.notify(application, &device, &channel, &id);
+                with_event_id(response.accepted(&id), &id)
             }
+            Err(response) => response,
+        };
+        with_suggested_interval(response, interval)
+    }
+}
 
-            // ok, but rejected
-            Ok(PublishOutcome::QueueFull) => {
-                DOWNSTREAM_EVENTS_COUNTER
-                    .with_label_values(&["http", "QueueFull"])
-                    .inc();
-                Ok(HttpResponse::build(http::StatusCode::SERVICE_UNAVAILABLE).finish())
-            }
+/// Record the duration of sending an event downstream, separate from handling the request.
+async fn timed<F: Future>(publish: F) -> F::Output {
+    let started = Instant::now();
+    let result = publish.await;
+    DOWNSTREAM_PUBLISH_DURATION.observe(started.elapsed().as_secs_f64());
+    result
+}
+
+/// Ensure the message has an ID, so that we can report it back.
+fn ensure_id(publish: &mut Publish) -> String {
+    publish
+        .options
+        .id
+        .get_or_insert_with(|| uuid::Uuid::new_v4().to_string())
+        .clone()
+}
+
+/// The application, device, and channel to acknowledge a message for.
+fn ack_target<'a>(publish: &Publish<'a>) -> (&'a registry::v1::Application, String, String) {
+    (
+        publish.application,
+        publish.device.name.clone(),
+        publish.channel.clone(),
+    )
+}
 
-            // internal error
-            Err(err) => {
-                DOWNSTREAM_EVENTS_COUNTER
-                    .with_label_values(&["http", "Error"])
-                    .inc();
-                Ok(HttpResponse::InternalServerError().json(ErrorInformation {
-                    error: "InternalError".into(),
+/// Add the ID of the accepted message to the response, so that the device can refer to it.
+fn with_event_id(mut response: HttpResponse, id: &str) -> HttpResponse {
+    if let Ok(value) = HeaderValue::from_str(id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(HEADER_EVENT_ID), value);
+    }
+    response
+}
+
+/// Add the suggested publish interval to the response, rounded up to full seconds.
+fn with_suggested_interval(mut response: HttpResponse, interval: Option<Duration>) -> HttpResponse {
+    if let Some(interval) = interval {
+        let seconds = interval.as_secs() + u64::from(interval.subsec_nanos() > 0);
+        response.headers_mut().insert(
+            HeaderName::from_static(HEADER_SUGGESTED_INTERVAL),
+            HeaderValue::from(seconds),
+        );
+    }
+    response
+}
+
+/// Evaluate the outcome of a publish operation.
+///
+/// Returns the error response in case the message was not accepted.
+fn outcome(result: Result<PublishOutcome, PublishError>) -> Result<(), HttpResponse> {
+    match result {
+        // ok, and accepted
+        Ok(PublishOutcome::Accepted) => {
+            DOWNSTREAM_EVENTS_COUNTER
+                .with_label_values(&["http", "Accepted"])
+                .inc();
+            Ok(())
+        }
+
+        // ok, but rejected
+        Ok(PublishOutcome::Rejected) => {
+            DOWNSTREAM_EVENTS_COUNTER
+                .with_label_values(&["http", "Rejected"])
+                .inc();
+            Err(HttpResponse::build(http::StatusCode::NOT_ACCEPTABLE).finish())
+        }
+
+        // ok, but rejected
+        Ok(PublishOutcome::QueueFull) => {
+            DOWNSTREAM_EVENTS_COUNTER
+                .with_label_values(&["http", "QueueFull"])
+                .inc();
+            Err(HttpResponse::build(http::StatusCode::SERVICE_UNAVAILABLE).finish())
+        }
+
+        // too many, or too large properties
+        Err(PublishError::Headers(err)) => {
+            DOWNSTREAM_EVENTS_COUNTER
+                .with_label_values(&["http", "Rejected"])
+                .inc();
+            Err(
+                HttpResponse::build(http::StatusCode::PAYLOAD_TOO_LARGE).json(ErrorInformation {
+                    error: "HeadersTooLarge".into(),
                     message: err.to_string(),
-                }))
-            }
+                }),
+            )
         }
+
+        // internal error
+        Err(err) => {
+            DOWNSTREAM_EVENTS_COUNTER
+                .with_label_values(&["http", "Error"])
+                .inc();
+            Err(HttpResponse::InternalServerError().json(ErrorInformation {
+                error: "InternalError".into(),
+                message: err.to_string(),
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_id() {
+        let response = with_event_id(HttpResponse::Accepted().finish(), "msg1");
+
+        assert_eq!(
+            response.headers().get(HEADER_EVENT_ID),
+            Some(&HeaderValue::from_static("msg1"))
+        );
+    }
+
+    #[test]
+    fn test_suggested_interval() {
+        let response = with_suggested_interval(
+            HttpResponse::Accepted().finish(),
+            Some(Duration::from_millis(1500)),
+        );
+
+        assert_eq!(
+            response.headers().get(HEADER_SUGGESTED_INTERVAL),
+            Some(&HeaderValue::from(2u64))
+        );
     }
 }
@@ -0,0 +1,195 @@
+use drogue_cloud_endpoint_common::error::EndpointError;
+use drogue_cloud_service_api::webapp::web::Bytes;
+use percent_encoding::percent_decode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Handling of form-encoded (`application/x-www-form-urlencoded`) payloads.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FormConfig {
+    /// Convert form-encoded payloads into a JSON object.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+impl FormConfig {
+    /// Convert the payload if it is form-encoded, and conversion is enabled.
+    ///
+    /// Returns the (possibly changed) content type and payload.
+    pub fn convert(
+        &self,
+        content_type: Option<String>,
+        body: Bytes,
+    ) -> Result<(Option<String>, Bytes), EndpointError> {
+        match content_type {
+            Some(content_type) if self.enabled && is_form(&content_type) => {
+                let json = form_to_json(&body)?;
+                let body =
+                    serde_json::to_vec(&json).map_err(|err| EndpointError::InvalidFormat {
+                        source: Box::new(err),
+                    })?;
+                Ok((Some(mime::APPLICATION_JSON.to_string()), body.into()))
+            }
+            content_type => Ok((content_type, body)),
+        }
+    }
+}
+
+fn is_form(content_type: &str) -> bool {
+    content_type
+        .parse::<mime::Mime>()
+        .map(|mime| mime.essence_str() == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str())
+        .unwrap_or_default()
+}
+
+/// Convert form data into a JSON object.
+///
+/// All values are strings. Repeated fields are converted into an array of strings, keeping the
+/// order in which the values appeared.
+pub fn form_to_json(body: &[u8]) -> Result<Value, EndpointError> {
+    let body = std::str::from_utf8(body).map_err(|err| EndpointError::InvalidFormat {
+        source: Box::new(err),
+    })?;
+
+    let mut result = Map::new();
+
+    for pair in body.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let key = decode(key)?;
+        let value = decode(value)?;
+
+        if key.is_empty() {
+            return Err(EndpointError::InvalidRequest {
+                details: "Form field with empty name".into(),
+            });
+        }
+
+        match result.get_mut(&key) {
+            None => {
+                result.insert(key, Value::String(value));
+            }
+            Some(Value::Array(values)) => values.push(Value::String(value)),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, Value::String(value)]);
+            }
+        }
+    }
+
+    Ok(Value::Object(result))
+}
+
+/// Decode a form-encoded key or value.
+fn decode(value: &str) -> Result<String, EndpointError> {
+    // reject broken escape sequences, instead of passing them on
+    let bytes = value.as_bytes();
+    for (i, _) in value.match_indices('%') {
+        let valid = bytes
+            .get(i + 1..i + 3)
+            .map(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .unwrap_or_default();
+        if !valid {
+            return Err(EndpointError::InvalidRequest {
+                details: format!("Invalid escape sequence in form data: {value}"),
+            });
+        }
+    }
+
+    let value = value.replace('+', " ");
+    percent_decode(value.as_bytes())
+        .decode_utf8()
+        .map(|value| value.into_owned())
+        .map_err(|err| EndpointError::InvalidFormat {
+            source: Box::new(err),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_single() {
+        assert_eq!(
+            form_to_json(b"temp=42&name=Living+room%21").unwrap(),
+            json!({"temp": "42", "name": "Living room!"})
+        );
+        assert_eq!(
+            form_to_json(b"flag&empty=").unwrap(),
+            json!({"flag": "", "empty": ""})
+        );
+        assert_eq!(form_to_json(b"").unwrap(), json!({}));
+    }
+
+    #[test]
+    fn test_repeated() {
+        assert_eq!(
+            form_to_json(b"temp=1&temp=2&hum=50&temp=3").unwrap(),
+            json!({"temp": ["1", "2", "3"], "hum": "50"})
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(matches!(
+            form_to_json(b"temp=%zz"),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+        assert!(matches!(
+            form_to_json(b"temp=42%"),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+        assert!(matches!(
+            form_to_json(b"=42"),
+            Err(EndpointError::InvalidRequest { .. })
+        ));
+        assert!(matches!(
+            form_to_json(b"temp=%FF"),
+            Err(EndpointError::InvalidFormat { .. })
+        ));
+        assert!(matches!(
+            form_to_json(&[b't', b'=', 0xFF]),
+            Err(EndpointError::InvalidFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_convert() {
+        let enabled = FormConfig { enabled: true };
+
+        let (content_type, body) = enabled
+            .convert(
+                Some("application/x-www-form-urlencoded; charset=utf-8".into()),
+                Bytes::from_static(b"temp=42"),
+            )
+            .unwrap();
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            json!({"temp": "42"})
+        );
+
+        // other content types are passed through
+
+        let (content_type, body) = enabled
+            .convert(Some("text/plain".into()), Bytes::from_static(b"temp=42"))
+            .unwrap();
+        assert_eq!(content_type.as_deref(), Some("text/plain"));
+        assert_eq!(body.as_ref(), b"temp=42");
+
+        // disabled by default
+
+        let (content_type, body) = FormConfig::default()
+            .convert(
+                Some("application/x-www-form-urlencoded".into()),
+                Bytes::from_static(b"temp=42"),
+            )
+            .unwrap();
+        assert_eq!(
+            content_type.as_deref(),
+            Some("application/x-www-form-urlencoded")
+        );
+        assert_eq!(body.as_ref(), b"temp=42");
+    }
+}
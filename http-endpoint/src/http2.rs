@@ -0,0 +1,146 @@
+use anyhow::Context;
+use drogue_cloud_service_api::webapp::{dev::Server, web::ServiceConfig, App, HttpServer};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Configuration of HTTP/2 without TLS.
+///
+/// With TLS, HTTP/2 is always negotiated using ALPN. Without TLS, clients must use "prior
+/// knowledge", which requires a dedicated listener.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Http2Config {
+    /// Accept HTTP/2 (and HTTP/1.1) without TLS on this address.
+    ///
+    /// The listener is only started if the address is set.
+    #[serde(default)]
+    pub prior_knowledge_bind_addr: Option<String>,
+    /// The keep-alive timeout of idle connections.
+    ///
+    /// Gateways multiplexing many devices over a single connection should keep it open, rather
+    /// than re-connecting.
+    #[serde(default = "default_keep_alive", with = "humantime_serde")]
+    pub keep_alive: Duration,
+}
+
+const fn default_keep_alive() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl Default for Http2Config {
+    fn default() -> Self {
+        Self {
+            prior_knowledge_bind_addr: None,
+            keep_alive: default_keep_alive(),
+        }
+    }
+}
+
+impl Http2Config {
+    /// Validate the configuration, returning the address to bind the prior knowledge listener to.
+    pub fn validate(&self) -> anyhow::Result<Option<SocketAddr>> {
+        if self.keep_alive.is_zero() {
+            anyhow::bail!("HTTP/2 keep-alive must not be zero");
+        }
+
+        self.prior_knowledge_bind_addr
+            .as_ref()
+            .map(|addr| {
+                addr.to_socket_addrs()
+                    .with_context(|| format!("Invalid HTTP/2 bind address: {addr}"))?
+                    .next()
+                    .with_context(|| format!("HTTP/2 bind address did not resolve: {addr}"))
+            })
+            .transpose()
+    }
+
+    /// Create the listener for HTTP/2 with prior knowledge, if configured.
+    ///
+    /// The application is the same as the one of the main listener, but without TLS and client
+    /// certificates. Devices have to authenticate using credentials.
+    pub fn server<F>(&self, app: F) -> anyhow::Result<Option<Server>>
+    where
+        F: Fn(&mut ServiceConfig) + Send + Clone + 'static,
+    {
+        let addr = match self.validate()? {
+            Some(addr) => addr,
+            None => return Ok(None),
+        };
+
+        log::info!("Accepting HTTP/2 with prior knowledge on: {addr}");
+
+        let server = HttpServer::new(move || App::new().configure(app.clone()))
+            .keep_alive(self.keep_alive)
+            .bind_auto_h2c(addr)?
+            .run();
+
+        Ok(Some(server))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_cloud_service_api::webapp::{web, HttpResponse};
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Http2Config::default().validate().unwrap(), None);
+
+        let config = Http2Config {
+            prior_knowledge_bind_addr: Some("127.0.0.1:8081".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate().unwrap(),
+            Some("127.0.0.1:8081".parse().unwrap())
+        );
+
+        let config = Http2Config {
+            prior_knowledge_bind_addr: Some("not an address".into()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Http2Config {
+            keep_alive: Duration::ZERO,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_h2_request() {
+        // find a free port
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let config = Http2Config {
+            prior_knowledge_bind_addr: Some(addr.to_string()),
+            ..Default::default()
+        };
+        let server = config
+            .server(|cfg| {
+                cfg.route("/", web::get().to(HttpResponse::Ok));
+            })
+            .unwrap()
+            .unwrap();
+        let handle = server.handle();
+        actix_rt::spawn(server);
+
+        let client = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+        let response = client.get(format!("http://{addr}/")).send().await.unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.version(), reqwest::Version::HTTP_2);
+
+        handle.stop(true).await;
+    }
+}
@@ -1,34 +1,77 @@
+mod ack;
+mod application;
+mod batch;
+mod bearer;
+mod channel;
+mod cloud_events;
 mod command;
+mod content_type;
+mod credentials;
 mod downstream;
+mod form;
+mod http2;
+mod metrics;
+mod payload;
+mod register;
+mod response;
+mod signature;
 mod telemetry;
 mod ttn;
 mod x509;
 
+use crate::{
+    ack::{AckWebhook, AckWebhookConfig},
+    application::{ApplicationCheckConfig, ApplicationLookup, ApplicationVerifier},
+    batch::BatchConfig,
+    bearer::{AuthMode, BearerAuthenticator, DeviceLookup, TokenValidator},
+    channel::ChannelConfig,
+    cloud_events::CloudEventsConfig,
+    content_type::ContentTypeConfig,
+    credentials::UrlCredentialsConfig,
+    form::FormConfig,
+    http2::Http2Config,
+    payload::PayloadConfig,
+    register::{AutoRegister, AutoRegisterConfig, DeviceRegistration},
+    response::ResponseConfig,
+    signature::{SignatureConfig, SignatureVerifier},
+    telemetry::{PublishAuthenticator, PublishConfig},
+};
 use actix_web::{web, HttpResponse, Responder};
+use drogue_client::registry;
 use drogue_cloud_endpoint_common::{
+    audit::{AuditConfig, AuditLogger},
     auth::{AuthConfig, DeviceAuthenticator},
     command::{Commands, KafkaCommandSource, KafkaCommandSourceConfig},
     psk::{set_ssl_identity, Identity, VerifiedIdentity},
-    sender::{DownstreamSender, ExternalClientPoolConfig},
-    sink::KafkaSink,
+    sampling::{TraceSampler, TraceSamplingConfig},
+    sender::{DeadlineConfig, DownstreamSender, DownstreamSenderConfig, ExternalClientPoolConfig},
+    sink::{create_routed_topics, KafkaReadinessConfig, KafkaSink, RoutingConfig},
 };
 use drogue_cloud_service_api::auth::device::authn::PreSharedKeyOutcome;
 use drogue_cloud_service_api::{
-    kafka::KafkaClientConfig,
+    kafka::{KafkaClientConfig, TopicNaming},
     webapp::{self as actix_web},
 };
 use drogue_cloud_service_common::{
     actix::http::{HttpBuilder, HttpConfig},
     app::{Startup, StartupExt},
+    auth::openid,
+    client::ClientConfig,
     defaults,
+    effective_config::log_effective_config,
     tls::TlsAuthConfig,
 };
-use serde::Deserialize;
+use futures::TryFutureExt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Config {
     pub auth: AuthConfig,
 
+    #[serde(default)]
+    pub audit: AuditConfig,
+
     pub command_source_kafka: KafkaCommandSourceConfig,
 
     pub kafka_downstream_config: KafkaClientConfig,
@@ -39,11 +82,142 @@ pub struct Config {
     #[serde(default = "defaults::check_kafka_topic_ready")]
     pub check_kafka_topic_ready: bool,
 
+    /// Reporting the endpoint as not ready, while the Kafka cluster can't be reached.
+    #[serde(default)]
+    pub kafka_readiness: KafkaReadinessConfig,
+
     #[serde(default)]
     pub endpoint_pool: ExternalClientPoolConfig,
 
+    #[serde(default)]
+    pub downstream: DownstreamSenderConfig,
+
+    /// Routing of events to topics, based on their channel.
+    #[serde(default)]
+    pub routing: RoutingConfig,
+
     #[serde(default)]
     pub http: HttpConfig,
+
+    /// Access to the registry, required when verifying applications, or registering devices.
+    #[serde(default)]
+    pub registry: Option<ClientConfig>,
+
+    #[serde(default)]
+    pub application_check: ApplicationCheckConfig,
+
+    #[serde(default)]
+    pub response: ResponseConfig,
+
+    /// Hierarchical channels, in the path of publish requests.
+    #[serde(default)]
+    pub channels: ChannelConfig,
+
+    /// Inferring the content type of payloads, published without one.
+    #[serde(default)]
+    pub content_type: ContentTypeConfig,
+
+    /// Accepting form-encoded payloads.
+    #[serde(default)]
+    pub form: FormConfig,
+
+    /// Accepting events using the CloudEvents HTTP binding.
+    #[serde(default)]
+    pub cloud_events: CloudEventsConfig,
+
+    /// Accepting multiple messages with a single request.
+    #[serde(default)]
+    pub batch: BatchConfig,
+
+    /// Accepting HTTP/2 without TLS.
+    #[serde(default)]
+    pub http2: Http2Config,
+
+    /// Sampling of the traces of publish requests.
+    #[serde(default)]
+    pub trace_sampling: TraceSamplingConfig,
+
+    /// The maximum processing time of publish requests.
+    #[serde(default)]
+    pub deadline: DeadlineConfig,
+
+    /// Acknowledging accepted messages to a webhook.
+    #[serde(default)]
+    pub ack_webhook: AckWebhookConfig,
+
+    /// Accepting device credentials embedded in the request URL.
+    #[serde(default)]
+    pub url_credentials: UrlCredentialsConfig,
+
+    /// The credentials accepted in the `Authorization` header.
+    #[serde(default)]
+    pub auth_mode: AuthMode,
+
+    /// Validating the bearer tokens of devices, required when accepting bearer tokens.
+    #[serde(default, skip_serializing)]
+    pub bearer: Option<openid::AuthenticatorConfig>,
+
+    /// Verifying HMAC signed payloads.
+    #[serde(default)]
+    pub signature: SignatureConfig,
+
+    /// Limiting the size of payloads.
+    #[serde(default)]
+    pub payload: PayloadConfig,
+
+    /// Registering the devices gateways publish for, if they don't exist yet.
+    #[serde(default)]
+    pub auto_register: AutoRegisterConfig,
+
+    /// The naming of the topics, must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            auth: AuthConfig {
+                auth_disabled: false,
+                url: defaults::authentication_url(),
+                token_config: None,
+                client: Default::default(),
+            },
+            audit: Default::default(),
+            command_source_kafka: KafkaCommandSourceConfig {
+                topic: None,
+                consumer_group: "http_endpoint".into(),
+            },
+            kafka_downstream_config: Default::default(),
+            kafka_command_config: Default::default(),
+            instance: defaults::instance(),
+            check_kafka_topic_ready: defaults::check_kafka_topic_ready(),
+            kafka_readiness: Default::default(),
+            endpoint_pool: Default::default(),
+            downstream: Default::default(),
+            routing: Default::default(),
+            http: Default::default(),
+            registry: Default::default(),
+            application_check: Default::default(),
+            response: Default::default(),
+            channels: Default::default(),
+            content_type: Default::default(),
+            form: Default::default(),
+            cloud_events: Default::default(),
+            batch: Default::default(),
+            http2: Default::default(),
+            trace_sampling: Default::default(),
+            deadline: Default::default(),
+            ack_webhook: Default::default(),
+            url_credentials: Default::default(),
+            auth_mode: Default::default(),
+            bearer: Default::default(),
+            signature: Default::default(),
+            payload: Default::default(),
+            auto_register: Default::default(),
+            topic_naming: Default::default(),
+        }
+    }
 }
 
 async fn index() -> impl Responder {
@@ -52,21 +226,97 @@ async fn index() -> impl Responder {
 
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
     log::info!("Starting HTTP service endpoint");
+    log_effective_config(&config);
 
-    let sender = DownstreamSender::new(
-        KafkaSink::from_config(
-            config.kafka_downstream_config,
-            config.check_kafka_topic_ready,
-        )?,
-        config.instance,
-        config.endpoint_pool,
-    )?;
+    if config.routing.create_topics {
+        create_routed_topics(config.kafka_downstream_config.clone(), &config.routing).await?;
+    }
+
+    let sink = KafkaSink::from_config(
+        config.downstream.idempotence.apply(
+            config
+                .downstream
+                .ordering
+                .apply(config.kafka_downstream_config),
+        ),
+        config.check_kafka_topic_ready,
+    )?
+    .with_routing(config.routing)
+    .with_topic_naming(config.topic_naming.clone());
+    let kafka_readiness = sink.readiness(&config.kafka_readiness);
+
+    let sender = DownstreamSender::new(sink, config.instance, config.endpoint_pool)?
+        .with_config(config.downstream);
+    let downstream_health = sender.health();
+    let maintenance = sender.maintenance();
+    let rate_limiter = sender.rate_limiter();
+    #[cfg(unix)]
+    let shutdown = sender.shutdown();
     let commands = Commands::new();
 
     let http_server_commands = commands.clone();
 
     let device_authenticator = DeviceAuthenticator::new(config.auth).await?;
 
+    let registry = match config.registry {
+        Some(registry) => {
+            let registry: registry::v1::Client = registry.into_client().await?;
+            Some(Arc::new(registry))
+        }
+        None => None,
+    };
+    let application_verifier = ApplicationVerifier::new(
+        config.application_check,
+        registry
+            .clone()
+            .map(|registry| registry as Arc<dyn ApplicationLookup>),
+    )?;
+    let bearer_validator = match config.bearer {
+        Some(bearer) => Some(Arc::new(bearer.into_client().await?) as Arc<dyn TokenValidator>),
+        None => None,
+    };
+    let bearer = BearerAuthenticator::new(
+        config.auth_mode,
+        bearer_validator,
+        registry
+            .clone()
+            .map(|registry| registry as Arc<dyn DeviceLookup>),
+    )?;
+    let register = AutoRegister::new(
+        config.auto_register,
+        registry.map(|registry| registry as Arc<dyn DeviceRegistration>),
+    )?;
+    let response = config.response;
+    let cloud_events = config.cloud_events;
+    let batch = config.batch;
+    let audit = AuditLogger::new(config.audit);
+    let sampler = TraceSampler::new(config.trace_sampling);
+    let deadline = config.deadline;
+    let ack = AckWebhook::new(config.ack_webhook);
+    let url_credentials = config.url_credentials;
+    if url_credentials.enabled {
+        log::warn!("Accepting device credentials from the request URL, these may leak through logs or proxies");
+    }
+    let signature = SignatureVerifier::new(config.signature);
+    let publish_authenticator = PublishAuthenticator {
+        auth: device_authenticator.clone(),
+        audit: audit.clone(),
+        verifier: application_verifier,
+        credentials: url_credentials,
+        bearer,
+        signature: signature.clone(),
+        register,
+    };
+    let payload = config.payload;
+    let publish_config = PublishConfig {
+        response: response.clone(),
+        form: config.form,
+        cloud_events: cloud_events.clone(),
+        payload: payload.clone(),
+        channels: config.channels,
+        content_type: config.content_type,
+    };
+
     let disable_tls_psk: bool = config.http.disable_tls_psk;
     let mut tls_auth_config = TlsAuthConfig::default();
     if !disable_tls_psk {
@@ -106,14 +356,27 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         }));
     }
 
-    let main = HttpBuilder::new(config.http, Some(startup.runtime_config()), move |cfg| {
+    let app = move |cfg: &mut web::ServiceConfig| {
         cfg.app_data(web::Data::new(sender.clone()))
             .app_data(web::Data::new(http_server_commands.clone()))
             .app_data(web::Data::new(device_authenticator.clone()))
+            .app_data(web::Data::new(audit.clone()))
+            .app_data(web::Data::new(publish_authenticator.clone()))
+            .app_data(web::Data::new(publish_config.clone()))
+            .app_data(web::Data::new(response.clone()))
+            .app_data(web::Data::new(cloud_events.clone()))
+            .app_data(web::Data::new(batch.clone()))
+            .app_data(web::Data::new(sampler.clone()))
+            .app_data(web::Data::new(deadline.clone()))
+            .app_data(web::Data::new(ack.clone()))
+            .app_data(web::Data::new(signature.clone()))
+            .app_data(web::Data::new(payload.clone()))
+            .app_data(payload.extractor_config())
             .service(web::resource("/").route(web::get().to(index)))
             // the standard endpoint
             .service(
                 web::scope("/v1")
+                    .wrap_fn(|req, srv| metrics::observe("telemetry", req, srv))
                     .service(
                         web::resource("/{channel}").route(web::post().to(telemetry::publish_plain)),
                     )
@@ -125,29 +388,52 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
             // The Things Network variant
             .service(
                 web::scope("/ttn")
+                    .wrap_fn(|req, srv| metrics::observe("ttn", req, srv))
                     .route("/", web::post().to(ttn::publish_v2))
                     .route("/v2", web::post().to(ttn::publish_v2))
                     .route("/v3", web::post().to(ttn::publish_v3)),
             );
-    })
-    .tls_auth_config(tls_auth_config)
-    .on_connect(move |con, ext| {
-        let (mut psk, cert) = x509::from_socket(con);
-
-        // Disable PSK identity
-        if disable_tls_psk {
-            psk = None;
+
+        // The CloudEvents variant
+        if cloud_events.enabled {
+            cfg.service(
+                web::resource("/cloudevents")
+                    .wrap_fn(|req, srv| metrics::observe("cloudevents", req, srv))
+                    .route(web::post().to(cloud_events::publish)),
+            );
         }
 
-        if let Some(cert) = cert {
-            if !cert.0.is_empty() {
-                log::debug!("Added {} client certificates", cert.0.len());
-                ext.insert(cert);
-            }
+        // The batch variant
+        if batch.enabled {
+            cfg.service(
+                web::resource("/batch/{channel}")
+                    .wrap_fn(|req, srv| metrics::observe("batch", req, srv))
+                    .route(web::post().to(batch::publish)),
+            );
         }
-        ext.insert(psk);
-    })
-    .run()?;
+    };
+
+    let http2 = config.http2.server(app.clone())?;
+
+    let main = HttpBuilder::new(config.http, Some(startup.runtime_config()), app)
+        .tls_auth_config(tls_auth_config)
+        .on_connect(move |con, ext| {
+            let (mut psk, cert) = x509::from_socket(con);
+
+            // Disable PSK identity
+            if disable_tls_psk {
+                psk = None;
+            }
+
+            if let Some(cert) = cert {
+                if !cert.0.is_empty() {
+                    log::debug!("Added {} client certificates", cert.0.len());
+                    ext.insert(cert);
+                }
+            }
+            ext.insert(psk);
+        })
+        .run()?;
 
     // command source
 
@@ -155,12 +441,28 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         commands,
         config.kafka_command_config,
         config.command_source_kafka,
+        &config.topic_naming,
     )?;
 
     // spawn
 
     startup.spawn(main);
+    if let Some(http2) = http2 {
+        startup.spawn(http2.err_into());
+    }
     startup.check(command_source);
+    if let Some(downstream_health) = downstream_health {
+        startup.check(downstream_health);
+    }
+    startup.check(maintenance.clone());
+    startup.spawn(rate_limiter.run_eviction());
+    if let Some(kafka_readiness) = kafka_readiness {
+        startup.check(kafka_readiness);
+    }
+    #[cfg(unix)]
+    startup.spawn(maintenance.listen());
+    #[cfg(unix)]
+    startup.spawn(shutdown.listen());
 
     // done
 
@@ -0,0 +1,153 @@
+//! Metrics of the HTTP requests.
+//!
+//! The metrics are registered with the default registry, which is served by the health server.
+
+use drogue_cloud_service_api::webapp::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::{header, StatusCode},
+    Error,
+};
+use futures::Future;
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter_vec, Histogram, HistogramVec,
+    IntCounterVec,
+};
+use std::time::Instant;
+
+lazy_static! {
+    pub static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "drogue_http_requests",
+        "HTTP requests, by route, status, and content type",
+        &["route", "status", "content_type"],
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "drogue_http_request_duration_seconds",
+        "Duration of handling HTTP requests, by route",
+        &["route"],
+        vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]
+    )
+    .unwrap();
+    pub static ref DOWNSTREAM_PUBLISH_DURATION: Histogram = register_histogram!(
+        "drogue_http_downstream_publish_duration_seconds",
+        "Duration of sending events downstream",
+        vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+    )
+    .unwrap();
+}
+
+/// The content type of a request, as metrics label.
+///
+/// The content type is provided by the client, so it gets mapped to a fixed set of labels.
+pub fn content_type_label(content_type: Option<&str>) -> &'static str {
+    let mime = match content_type {
+        Some(content_type) => content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase(),
+        None => return "none",
+    };
+
+    match mime.as_str() {
+        "application/cloudevents+json" | "application/cloudevents-batch+json" => "cloudevents",
+        "application/x-www-form-urlencoded" => "form",
+        "application/x-ndjson" => "ndjson",
+        "application/octet-stream" => "binary",
+        mime if mime.ends_with("/json") || mime.ends_with("+json") => "json",
+        mime if mime.starts_with("text/") => "text",
+        _ => "other",
+    }
+}
+
+/// Record the requests of a route, to be used with `wrap_fn`.
+pub fn observe<S, B>(
+    route: &'static str,
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+{
+    let content_type = content_type_label(
+        req.headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok()),
+    );
+    let started = Instant::now();
+    let response = srv.call(req);
+
+    async move {
+        let response = response.await;
+        let status = match &response {
+            Ok(response) => response.status(),
+            Err(err) => err.as_response_error().status_code(),
+        };
+        record(route, status, content_type, started);
+        response
+    }
+}
+
+fn record(route: &str, status: StatusCode, content_type: &str, started: Instant) {
+    HTTP_REQUESTS
+        .with_label_values(&[route, status.as_str(), content_type])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[route])
+        .observe(started.elapsed().as_secs_f64());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_cloud_service_api::webapp::{test, web, App, HttpResponse};
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(content_type_label(None), "none");
+        assert_eq!(
+            content_type_label(Some("application/json; charset=utf-8")),
+            "json"
+        );
+        assert_eq!(
+            content_type_label(Some("application/vnd.acme+json")),
+            "json"
+        );
+        assert_eq!(
+            content_type_label(Some("application/cloudevents+json")),
+            "cloudevents"
+        );
+        assert_eq!(content_type_label(Some("Text/Plain")), "text");
+        assert_eq!(content_type_label(Some("image/png")), "other");
+    }
+
+    #[actix_rt::test]
+    async fn test_observe() {
+        let count = || {
+            HTTP_REQUESTS
+                .with_label_values(&["test", "404", "json"])
+                .get()
+        };
+        let before = count();
+
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/test")
+                    .wrap_fn(|req, srv| observe("test", req, srv))
+                    .route(web::post().to(|| async { HttpResponse::NotFound().finish() })),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/test")
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(count(), before + 1);
+    }
+}
@@ -0,0 +1,192 @@
+//! Limiting the size of request payloads.
+//!
+//! Payloads with a `Content-Encoding` of `gzip`, `deflate`, `br`, or `zstd` are decoded when being
+//! extracted, so the limit applies to the decoded payload. The content type is kept, as it
+//! describes the decoded payload.
+
+use drogue_cloud_endpoint_common::error::EndpointError;
+use drogue_cloud_service_api::webapp::web;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PayloadConfig {
+    /// The maximum size of a (decoded) payload, larger payloads are rejected with
+    /// `413 Payload Too Large`.
+    #[serde(default = "default_max_size")]
+    pub max_size: usize,
+
+    /// The maximum size of payloads, by channel, overriding the default maximum size.
+    #[serde(default)]
+    pub channels: HashMap<String, usize>,
+}
+
+const fn default_max_size() -> usize {
+    256 * 1024
+}
+
+impl Default for PayloadConfig {
+    fn default() -> Self {
+        Self {
+            max_size: default_max_size(),
+            channels: Default::default(),
+        }
+    }
+}
+
+impl PayloadConfig {
+    /// The configuration of the payload extractors.
+    ///
+    /// This allows the largest of all limits, the limit of the channel is checked once the
+    /// channel is known.
+    pub fn extractor_config(&self) -> web::PayloadConfig {
+        let limit = self
+            .channels
+            .values()
+            .copied()
+            .fold(self.max_size, usize::max);
+        web::PayloadConfig::new(limit)
+    }
+
+    /// Check the size of a payload, against the limit of its channel.
+    pub fn check(&self, channel: &str, size: usize) -> Result<(), EndpointError> {
+        let details = match self.channels.get(channel) {
+            Some(limit) if size > *limit => format!(
+                "{size} bytes exceed the limit of channel '{channel}' of {limit} bytes, which overrides the default limit of {} bytes",
+                self.max_size
+            ),
+            Some(_) => return Ok(()),
+            None if size > self.max_size => format!(
+                "{size} bytes exceed the default limit of {} bytes, channel '{channel}' has no limit of its own",
+                self.max_size
+            ),
+            None => return Ok(()),
+        };
+
+        Err(EndpointError::PayloadTooLarge { details })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_cloud_service_api::webapp::{
+        http::{header, StatusCode},
+        test::TestRequest,
+        FromRequest, ResponseError,
+    };
+    use flate2::{
+        write::{DeflateEncoder, GzEncoder},
+        Compression,
+    };
+    use serde_json::json;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    async fn extract(
+        config: &PayloadConfig,
+        encoding: &str,
+        body: Vec<u8>,
+    ) -> Result<web::Bytes, StatusCode> {
+        let (req, mut payload) = TestRequest::post()
+            .app_data(config.extractor_config())
+            .insert_header((header::CONTENT_ENCODING, encoding))
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .set_payload(body)
+            .to_http_parts();
+
+        web::Bytes::from_request(&req, &mut payload)
+            .await
+            .map_err(|err| err.as_response_error().status_code())
+    }
+
+    #[tokio::test]
+    async fn test_gzip() {
+        let json = json!({"temp": 42}).to_string();
+
+        let body = extract(&Default::default(), "gzip", gzip(json.as_bytes()))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            json!({"temp": 42})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deflate() {
+        let json = json!({"temp": 42}).to_string();
+
+        let body = extract(&Default::default(), "deflate", deflate(json.as_bytes()))
+            .await
+            .unwrap();
+
+        assert_eq!(body, json.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_decoded_size_limited() {
+        let config = PayloadConfig {
+            max_size: 1024,
+            ..Default::default()
+        };
+        // compresses well below the limit
+        let json = json!({ "data": "0".repeat(4096) }).to_string();
+        let body = gzip(json.as_bytes());
+        assert!(body.len() < config.max_size);
+
+        assert_eq!(
+            extract(&config, "gzip", body).await,
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+    }
+
+    fn channel_config() -> PayloadConfig {
+        PayloadConfig {
+            max_size: 1024,
+            channels: [("telemetry".into(), 16), ("firmware".into(), 4096)].into(),
+        }
+    }
+
+    #[test]
+    fn test_channel_limit() {
+        let config = channel_config();
+
+        assert!(config.check("telemetry", 16).is_ok());
+        assert!(matches!(
+            config.check("telemetry", 17),
+            Err(EndpointError::PayloadTooLarge { .. })
+        ));
+        assert!(config.check("firmware", 4096).is_ok());
+
+        // fall back to the default limit
+        assert!(config.check("state", 1024).is_ok());
+        assert!(matches!(
+            config.check("state", 1025),
+            Err(EndpointError::PayloadTooLarge { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_extractor_allows_largest_limit() {
+        let config = channel_config();
+        let body = vec![b'0'; 4096];
+
+        assert_eq!(
+            extract(&config, "identity", body).await.map(|b| b.len()),
+            Ok(4096)
+        );
+    }
+}
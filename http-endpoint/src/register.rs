@@ -0,0 +1,440 @@
+//! Registering unknown devices, when a gateway publishes on their behalf for the first time.
+
+use async_trait::async_trait;
+use drogue_client::{error::ClientError, meta, registry};
+use drogue_cloud_endpoint_common::{
+    error::EndpointError,
+    sender::{RateLimit, RateLimitConfig, RateLimiter},
+};
+use http::StatusCode;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+lazy_static! {
+    pub static ref AUTO_REGISTER_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "drogue_auto_register",
+        "Devices registered automatically",
+        &["outcome"],
+    )
+    .unwrap();
+}
+
+/// Annotation of the application, allowing its devices to be registered automatically.
+pub const ANNOTATION_AUTO_REGISTER: &str = "drogue.io/auto-register-devices";
+
+/// Registering devices, which a gateway publishes for, but which don't exist in the registry.
+///
+/// This must be enabled by the endpoint, as well as by the application, using the
+/// `drogue.io/auto-register-devices` annotation.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AutoRegisterConfig {
+    /// Enable registering devices, requires access to the registry.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The maximum rate of registrations per application.
+    #[serde(default = "default_rate_limit")]
+    pub rate_limit: RateLimit,
+}
+
+const fn default_rate_limit() -> RateLimit {
+    RateLimit { rate: 1, burst: 10 }
+}
+
+impl Default for AutoRegisterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rate_limit: default_rate_limit(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait DeviceRegistration: Send + Sync {
+    async fn get(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<Option<registry::v1::Device>, ClientError>;
+
+    async fn create(&self, device: &registry::v1::Device) -> Result<(), ClientError>;
+}
+
+#[async_trait]
+impl DeviceRegistration for registry::v1::Client {
+    async fn get(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<Option<registry::v1::Device>, ClientError> {
+        self.get_device(application, device).await
+    }
+
+    async fn create(&self, device: &registry::v1::Device) -> Result<(), ClientError> {
+        self.create_device(device).await.map(|_| ())
+    }
+}
+
+/// Registers devices, as configured by the [`AutoRegisterConfig`].
+#[derive(Clone)]
+pub struct AutoRegister {
+    registry: Option<Arc<dyn DeviceRegistration>>,
+    limiter: RateLimiter,
+}
+
+impl AutoRegister {
+    /// Create a new instance.
+    ///
+    /// Enabling the registration requires access to the registry.
+    pub fn new(
+        config: AutoRegisterConfig,
+        registry: Option<Arc<dyn DeviceRegistration>>,
+    ) -> anyhow::Result<Self> {
+        if !config.enabled {
+            return Ok(Self {
+                registry: None,
+                limiter: Default::default(),
+            });
+        }
+
+        if registry.is_none() {
+            anyhow::bail!("Registering devices requires access to the registry");
+        }
+
+        Ok(Self {
+            registry,
+            limiter: RateLimiter::new(RateLimitConfig {
+                tenant: Some(config.rate_limit),
+                ..Default::default()
+            }),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.registry.is_some()
+    }
+
+    /// Check if the devices of the application may be registered.
+    pub fn is_enabled_for(&self, application: &registry::v1::Application) -> bool {
+        self.is_enabled()
+            && application
+                .metadata
+                .annotations
+                .get(ANNOTATION_AUTO_REGISTER)
+                .map(|value| value == "true")
+                .unwrap_or_default()
+    }
+
+    /// Register a device, which may then be used by the gateway.
+    ///
+    /// If the device got registered concurrently, it is only accepted if the gateway may use it.
+    /// Devices which already exist are never changed, and fail the registration.
+    pub async fn register(
+        &self,
+        application: &registry::v1::Application,
+        gateway: &str,
+        device: &str,
+    ) -> Result<registry::v1::Device, EndpointError> {
+        let registry = match &self.registry {
+            Some(registry) if self.is_enabled_for(application) => registry,
+            _ => return Err(EndpointError::AuthenticationError),
+        };
+
+        let app = &application.metadata.name;
+
+        if let Err(err) = self.limiter.check(app, device) {
+            AUTO_REGISTER_COUNTER
+                .with_label_values(&["RateLimited"])
+                .inc();
+            return Err(err);
+        }
+
+        let result = match registry.get(app, device).await {
+            Ok(Some(_)) => {
+                log::debug!(
+                    "Device {:?} already exists, not registering it for gateway {:?}",
+                    device,
+                    gateway
+                );
+                Err(EndpointError::AuthenticationError)
+            }
+            Ok(None) => {
+                let new_device = Self::new_device(app, gateway, device)?;
+                match registry.create(&new_device).await {
+                    Ok(()) => {
+                        log::info!(
+                            "Registered device {:?} of application {:?} for gateway {:?}",
+                            device,
+                            app,
+                            gateway
+                        );
+                        Ok(new_device)
+                    }
+                    Err(ClientError::Service { code, .. }) if code == StatusCode::CONFLICT => {
+                        self.registered_concurrently(registry.as_ref(), app, gateway, device)
+                            .await
+                    }
+                    Err(err) => {
+                        log::warn!("Failed to register device {:?}: {}", device, err);
+                        Err(EndpointError::AuthenticationError)
+                    }
+                }
+            }
+            Err(err) => {
+                log::warn!("Failed to look up device {:?}: {}", device, err);
+                Err(EndpointError::AuthenticationError)
+            }
+        };
+
+        AUTO_REGISTER_COUNTER
+            .with_label_values(&[match result {
+                Ok(_) => "Registered",
+                Err(_) => "Failed",
+            }])
+            .inc();
+
+        result
+    }
+
+    /// Get the device registered by a concurrent request, if the gateway may use it.
+    async fn registered_concurrently(
+        &self,
+        registry: &dyn DeviceRegistration,
+        application: &str,
+        gateway: &str,
+        device: &str,
+    ) -> Result<registry::v1::Device, EndpointError> {
+        let registered = match registry.get(application, device).await {
+            Ok(Some(registered)) => registered,
+            _ => return Err(EndpointError::AuthenticationError),
+        };
+
+        match registered.section::<registry::v1::DeviceSpecGatewaySelector>() {
+            Some(Ok(selector)) if selector.match_names.iter().any(|name| name == gateway) => {
+                log::debug!("Device {:?} got registered concurrently", device);
+                Ok(registered)
+            }
+            _ => Err(EndpointError::AuthenticationError),
+        }
+    }
+
+    fn new_device(
+        application: &str,
+        gateway: &str,
+        device: &str,
+    ) -> Result<registry::v1::Device, EndpointError> {
+        let mut new_device = registry::v1::Device {
+            metadata: meta::v1::ScopedMetadata {
+                application: application.into(),
+                name: device.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        new_device
+            .set_section(registry::v1::DeviceSpecGatewaySelector {
+                match_names: vec![gateway.into()],
+            })
+            .map_err(|err| {
+                log::warn!(
+                    "Failed to encode the gateway of device {:?}: {}",
+                    device,
+                    err
+                );
+                EndpointError::AuthenticationError
+            })?;
+
+        Ok(new_device)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_client::error::ErrorInformation;
+    use std::{collections::HashMap, sync::Mutex};
+
+    /// A registry, which may report a conflict when creating a device.
+    #[derive(Default)]
+    struct MockRegistry {
+        devices: Mutex<HashMap<String, registry::v1::Device>>,
+        /// A device registered concurrently, when creating the device.
+        concurrent: Mutex<Option<registry::v1::Device>>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl DeviceRegistration for MockRegistry {
+        async fn get(
+            &self,
+            _application: &str,
+            device: &str,
+        ) -> Result<Option<registry::v1::Device>, ClientError> {
+            Ok(self.devices.lock().unwrap().get(device).cloned())
+        }
+
+        async fn create(&self, device: &registry::v1::Device) -> Result<(), ClientError> {
+            if self.fail {
+                return Err(ClientError::Service {
+                    code: StatusCode::INTERNAL_SERVER_ERROR,
+                    error: ErrorInformation {
+                        error: "InternalError".into(),
+                        message: "Failed".into(),
+                    },
+                });
+            }
+
+            let mut devices = self.devices.lock().unwrap();
+            if let Some(concurrent) = self.concurrent.lock().unwrap().take() {
+                devices.insert(device.metadata.name.clone(), concurrent);
+            }
+            if devices.contains_key(&device.metadata.name) {
+                return Err(ClientError::Service {
+                    code: StatusCode::CONFLICT,
+                    error: ErrorInformation {
+                        error: "Conflict".into(),
+                        message: "Device already exists".into(),
+                    },
+                });
+            }
+            devices.insert(device.metadata.name.clone(), device.clone());
+            Ok(())
+        }
+    }
+
+    fn application(annotations: &[(&str, &str)]) -> registry::v1::Application {
+        registry::v1::Application {
+            metadata: meta::v1::NonScopedMetadata {
+                name: "app1".into(),
+                annotations: annotations
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn auto_register(registry: Arc<MockRegistry>, rate_limit: RateLimit) -> AutoRegister {
+        AutoRegister::new(
+            AutoRegisterConfig {
+                enabled: true,
+                rate_limit,
+            },
+            Some(registry),
+        )
+        .unwrap()
+    }
+
+    fn gateways(device: &registry::v1::Device) -> Vec<String> {
+        device
+            .section::<registry::v1::DeviceSpecGatewaySelector>()
+            .unwrap()
+            .unwrap()
+            .match_names
+    }
+
+    #[test]
+    fn test_requires_registry() {
+        assert!(AutoRegister::new(
+            AutoRegisterConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            None
+        )
+        .is_err());
+        assert!(!AutoRegister::new(Default::default(), None)
+            .unwrap()
+            .is_enabled());
+    }
+
+    #[tokio::test]
+    async fn test_register() {
+        let registry = Arc::new(MockRegistry::default());
+        let register = auto_register(registry.clone(), default_rate_limit());
+        let app = application(&[(ANNOTATION_AUTO_REGISTER, "true")]);
+
+        let device = register.register(&app, "gw1", "device1").await.unwrap();
+
+        assert_eq!(device.metadata.application, "app1");
+        assert_eq!(device.metadata.name, "device1");
+        assert_eq!(gateways(&device), vec!["gw1".to_string()]);
+        assert!(registry.devices.lock().unwrap().contains_key("device1"));
+
+        // the device exists now
+        assert!(matches!(
+            register.register(&app, "gw1", "device1").await,
+            Err(EndpointError::AuthenticationError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_application_policy() {
+        let register = auto_register(Default::default(), default_rate_limit());
+
+        for app in [
+            application(&[]),
+            application(&[(ANNOTATION_AUTO_REGISTER, "false")]),
+        ] {
+            assert!(!register.is_enabled_for(&app));
+            assert!(matches!(
+                register.register(&app, "gw1", "device1").await,
+                Err(EndpointError::AuthenticationError)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conflict() {
+        let app = application(&[(ANNOTATION_AUTO_REGISTER, "true")]);
+
+        // registered concurrently, for the same gateway
+        let registry = Arc::new(MockRegistry::default());
+        *registry.concurrent.lock().unwrap() =
+            Some(AutoRegister::new_device("app1", "gw1", "device1").unwrap());
+        let register = auto_register(registry, default_rate_limit());
+        let device = register.register(&app, "gw1", "device1").await.unwrap();
+        assert_eq!(gateways(&device), vec!["gw1".to_string()]);
+
+        // registered concurrently, for a different gateway
+        let registry = Arc::new(MockRegistry::default());
+        *registry.concurrent.lock().unwrap() =
+            Some(AutoRegister::new_device("app1", "gw2", "device1").unwrap());
+        let register = auto_register(registry, default_rate_limit());
+        assert!(matches!(
+            register.register(&app, "gw1", "device1").await,
+            Err(EndpointError::AuthenticationError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failure() {
+        let registry = Arc::new(MockRegistry {
+            fail: true,
+            ..Default::default()
+        });
+        let register = auto_register(registry, default_rate_limit());
+        let app = application(&[(ANNOTATION_AUTO_REGISTER, "true")]);
+
+        assert!(matches!(
+            register.register(&app, "gw1", "device1").await,
+            Err(EndpointError::AuthenticationError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let register = auto_register(Default::default(), RateLimit { rate: 0, burst: 1 });
+        let app = application(&[(ANNOTATION_AUTO_REGISTER, "true")]);
+
+        assert!(register.register(&app, "gw1", "device1").await.is_ok());
+        assert!(matches!(
+            register.register(&app, "gw1", "device2").await,
+            Err(EndpointError::RateLimited { .. })
+        ));
+    }
+}
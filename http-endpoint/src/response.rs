@@ -0,0 +1,75 @@
+use drogue_cloud_service_api::webapp::{http, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The body of a response to a successful publish operation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SuccessBody {
+    /// An empty body.
+    #[default]
+    Empty,
+    /// A simple JSON acknowledgement: `{"success":true}`.
+    Simple,
+    /// A JSON acknowledgement, including the ID of the message.
+    Detailed,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ResponseConfig {
+    #[serde(default)]
+    pub success_body: SuccessBody,
+}
+
+impl ResponseConfig {
+    /// Create the response for an accepted message, which didn't receive a command.
+    pub fn accepted(&self, id: &str) -> HttpResponse {
+        let mut response = HttpResponse::build(http::StatusCode::ACCEPTED);
+        match self.success_body {
+            SuccessBody::Empty => response.finish(),
+            SuccessBody::Simple => response.json(json!({"success": true})),
+            SuccessBody::Detailed => response.json(json!({"success": true, "id": id})),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_cloud_service_api::webapp::body::to_bytes;
+    use serde_json::Value;
+
+    async fn accepted(success_body: SuccessBody) -> (http::StatusCode, Vec<u8>) {
+        let response = ResponseConfig { success_body }.accepted("msg1");
+        let status = response.status();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_empty() {
+        let (status, body) = accepted(SuccessBody::Empty).await;
+        assert_eq!(status, http::StatusCode::ACCEPTED);
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_simple() {
+        let (status, body) = accepted(SuccessBody::Simple).await;
+        assert_eq!(status, http::StatusCode::ACCEPTED);
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            json!({"success": true})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_detailed() {
+        let (status, body) = accepted(SuccessBody::Detailed).await;
+        assert_eq!(status, http::StatusCode::ACCEPTED);
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            json!({"success": true, "id": "msg1"})
+        );
+    }
+}
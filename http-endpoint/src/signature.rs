@@ -0,0 +1,442 @@
+use chrono::Utc;
+use drogue_client::{dialect, registry, Section, Translator};
+use drogue_cloud_endpoint_common::error::EndpointError;
+use drogue_cloud_service_api::webapp::HttpRequest;
+use hmac::{digest::KeyInit, Hmac, Mac};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Header carrying the base64 encoded signature of the payload.
+const HEADER_SIGNATURE: &str = "X-Signature";
+/// Header carrying the time of signing, in seconds since the epoch.
+const HEADER_TIMESTAMP: &str = "X-Signature-Timestamp";
+/// Header carrying a unique value of the request.
+const HEADER_NONCE: &str = "X-Signature-Nonce";
+
+/// The signing key of a device.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeviceSignatureSpec {
+    /// The shared key, used as it is.
+    pub key: String,
+}
+
+dialect!(DeviceSignatureSpec[Section::Spec => "signature"]);
+
+/// The algorithm of the HMAC.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HmacAlgorithm {
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HmacAlgorithm {
+    /// Verify the signature of a message, in constant time.
+    fn verify(&self, key: &[u8], message: &[&[u8]], signature: &[u8]) -> bool {
+        match self {
+            Self::Sha256 => verify::<Hmac<sha2::Sha256>>(key, message, signature),
+            Self::Sha384 => verify::<Hmac<sha2::Sha384>>(key, message, signature),
+            Self::Sha512 => verify::<Hmac<sha2::Sha512>>(key, message, signature),
+        }
+    }
+}
+
+fn verify<M: Mac + KeyInit>(key: &[u8], message: &[&[u8]], signature: &[u8]) -> bool {
+    let mut mac = match <M as KeyInit>::new_from_slice(key) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    for part in message {
+        mac.update(part);
+    }
+    mac.verify_slice(signature).is_ok()
+}
+
+/// Verifying HMAC signed payloads.
+///
+/// Only devices with a signing key in their `signature` spec section must sign their payloads.
+/// The signature covers the timestamp, the nonce, the request and the payload, as
+/// `<timestamp>.<nonce>.<method>.<path>.<channel>.<payload>`. The path includes the query.
+///
+/// Replayed requests are only detected per instance, as the nonces are not shared between
+/// replicas.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignatureConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub algorithm: HmacAlgorithm,
+    /// The maximum difference between the timestamp of the signature and the server time.
+    #[serde(default = "default_window", with = "humantime_serde")]
+    pub window: Duration,
+    /// The number of nonces to remember, for detecting replayed requests.
+    ///
+    /// This should be at least the number of signed requests an instance receives during the
+    /// window, otherwise nonces get evicted while their requests could still be replayed.
+    #[serde(default = "default_nonce_cache_size")]
+    pub nonce_cache_size: NonZeroUsize,
+}
+
+const fn default_window() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+const fn default_nonce_cache_size() -> NonZeroUsize {
+    unsafe { NonZeroUsize::new_unchecked(10 * 1024) }
+}
+
+impl Default for SignatureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            algorithm: Default::default(),
+            window: default_window(),
+            nonce_cache_size: default_nonce_cache_size(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SignatureVerifier {
+    config: SignatureConfig,
+    /// The nonces of valid requests, with the timestamp of their signature.
+    nonces: Arc<Mutex<LruCache<String, i64>>>,
+}
+
+/// The parts of a request, covered by the signature.
+struct Signed<'a> {
+    method: &'a str,
+    path: &'a str,
+    channel: &'a str,
+    body: &'a [u8],
+}
+
+impl SignatureVerifier {
+    pub fn new(config: SignatureConfig) -> Self {
+        Self {
+            nonces: Arc::new(Mutex::new(LruCache::new(config.nonce_cache_size))),
+            config,
+        }
+    }
+
+    /// Verify the signature of a payload, published by an authenticated device to a channel.
+    ///
+    /// The `body` must be the raw body of the request.
+    pub fn verify(
+        &self,
+        application: &registry::v1::Application,
+        device: &registry::v1::Device,
+        req: &HttpRequest,
+        channel: &str,
+        body: &[u8],
+    ) -> Result<(), EndpointError> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let key = match device.section::<DeviceSignatureSpec>() {
+            Some(Ok(spec)) => spec.key,
+            Some(Err(err)) => {
+                return Err(EndpointError::ConfigurationError {
+                    details: format!("Invalid signature section: {err}"),
+                })
+            }
+            None => return Ok(()),
+        };
+
+        let signature = header(req, HEADER_SIGNATURE)?;
+        let timestamp = header(req, HEADER_TIMESTAMP)?
+            .parse::<i64>()
+            .map_err(|_| invalid("Invalid signature timestamp"))?;
+        let nonce = header(req, HEADER_NONCE)?;
+
+        let path = req
+            .uri()
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or_else(|| req.path());
+
+        self.verify_parts(
+            &format!("{}/{}", application.metadata.name, device.metadata.name),
+            key.as_bytes(),
+            signature,
+            timestamp,
+            nonce,
+            Signed {
+                method: req.method().as_str(),
+                path,
+                channel,
+                body,
+            },
+            Utc::now().timestamp(),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn verify_parts(
+        &self,
+        scope: &str,
+        key: &[u8],
+        signature: &str,
+        timestamp: i64,
+        nonce: &str,
+        signed: Signed,
+        now: i64,
+    ) -> Result<(), EndpointError> {
+        if now.abs_diff(timestamp) > self.config.window.as_secs() {
+            return Err(invalid(
+                "Signature timestamp outside of the accepted window",
+            ));
+        }
+        if nonce.is_empty() {
+            return Err(invalid("Empty signature nonce"));
+        }
+
+        let signature = base64::decode(signature).map_err(|_| invalid("Invalid signature"))?;
+        let timestamp = timestamp.to_string();
+        let message = [
+            timestamp.as_bytes(),
+            b".",
+            nonce.as_bytes(),
+            b".",
+            signed.method.as_bytes(),
+            b".",
+            signed.path.as_bytes(),
+            b".",
+            signed.channel.as_bytes(),
+            b".",
+            signed.body,
+        ];
+        if !self.config.algorithm.verify(key, &message, &signature) {
+            return Err(invalid("Signature mismatch"));
+        }
+
+        // only remember nonces of valid requests, so that others can't evict them
+        let mut nonces = self.nonces.lock().unwrap();
+        let key = format!("{scope}/{nonce}");
+        if nonces.contains(&key) {
+            return Err(invalid("Signature nonce was already used"));
+        }
+        if let Some((_, evicted)) = nonces.push(key, now) {
+            if now.abs_diff(evicted) <= self.config.window.as_secs() {
+                log::warn!("Evicted a signature nonce inside of the window, the nonce cache is too small to detect all replayed requests");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn header<'r>(req: &'r HttpRequest, name: &str) -> Result<&'r str, EndpointError> {
+    req.headers()
+        .get(name)
+        .ok_or_else(|| invalid(&format!("Missing header '{name}'")))?
+        .to_str()
+        .map_err(|_| invalid(&format!("Invalid value of header '{name}'")))
+}
+
+fn invalid(details: &str) -> EndpointError {
+    EndpointError::InvalidSignature {
+        details: details.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: &[u8] = b"secret";
+    const NOW: i64 = 1_650_000_000;
+
+    fn verifier() -> SignatureVerifier {
+        SignatureVerifier::new(SignatureConfig {
+            enabled: true,
+            ..Default::default()
+        })
+    }
+
+    fn sign_request(timestamp: i64, nonce: &str, path: &str, channel: &str, body: &[u8]) -> String {
+        let mut mac = <Hmac<sha2::Sha256> as KeyInit>::new_from_slice(KEY).unwrap();
+        mac.update(format!("{timestamp}.{nonce}.POST.{path}.{channel}.").as_bytes());
+        mac.update(body);
+        base64::encode(mac.finalize().into_bytes())
+    }
+
+    fn sign(timestamp: i64, nonce: &str, body: &[u8]) -> String {
+        sign_request(timestamp, nonce, "/v1/telemetry", "telemetry", body)
+    }
+
+    fn signed<'a>(path: &'a str, channel: &'a str) -> Signed<'a> {
+        Signed {
+            method: "POST",
+            path,
+            channel,
+            body: b"{}",
+        }
+    }
+
+    fn verify(
+        verifier: &SignatureVerifier,
+        signature: &str,
+        timestamp: i64,
+        nonce: &str,
+    ) -> Result<(), EndpointError> {
+        verifier.verify_parts(
+            "app1/device1",
+            KEY,
+            signature,
+            timestamp,
+            nonce,
+            signed("/v1/telemetry", "telemetry"),
+            NOW,
+        )
+    }
+
+    #[test]
+    fn test_valid_signature() {
+        let verifier = verifier();
+
+        assert!(verify(&verifier, &sign(NOW, "n1", b"{}"), NOW, "n1").is_ok());
+        // within the window, in both directions
+        assert!(verify(&verifier, &sign(NOW - 300, "n2", b"{}"), NOW - 300, "n2").is_ok());
+        assert!(verify(&verifier, &sign(NOW + 300, "n3", b"{}"), NOW + 300, "n3").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        let verifier = verifier();
+
+        for signature in [
+            sign(NOW, "n1", b"{\"foo\":1}"),
+            sign(NOW - 1, "n1", b"{}"),
+            sign(NOW, "n2", b"{}"),
+            "not-base64!".to_string(),
+        ] {
+            assert!(matches!(
+                verify(&verifier, &signature, NOW, "n1"),
+                Err(EndpointError::InvalidSignature { .. })
+            ));
+        }
+
+        // the algorithm must match
+        let verifier = SignatureVerifier::new(SignatureConfig {
+            enabled: true,
+            algorithm: HmacAlgorithm::Sha512,
+            ..Default::default()
+        });
+        assert!(verify(&verifier, &sign(NOW, "n1", b"{}"), NOW, "n1").is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let verifier = verifier();
+
+        // outside the window
+        for timestamp in [NOW - 301, NOW + 301] {
+            assert!(matches!(
+                verify(&verifier, &sign(timestamp, "n1", b"{}"), timestamp, "n1"),
+                Err(EndpointError::InvalidSignature { .. })
+            ));
+        }
+
+        // reused nonce
+        let signature = sign(NOW, "n1", b"{}");
+        assert!(verify(&verifier, &signature, NOW, "n1").is_ok());
+        assert!(matches!(
+            verify(&verifier, &signature, NOW, "n1"),
+            Err(EndpointError::InvalidSignature { .. })
+        ));
+        // the nonce is scoped to the device
+        assert!(verifier
+            .verify_parts(
+                "app1/device2",
+                KEY,
+                &signature,
+                NOW,
+                "n1",
+                signed("/v1/telemetry", "telemetry"),
+                NOW
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_retargeted() {
+        let verifier = verifier();
+        let signature = sign(NOW, "n1", b"{}");
+
+        for (path, channel) in [
+            ("/v1/commands", "telemetry"),
+            ("/v1/telemetry", "commands"),
+            ("/v1/telemetry?as=device2", "telemetry"),
+        ] {
+            assert!(matches!(
+                verifier.verify_parts(
+                    "app1/device1",
+                    KEY,
+                    &signature,
+                    NOW,
+                    "n1",
+                    signed(path, channel),
+                    NOW
+                ),
+                Err(EndpointError::InvalidSignature { .. })
+            ));
+        }
+
+        let mut other = signed("/v1/telemetry", "telemetry");
+        other.method = "PUT";
+        assert!(verifier
+            .verify_parts("app1/device1", KEY, &signature, NOW, "n1", other, NOW)
+            .is_err());
+    }
+
+    #[test]
+    fn test_verify_request() {
+        use drogue_cloud_service_api::webapp::test::TestRequest;
+
+        let verifier = verifier();
+        let now = Utc::now().timestamp();
+        let mut application = registry::v1::Application::default();
+        application.metadata.name = "app1".into();
+        let mut device = registry::v1::Device::default();
+        device.metadata.name = "device1".into();
+
+        // devices without a key are not affected
+        let req = TestRequest::post().uri("/cloudevents").to_http_request();
+        assert!(verifier
+            .verify(&application, &device, &req, "telemetry", b"{}")
+            .is_ok());
+
+        // devices with a key must sign
+        device
+            .set_section(DeviceSignatureSpec {
+                key: String::from_utf8(KEY.to_vec()).unwrap(),
+            })
+            .unwrap();
+        assert!(matches!(
+            verifier.verify(&application, &device, &req, "telemetry", b"{}"),
+            Err(EndpointError::InvalidSignature { .. })
+        ));
+
+        let req = TestRequest::post()
+            .uri("/cloudevents")
+            .insert_header((HEADER_TIMESTAMP, now.to_string()))
+            .insert_header((HEADER_NONCE, "n1"))
+            .insert_header((
+                HEADER_SIGNATURE,
+                sign_request(now, "n1", "/cloudevents", "telemetry", b"{}"),
+            ))
+            .to_http_request();
+        assert!(verifier
+            .verify(&application, &device, &req, "telemetry", b"{}")
+            .is_ok());
+    }
+}
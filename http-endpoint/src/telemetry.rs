@@ -1,18 +1,45 @@
-use crate::downstream::HttpCommandSender;
+use crate::{
+    ack::AckWebhook,
+    application::ApplicationVerifier,
+    bearer::BearerAuthenticator,
+    channel::ChannelConfig,
+    cloud_events::{CloudEventsConfig, MappedEvent},
+    content_type::ContentTypeConfig,
+    credentials::UrlCredentialsConfig,
+    downstream::HttpCommandSender,
+    form::FormConfig,
+    payload::PayloadConfig,
+    register::AutoRegister,
+    response::ResponseConfig,
+    signature::SignatureVerifier,
+};
+use drogue_client::{error::ClientError, registry};
 use drogue_cloud_endpoint_common::{
-    auth::DeviceAuthenticator,
+    audit::AuditLogger,
+    auth::{AuthValue, DeviceAuthenticator, Username},
     command::Commands,
     error::{EndpointError, HttpEndpointError},
     psk::VerifiedIdentity,
-    sender::{self, DownstreamSender, PublishIdPair},
+    sampling::TraceSampler,
+    sender::{self, DeadlineConfig, DownstreamSender, PublishIdPair},
     x509::ClientCertificateChain,
 };
 use drogue_cloud_service_api::{
     auth::device::authn,
-    webapp::{http::header, web, HttpRequest, HttpResponse},
+    webapp::{
+        http::header::{self, HeaderValue},
+        web, HttpRequest, HttpResponse,
+    },
 };
 use serde::Deserialize;
-use tracing::instrument;
+use tracing::Instrument;
+
+/// Header carrying the record key, provided by the client.
+const HEADER_MESSAGE_KEY: &str = "X-Message-Key";
+/// Header carrying the payload schema version, if not provided as query parameter.
+const HEADER_SCHEMA_VERSION: &str = "X-Schema-Version";
+/// Header carrying the idempotence key, provided by the client.
+const HEADER_IDEMPOTENCY_KEY: &str = "Idempotency-Key";
 
 #[derive(Debug, Deserialize)]
 pub struct PublishCommonOptions {
@@ -20,6 +47,7 @@ pub struct PublishCommonOptions {
     pub device: Option<String>,
 
     pub data_schema: Option<String>,
+    pub schema_version: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,11 +61,35 @@ pub struct PublishOptions {
     pub ct: Option<u64>,
 }
 
+/// Get the application the device claims to publish for.
+///
+/// This is either the explicit application parameter, or the scope of a basic auth username.
+fn claimed_application(
+    opts: &PublishCommonOptions,
+    authorization: Option<&HeaderValue>,
+) -> Option<String> {
+    if let Some(application) = &opts.application {
+        return Some(application.clone());
+    }
+
+    match authorization.map(AuthValue::from) {
+        Some(AuthValue::Basic {
+            username: Username::Scoped { scope, .. },
+            ..
+        }) => Some(scope),
+        _ => None,
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn publish_plain(
     sender: web::Data<DownstreamSender>,
-    auth: web::Data<DeviceAuthenticator>,
+    authenticator: web::Data<PublishAuthenticator>,
+    config: web::Data<PublishConfig>,
+    ack: web::Data<AckWebhook>,
     commands: web::Data<Commands>,
+    sampler: web::Data<TraceSampler>,
+    deadline: web::Data<DeadlineConfig>,
     channel: web::Path<String>,
     web::Query(opts): web::Query<PublishOptions>,
     req: HttpRequest,
@@ -45,9 +97,13 @@ pub async fn publish_plain(
     certs: Option<ClientCertificateChain>,
     verified_identity: Option<VerifiedIdentity>,
 ) -> Result<HttpResponse, HttpEndpointError> {
-    publish(
+    let span = sampler.span(&req, || tracing::info_span!("publish", %channel, ?opts));
+    let backpressure = sender.clone();
+    let request = publish(
         sender,
-        auth,
+        authenticator,
+        config,
+        ack,
         commands,
         channel.into_inner(),
         None,
@@ -56,15 +112,24 @@ pub async fn publish_plain(
         body,
         certs,
         verified_identity,
-    )
-    .await
+    );
+
+    deadline
+        .run(request)
+        .instrument(span)
+        .await
+        .map_err(|err| backpressure.backpressure(err))?
 }
 
 #[allow(clippy::too_many_arguments)]
 pub async fn publish_tail(
     sender: web::Data<DownstreamSender>,
-    auth: web::Data<DeviceAuthenticator>,
+    authenticator: web::Data<PublishAuthenticator>,
+    config: web::Data<PublishConfig>,
+    ack: web::Data<AckWebhook>,
     commands: web::Data<Commands>,
+    sampler: web::Data<TraceSampler>,
+    deadline: web::Data<DeadlineConfig>,
     path: web::Path<(String, String)>,
     web::Query(opts): web::Query<PublishOptions>,
     req: HttpRequest,
@@ -73,26 +138,43 @@ pub async fn publish_tail(
     verified_identity: Option<VerifiedIdentity>,
 ) -> Result<HttpResponse, HttpEndpointError> {
     let (channel, suffix) = path.into_inner();
-    publish(
+    let (channel, suffix) = config.channels.split(channel, suffix)?;
+    let span = sampler.span(
+        &req,
+        || tracing::info_span!("publish", %channel, ?suffix, ?opts),
+    );
+    let backpressure = sender.clone();
+    let request = publish(
         sender,
-        auth,
+        authenticator,
+        config,
+        ack,
         commands,
         channel,
-        Some(suffix),
+        suffix,
         opts,
         req,
         body,
         certs,
         verified_identity,
-    )
-    .await
+    );
+
+    deadline
+        .run(request)
+        .instrument(span)
+        .await
+        .map_err(|err| backpressure.backpressure(err))?
 }
 
+/// Publish a telemetry request.
+///
+/// The span of the request is created by the caller, only if the request is sampled.
 #[allow(clippy::too_many_arguments)]
-#[instrument(skip(downstream, auth, commands, body))]
 pub async fn publish(
     downstream: web::Data<DownstreamSender>,
-    auth: web::Data<DeviceAuthenticator>,
+    authenticator: web::Data<PublishAuthenticator>,
+    config: web::Data<PublishConfig>,
+    ack: web::Data<AckWebhook>,
     commands: web::Data<Commands>,
     channel: String,
     suffix: Option<String>,
@@ -104,49 +186,312 @@ pub async fn publish(
 ) -> Result<HttpResponse, HttpEndpointError> {
     log::debug!("Publish to '{}'", channel);
 
-    let (application, device, r#as) = match auth
-        .authenticate_http(
-            opts.common.application,
-            opts.common.device,
-            req.headers().get(http::header::AUTHORIZATION),
-            certs.map(|c| c.0),
-            verified_identity,
-            opts.r#as.clone(),
-        )
-        .await
-        .map_err(|err| HttpEndpointError(err.into()))?
-        .outcome
-    {
-        authn::Outcome::Fail => return Err(HttpEndpointError(EndpointError::AuthenticationError)),
-        authn::Outcome::Pass {
-            application,
-            device,
-            r#as,
-        } => (application, device, r#as),
-    };
+    downstream.check_maintenance()?;
+
+    let (application, authenticated, PublishIdPair { device, sender }) = authenticator
+        .authenticate(&opts, &req, certs, verified_identity)
+        .await?;
 
-    let PublishIdPair { device, sender } = PublishIdPair::with_devices(device, r#as);
+    authenticator
+        .signature
+        .verify(&application, &authenticated, &req, &channel, &body)?;
+
+    // unwrap structured CloudEvents
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    let (channel, event, content_type, body) =
+        match config
+            .cloud_events
+            .unwrap_structured(&channel, content_type.as_deref(), &body)?
+        {
+            Some(MappedEvent {
+                channel,
+                options,
+                body,
+                ..
+            }) => {
+                let content_type = options.content_type.clone();
+                (channel, options, content_type, body.into())
+            }
+            None => (channel, Default::default(), content_type, body),
+        };
+
+    downstream.check_rate_limit(&application, &device)?;
+    downstream.check_capacity(&application, &device, &channel)?;
+    config.payload.check(&channel, body.len())?;
+
+    // convert form data
+
+    let (content_type, body) = config.form.convert(content_type, body)?;
+
+    // infer a missing content type
+
+    let content_type = config.content_type.infer(content_type, &body);
 
     // publish
 
+    let key = header_value(&req, HEADER_MESSAGE_KEY)?;
+    let schema_version = match opts.common.schema_version.as_deref() {
+        Some(schema_version) => Some(schema_version),
+        None => header_value(&req, HEADER_SCHEMA_VERSION)?,
+    };
+
+    let mut options = sender::PublishOptions {
+        data_schema: opts.common.data_schema.or(event.data_schema),
+        topic: suffix,
+        content_type,
+        key: downstream.client_key(key)?,
+        idempotence_key: downstream
+            .client_idempotence_key(header_value(&req, HEADER_IDEMPOTENCY_KEY)?)?,
+        ..event
+    };
+    downstream.check_timestamp(&mut options, &body)?;
+    downstream.check_schema_version(&mut options, schema_version)?;
+
     let publish = sender::Publish {
         channel,
         application: &application,
         device,
         sender,
-        options: sender::PublishOptions {
-            data_schema: opts.common.data_schema,
-            topic: suffix,
-            content_type: req
-                .headers()
-                .get(header::CONTENT_TYPE)
-                .and_then(|v| v.to_str().ok())
-                .map(|s| s.to_string()),
-            ..Default::default()
-        },
+        options,
     };
 
     downstream
-        .publish_and_await(publish, commands, opts.ct, body)
+        .publish_and_await(publish, &config.response, &ack, commands, opts.ct, body)
         .await
 }
+
+/// Get the value of an optional header, rejecting values which are not valid strings.
+fn header_value<'r>(req: &'r HttpRequest, name: &str) -> Result<Option<&'r str>, EndpointError> {
+    req.headers()
+        .get(name)
+        .map(|v| v.to_str())
+        .transpose()
+        .map_err(|_| EndpointError::InvalidRequest {
+            details: format!("Invalid value of header '{name}'"),
+        })
+}
+
+/// The configuration of publish requests.
+///
+/// Grouped into a single struct, as the handlers would otherwise exceed the maximum number of
+/// extractors.
+#[derive(Clone, Debug)]
+pub struct PublishConfig {
+    pub response: ResponseConfig,
+    pub form: FormConfig,
+    pub cloud_events: CloudEventsConfig,
+    pub payload: PayloadConfig,
+    pub channels: ChannelConfig,
+    pub content_type: ContentTypeConfig,
+}
+
+/// Authenticating the devices of publish requests.
+#[derive(Clone)]
+pub struct PublishAuthenticator {
+    pub auth: DeviceAuthenticator,
+    pub audit: AuditLogger,
+    pub verifier: ApplicationVerifier,
+    pub credentials: UrlCredentialsConfig,
+    pub bearer: BearerAuthenticator,
+    pub signature: SignatureVerifier,
+    pub register: AutoRegister,
+}
+
+impl PublishAuthenticator {
+    /// Verify the application and authenticate the device of a publish request.
+    ///
+    /// This returns the authenticated device, in addition to the IDs to publish with. The outcome
+    /// of the authentication is recorded in the audit log.
+    pub(crate) async fn authenticate(
+        &self,
+        opts: &PublishOptions,
+        req: &HttpRequest,
+        certs: Option<ClientCertificateChain>,
+        verified_identity: Option<VerifiedIdentity>,
+    ) -> Result<
+        (
+            registry::v1::Application,
+            registry::v1::Device,
+            PublishIdPair,
+        ),
+        HttpEndpointError,
+    > {
+        let authorization = self.credentials.authorization(req);
+
+        // bearer tokens are issued for a single device, and can't be used by gateways
+
+        if opts.r#as.is_some()
+            && self.bearer.mode().accepts_bearer()
+            && matches!(
+                authorization.as_deref().map(AuthValue::from),
+                Some(AuthValue::Bearer(_))
+            )
+        {
+            return Err(HttpEndpointError(EndpointError::InvalidRequest {
+                details: "Publishing on behalf of another device ('as') is not supported with bearer tokens".into(),
+            }));
+        }
+
+        // check the application, before trying to authenticate, failing the same way
+
+        if let Some(application) = claimed_application(&opts.common, authorization.as_deref()) {
+            if let Err(err) = self.verifier.verify(&application).await {
+                if matches!(err, EndpointError::AuthenticationError) {
+                    self.audit.log_http(
+                        req,
+                        Some(&application),
+                        opts.common.device.as_deref(),
+                        &Ok(authn::Outcome::Fail),
+                    );
+                }
+                return Err(HttpEndpointError(err));
+            }
+        }
+
+        let result = self
+            .authenticate_as(
+                opts,
+                authorization.as_deref(),
+                certs.clone(),
+                verified_identity.clone(),
+                opts.r#as.clone(),
+            )
+            .await;
+
+        self.audit.log_http(
+            req,
+            opts.common.application.as_deref(),
+            opts.common.device.as_deref(),
+            &result,
+        );
+
+        let outcome = match (
+            result.map_err(|err| HttpEndpointError(err.into()))?,
+            &opts.r#as,
+        ) {
+            (authn::Outcome::Fail, Some(r#as)) if self.register.is_enabled() => {
+                self.register_as(
+                    opts,
+                    authorization.as_deref(),
+                    certs,
+                    verified_identity,
+                    r#as,
+                )
+                .await?
+            }
+            (outcome, _) => outcome,
+        };
+
+        let (application, device, r#as) = match outcome {
+            authn::Outcome::Fail => {
+                return Err(HttpEndpointError(EndpointError::AuthenticationError))
+            }
+            authn::Outcome::Pass {
+                application,
+                device,
+                r#as,
+            } => (application, device, r#as),
+        };
+
+        Ok((
+            application,
+            device.clone(),
+            PublishIdPair::with_devices(device, r#as),
+        ))
+    }
+
+    async fn authenticate_as(
+        &self,
+        opts: &PublishOptions,
+        authorization: Option<&HeaderValue>,
+        certs: Option<ClientCertificateChain>,
+        verified_identity: Option<VerifiedIdentity>,
+        r#as: Option<String>,
+    ) -> Result<authn::Outcome, ClientError> {
+        // client certificates are checked by the authentication service, against the trust anchors
+        // of the application
+
+        if self.bearer.mode().requires_certificate() {
+            return match certs {
+                Some(certs) => self
+                    .auth
+                    .authenticate_http(
+                        opts.common.application.clone(),
+                        opts.common.device.clone(),
+                        None,
+                        Some(certs.0),
+                        None,
+                        r#as,
+                    )
+                    .await
+                    .map(|response| response.outcome),
+                None => Ok(authn::Outcome::Fail),
+            };
+        }
+
+        // bearer tokens carry the identity of the device, basic credentials are checked by the
+        // authentication service
+
+        match authorization.map(AuthValue::from) {
+            Some(AuthValue::Bearer(token)) => self.bearer.authenticate(&token).await,
+            Some(AuthValue::Basic { .. }) if !self.bearer.mode().accepts_basic() => {
+                Ok(authn::Outcome::Fail)
+            }
+            _ => self
+                .auth
+                .authenticate_http(
+                    opts.common.application.clone(),
+                    opts.common.device.clone(),
+                    authorization,
+                    certs.map(|c| c.0),
+                    verified_identity,
+                    r#as,
+                )
+                .await
+                .map(|response| response.outcome),
+        }
+    }
+
+    /// Register the device a gateway publishes for, if it doesn't exist yet.
+    ///
+    /// The gateway must authenticate on its own, and the application must allow registering its
+    /// devices. Otherwise, the authentication fails, as before.
+    async fn register_as(
+        &self,
+        opts: &PublishOptions,
+        authorization: Option<&HeaderValue>,
+        certs: Option<ClientCertificateChain>,
+        verified_identity: Option<VerifiedIdentity>,
+        r#as: &str,
+    ) -> Result<authn::Outcome, HttpEndpointError> {
+        let outcome = self
+            .authenticate_as(opts, authorization, certs, verified_identity, None)
+            .await
+            .map_err(|err| HttpEndpointError(err.into()))?;
+
+        match outcome {
+            authn::Outcome::Pass {
+                application,
+                device,
+                r#as: None,
+            } if self.register.is_enabled_for(&application) => {
+                let as_device = self
+                    .register
+                    .register(&application, &device.metadata.name, r#as)
+                    .await?;
+                Ok(authn::Outcome::Pass {
+                    application,
+                    device,
+                    r#as: Some(as_device),
+                })
+            }
+            _ => Ok(authn::Outcome::Fail),
+        }
+    }
+}
@@ -4,13 +4,17 @@ mod v3;
 pub use v2::*;
 pub use v3::*;
 
-use crate::telemetry::PublishCommonOptions;
+use crate::{
+    ack::AckWebhook, downstream::HttpCommandSender, response::ResponseConfig,
+    signature::SignatureVerifier, telemetry::PublishCommonOptions,
+};
 use chrono::{DateTime, Utc};
 use drogue_client::registry;
 use drogue_cloud_endpoint_common::{
+    audit::AuditLogger,
     auth::DeviceAuthenticator,
     error::{EndpointError, HttpEndpointError},
-    sender::{self, DownstreamSender, PublishId, PublishIdPair, Publisher},
+    sender::{self, DownstreamSender, PublishId, PublishIdPair},
     x509::ClientCertificateChain,
 };
 use drogue_cloud_service_api::{
@@ -53,30 +57,44 @@ pub struct Uplink {
     pub payload_fields: Value,
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn publish_uplink(
     downstream: web::Data<DownstreamSender>,
     auth: web::Data<DeviceAuthenticator>,
+    audit: web::Data<AuditLogger>,
+    response: web::Data<ResponseConfig>,
+    ack: web::Data<AckWebhook>,
+    signature: web::Data<SignatureVerifier>,
     opts: PublishCommonOptions,
     req: HttpRequest,
     cert: Option<ClientCertificateChain>,
     body: web::Bytes,
     uplink: Uplink,
 ) -> Result<HttpResponse, HttpEndpointError> {
+    downstream.check_maintenance()?;
+
     let device_id = uplink.device_id;
 
-    let (application, device, r#as) = match auth
+    let result = auth
         .authenticate_http(
-            opts.application,
-            opts.device,
+            opts.application.clone(),
+            opts.device.clone(),
             req.headers().get(http::header::AUTHORIZATION),
             cert.map(|c| c.0),
             None,
             Some(device_id.clone()),
         )
         .await
-        .map_err(|err| HttpEndpointError(err.into()))?
-        .outcome
-    {
+        .map(|response| response.outcome);
+
+    audit.log_http(
+        &req,
+        opts.application.as_deref(),
+        opts.device.as_deref(),
+        &result,
+    );
+
+    let (application, device, r#as) = match result.map_err(|err| HttpEndpointError(err.into()))? {
         authn::Outcome::Fail => return Err(HttpEndpointError(EndpointError::AuthenticationError)),
         authn::Outcome::Pass {
             application,
@@ -85,6 +103,8 @@ async fn publish_uplink(
         } => (application, device, r#as),
     };
 
+    signature.verify(&application, &device, &req, &uplink.port, &body)?;
+
     log::info!(
         "Application / Device / Device(as): {:?} / {:?} / {:?}",
         application,
@@ -132,8 +152,12 @@ async fn publish_uplink(
 
     let PublishIdPair { device, sender } = PublishIdPair::with_devices(device, r#as);
 
+    downstream.check_rate_limit(&application, &device)?;
+
     send_uplink(
         downstream,
+        &response,
+        &ack,
         application,
         device,
         sender,
@@ -151,6 +175,8 @@ async fn publish_uplink(
 #[inline]
 async fn send_uplink<B>(
     downstream: web::Data<DownstreamSender>,
+    response: &ResponseConfig,
+    ack: &AckWebhook,
     application: registry::v1::Application,
     device: PublishId,
     sender: PublishId,
@@ -165,7 +191,7 @@ where
     B: AsRef<[u8]> + Send + Sync,
 {
     Ok(downstream
-        .publish_http_default(
+        .publish_http(
             sender::Publish {
                 channel: port,
                 application: &application,
@@ -179,6 +205,8 @@ where
                     ..Default::default()
                 },
             },
+            response,
+            ack,
             body,
         )
         .await)
@@ -1,8 +1,12 @@
 use crate::{
+    ack::AckWebhook,
+    response::ResponseConfig,
+    signature::SignatureVerifier,
     telemetry::PublishCommonOptions,
     ttn::{publish_uplink, Uplink},
 };
 use drogue_cloud_endpoint_common::{
+    audit::AuditLogger,
     auth::DeviceAuthenticator,
     error::{EndpointError, HttpEndpointError},
     sender::DownstreamSender,
@@ -11,9 +15,14 @@ use drogue_cloud_endpoint_common::{
 use drogue_cloud_service_api::webapp::{web, HttpRequest, HttpResponse};
 use drogue_ttn::v2;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn publish_v2(
     sender: web::Data<DownstreamSender>,
     auth: web::Data<DeviceAuthenticator>,
+    audit: web::Data<AuditLogger>,
+    response: web::Data<ResponseConfig>,
+    ack: web::Data<AckWebhook>,
+    signature: web::Data<SignatureVerifier>,
     web::Query(opts): web::Query<PublishCommonOptions>,
     req: HttpRequest,
     body: web::Bytes,
@@ -29,6 +38,10 @@ pub async fn publish_v2(
     publish_uplink(
         sender,
         auth,
+        audit,
+        response,
+        ack,
+        signature,
         opts,
         req,
         cert,
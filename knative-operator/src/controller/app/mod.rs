@@ -24,7 +24,7 @@ use drogue_cloud_operator_common::controller::{
         ReconcileError, ReconcileProcessor, ReconcileState, Reconciler,
     },
 };
-use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
+use drogue_cloud_service_api::kafka::ResourceType;
 use k8s_openapi::api::apps::v1::Deployment;
 use kube::Api;
 use operator_framework::install::Delete;
@@ -172,7 +172,10 @@ impl<'a> Reconciler for ApplicationReconciler<'a> {
     ) -> Result<ProcessOutcome<Self::Output>, ReconcileError> {
         // delete
 
-        let topic_name = make_kafka_resource_name(ResourceType::Events(&ctx.app.metadata.name));
+        let topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Events(&ctx.app.metadata.name));
 
         // remove deployment
 
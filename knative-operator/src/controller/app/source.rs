@@ -9,7 +9,7 @@ use drogue_cloud_operator_common::controller::reconciler::{
     progress::{self, OperationOutcome, ProgressOperation},
     ReconcileError,
 };
-use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
+use drogue_cloud_service_api::kafka::ResourceType;
 use humantime::format_duration;
 use k8s_openapi::{api::apps::v1::Deployment, apimachinery::pkg::apis::meta::v1::LabelSelector};
 use kube::Api;
@@ -31,7 +31,10 @@ impl CreateDeployment<'_> {
         ctx: &ConstructContext,
         spec: &KnativeAppSpec,
     ) -> Result<Deployment, ReconcileError> {
-        let topic_name = make_kafka_resource_name(ResourceType::Events(&ctx.app.metadata.name));
+        let topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Events(&ctx.app.metadata.name));
 
         let deployment = create_or_update(
             self.deployments,
@@ -1,6 +1,6 @@
 pub mod app;
 
-use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use drogue_cloud_service_api::kafka::{KafkaClientConfig, TopicNaming};
 use serde::Deserialize;
 
 #[derive(Clone, Debug, Deserialize)]
@@ -13,6 +13,10 @@ pub struct ControllerConfig {
 
     #[serde(default)]
     pub kafka: KafkaClientConfig,
+
+    /// The naming of the topics of applications, which must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -22,7 +22,6 @@ use kube::{api::ListParams, Api};
 use kube_runtime::watcher;
 use serde::Deserialize;
 use std::{fmt::Debug, sync::Arc};
-use tokio::sync::Mutex;
 
 pub const LABEL_APP_MARKER: &str = "drogue.io/application";
 /// We need an annotation to store the actual Drogue Cloud application name, which is not a valid
@@ -67,6 +66,12 @@ fn is_relevant(event: &Event) -> Option<String> {
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
     log::debug!("Config: {:#?}", config);
 
+    config
+        .controller
+        .topic_naming
+        .validate()
+        .map_err(|err| anyhow::anyhow!("Invalid topic naming: {err}"))?;
+
     let kube = kube::client::Client::try_default()
         .await
         .context("Failed to create Kubernetes client")?;
@@ -81,17 +86,18 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     // controller
 
-    let controller = Arc::new(Mutex::new(BaseController::new(
+    let controller = Arc::new(BaseController::new(
         config.work_queue,
         "knative-app",
         ApplicationController::new(config.controller, registry, deployments.clone()),
-    )?));
+    )?);
 
     // event source - device registry
 
     let registry_dispatcher =
         EventDispatcher::one(FnEventProcessor::new(controller.clone(), is_relevant));
     let registry = KafkaEventStream::new(config.kafka_source)?;
+    startup.check(registry.health());
     let registry = registry.run(registry_dispatcher);
 
     // event source - Deployment
@@ -2,7 +2,7 @@ use drogue_cloud_endpoint_common::{
     auth::AuthConfig, command::KafkaCommandSourceConfig, sender::ExternalClientPoolConfig,
 };
 use drogue_cloud_mqtt_common::server::{MqttServerOptions, TlsConfig};
-use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use drogue_cloud_service_api::kafka::{KafkaClientConfig, TopicNaming};
 use drogue_cloud_service_common::defaults;
 use drogue_cloud_service_common::state::StateControllerConfiguration;
 use serde::Deserialize;
@@ -80,6 +80,10 @@ pub struct Config {
     pub endpoint_pool: ExternalClientPoolConfig,
 
     pub state: StateControllerConfiguration,
+
+    /// The naming of the topics, must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
 
 impl TlsConfig for Config {
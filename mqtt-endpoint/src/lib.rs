@@ -46,7 +46,8 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
             KafkaSink::from_config(
                 config.kafka_downstream_config.clone(),
                 config.check_kafka_topic_ready,
-            )?,
+            )?
+            .with_topic_naming(config.topic_naming.clone()),
             config.instance.clone(),
             config.endpoint_pool.clone(),
         )?,
@@ -113,6 +114,7 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         commands,
         config.kafka_command_config,
         config.command_source_kafka,
+        &config.topic_naming,
     )?;
 
     // run
@@ -131,7 +131,8 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     let sender = UpstreamSender::new(
         config.instance,
-        KafkaSink::from_config(config.command_kafka_sink, config.check_kafka_topic_ready)?,
+        KafkaSink::from_config(config.command_kafka_sink, config.check_kafka_topic_ready)?
+            .with_topic_naming(config.service.topic_naming.clone()),
         config.endpoint_pool,
     )?;
 
@@ -4,7 +4,7 @@ mod stream;
 
 pub use app::App;
 
-use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use drogue_cloud_service_api::kafka::{KafkaClientConfig, TopicNaming};
 use serde::Deserialize;
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -15,4 +15,8 @@ pub struct ServiceConfig {
     pub enable_username_password_auth: bool,
     #[serde(default)]
     pub disable_api_keys: bool,
+
+    /// The naming of the topics, must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
@@ -187,7 +187,11 @@ impl Session {
 
         let stream_config = EventStreamConfig {
             kafka: app_res
-                .kafka_target(KafkaEventType::Events, &self.config.kafka)
+                .kafka_target(
+                    &self.config.topic_naming,
+                    KafkaEventType::Events,
+                    &self.config.kafka,
+                )
                 .map(|target| target.into())
                 .map_err(|_| v5::codec::SubscribeAckReason::UnspecifiedError)?,
             consumer_group: group_id.map(|s| format!("{app}.{s}")),
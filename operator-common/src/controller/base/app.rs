@@ -3,6 +3,28 @@ use async_trait::async_trait;
 use drogue_client::{core, error::ClientError, registry, Translator};
 use std::ops::Deref;
 
+/// Annotation on the application, setting the priority of its work queue entries.
+pub const ANNOTATION_RECONCILE_PRIORITY: &str = "drogue.io/reconcile-priority";
+
+/// Get the work queue priority of an application, from its annotation.
+pub fn reconcile_priority(app: &registry::v1::Application) -> Option<i64> {
+    let value = app
+        .metadata
+        .annotations
+        .get(ANNOTATION_RECONCILE_PRIORITY)?;
+    match value.trim().parse() {
+        Ok(priority) => Some(priority),
+        Err(_) => {
+            log::info!(
+                "Ignoring invalid reconcile priority of application '{}': {}",
+                app.metadata.name,
+                value
+            );
+            None
+        }
+    }
+}
+
 #[async_trait]
 impl<S> ResourceOperations<String, registry::v1::Application, registry::v1::Application> for S
 where
@@ -41,4 +63,31 @@ where
     fn ref_output(input: &registry::v1::Application) -> &registry::v1::Application {
         input
     }
+
+    fn priority(input: &registry::v1::Application) -> Option<i64> {
+        reconcile_priority(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reconcile_priority() {
+        let app = |priority: Option<&str>| {
+            let mut app = registry::v1::Application::default();
+            if let Some(priority) = priority {
+                app.metadata
+                    .annotations
+                    .insert(ANNOTATION_RECONCILE_PRIORITY.into(), priority.into());
+            }
+            app
+        };
+
+        assert_eq!(reconcile_priority(&app(None)), None);
+        assert_eq!(reconcile_priority(&app(Some("10"))), Some(10));
+        assert_eq!(reconcile_priority(&app(Some(" -5 "))), Some(-5));
+        assert_eq!(reconcile_priority(&app(Some("high"))), None);
+    }
 }
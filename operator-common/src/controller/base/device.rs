@@ -1,4 +1,7 @@
-use crate::controller::{base::ResourceOperations, reconciler::ReconcileError};
+use crate::controller::{
+    base::{reconcile_priority, ResourceOperations},
+    reconciler::ReconcileError,
+};
 use async_trait::async_trait;
 use drogue_client::{core, error::ClientError, registry, Translator};
 use futures::try_join;
@@ -57,4 +60,8 @@ where
     ) -> &registry::v1::Device {
         &input.1
     }
+
+    fn priority(input: &(registry::v1::Application, registry::v1::Device)) -> Option<i64> {
+        reconcile_priority(&input.0)
+    }
 }
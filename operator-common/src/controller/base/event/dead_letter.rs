@@ -0,0 +1,29 @@
+use async_trait::async_trait;
+use drogue_cloud_registry_events::{Event, EventSender, SendEvent};
+
+/// A sink for events, which could not be dispatched.
+#[async_trait]
+pub trait DeadLetterSink<E>: Send + Sync {
+    async fn dead_letter(&self, event: &E) -> anyhow::Result<()>;
+}
+
+/// A dead letter sink, forwarding registry events to an [`EventSender`].
+#[derive(Clone, Debug)]
+pub struct EventSenderDeadLetterSink<S>(pub S)
+where
+    S: EventSender;
+
+#[async_trait]
+impl<S> DeadLetterSink<Event> for EventSenderDeadLetterSink<S>
+where
+    S: EventSender,
+{
+    async fn dead_letter(&self, event: &Event) -> anyhow::Result<()> {
+        log::warn!("Forwarding event to dead letter sink: {:?}", event);
+        event
+            .clone()
+            .send_with(&self.0)
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to send dead letter: {}", err))
+    }
+}
@@ -1,10 +1,49 @@
+mod dead_letter;
 mod processor;
 
+pub use dead_letter::*;
 pub use processor::*;
 
 use async_trait::async_trait;
 use drogue_cloud_registry_events::stream::EventHandler;
-use std::boxed::Box;
+use serde::{Deserialize, Serialize};
+use std::{boxed::Box, time::Duration};
+
+/// Retry settings of the [`EventDispatcher`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EventDispatcherConfig {
+    /// Number of retries, before giving up on an event.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: usize,
+    /// The delay before the first retry, doubled with every retry.
+    #[serde(default = "default_initial_backoff", with = "humantime_serde")]
+    pub initial_backoff: Duration,
+    /// The maximum delay between two retries.
+    #[serde(default = "default_max_backoff", with = "humantime_serde")]
+    pub max_backoff: Duration,
+}
+
+const fn default_max_retries() -> usize {
+    5
+}
+
+const fn default_initial_backoff() -> Duration {
+    Duration::from_millis(100)
+}
+
+const fn default_max_backoff() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl Default for EventDispatcherConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: default_max_retries(),
+            initial_backoff: default_initial_backoff(),
+            max_backoff: default_max_backoff(),
+        }
+    }
+}
 
 #[async_trait]
 impl<E> EventHandler for EventDispatcher<E>
@@ -15,10 +54,23 @@ where
     type Error = ();
 
     async fn handle(&self, event: &Self::Event) -> Result<(), Self::Error> {
-        for processor in &self.processors {
-            if processor.handle(event).await? {
-                return Ok(());
+        let mut retries = 0;
+        let mut backoff = self.config.initial_backoff;
+
+        while self.dispatch(event).await.is_err() {
+            if retries >= self.config.max_retries {
+                return self.exhausted(event).await;
             }
+
+            retries += 1;
+            log::info!(
+                "Failed to dispatch event, retrying in {:?} ({}/{})",
+                backoff,
+                retries,
+                self.config.max_retries
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, self.config.max_backoff);
         }
 
         Ok(())
@@ -26,14 +78,24 @@ where
 }
 
 /// Dispatch events to the different [`EventHandler`]s.
+///
+/// Failing to dispatch an event will be retried, according to the [`EventDispatcherConfig`].
+/// Once the retries are exhausted, the event will be forwarded to the dead letter sink, if one is
+/// set. Otherwise, the error is reported to the caller.
 pub struct EventDispatcher<E> {
     processors: Vec<Box<dyn EventProcessor<E>>>,
+    config: EventDispatcherConfig,
+    dead_letter: Option<Box<dyn DeadLetterSink<E>>>,
 }
 
 impl<E> EventDispatcher<E> {
     /// Create a new instance for a list of events handlers.
     pub fn new(processors: Vec<Box<dyn EventProcessor<E>>>) -> Self {
-        Self { processors }
+        Self {
+            processors,
+            config: Default::default(),
+            dead_letter: None,
+        }
     }
 
     /// Create a new instance for a single handler.
@@ -43,4 +105,123 @@ impl<E> EventDispatcher<E> {
     {
         Self::new(vec![Box::new(processor)])
     }
+
+    /// Set the retry configuration.
+    pub fn with_config(mut self, config: EventDispatcherConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set a sink for events which failed, and exhausted their retries.
+    pub fn dead_letter<D>(mut self, dead_letter: D) -> Self
+    where
+        D: DeadLetterSink<E> + 'static,
+    {
+        self.dead_letter = Some(Box::new(dead_letter));
+        self
+    }
+
+    async fn dispatch(&self, event: &E) -> Result<(), ()> {
+        for processor in &self.processors {
+            if processor.handle(event).await? {
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn exhausted(&self, event: &E) -> Result<(), ()> {
+        match &self.dead_letter {
+            Some(dead_letter) => dead_letter.dead_letter(event).await.map_err(|err| {
+                log::warn!("Failed to forward event to dead letter sink: {}", err);
+            }),
+            None => {
+                log::warn!("Failed to dispatch event, retries exhausted");
+                Err(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    /// A processor, failing a number of times before succeeding.
+    struct FailingProcessor {
+        failures: usize,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl EventProcessor<String> for FailingProcessor {
+        async fn handle(&self, _event: &String) -> Result<bool, ()> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst);
+            match calls < self.failures {
+                true => Err(()),
+                false => Ok(true),
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct CollectingDeadLetterSink(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl DeadLetterSink<String> for CollectingDeadLetterSink {
+        async fn dead_letter(&self, event: &String) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+    }
+
+    fn dispatcher(failures: usize) -> (EventDispatcher<String>, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::default());
+        let dispatcher = EventDispatcher::one(FailingProcessor {
+            failures,
+            calls: calls.clone(),
+        })
+        .with_config(EventDispatcherConfig {
+            max_retries: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        });
+        (dispatcher, calls)
+    }
+
+    #[tokio::test]
+    async fn test_retry_then_success() {
+        let (dispatcher, calls) = dispatcher(2);
+        let dead_letter = CollectingDeadLetterSink::default();
+        let dispatcher = dispatcher.dead_letter(dead_letter.clone());
+
+        assert_eq!(dispatcher.handle(&"event".to_string()).await, Ok(()));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(dead_letter.0.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retry_exhausted_to_dead_letter() {
+        let (dispatcher, calls) = dispatcher(usize::MAX);
+        let dead_letter = CollectingDeadLetterSink::default();
+        let dispatcher = dispatcher.dead_letter(dead_letter.clone());
+
+        assert_eq!(dispatcher.handle(&"event".to_string()).await, Ok(()));
+        // initial attempt, plus three retries
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(*dead_letter.0.lock().unwrap(), vec!["event".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_exhausted_without_dead_letter() {
+        let (dispatcher, calls) = dispatcher(usize::MAX);
+
+        assert_eq!(dispatcher.handle(&"event".to_string()).await, Err(()));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
 }
@@ -1,7 +1,6 @@
 use crate::controller::base::{BaseController, ControllerOperation, Key};
 use async_trait::async_trait;
 use std::{boxed::Box, sync::Arc};
-use tokio::sync::Mutex;
 use tracing::instrument;
 
 #[cfg(feature = "with_kube")]
@@ -12,50 +11,65 @@ pub trait EventProcessor<E>: Send + Sync {
     async fn handle(&self, event: &E) -> Result<bool, ()>;
 }
 
-pub struct FnEventProcessor<E, K, RI, RO, O>
+/// Processing the keys, extracted from events.
+///
+/// Implementations must be safe to call concurrently, the processors don't serialize the calls.
+#[async_trait]
+pub trait KeyProcessor<K>: Send + Sync {
+    async fn process(&self, key: K) -> Result<(), ()>;
+}
+
+#[async_trait]
+impl<K, RI, RO, O> KeyProcessor<K> for BaseController<K, RI, RO, O>
+where
+    K: Key,
+    RI: Clone + Send + Sync + 'static,
+    RO: Clone + Send + Sync + 'static,
+    O: ControllerOperation<K, RI, RO> + Send + Sync + 'static,
+{
+    async fn process(&self, key: K) -> Result<(), ()> {
+        BaseController::process(self, key).await
+    }
+}
+
+pub struct FnEventProcessor<E, K, P>
 where
     E: Send + Sync,
     K: Key,
-    RI: Clone + Send + Sync,
-    RO: Clone + Send + Sync,
-    O: ControllerOperation<K, RI, RO> + Send + Sync,
+    P: KeyProcessor<K>,
 {
-    base_controller: Arc<Mutex<BaseController<K, RI, RO, O>>>,
+    processor: Arc<P>,
     f: Box<dyn Fn(&E) -> Option<K> + Send + Sync>,
 }
 
-impl<E, K, RI, RO, O> FnEventProcessor<E, K, RI, RO, O>
+impl<E, K, P> FnEventProcessor<E, K, P>
 where
     E: Send + Sync,
     K: Key,
-    RI: Clone + Send + Sync,
-    RO: Clone + Send + Sync,
-    O: ControllerOperation<K, RI, RO> + Send + Sync,
+    P: KeyProcessor<K>,
 {
-    pub fn new<F>(base_controller: Arc<Mutex<BaseController<K, RI, RO, O>>>, f: F) -> Self
+    pub fn new<F>(processor: Arc<P>, f: F) -> Self
     where
         F: Fn(&E) -> Option<K> + Send + Sync + 'static,
     {
         Self {
-            base_controller,
+            processor,
             f: Box::new(f),
         }
     }
 }
 
 #[async_trait]
-impl<E, K, RI, RO, O> EventProcessor<E> for FnEventProcessor<E, K, RI, RO, O>
+impl<E, K, P> EventProcessor<E> for FnEventProcessor<E, K, P>
 where
     E: Send + Sync + 'static,
     K: Key,
-    RI: Clone + Send + Sync + 'static,
-    RO: Clone + Send + Sync + 'static,
-    O: ControllerOperation<K, RI, RO> + Send + Sync + 'static,
+    P: KeyProcessor<K> + 'static,
 {
     #[instrument(skip_all, level = "debug", ret)]
     async fn handle(&self, event: &E) -> Result<bool, ()> {
         if let Some(key) = (self.f)(event) {
-            self.base_controller.lock().await.process(key).await?;
+            self.processor.process(key).await?;
             Ok(true)
         } else {
             Ok(false)
@@ -72,29 +86,21 @@ pub enum NameSource {
 }
 
 #[cfg(feature = "with_kube")]
-pub struct ResourceProcessor<K, RI, RO, O>
+pub struct ResourceProcessor<P>
 where
-    K: Key,
-    RI: Clone + Send + Sync + 'static,
-    RO: Clone + Send + Sync + 'static,
-    O: ControllerOperation<K, RI, RO> + Send + Sync + 'static,
+    P: KeyProcessor<String>,
 {
-    controller: Arc<Mutex<BaseController<K, RI, RO, O>>>,
+    controller: Arc<P>,
     /// The source for the name of the resource to reconcile
     source: NameSource,
 }
 
 #[cfg(feature = "with_kube")]
-impl<RI, RO, O> ResourceProcessor<String, RI, RO, O>
+impl<P> ResourceProcessor<P>
 where
-    RI: Clone + Send + Sync + 'static,
-    RO: Clone + Send + Sync + 'static,
-    O: ControllerOperation<String, RI, RO> + Send + Sync + 'static,
+    P: KeyProcessor<String>,
 {
-    pub fn new(
-        controller: Arc<Mutex<BaseController<String, RI, RO, O>>>,
-        source: NameSource,
-    ) -> Self {
+    pub fn new(controller: Arc<P>, source: NameSource) -> Self {
         Self { controller, source }
     }
 
@@ -117,19 +123,17 @@ where
 
 #[cfg(feature = "with_kube")]
 #[async_trait]
-impl<R, RI, RO, O> EventProcessor<R> for ResourceProcessor<String, RI, RO, O>
+impl<R, P> EventProcessor<R> for ResourceProcessor<P>
 where
     R: Resource + Send + Sync,
-    RI: Clone + Send + Sync + 'static,
-    RO: Clone + Send + Sync + 'static,
-    O: ControllerOperation<String, RI, RO> + Send + Sync + 'static,
+    P: KeyProcessor<String> + 'static,
 {
     #[instrument(skip_all, fields(meta=?event.meta()), ret)]
     async fn handle(&self, event: &R) -> Result<bool, ()> {
         let key = self.extract(event);
         log::debug!("Extracted key from event: {:?}", key);
         if let Some(key) = key {
-            self.controller.lock().await.process(key).await?;
+            self.controller.process(key).await?;
             Ok(true)
         } else {
             Ok(false)
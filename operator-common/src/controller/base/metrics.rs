@@ -0,0 +1,34 @@
+use crate::controller::base::queue::QueueStats;
+use lazy_static::lazy_static;
+use prometheus::{register_gauge_vec, register_int_gauge_vec, GaugeVec, IntGaugeVec};
+
+lazy_static! {
+    static ref QUEUE_DEPTH: IntGaugeVec = register_int_gauge_vec!(
+        "drogue_operator_queue_depth",
+        "Entries in the work queue, due or not",
+        &["instance", "type"]
+    )
+    .unwrap();
+    static ref QUEUE_DELAYED: IntGaugeVec = register_int_gauge_vec!(
+        "drogue_operator_queue_delayed",
+        "Entries in the work queue, which wait for a retry or backoff",
+        &["instance", "type"]
+    )
+    .unwrap();
+    static ref QUEUE_OLDEST_AGE: GaugeVec = register_gauge_vec!(
+        "drogue_operator_queue_oldest_age_seconds",
+        "Time the oldest due entry of the work queue is waiting for being processed",
+        &["instance", "type"]
+    )
+    .unwrap();
+}
+
+/// Record the state of a work queue.
+pub fn record(instance: &str, r#type: &str, stats: &QueueStats) {
+    let labels = &[instance, r#type];
+    QUEUE_DEPTH.with_label_values(labels).set(stats.depth);
+    QUEUE_DELAYED.with_label_values(labels).set(stats.delayed);
+    QUEUE_OLDEST_AGE
+        .with_label_values(labels)
+        .set(stats.oldest_age.unwrap_or_default().as_secs_f64());
+}
@@ -2,6 +2,7 @@ mod app;
 mod conditions;
 mod device;
 mod event;
+mod metrics;
 pub mod queue;
 
 pub use app::*;
@@ -10,13 +11,17 @@ pub use device::*;
 pub use event::*;
 
 use crate::controller::{
-    base::queue::{WorkQueueConfig, WorkQueueHandler, WorkQueueReader, WorkQueueWriter},
+    base::queue::{
+        ReconcileLimiter, ReconcileRateLimit, WorkQueueConfig, WorkQueueHandler, WorkQueueReader,
+        WorkQueueReaderOptions, WorkQueueWriter,
+    },
     reconciler::ReconcileError,
 };
 use anyhow::Context;
 use async_trait::async_trait;
 use drogue_client::error::ClientError;
 use std::{
+    collections::HashMap,
     fmt::Debug,
     fmt::Formatter,
     marker::PhantomData,
@@ -24,7 +29,7 @@ use std::{
     sync::Arc,
     time::Duration,
 };
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedMutexGuard, Semaphore};
 use tracing::instrument;
 
 pub const CONDITION_RECONCILED: &str = "Reconciled";
@@ -66,7 +71,7 @@ where
 {
     writer: WorkQueueWriter,
     _reader: WorkQueueReader<K>,
-    inner: Arc<Mutex<InnerBaseController<K, RI, RO, O>>>,
+    inner: Arc<InnerBaseController<K, RI, RO, O>>,
 }
 
 impl<K, RI, RO, O> BaseController<K, RI, RO, O>
@@ -83,10 +88,11 @@ where
     ) -> Result<Self, anyhow::Error> {
         let r#type = r#type.into();
 
-        let inner = Arc::new(Mutex::new(InnerBaseController {
-            _marker: PhantomData,
+        let inner = Arc::new(InnerBaseController::new(
             operation,
-        }));
+            config.rate_limit,
+            config.concurrency,
+        ));
 
         let pool = config
             .pg
@@ -95,8 +101,24 @@ where
 
         let instance = config.instance;
 
-        let writer = WorkQueueWriter::new(pool.clone(), instance.clone(), r#type.clone());
-        let reader = WorkQueueReader::new(pool, instance, r#type, Handler(inner.clone()));
+        let writer = WorkQueueWriter::new(pool.clone(), instance.clone(), r#type.clone())
+            .with_priority(config.priority.clone());
+        let reader = WorkQueueReader::with_options(
+            pool,
+            instance,
+            r#type,
+            Handler {
+                inner: inner.clone(),
+                writer: writer.clone(),
+            },
+            WorkQueueReaderOptions {
+                fairness: config.fairness,
+                priority: config.priority,
+                concurrency: config.concurrency,
+                metrics_interval: Some(config.metrics_interval),
+                ..Default::default()
+            },
+        );
 
         Ok(Self {
             writer,
@@ -105,20 +127,28 @@ where
         })
     }
 
-    pub async fn process(&mut self, key: K) -> Result<(), ()> {
-        if let Some(queue) = self.inner.lock().await.process(key).await? {
-            self.writer.add(queue.0, queue.1).await?;
+    pub async fn process(&self, key: K) -> Result<(), ()> {
+        if let Some((key, after, priority)) = self.inner.process(key).await? {
+            self.writer.add_with_priority(key, after, priority).await?;
         }
         Ok(())
     }
 }
 
-struct Handler<K, RI, RO, O>(pub Arc<Mutex<InnerBaseController<K, RI, RO, O>>>)
+/// Handles entries of the work queue.
+///
+/// Keys which need to be processed again are added back by the handler, along with the priority
+/// of their resource, which the work queue reader wouldn't know.
+struct Handler<K, RI, RO, O>
 where
     K: Key,
     RI: Clone + Send + Sync + 'static,
     RO: Clone + Send + Sync + 'static,
-    O: ControllerOperation<K, RI, RO> + Send + Sync + 'static;
+    O: ControllerOperation<K, RI, RO> + Send + Sync + 'static,
+{
+    inner: Arc<InnerBaseController<K, RI, RO, O>>,
+    writer: WorkQueueWriter,
+}
 
 #[async_trait]
 impl<K, RI, RO, O> WorkQueueHandler<K> for Handler<K, RI, RO, O>
@@ -129,7 +159,58 @@ where
     O: ControllerOperation<K, RI, RO> + Send + Sync + 'static,
 {
     async fn handle(&self, key: K) -> Result<Option<(K, Duration)>, ()> {
-        self.0.lock().await.process(key).await
+        if let Some((key, after, priority)) = self.inner.process(key).await? {
+            self.writer.add_with_priority(key, after, priority).await?;
+        }
+        Ok(None)
+    }
+}
+
+/// Per-key locks, serializing the processing of a key.
+///
+/// Locks are removed once they are released, and nobody is waiting for them.
+#[derive(Default)]
+struct KeyLocks(std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>);
+
+impl KeyLocks {
+    async fn lock(&self, key: String) -> KeyGuard<'_> {
+        let lock = self
+            .0
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let guard = lock.clone().lock_owned().await;
+        KeyGuard {
+            locks: self,
+            key,
+            lock,
+            guard: Some(guard),
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+struct KeyGuard<'a> {
+    locks: &'a KeyLocks,
+    key: String,
+    lock: Arc<Mutex<()>>,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for KeyGuard<'_> {
+    fn drop(&mut self) {
+        let mut locks = self.locks.0.lock().unwrap();
+        self.guard.take();
+        // only held by the map and us, so nobody is waiting for it
+        if Arc::strong_count(&self.lock) <= 2 {
+            locks.remove(&self.key);
+        }
     }
 }
 
@@ -142,6 +223,9 @@ where
 {
     _marker: PhantomData<(K, RI, RO)>,
     operation: O,
+    limiter: Option<Mutex<ReconcileLimiter>>,
+    permits: Semaphore,
+    locks: KeyLocks,
 }
 
 impl<K, RI, RO, O> InnerBaseController<K, RI, RO, O>
@@ -153,17 +237,45 @@ where
 {
     const MAX_RETRIES: usize = 10;
 
+    fn new(operation: O, rate_limit: Option<ReconcileRateLimit>, concurrency: usize) -> Self {
+        Self {
+            _marker: PhantomData,
+            operation,
+            limiter: rate_limit.map(|limit| Mutex::new(ReconcileLimiter::new(limit))),
+            permits: Semaphore::new(concurrency.max(1)),
+            locks: Default::default(),
+        }
+    }
+
     /// Process a key, locally retrying.
     ///
     /// This runs the operation, and does local retries if they are immediate.
     ///
     /// After a few retries, or when a long-term retry comes back, we forward that to the
     /// work queue and continue.
-    pub async fn process(&mut self, key: K) -> Result<Option<(K, Duration)>, ()> {
+    ///
+    /// If a rate limit is configured, every run of the operation waits for its turn.
+    ///
+    /// Different keys are processed in parallel, up to the configured concurrency. The same key
+    /// is never processed in parallel.
+    ///
+    /// A key forwarded to the work queue carries the last known priority of its resource.
+    pub async fn process(&self, key: K) -> Result<Option<(K, Duration, Option<i64>)>, ()> {
+        let _guard = self.locks.lock(key.to_string()).await;
+        let _permit = self.permits.acquire().await.map_err(|_| ())?;
+
         let mut retries: usize = 0;
+        let mut priority = None;
         loop {
+            if let Some(limiter) = &self.limiter {
+                limiter.lock().await.acquire().await;
+            }
             let result = self.operation.process(&key).await;
             log::debug!("Processing({:?}/{}) -> {:?}", key, retries, result);
+            let result = result.map(|(outcome, current)| {
+                priority = current.or(priority);
+                outcome
+            });
             match result {
                 Ok(OperationOutcome::Complete) | Err(ReconcileError::Permanent(_)) => {
                     break Ok(None)
@@ -172,14 +284,14 @@ where
                     retries += 1;
                     if retries > Self::MAX_RETRIES {
                         log::debug!("Max retries reached, reschedule ...");
-                        break Ok(Some((key, Duration::ZERO)));
+                        break Ok(Some((key, Duration::ZERO, priority)));
                     } else {
                         log::debug!("Retry ...");
                         continue;
                     }
                 }
                 Ok(OperationOutcome::RetryLater(delay)) => {
-                    break Ok(Some((key, delay)));
+                    break Ok(Some((key, delay, priority)));
                 }
             }
         }
@@ -219,6 +331,11 @@ where
     async fn update_if(&self, original: &RO, current: RO) -> Result<(), ReconcileError>;
 
     fn ref_output(input: &RI) -> &RO;
+
+    /// The priority of the resource in the work queue, [`None`] for the configured default.
+    fn priority(_input: &RI) -> Option<i64> {
+        None
+    }
 }
 
 #[async_trait]
@@ -232,47 +349,53 @@ where
 
     #[instrument(skip(self), ret)]
     /// Process the key, any permanent error returned is a fatal error,
-    async fn process(&self, key: &K) -> Result<OperationOutcome, ReconcileError> {
+    ///
+    /// Along with the outcome, the priority of the resource is returned, if it was read.
+    async fn process(&self, key: &K) -> Result<(OperationOutcome, Option<i64>), ReconcileError> {
         // read the resource ...
         match self.get(key).await {
             // ... and process it
-            Ok(Some(resource)) => match self.process_resource(resource.clone()).await {
-                // ... completed -> store and return(done)
-                Ok(ProcessOutcome::Complete(outcome)) => {
-                    self.update_if(Self::ref_output(&resource), outcome).await?;
-                    Ok(OperationOutcome::Complete)
-                }
-                // ... need to re-try -> store and return(retry)
-                Ok(ProcessOutcome::Retry(outcome, delay)) => {
-                    self.update_if(Self::ref_output(&resource), outcome).await?;
-                    Ok(OperationOutcome::retry(delay))
-                }
-                Err(ReconcileError::Temporary(msg)) => {
-                    let outcome = self
-                        .recover(&msg, resource.clone())
-                        .await
-                        .map_err(|_| ReconcileError::permanent("Failed to recover"))?;
-                    self.update_if(Self::ref_output(&resource), outcome).await?;
-                    Ok(OperationOutcome::RetryNow)
-                }
-                Err(ReconcileError::Permanent(msg)) => {
-                    let outcome = self
-                        .recover(&msg, resource.clone())
-                        .await
-                        .map_err(|_| ReconcileError::permanent("Failed to recover"))?;
-                    self.update_if(Self::ref_output(&resource), outcome).await?;
-                    Ok(OperationOutcome::Complete)
-                }
-            },
+            Ok(Some(resource)) => {
+                let priority = Self::priority(&resource);
+                let outcome = match self.process_resource(resource.clone()).await {
+                    // ... completed -> store and return(done)
+                    Ok(ProcessOutcome::Complete(outcome)) => {
+                        self.update_if(Self::ref_output(&resource), outcome).await?;
+                        OperationOutcome::Complete
+                    }
+                    // ... need to re-try -> store and return(retry)
+                    Ok(ProcessOutcome::Retry(outcome, delay)) => {
+                        self.update_if(Self::ref_output(&resource), outcome).await?;
+                        OperationOutcome::retry(delay)
+                    }
+                    Err(ReconcileError::Temporary(msg)) => {
+                        let outcome = self
+                            .recover(&msg, resource.clone())
+                            .await
+                            .map_err(|_| ReconcileError::permanent("Failed to recover"))?;
+                        self.update_if(Self::ref_output(&resource), outcome).await?;
+                        OperationOutcome::RetryNow
+                    }
+                    Err(ReconcileError::Permanent(msg)) => {
+                        let outcome = self
+                            .recover(&msg, resource.clone())
+                            .await
+                            .map_err(|_| ReconcileError::permanent("Failed to recover"))?;
+                        self.update_if(Self::ref_output(&resource), outcome).await?;
+                        OperationOutcome::Complete
+                    }
+                };
+                Ok((outcome, priority))
+            }
             // ... nothing found -> we are done here
             Ok(None) => {
                 // resource is gone, we have finalizers to guard against this
-                Ok(OperationOutcome::Complete)
+                Ok((OperationOutcome::Complete, None))
             }
             // ... error -> retry
             Err(err) => {
                 log::debug!("Reconciliation failed (RetryNow): {}", err);
-                Ok(OperationOutcome::RetryNow)
+                Ok((OperationOutcome::RetryNow, None))
             }
         }
     }
@@ -318,3 +441,139 @@ impl<T> DerefMut for ProcessOutcome<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+
+    /// Records how many keys are processed in parallel.
+    #[derive(Default)]
+    struct Probe {
+        running: StdMutex<HashMap<String, usize>>,
+        max_total: StdMutex<usize>,
+        max_per_key: StdMutex<usize>,
+    }
+
+    impl Probe {
+        fn enter(&self, key: &str) {
+            let mut running = self.running.lock().unwrap();
+            let current = running.entry(key.to_string()).or_default();
+            *current += 1;
+            let per_key = *current;
+            let total = running.values().sum();
+
+            let mut max_per_key = self.max_per_key.lock().unwrap();
+            *max_per_key = (*max_per_key).max(per_key);
+            let mut max_total = self.max_total.lock().unwrap();
+            *max_total = (*max_total).max(total);
+        }
+
+        fn leave(&self, key: &str) {
+            *self.running.lock().unwrap().get_mut(key).unwrap() -= 1;
+        }
+    }
+
+    #[async_trait]
+    impl ResourceOperations<String, (), ()> for Probe {
+        async fn get(&self, _: &String) -> Result<Option<()>, ClientError> {
+            Ok(Some(()))
+        }
+
+        async fn update_if(&self, _: &(), _: ()) -> Result<(), ReconcileError> {
+            Ok(())
+        }
+
+        fn ref_output(input: &()) -> &() {
+            input
+        }
+    }
+
+    #[async_trait]
+    impl ControllerOperation<String, (), ()> for Probe {
+        async fn process_resource(&self, _: ()) -> Result<ProcessOutcome<()>, ReconcileError> {
+            Ok(ProcessOutcome::Complete(()))
+        }
+
+        async fn process(
+            &self,
+            key: &String,
+        ) -> Result<(OperationOutcome, Option<i64>), ReconcileError> {
+            self.enter(key);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.leave(key);
+            Ok((OperationOutcome::Complete, None))
+        }
+
+        async fn recover(&self, _: &str, _: ()) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrency() {
+        let controller = InnerBaseController::new(Probe::default(), None, 4);
+
+        let (app1, app2, app1_again) = tokio::join!(
+            controller.process("app1".to_string()),
+            controller.process("app2".to_string()),
+            controller.process("app1".to_string()),
+        );
+        assert_eq!(app1, Ok(None));
+        assert_eq!(app2, Ok(None));
+        assert_eq!(app1_again, Ok(None));
+
+        // different apps reconcile concurrently, events of one app serialize
+        assert_eq!(*controller.operation.max_total.lock().unwrap(), 2);
+        assert_eq!(*controller.operation.max_per_key.lock().unwrap(), 1);
+
+        assert_eq!(controller.locks.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_sequential() {
+        let controller = InnerBaseController::new(Probe::default(), None, 1);
+
+        let _ = tokio::join!(
+            controller.process("app1".to_string()),
+            controller.process("app2".to_string()),
+        );
+
+        assert_eq!(*controller.operation.max_total.lock().unwrap(), 1);
+    }
+
+    /// Processing keys directly, without the work queue of re-scheduled keys.
+    struct Unqueued(InnerBaseController<String, (), (), Probe>);
+
+    #[async_trait]
+    impl KeyProcessor<String> for Unqueued {
+        async fn process(&self, key: String) -> Result<(), ()> {
+            self.0.process(key).await.map(|_| ())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_concurrency() {
+        let controller = Arc::new(Unqueued(InnerBaseController::new(
+            Probe::default(),
+            None,
+            4,
+        )));
+        let processor =
+            FnEventProcessor::new(controller.clone(), |event: &String| Some(event.clone()));
+
+        let (app1, app2, app1_again) = tokio::join!(
+            processor.handle(&"app1".to_string()),
+            processor.handle(&"app2".to_string()),
+            processor.handle(&"app1".to_string()),
+        );
+        assert_eq!(app1, Ok(true));
+        assert_eq!(app2, Ok(true));
+        assert_eq!(app1_again, Ok(true));
+
+        // events of different apps are handled concurrently, events of one app serialize
+        let probe = &controller.0.operation;
+        assert_eq!(*probe.max_total.lock().unwrap(), 2);
+        assert_eq!(*probe.max_per_key.lock().unwrap(), 1);
+    }
+}
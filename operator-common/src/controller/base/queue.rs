@@ -1,27 +1,261 @@
-use crate::controller::base::Key;
+use crate::controller::base::{metrics, Key};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use deadpool_postgres::{tokio_postgres::types::Type, Pool, PoolError};
 use drogue_cloud_database_common::{postgres, Client};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::future::Future;
 use std::marker::PhantomData;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::{sync::Semaphore, time::Instant};
 use tracing::instrument;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct WorkQueueConfig {
     pub pg: postgres::Config,
     pub instance: String,
+    /// Fair scheduling of entries across namespaces, disabled if missing.
+    #[serde(default)]
+    pub fairness: Option<FairnessConfig>,
+    /// Global limit of the reconcile rate, disabled if missing.
+    #[serde(default)]
+    pub rate_limit: Option<ReconcileRateLimit>,
+    /// Prioritized processing of entries by namespace, disabled if missing.
+    #[serde(default)]
+    pub priority: Option<PriorityConfig>,
+    /// The number of entries processed in parallel.
+    ///
+    /// Entries of the same key are never processed in parallel.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// The interval of reporting the state of the work queue as metrics.
+    #[serde(default = "default_metrics_interval", with = "humantime_serde")]
+    pub metrics_interval: Duration,
 }
 
+const fn default_concurrency() -> usize {
+    1
+}
+
+const fn default_metrics_interval() -> Duration {
+    Duration::from_secs(15)
+}
+
+/// The state of a work queue.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// The number of entries, due or not.
+    pub depth: i64,
+    /// The number of entries, which are not yet due, waiting for a retry or backoff.
+    pub delayed: i64,
+    /// The time the oldest due entry is waiting, [`None`] if no entry is due.
+    pub oldest_age: Option<Duration>,
+}
+
+/// Configuration of the prioritized processing of work queue entries.
+///
+/// The priority of an entry is stored with the entry when it is added. Of the due entries, the
+/// one with the highest priority is processed first. Entries of the same priority are processed
+/// in the order they became due. To prevent starving entries of a low priority, the priority of
+/// an entry is raised by one for every `aging` period it is overdue.
+///
+/// The priority of an entry is taken from the resource, if it has one (e.g. the
+/// `drogue.io/reconcile-priority` annotation of the application), or from its namespace.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PriorityConfig {
+    /// The priority of entries by namespace (e.g. the application name), defaults to zero.
+    #[serde(default)]
+    pub namespaces: HashMap<String, i64>,
+    /// The period an entry must be overdue, for its priority to be raised by one.
+    ///
+    /// A zero duration disables the aging.
+    #[serde(default = "default_aging", with = "humantime_serde")]
+    pub aging: Duration,
+}
+
+const fn default_aging() -> Duration {
+    Duration::from_secs(30)
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        Self {
+            namespaces: Default::default(),
+            aging: default_aging(),
+        }
+    }
+}
+
+impl PriorityConfig {
+    /// The priority of an entry, when it is added.
+    ///
+    /// The priority of the resource takes precedence over the priority of the namespace.
+    fn priority(&self, key: &str, priority: Option<i64>) -> i64 {
+        priority
+            .or_else(|| self.namespaces.get(namespace(key)).copied())
+            .unwrap_or_default()
+    }
+}
+
+/// Configuration of the global reconcile rate limit.
+///
+/// During mass-change storms, e.g. after a platform upgrade, all entries become due at the same
+/// time. Limiting the rate smooths the load on the cluster, while still processing all entries
+/// eventually. Immediate retries of an entry are limited as well.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReconcileRateLimit {
+    /// The steady number of reconciliations per second.
+    pub rate: u32,
+    /// The number of reconciliations which may be processed at once, before the rate applies.
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+}
+
+const fn default_burst() -> u32 {
+    10
+}
+
+/// Token bucket, limiting the rate of reconciliations.
+#[derive(Debug)]
+pub struct ReconcileLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl ReconcileLimiter {
+    pub fn new(config: ReconcileRateLimit) -> Self {
+        let burst = config.burst.max(1) as f64;
+        Self {
+            rate: config.rate.max(1) as f64,
+            burst,
+            tokens: burst,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+
+    /// Wait until the next reconciliation may be processed.
+    pub async fn acquire(&mut self) {
+        self.refill(Instant::now());
+        if self.tokens < 1.0 {
+            let wait = Duration::from_secs_f64((1.0 - self.tokens) / self.rate);
+            log::debug!("Reconcile rate exceeded, waiting {:?}", wait);
+            tokio::time::sleep(wait).await;
+            self.refill(Instant::now());
+        }
+        self.tokens -= 1.0;
+    }
+}
+
+/// Configuration of the fair scheduling of work queue entries.
+///
+/// The namespace of an entry is the part of its key before the first slash, or the full key if
+/// there is none. If entries of more than one namespace are due, a namespace may only be processed
+/// `budget` times in a row, before an entry of a different namespace gets processed.
+///
+/// The key is the string form of the controller's [`Key`]. Controllers of devices use
+/// `<application>/<device>`, so the devices of one application can't starve other applications.
+/// Controllers of applications, like the topic operator, use the bare application name. As a key
+/// is only queued once, every application is a namespace of its own, and the entries are
+/// processed in the order they are due. Sharing a budget between the applications of a tenant
+/// would require the key to carry the tenant.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FairnessConfig {
+    /// The number of entries of a namespace to process in a row, while others are waiting.
+    #[serde(default = "default_budget")]
+    pub budget: usize,
+    /// The number of due entries to consider when selecting the next one.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+}
+
+const fn default_budget() -> usize {
+    4
+}
+
+const fn default_batch_size() -> usize {
+    32
+}
+
+impl Default for FairnessConfig {
+    fn default() -> Self {
+        Self {
+            budget: default_budget(),
+            batch_size: default_batch_size(),
+        }
+    }
+}
+
+/// Get the namespace of a work queue key.
+fn namespace(key: &str) -> &str {
+    key.split_once('/').map(|(ns, _)| ns).unwrap_or(key)
+}
+
+/// Selects the next entry, limiting the number of entries processed in a row for a namespace.
+///
+/// The candidates are expected to be ordered by their due time. As only the selection is
+/// changed, and a key is only present once in the queue, the processing order of a single key
+/// is not affected.
+#[derive(Debug)]
+struct FairScheduler {
+    budget: usize,
+    last: Option<String>,
+    consecutive: usize,
+}
+
+impl FairScheduler {
+    fn new(budget: usize) -> Self {
+        Self {
+            budget: budget.max(1),
+            last: None,
+            consecutive: 0,
+        }
+    }
+
+    /// Select the index of the next candidate.
+    fn select<S: AsRef<str>>(&mut self, candidates: &[S]) -> Option<usize> {
+        let first = candidates.first()?;
+
+        let exhausted = self.consecutive >= self.budget
+            && self.last.as_deref() == Some(namespace(first.as_ref()));
+
+        let idx = match exhausted {
+            true => candidates
+                .iter()
+                .position(|key| Some(namespace(key.as_ref())) != self.last.as_deref())
+                .unwrap_or(0),
+            false => 0,
+        };
+
+        let ns = namespace(candidates[idx].as_ref());
+        if self.last.as_deref() == Some(ns) {
+            self.consecutive += 1;
+        } else {
+            self.last = Some(ns.to_string());
+            self.consecutive = 1;
+        }
+
+        Some(idx)
+    }
+}
+
+#[derive(Clone)]
 pub struct WorkQueueWriter {
     instance: String,
     r#type: String,
     pool: Pool,
+    priority: Option<Arc<PriorityConfig>>,
 }
 
 impl Debug for WorkQueueWriter {
@@ -30,6 +264,7 @@ impl Debug for WorkQueueWriter {
             .field("instance", &self.instance)
             .field("type", &self.r#type)
             .field("pool", &"...")
+            .field("priority", &self.priority)
             .finish()
     }
 }
@@ -62,6 +297,24 @@ where
 #[derive(Clone, Debug)]
 pub struct WorkQueueReaderOptions {
     pub delay: Duration,
+    pub fairness: Option<FairnessConfig>,
+    pub priority: Option<PriorityConfig>,
+    /// The number of entries handled in parallel.
+    pub concurrency: usize,
+    /// The interval of reporting the state of the work queue as metrics, disabled if missing.
+    pub metrics_interval: Option<Duration>,
+}
+
+impl Default for WorkQueueReaderOptions {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_secs(5),
+            fairness: None,
+            priority: None,
+            concurrency: default_concurrency(),
+            metrics_interval: None,
+        }
+    }
 }
 
 impl<K> WorkQueueReader<K>
@@ -72,15 +325,7 @@ where
     where
         H: WorkQueueHandler<K> + 'static,
     {
-        Self::with_options(
-            pool,
-            instance,
-            r#type,
-            handler,
-            WorkQueueReaderOptions {
-                delay: Duration::from_secs(5),
-            },
-        )
+        Self::with_options(pool, instance, r#type, handler, Default::default())
     }
 
     pub fn with_options<H>(
@@ -94,32 +339,60 @@ where
         H: WorkQueueHandler<K> + 'static,
     {
         let running = Arc::new(AtomicBool::new(true));
-        let inner = InnerReader::<K> {
+        let writer = WorkQueueWriter::new(pool, instance, r#type).with_priority(opts.priority);
+        let in_flight = Arc::new(std::sync::Mutex::new(HashSet::new()));
+        let mut inner = InnerReader::<K> {
             _marker: PhantomData,
-            instance: instance.clone(),
-            r#type: r#type.clone(),
-            pool: pool.clone(),
+            writer: writer.clone(),
             running: running.clone(),
             delay: opts.delay,
+            batch_size: opts
+                .fairness
+                .as_ref()
+                .map(|f| f.batch_size)
+                .unwrap_or(1)
+                .max(1),
+            scheduler: opts.fairness.map(|f| FairScheduler::new(f.budget)),
+            in_flight: in_flight.clone(),
         };
-        let writer = WorkQueueWriter {
-            instance,
-            r#type,
-            pool,
-        };
+        if let Some(interval) = opts.metrics_interval {
+            tokio::spawn(report_metrics(writer.clone(), running.clone(), interval));
+        }
+        let handler = Arc::new(handler);
+        let permits = Arc::new(Semaphore::new(opts.concurrency.max(1)));
         tokio::spawn(async move {
-            while let Some(entry) = inner.next().await {
-                match handler.handle(entry.key.clone()).await {
-                    Ok(Some((rt, after))) => {
-                        if writer.add(rt, after).await.is_ok() {
-                            inner.ack(entry).await;
+            loop {
+                // wait for a free slot, before fetching the next entry
+                let permit = match permits.clone().acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                let entry = match inner.next().await {
+                    Some(entry) => entry,
+                    None => break,
+                };
+
+                let key = entry.key.to_string();
+                in_flight.lock().unwrap().insert(key.clone());
+
+                let handler = handler.clone();
+                let writer = writer.clone();
+                let in_flight = in_flight.clone();
+                tokio::spawn(async move {
+                    match handler.handle(entry.key.clone()).await {
+                        Ok(Some((rt, after))) => {
+                            if writer.add(rt, after).await.is_ok() {
+                                writer.ack(entry).await;
+                            }
                         }
+                        Ok(None) => {
+                            writer.ack(entry).await;
+                        }
+                        _ => {}
                     }
-                    Ok(None) => {
-                        inner.ack(entry).await;
-                    }
-                    _ => {}
-                }
+                    in_flight.lock().unwrap().remove(&key);
+                    drop(permit);
+                });
             }
             log::info!("Exiting worker loop");
         });
@@ -146,24 +419,60 @@ impl WorkQueueWriter {
             instance,
             r#type,
             pool,
+            priority: None,
         }
     }
 
+    /// Store the priority of added entries, disabled if [`None`].
+    pub fn with_priority(mut self, priority: Option<PriorityConfig>) -> Self {
+        self.priority = priority.map(Arc::new);
+        self
+    }
+
     #[instrument(err(Debug))]
     pub async fn add<K>(&self, key: K, after: Duration) -> Result<(), ()>
     where
         K: Key,
     {
-        self.insert(key, after).await.map_err(|_| ())
+        self.add_with_priority(key, after, None).await
+    }
+
+    /// Add an entry, with the priority of its resource.
+    ///
+    /// Without a priority of the resource, the priority of its namespace is used. If the
+    /// prioritized processing is disabled, the priority is ignored.
+    #[instrument(err(Debug))]
+    pub async fn add_with_priority<K>(
+        &self,
+        key: K,
+        after: Duration,
+        priority: Option<i64>,
+    ) -> Result<(), ()>
+    where
+        K: Key,
+    {
+        self.insert(key, after, priority).await.map_err(|_| ())
     }
 
     #[instrument(err)]
-    async fn insert<K>(&self, key: K, after: Duration) -> Result<(), PoolError>
+    async fn insert<K>(
+        &self,
+        key: K,
+        after: Duration,
+        priority: Option<i64>,
+    ) -> Result<(), PoolError>
     where
         K: Key,
     {
         let c = self.pool.get().await?;
 
+        let key = key.to_string();
+        let priority = self
+            .priority
+            .as_ref()
+            .map(|config| config.priority(&key, priority))
+            .unwrap_or_default();
+
         let after =
             chrono::Duration::from_std(after).unwrap_or_else(|_| chrono::Duration::max_value());
         let ts = Utc::now() + after;
@@ -181,17 +490,20 @@ INSERT INTO WORKQUEUE (
     INSTANCE,
     TYPE,
     KEY,
-    TS
+    TS,
+    PRIORITY
 ) VALUES (
     $1,
     $2,
     $3,
-    $4
+    $4,
+    $5
 )
 ON CONFLICT (INSTANCE, TYPE, KEY) 
 DO
     UPDATE SET
         TS = EXCLUDED.TS,
+        PRIORITY = EXCLUDED.PRIORITY,
         REV = WORKQUEUE.REV + 1
     WHERE
             WORKQUEUE.TS > EXCLUDED.TS
@@ -207,6 +519,58 @@ DO
                     Type::VARCHAR,
                     Type::VARCHAR,
                     Type::TIMESTAMPTZ,
+                    Type::INT8,
+                ],
+            )
+            .await?;
+
+        let r = c
+            .execute(&stmt, &[&self.instance, &self.r#type, &key, &ts, &priority])
+            .await;
+
+        log::debug!("Insert result: {:?}", r);
+
+        // try result
+
+        r?;
+
+        // done
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn ack<K: Key>(&self, entry: Entry<K>) {
+        if let Err(err) = self
+            .remove(entry.key.to_string(), entry.timestamp, entry.rev)
+            .await
+        {
+            // FIXME: need circuit breaker
+            log::info!("Failed to acknowledge work queue entry: {}", err);
+        }
+    }
+
+    #[instrument(skip(self), ret, err)]
+    async fn remove(&self, key: String, ts: DateTime<Utc>, rev: u64) -> Result<(), anyhow::Error> {
+        let c = self.pool.get().await?;
+
+        let sql = r#"
+DELETE FROM WORKQUEUE WHERE
+    INSTANCE = $1 AND
+    TYPE = $2 AND
+    KEY = $3 AND
+    TS <= $4 AND
+    REV = $5
+"#;
+        let stmt = c
+            .prepare_typed(
+                sql,
+                &[
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::TIMESTAMPTZ,
+                    Type::INT8,
                 ],
             )
             .await?;
@@ -214,11 +578,11 @@ DO
         let r = c
             .execute(
                 &stmt,
-                &[&self.instance, &self.r#type, &key.to_string(), &ts],
+                &[&self.instance, &self.r#type, &key, &ts, &(rev as i64)],
             )
             .await;
 
-        log::debug!("Insert result: {:?}", r);
+        log::debug!("Delete result: {:?}", r);
 
         // try result
 
@@ -228,6 +592,48 @@ DO
 
         Ok(())
     }
+
+    /// Get the current state of the work queue.
+    #[instrument(skip(self), ret, err)]
+    pub async fn stats(&self) -> Result<QueueStats, anyhow::Error> {
+        let c = self.pool.get().await?;
+
+        let sql = r#"
+SELECT
+    COUNT(*) AS DEPTH,
+    COUNT(*) FILTER (WHERE TS > now()) AS DELAYED,
+    EXTRACT(EPOCH FROM now() - MIN(TS) FILTER (WHERE TS <= now()))::FLOAT8 AS OLDEST_AGE
+FROM
+    WORKQUEUE
+WHERE
+    INSTANCE = $1 AND
+    TYPE = $2
+"#;
+        let stmt = c
+            .prepare_typed(sql, &[Type::VARCHAR, Type::VARCHAR])
+            .await?;
+
+        let row = c.query_one(&stmt, &[&self.instance, &self.r#type]).await?;
+
+        Ok(QueueStats {
+            depth: row.try_get("DEPTH")?,
+            delayed: row.try_get("DELAYED")?,
+            oldest_age: row
+                .try_get::<_, Option<f64>>("OLDEST_AGE")?
+                .map(|age| Duration::from_secs_f64(age.max(0.0))),
+        })
+    }
+}
+
+/// Periodically report the state of the work queue as metrics, while the reader is running.
+async fn report_metrics(writer: WorkQueueWriter, running: Arc<AtomicBool>, interval: Duration) {
+    while running.load(Ordering::Relaxed) {
+        match writer.stats().await {
+            Ok(stats) => metrics::record(&writer.instance, &writer.r#type, &stats),
+            Err(err) => log::info!("Failed to read work queue metrics: {}", err),
+        }
+        tokio::time::sleep(interval).await;
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -240,10 +646,12 @@ pub struct Entry<K: Key> {
 struct InnerReader<K> {
     _marker: PhantomData<K>,
     running: Arc<AtomicBool>,
-    instance: String,
-    r#type: String,
-    pool: Pool,
+    writer: WorkQueueWriter,
     delay: Duration,
+    batch_size: usize,
+    scheduler: Option<FairScheduler>,
+    /// Keys currently being handled, which must not be fetched again.
+    in_flight: Arc<std::sync::Mutex<HashSet<String>>>,
 }
 
 impl<K> InnerReader<K>
@@ -251,7 +659,7 @@ where
     K: Key,
 {
     #[instrument(skip(self), level = "debug", ret)]
-    async fn next(&self) -> Option<Entry<K>> {
+    async fn next(&mut self) -> Option<Entry<K>> {
         while self.running.load(Ordering::Relaxed) {
             match self.fetch().await {
                 Ok(Some(next)) => {
@@ -273,8 +681,11 @@ where
     }
 
     #[instrument(skip(self), level = "debug", ret, err)]
-    async fn fetch(&self) -> Result<Option<Entry<K>>, anyhow::Error> {
-        let c = self.pool.get().await?;
+    async fn fetch(&mut self) -> Result<Option<Entry<K>>, anyhow::Error> {
+        let c = self.writer.pool.get().await?;
+
+        // Without a priority, all entries have the same priority of zero. The aging raises the
+        // priority of an entry by one, for every full period it is overdue.
 
         let query = r#"
 SELECT
@@ -286,95 +697,222 @@ FROM
 WHERE
     INSTANCE = $1 AND
     TYPE = $2 AND
-    TS < now()
+    TS < now() AND
+    NOT (KEY = ANY($4))
 ORDER BY
+    CASE WHEN $5 THEN PRIORITY ELSE 0 END
+        + CASE WHEN $6 > 0 THEN FLOOR(EXTRACT(EPOCH FROM now() - TS) / $6)::INT8 ELSE 0 END
+        DESC,
     TS ASC
-LIMIT 1
+LIMIT $3
 "#;
 
         let stmt = c
-            .prepare_typed(query, &[Type::VARCHAR, Type::VARCHAR])
+            .prepare_typed(
+                query,
+                &[
+                    Type::VARCHAR,
+                    Type::VARCHAR,
+                    Type::INT8,
+                    Type::VARCHAR_ARRAY,
+                    Type::BOOL,
+                    Type::FLOAT8,
+                ],
+            )
             .await?;
 
+        let prioritized = self.writer.priority.is_some();
+        let aging = self
+            .writer
+            .priority
+            .as_ref()
+            .map(|config| config.aging.as_secs_f64())
+            .unwrap_or_default();
+
         loop {
-            if let Some(row) = c.query_opt(&stmt, &[&self.instance, &self.r#type]).await? {
+            let in_flight = self
+                .in_flight
+                .lock()
+                .unwrap()
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            let rows = c
+                .query(
+                    &stmt,
+                    &[
+                        &self.writer.instance,
+                        &self.writer.r#type,
+                        &(self.batch_size as i64),
+                        &in_flight,
+                        &prioritized,
+                        &aging,
+                    ],
+                )
+                .await?;
+
+            if rows.is_empty() {
+                return Ok(None);
+            }
+
+            let mut keys = Vec::with_capacity(rows.len());
+            let mut entries = Vec::with_capacity(rows.len());
+
+            for row in rows {
                 let key: String = row.try_get("KEY")?;
                 let timestamp = row.try_get("TS")?;
                 let rev = row.try_get::<_, i64>("REV")? as u64;
 
                 match K::from_string(key.clone()) {
-                    Ok(key) => {
-                        return Ok(Some(Entry {
-                            key,
+                    Ok(parsed) => {
+                        keys.push(key);
+                        entries.push(Entry {
+                            key: parsed,
                             timestamp,
                             rev,
-                        }))
+                        });
                     }
                     Err(_) => {
                         log::info!("Failed to read next entry");
-                        if let Err(err) = self.do_ack(key, timestamp, rev).await {
+                        if let Err(err) = self.writer.remove(key, timestamp, rev).await {
                             // FIXME: circuit breaker
                             log::warn!("Failed to ack invalid entry: {}", err);
                         }
                     }
                 };
-            } else {
-                return Ok(None);
+            }
+
+            let idx = match &mut self.scheduler {
+                Some(scheduler) => scheduler.select(&keys),
+                None => (!entries.is_empty()).then_some(0),
+            };
+
+            if let Some(idx) = idx {
+                return Ok(Some(entries.swap_remove(idx)));
             }
         }
     }
+}
 
-    #[instrument(skip(self))]
-    async fn ack(&self, entry: Entry<K>) {
-        if let Err(err) = self
-            .do_ack(entry.key.to_string(), entry.timestamp, entry.rev)
-            .await
-        {
-            // FIXME: need circuit breaker
-            log::info!("Failed to acknowledge work queue entry: {}", err);
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Simulate processing a queue, returning the processed keys.
+    fn run(
+        scheduler: &mut FairScheduler,
+        mut queue: Vec<String>,
+        batch_size: usize,
+    ) -> Vec<String> {
+        let mut result = vec![];
+        while !queue.is_empty() {
+            let batch = &queue[..batch_size.min(queue.len())];
+            let idx = scheduler.select(batch).unwrap();
+            result.push(queue.remove(idx));
         }
+        result
     }
 
-    #[instrument(skip(self), ret, err)]
-    async fn do_ack(&self, key: String, ts: DateTime<Utc>, rev: u64) -> Result<(), anyhow::Error> {
-        let c = self.pool.get().await?;
+    #[test]
+    fn test_namespace() {
+        assert_eq!(namespace("app1/device1"), "app1");
+        assert_eq!(namespace("app1"), "app1");
 
-        let sql = r#"
-DELETE FROM WORKQUEUE WHERE
-    INSTANCE = $1 AND
-    TYPE = $2 AND
-    KEY = $3 AND
-    TS <= $4 AND
-    REV = $5
-"#;
-        let stmt = c
-            .prepare_typed(
-                sql,
-                &[
-                    Type::VARCHAR,
-                    Type::VARCHAR,
-                    Type::VARCHAR,
-                    Type::TIMESTAMPTZ,
-                    Type::INT8,
-                ],
-            )
-            .await?;
+        // the keys of the controllers
+        let key = ("app1".to_string(), "device1".to_string());
+        assert_eq!(namespace(&Key::to_string(&key)), "app1");
+        let key = "app1".to_string();
+        assert_eq!(namespace(&Key::to_string(&key)), "app1");
+    }
 
-        let r = c
-            .execute(
-                &stmt,
-                &[&self.instance, &self.r#type, &key, &ts, &(rev as i64)],
-            )
-            .await;
+    #[test]
+    fn test_application_keys() {
+        // every application is a namespace of its own, processed in the order they are due
+        let queue = (0..10).map(|i| format!("app{i}")).collect::<Vec<_>>();
+        let result = run(&mut FairScheduler::new(1), queue.clone(), 32);
+        assert_eq!(result, queue);
+    }
 
-        log::debug!("Delete result: {:?}", r);
+    #[test]
+    fn test_fair_distribution() {
+        // a storm of entries for "a", followed by a few of "b"
+        let mut queue = (0..20).map(|i| format!("a/{i}")).collect::<Vec<_>>();
+        queue.extend((0..4).map(|i| format!("b/{i}")));
 
-        // try result
+        let result = run(&mut FairScheduler::new(2), queue, 32);
 
-        r?;
+        // "b" must not wait until "a" is finished
 
-        // done
+        let namespaces = result.iter().map(|k| namespace(k)).collect::<Vec<_>>();
+        assert_eq!(
+            &namespaces[..12],
+            &["a", "a", "b", "a", "a", "b", "a", "a", "b", "a", "a", "b"]
+        );
 
-        Ok(())
+        // per-key ordering, within a namespace, is kept
+
+        let mut last = HashMap::new();
+        for key in &result {
+            let (ns, n) = key.split_once('/').unwrap();
+            let n: u32 = n.parse().unwrap();
+            if let Some(prev) = last.insert(ns, n) {
+                assert!(prev < n, "Out of order: {prev} before {n}");
+            }
+        }
+        assert_eq!(result.len(), 24);
+    }
+
+    #[test]
+    fn test_priority() {
+        let config = PriorityConfig {
+            namespaces: HashMap::from([("safety".to_string(), 10)]),
+            ..Default::default()
+        };
+
+        // by namespace
+        assert_eq!(config.priority("safety/device1", None), 10);
+        assert_eq!(config.priority("safety", None), 10);
+        assert_eq!(config.priority("bulk", None), 0);
+
+        // by resource
+        assert_eq!(config.priority("bulk", Some(5)), 5);
+        assert_eq!(config.priority("safety", Some(-1)), -1);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_rate() {
+        let mut limiter = ReconcileLimiter::new(ReconcileRateLimit { rate: 50, burst: 5 });
+
+        // the burst is processed right away
+
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        // the rest at the steady rate, 20 entries at 50/s
+
+        let start = Instant::now();
+        for _ in 0..20 {
+            limiter.acquire().await;
+        }
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(380),
+            "Too fast: {elapsed:?}"
+        );
+        assert!(
+            elapsed < Duration::from_millis(1000),
+            "Too slow: {elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn test_single_namespace() {
+        let queue = (0..5).map(|i| format!("a/{i}")).collect::<Vec<_>>();
+        let result = run(&mut FairScheduler::new(1), queue.clone(), 32);
+        assert_eq!(result, queue);
     }
 }
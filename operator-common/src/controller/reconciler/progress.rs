@@ -79,13 +79,7 @@ where
 
             context = match result {
                 Ok(OperationOutcome::Continue(context)) => {
-                    conditions.update(
-                        condition_type,
-                        ConditionStatus {
-                            status: Some(true),
-                            ..Default::default()
-                        },
-                    );
+                    conditions.update(condition_type, s.when_continued(&context));
                     context
                 }
                 Ok(OperationOutcome::Retry(mut context, when)) => {
@@ -136,6 +130,14 @@ where
 
     async fn run(&self, context: C) -> Result<C>;
 
+    /// The condition status when the operation completed, and the progress continues.
+    fn when_continued(&self, _context: &C) -> ConditionStatus {
+        ConditionStatus {
+            status: Some(true),
+            ..Default::default()
+        }
+    }
+
     fn when_skipped(&self, context: C) -> (C, ConditionStatus) {
         (context, ConditionStatus::default())
     }
@@ -0,0 +1,573 @@
+//! Leader election, using a Kubernetes lease.
+//!
+//! This allows running multiple replicas of an operator, with only the leader reconciling.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use drogue_cloud_service_api::health::{HealthCheckError, HealthChecked};
+use futures::{
+    future::{select, Either},
+    pin_mut, Future,
+};
+use k8s_openapi::{
+    api::coordination::v1::{Lease, LeaseSpec},
+    apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta},
+};
+use kube::{api::PostParams, Api};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LeaderElectionConfig {
+    /// Enable leader election, required when running more than one replica.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The name of the lease, defaults to the name of the operator.
+    #[serde(default)]
+    pub lease_name: Option<String>,
+    /// The namespace of the lease, defaults to the namespace of the Kubernetes client.
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// The identity of this replica, defaults to the hostname, which is the name of the pod.
+    #[serde(default)]
+    pub identity: Option<String>,
+    /// The time after which a lease, which wasn't renewed, may be taken over by another replica.
+    #[serde(default = "default_lease_duration", with = "humantime_serde")]
+    pub lease_duration: Duration,
+    /// The time the leader keeps trying to renew the lease, before it gives up leading.
+    ///
+    /// This must be shorter than the lease duration, so that the leader stops before another
+    /// replica may take over.
+    #[serde(default = "default_renew_deadline", with = "humantime_serde")]
+    pub renew_deadline: Duration,
+    /// The interval of renewing the lease, and of standbys trying to acquire it.
+    ///
+    /// This must be shorter than the renew deadline.
+    #[serde(default = "default_renew_interval", with = "humantime_serde")]
+    pub renew_interval: Duration,
+}
+
+const fn default_lease_duration() -> Duration {
+    Duration::from_secs(15)
+}
+
+const fn default_renew_deadline() -> Duration {
+    Duration::from_secs(10)
+}
+
+const fn default_renew_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl Default for LeaderElectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            lease_name: None,
+            namespace: None,
+            identity: None,
+            lease_duration: default_lease_duration(),
+            renew_deadline: default_renew_deadline(),
+            renew_interval: default_renew_interval(),
+        }
+    }
+}
+
+impl LeaderElectionConfig {
+    /// Check that the timings allow the leader to step down, before another replica takes over.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.renew_deadline >= self.lease_duration {
+            anyhow::bail!(
+                "The renew deadline ({:?}) must be shorter than the lease duration ({:?})",
+                self.renew_deadline,
+                self.lease_duration
+            );
+        }
+        if self.renew_interval >= self.renew_deadline {
+            anyhow::bail!(
+                "The renew interval ({:?}) must be shorter than the renew deadline ({:?})",
+                self.renew_interval,
+                self.renew_deadline
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Whether this replica is currently the leader.
+///
+/// Standbys report as not ready.
+#[derive(Clone, Debug, Default)]
+pub struct Leadership(Arc<AtomicBool>);
+
+impl Leadership {
+    pub fn is_leader(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, leader: bool) {
+        self.0.store(leader, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl HealthChecked for Leadership {
+    async fn is_ready(&self) -> Result<(), HealthCheckError> {
+        match self.is_leader() {
+            true => Ok(()),
+            false => HealthCheckError::nok("Not the leader"),
+        }
+    }
+}
+
+/// What to do with a lease.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Decision {
+    /// Take over the lease, which is free or expired.
+    Acquire,
+    /// Renew the lease, which we hold.
+    Renew,
+    /// Leave the lease to its current holder.
+    Follow,
+}
+
+/// The last observed state of the lease, and when it was observed.
+#[derive(Clone, Debug)]
+struct Observation {
+    spec: LeaseSpec,
+    time: Instant,
+}
+
+/// Decide what to do with the lease, tracking its changes in `observed`.
+///
+/// A lease expires once it wasn't changed for its duration. As the clocks of the replicas may
+/// differ, this uses the local time of observing the last change, rather than the renew time set
+/// by the holder.
+fn decide(
+    spec: &LeaseSpec,
+    identity: &str,
+    fallback: Duration,
+    observed: &mut Option<Observation>,
+    now: Instant,
+) -> Decision {
+    let changed = match observed {
+        Some(observation) if observation.spec == *spec => observation.time,
+        _ => {
+            *observed = Some(Observation {
+                spec: spec.clone(),
+                time: now,
+            });
+            now
+        }
+    };
+
+    let holder = match spec.holder_identity.as_deref() {
+        Some(holder) if holder == identity => return Decision::Renew,
+        Some(holder) if !holder.is_empty() => holder,
+        _ => return Decision::Acquire,
+    };
+
+    let duration = spec
+        .lease_duration_seconds
+        .map(|seconds| Duration::from_secs(seconds.max(0) as u64))
+        .unwrap_or(fallback);
+
+    match changed + duration > now {
+        true => {
+            log::debug!("Lease is held by {:?}", holder);
+            Decision::Follow
+        }
+        false => {
+            log::info!("Lease of {:?} expired", holder);
+            Decision::Acquire
+        }
+    }
+}
+
+/// Elects the leader, among the replicas of an operator.
+#[derive(Clone)]
+pub struct LeaderElection {
+    api: Api<Lease>,
+    name: String,
+    identity: String,
+    config: LeaderElectionConfig,
+    leadership: Leadership,
+    observed: Arc<Mutex<Option<Observation>>>,
+}
+
+impl LeaderElection {
+    /// Create a new instance, using the `default_name` if the lease name is not configured.
+    pub fn new(
+        client: kube::Client,
+        config: LeaderElectionConfig,
+        default_name: &str,
+    ) -> anyhow::Result<Self> {
+        config.validate()?;
+
+        let identity = match config
+            .identity
+            .clone()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+        {
+            Some(identity) if !identity.is_empty() => identity,
+            _ => anyhow::bail!(
+                "Leader election requires an identity, either configured or from the hostname"
+            ),
+        };
+
+        let api = match &config.namespace {
+            Some(namespace) => Api::namespaced(client, namespace),
+            None => Api::default_namespaced(client),
+        };
+
+        Ok(Self {
+            api,
+            name: config
+                .lease_name
+                .clone()
+                .unwrap_or_else(|| default_name.to_string()),
+            identity,
+            config,
+            leadership: Default::default(),
+            observed: Default::default(),
+        })
+    }
+
+    pub fn leadership(&self) -> Leadership {
+        self.leadership.clone()
+    }
+
+    /// Wait until elected, then run the operation, for as long as we are the leader.
+    ///
+    /// The operation is only created once elected. If the lease gets lost, the operation is
+    /// stopped and an error is returned, so that the operator restarts as a standby.
+    pub async fn run<F, Fut>(self, operation: F) -> anyhow::Result<()>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        self.acquire().await;
+
+        let operation = operation();
+        let keep = self.keep();
+        pin_mut!(operation, keep);
+
+        match select(keep, operation).await {
+            Either::Left((result, _)) | Either::Right((result, _)) => result,
+        }
+    }
+
+    /// Try acquiring the lease, until successful.
+    async fn acquire(&self) {
+        log::info!(
+            "Waiting to acquire lease {:?} as {:?}",
+            self.name,
+            self.identity
+        );
+
+        loop {
+            match tokio::time::timeout(self.config.renew_deadline, self.try_lead()).await {
+                Ok(Ok(true)) => break,
+                Ok(Ok(false)) => {}
+                Ok(Err(err)) => log::warn!("Failed to acquire lease {:?}: {}", self.name, err),
+                Err(_) => log::warn!("Timed out acquiring lease {:?}", self.name),
+            }
+            tokio::time::sleep(self.config.renew_interval).await;
+        }
+
+        log::info!("Acquired lease {:?}, leading", self.name);
+        self.leadership.set(true);
+    }
+
+    /// Keep renewing the lease, returning an error once it is lost.
+    ///
+    /// If the lease can't be renewed within the renew deadline, we give up leading, before
+    /// another replica may take over after the lease duration.
+    async fn keep(&self) -> anyhow::Result<()> {
+        let mut renewed = Instant::now();
+
+        loop {
+            tokio::time::sleep(self.config.renew_interval).await;
+
+            // the lease is valid from the time we sent the request, not when we got the response
+            let attempt = Instant::now();
+            let remaining = self.config.renew_deadline.saturating_sub(renewed.elapsed());
+
+            match tokio::time::timeout(remaining, self.try_lead()).await {
+                Ok(Ok(true)) => {
+                    renewed = attempt;
+                }
+                Ok(Ok(false)) => {
+                    self.leadership.set(false);
+                    anyhow::bail!("Lost lease {:?} to another replica", self.name);
+                }
+                Ok(Err(err)) => log::warn!("Failed to renew lease {:?}: {}", self.name, err),
+                Err(_) => log::warn!("Timed out renewing lease {:?}", self.name),
+            }
+
+            if renewed.elapsed() >= self.config.renew_deadline {
+                self.leadership.set(false);
+                anyhow::bail!("Failed to renew lease {:?} in time", self.name);
+            }
+        }
+    }
+
+    /// Acquire or renew the lease, returns `true` if we hold the lease afterwards.
+    async fn try_lead(&self) -> Result<bool, kube::Error> {
+        let now = Utc::now();
+
+        let result = match self.api.get_opt(&self.name).await? {
+            Some(mut lease) => {
+                let spec = lease.spec.take().unwrap_or_default();
+                let decision = decide(
+                    &spec,
+                    &self.identity,
+                    self.config.lease_duration,
+                    &mut self.observed.lock().unwrap(),
+                    Instant::now(),
+                );
+                let spec = match decision {
+                    Decision::Follow => return Ok(false),
+                    Decision::Renew => LeaseSpec {
+                        renew_time: Some(MicroTime(now)),
+                        lease_duration_seconds: Some(self.lease_duration_seconds()),
+                        ..spec
+                    },
+                    Decision::Acquire => LeaseSpec {
+                        lease_transitions: Some(spec.lease_transitions.unwrap_or_default() + 1),
+                        ..self.new_spec(now)
+                    },
+                };
+                lease.spec = Some(spec);
+                // carries the resource version, so that concurrent updates conflict
+                self.api
+                    .replace(&self.name, &PostParams::default(), &lease)
+                    .await
+            }
+            None => {
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(self.name.clone()),
+                        ..Default::default()
+                    },
+                    spec: Some(LeaseSpec {
+                        lease_transitions: Some(0),
+                        ..self.new_spec(now)
+                    }),
+                };
+                self.api.create(&PostParams::default(), &lease).await
+            }
+        };
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(err)) if err.code == 409 => {
+                log::debug!("Lease {:?} got updated concurrently", self.name);
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn new_spec(&self, now: DateTime<Utc>) -> LeaseSpec {
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            acquire_time: Some(MicroTime(now)),
+            renew_time: Some(MicroTime(now)),
+            lease_duration_seconds: Some(self.lease_duration_seconds()),
+            ..Default::default()
+        }
+    }
+
+    fn lease_duration_seconds(&self) -> i32 {
+        self.config
+            .lease_duration
+            .as_secs()
+            .try_into()
+            .unwrap_or(i32::MAX)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spec(holder: Option<&str>, renewed: DateTime<Utc>) -> LeaseSpec {
+        LeaseSpec {
+            holder_identity: holder.map(ToString::to_string),
+            renew_time: Some(MicroTime(renewed)),
+            lease_duration_seconds: Some(15),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_decide() {
+        let now = Instant::now();
+        let renewed = Utc::now();
+        let fallback = Duration::from_secs(15);
+        let first = |spec: &LeaseSpec| decide(spec, "pod1", fallback, &mut None, now);
+
+        assert_eq!(first(&Default::default()), Decision::Acquire);
+        assert_eq!(first(&spec(Some("pod1"), renewed)), Decision::Renew);
+        // a lease we see for the first time, is held for its duration
+        assert_eq!(first(&spec(Some("pod2"), renewed)), Decision::Follow);
+        assert_eq!(first(&spec(Some(""), renewed)), Decision::Acquire);
+    }
+
+    #[test]
+    fn test_decide_expired() {
+        let now = Instant::now();
+        let renewed = Utc::now();
+        let fallback = Duration::from_secs(15);
+        let mut observed = None;
+
+        let lease = spec(Some("pod2"), renewed);
+        assert_eq!(
+            decide(&lease, "pod1", fallback, &mut observed, now),
+            Decision::Follow
+        );
+        assert_eq!(
+            decide(
+                &lease,
+                "pod1",
+                fallback,
+                &mut observed,
+                now + Duration::from_secs(10)
+            ),
+            Decision::Follow
+        );
+        // not changed for the lease duration
+        assert_eq!(
+            decide(
+                &lease,
+                "pod1",
+                fallback,
+                &mut observed,
+                now + Duration::from_secs(16)
+            ),
+            Decision::Acquire
+        );
+        // we still renew a lease, which expired
+        assert_eq!(
+            decide(
+                &spec(Some("pod1"), renewed),
+                "pod1",
+                fallback,
+                &mut observed,
+                now + Duration::from_secs(60)
+            ),
+            Decision::Renew
+        );
+    }
+
+    #[test]
+    fn test_decide_local_clock() {
+        let now = Instant::now();
+        let fallback = Duration::from_secs(15);
+        let mut observed = None;
+
+        // the clock of the holder is behind, still we wait for the lease duration
+        let lease = spec(Some("pod2"), Utc::now() - chrono::Duration::minutes(1));
+        assert_eq!(
+            decide(&lease, "pod1", fallback, &mut observed, now),
+            Decision::Follow
+        );
+
+        // a renewal restarts the lease, whatever the renew time is
+        let renewed = spec(Some("pod2"), Utc::now() - chrono::Duration::minutes(2));
+        assert_eq!(
+            decide(
+                &renewed,
+                "pod1",
+                fallback,
+                &mut observed,
+                now + Duration::from_secs(10)
+            ),
+            Decision::Follow
+        );
+        assert_eq!(
+            decide(
+                &renewed,
+                "pod1",
+                fallback,
+                &mut observed,
+                now + Duration::from_secs(20)
+            ),
+            Decision::Follow
+        );
+        assert_eq!(
+            decide(
+                &renewed,
+                "pod1",
+                fallback,
+                &mut observed,
+                now + Duration::from_secs(26)
+            ),
+            Decision::Acquire
+        );
+    }
+
+    #[test]
+    fn test_decide_fallback_duration() {
+        let now = Instant::now();
+        let lease = LeaseSpec {
+            holder_identity: Some("pod2".into()),
+            renew_time: Some(MicroTime(Utc::now())),
+            ..Default::default()
+        };
+        let later = now + Duration::from_secs(10);
+
+        let mut observed = None;
+        decide(&lease, "pod1", Duration::from_secs(15), &mut observed, now);
+        assert_eq!(
+            decide(
+                &lease,
+                "pod1",
+                Duration::from_secs(15),
+                &mut observed,
+                later
+            ),
+            Decision::Follow
+        );
+
+        let mut observed = None;
+        decide(&lease, "pod1", Duration::from_secs(5), &mut observed, now);
+        assert_eq!(
+            decide(&lease, "pod1", Duration::from_secs(5), &mut observed, later),
+            Decision::Acquire
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(LeaderElectionConfig::default().validate().is_ok());
+        assert!(LeaderElectionConfig {
+            renew_deadline: Duration::from_secs(15),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(LeaderElectionConfig {
+            renew_interval: Duration::from_secs(10),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let leadership = Leadership::default();
+        assert!(leadership.is_ready().await.is_err());
+
+        leadership.set(true);
+        assert!(leadership.is_ready().await.is_ok());
+    }
+}
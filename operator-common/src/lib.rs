@@ -1,3 +1,5 @@
 pub mod controller;
 #[cfg(feature = "with_kube")]
+pub mod leader;
+#[cfg(feature = "with_kube")]
 pub mod watcher;
@@ -3,11 +3,12 @@ mod common;
 use async_trait::async_trait;
 use deadpool_postgres::Pool;
 use drogue_cloud_operator_common::controller::base::queue::{
-    WorkQueueHandler, WorkQueueReader, WorkQueueReaderOptions, WorkQueueWriter,
+    PriorityConfig, WorkQueueHandler, WorkQueueReader, WorkQueueReaderOptions, WorkQueueWriter,
 };
 use drogue_cloud_test_common::{client, db};
 use futures::lock::Mutex;
 use serial_test::serial;
+use std::collections::HashMap;
 use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
@@ -57,6 +58,7 @@ where
         handler.clone(),
         WorkQueueReaderOptions {
             delay: Duration::from_millis(250),
+            ..Default::default()
         },
     );
 
@@ -141,3 +143,85 @@ async fn test_inbox_nothing() {
 
     assert_eq!(Vec::<String>::new(), events);
 }
+
+/// Test the state of the queue, as reported by metrics.
+#[actix_rt::test]
+#[serial]
+async fn test_queue_stats() {
+    common::init();
+
+    let cli = client();
+    let db = db(&cli, |pg| pg).unwrap();
+
+    let pool: Pool = db.config.create_pool().unwrap();
+    let writer = WorkQueueWriter::new(pool, "drogue".into(), "foo".into());
+
+    let stats = writer.stats().await.unwrap();
+    assert_eq!(stats.depth, 0);
+    assert_eq!(stats.delayed, 0);
+    assert_eq!(stats.oldest_age, None);
+
+    writer.add("A".to_string(), Duration::ZERO).await.unwrap();
+    writer
+        .add("B".to_string(), Duration::from_secs(30))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let stats = writer.stats().await.unwrap();
+    assert_eq!(stats.depth, 2);
+    assert_eq!(stats.delayed, 1);
+    assert!(stats.oldest_age.unwrap() >= Duration::from_millis(500));
+}
+
+/// Test that entries of a higher priority are processed first, even if added later.
+#[actix_rt::test]
+#[serial]
+async fn test_priority() {
+    common::init();
+
+    let cli = client();
+    let db = db(&cli, |pg| pg).unwrap();
+
+    let pool: Pool = db.config.create_pool().unwrap();
+    let priority = PriorityConfig {
+        namespaces: HashMap::from([("safety".to_string(), 10)]),
+        ..Default::default()
+    };
+    let writer = WorkQueueWriter::new(pool.clone(), "drogue".into(), "foo".into())
+        .with_priority(Some(priority.clone()));
+
+    // the bulk entries are added first
+    for key in ["bulk1", "bulk2"] {
+        writer.add(key.to_string(), Duration::ZERO).await.unwrap();
+    }
+    // by namespace
+    writer
+        .add("safety".to_string(), Duration::ZERO)
+        .await
+        .unwrap();
+    // by resource, e.g. the annotation of the application
+    writer
+        .add_with_priority("critical".to_string(), Duration::ZERO, Some(20))
+        .await
+        .unwrap();
+
+    let handler = MockHandler::new();
+    let _reader = WorkQueueReader::with_options(
+        pool,
+        "drogue".into(),
+        "foo".into(),
+        handler.clone(),
+        WorkQueueReaderOptions {
+            delay: Duration::from_millis(250),
+            priority: Some(priority),
+            ..Default::default()
+        },
+    );
+    tokio::time::sleep(Duration::from_secs(5)).await;
+
+    assert_eq!(
+        handler.retrieve().await,
+        vec!["critical", "safety", "bulk1", "bulk2"]
+    );
+}
@@ -92,6 +92,7 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
     // event source
 
     let source = KafkaEventStream::new(config.kafka_source)?;
+    startup.check(source.health());
     let source = source.run(OutboxHandler(service));
 
     // run
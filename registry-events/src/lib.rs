@@ -68,6 +68,13 @@ impl<S: EventSender> SendEvent<S> for Vec<Event> {
 }
 
 impl Event {
+    /// The name of the application, the event belongs to.
+    pub fn application(&self) -> &str {
+        match self {
+            Self::Application { application, .. } | Self::Device { application, .. } => application,
+        }
+    }
+
     fn get_data(event: &cloudevents::Event) -> Result<EventData, EventError> {
         event
             .data()
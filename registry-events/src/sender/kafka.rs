@@ -12,12 +12,12 @@ use rdkafka::{
     util::Timeout,
     ClientConfig,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{convert::TryInto, time::Duration};
 use thiserror::Error;
 use tracing::instrument;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct KafkaSenderConfig {
     #[serde(flatten)]
     pub client: KafkaConfig,
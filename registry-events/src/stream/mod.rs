@@ -1,15 +1,23 @@
 mod event;
+mod reconnect;
 
 pub use drogue_cloud_event_common::stream::{EventStream, EventStreamError};
 pub use event::*;
+pub use reconnect::{ReconnectConfig, StreamHealth};
 
 use crate::{Event, EventError};
 use anyhow::bail;
 use drogue_cloud_event_common::stream::{CustomAck, EventStreamConfig, Handle};
 use drogue_cloud_service_api::kafka::KafkaConfig;
-use futures::{Stream, StreamExt, TryStreamExt};
-use rdkafka::error::KafkaError;
-use serde::Deserialize;
+use futures::{future::poll_fn, Stream, StreamExt};
+use rdkafka::{
+    error::KafkaError,
+    message::{BorrowedMessage, OwnedHeaders},
+    producer::{FutureProducer, FutureRecord},
+    util::Timeout,
+    ClientConfig, Message,
+};
+use serde::{Deserialize, Serialize};
 use std::{
     convert::TryInto,
     ops::{Deref, DerefMut},
@@ -24,6 +32,8 @@ pub enum KafkaStreamError {
     Stream(#[from] EventStreamError),
     #[error("Event failed: {0}")]
     Event(#[from] EventError),
+    #[error("Invalid configuration: {0}")]
+    Config(String),
 }
 
 impl From<KafkaError> for KafkaStreamError {
@@ -32,11 +42,151 @@ impl From<KafkaError> for KafkaStreamError {
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct KafkaStreamConfig {
     #[serde(flatten)]
     pub client: KafkaConfig,
     pub consumer_group: String,
+    /// How to handle messages, which are not a valid registry event.
+    #[serde(default)]
+    pub dead_letter: DeadLetterPolicy,
+    /// The topic to forward invalid messages to, required by [`DeadLetterPolicy::DeadLetter`].
+    #[serde(default)]
+    pub dead_letter_topic: Option<String>,
+    /// Only process events of some applications, all by default.
+    #[serde(default)]
+    pub applications: ApplicationFilter,
+    /// Reconnecting the stream, after it failed.
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+}
+
+/// Filter events by the name of their application.
+///
+/// An event passes, if its application starts with one of the included prefixes, and none of the
+/// excluded prefixes. Without included prefixes, all applications are included.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ApplicationFilter {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl ApplicationFilter {
+    pub fn matches(&self, application: &str) -> bool {
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|prefix| application.starts_with(prefix.as_str()));
+
+        included
+            && !self
+                .exclude
+                .iter()
+                .any(|prefix| application.starts_with(prefix.as_str()))
+    }
+}
+
+/// Handling of messages, which are not a valid registry event.
+///
+/// Invalid messages are always logged, including their partition, offset, and payload.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeadLetterPolicy {
+    /// Skip the message.
+    #[default]
+    Skip,
+    /// Forward the message to the dead-letter topic, then skip it.
+    DeadLetter,
+    /// Stop processing, the message will be received again after a restart.
+    Halt,
+}
+
+enum DeadLetter {
+    Skip,
+    Forward {
+        producer: FutureProducer,
+        topic: String,
+    },
+    Halt,
+}
+
+impl DeadLetter {
+    fn new(cfg: &KafkaStreamConfig) -> Result<Self, KafkaStreamError> {
+        Ok(match cfg.dead_letter {
+            DeadLetterPolicy::Skip => Self::Skip,
+            DeadLetterPolicy::DeadLetter => {
+                let topic = match &cfg.dead_letter_topic {
+                    Some(topic) => topic.clone(),
+                    None => {
+                        return Err(KafkaStreamError::Config(
+                            "Dead-letter policy requires a dead-letter topic".into(),
+                        ))
+                    }
+                };
+                let config: ClientConfig = cfg.client.client.clone().into();
+                Self::Forward {
+                    producer: config.create()?,
+                    topic,
+                }
+            }
+            DeadLetterPolicy::Halt => Self::Halt,
+        })
+    }
+
+    /// Handle an invalid message, returns an error if processing must stop.
+    async fn handle(
+        &self,
+        msg: &BorrowedMessage<'_>,
+        err: &KafkaStreamError,
+    ) -> Result<(), anyhow::Error> {
+        log::warn!(
+            "Invalid registry event - topic: {}, partition: {}, offset: {}, error: {}, payload: {:?}",
+            msg.topic(),
+            msg.partition(),
+            msg.offset(),
+            err,
+            msg.payload().map(String::from_utf8_lossy)
+        );
+
+        match self {
+            Self::Skip => Ok(()),
+            Self::Forward { producer, topic } => {
+                let headers = msg
+                    .headers()
+                    .map(|headers| headers.detach())
+                    .unwrap_or_else(OwnedHeaders::new)
+                    .add("dead-letter-error", err.to_string().as_str())
+                    .add(
+                        "dead-letter-source",
+                        format!("{}/{}/{}", msg.topic(), msg.partition(), msg.offset()).as_str(),
+                    );
+
+                let mut record = FutureRecord::<[u8], [u8]>::to(topic).headers(headers);
+                if let Some(key) = msg.key() {
+                    record = record.key(key);
+                }
+                if let Some(payload) = msg.payload() {
+                    record = record.payload(payload);
+                }
+
+                producer
+                    .send(record, Timeout::Never)
+                    .await
+                    .map_err(|(err, _)| err)?;
+
+                Ok(())
+            }
+            Self::Halt => bail!(
+                "Invalid registry event - partition: {}, offset: {}: {}",
+                msg.partition(),
+                msg.offset(),
+                err
+            ),
+        }
+    }
 }
 
 impl From<KafkaStreamConfig> for EventStreamConfig {
@@ -51,21 +201,108 @@ impl From<KafkaStreamConfig> for EventStreamConfig {
     }
 }
 
-pub struct KafkaEventStream<'s>(EventStream<'s, CustomAck>);
+pub struct KafkaEventStream<'s> {
+    stream: EventStream<'s, CustomAck>,
+    dead_letter: DeadLetter,
+    filter: ApplicationFilter,
+    config: KafkaStreamConfig,
+    health: StreamHealth,
+}
 
 impl<'s> KafkaEventStream<'s> {
     pub fn new(cfg: KafkaStreamConfig) -> Result<Self, KafkaStreamError> {
-        Ok(EventStream::new(cfg.into()).map(Self)?)
+        let dead_letter = DeadLetter::new(&cfg)?;
+        let filter = cfg.applications.clone();
+        Ok(Self {
+            stream: EventStream::new(cfg.clone().into())?,
+            dead_letter,
+            filter,
+            config: cfg,
+            health: Default::default(),
+        })
+    }
+
+    /// Use the provided health, instead of a new one.
+    ///
+    /// This allows registering the health check, before the stream gets created.
+    pub fn with_health(mut self, health: StreamHealth) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// The health of the stream, which is not ready while the stream is disconnected.
+    pub fn health(&self) -> StreamHealth {
+        self.health.clone()
+    }
+
+    /// The next valid event, handling invalid messages according to the dead-letter policy.
+    ///
+    /// Invalid messages, and events of filtered applications, get acknowledged, so that the
+    /// consumer makes progress.
+    async fn next_event(&mut self) -> Result<Option<Handle<'s, Event>>, anyhow::Error> {
+        loop {
+            let handle = match poll_fn(|cx| self.stream.poll_next_message(cx)).await {
+                Some(handle) => handle?,
+                None => return Ok(None),
+            };
+
+            let handle =
+                handle.map(|event| -> Result<Event, KafkaStreamError> { Ok(event?.try_into()?) });
+
+            if let Err(err) = handle.deref() {
+                self.dead_letter.handle(handle.message(), err).await?;
+                self.stream.ack(handle)?;
+                continue;
+            }
+
+            let handle = handle.try_map(|event| event)?;
+            if !self.filter.matches(handle.application()) {
+                log::debug!(
+                    "Skipping event of filtered application: {:?}",
+                    handle.deref()
+                );
+                self.stream.ack(handle)?;
+                continue;
+            }
+
+            return Ok(Some(handle));
+        }
     }
 }
 
 impl KafkaEventStream<'static> {
+    /// Run the stream, reconnecting if it fails.
+    ///
+    /// Only returns once reconnecting failed too often, according to the reconnect configuration.
     pub async fn run<H>(self, handler: H) -> Result<(), anyhow::Error>
+    where
+        H: EventHandler<Event = Event> + Send + Sync + 'static,
+    {
+        let config = self.config.clone();
+        let health = self.health.clone();
+        let mut initial = Some(self);
+
+        reconnect::reconnect(
+            &config.reconnect,
+            &health,
+            || match initial.take() {
+                Some(stream) => Ok(stream),
+                None => {
+                    log::info!("Reconnecting event stream");
+                    Ok(Self::new(config.clone())?)
+                }
+            },
+            |stream| stream.process(&handler),
+        )
+        .await
+    }
+
+    async fn process<H>(self, handler: &H) -> Result<(), anyhow::Error>
     where
         H: EventHandler<Event = Event> + Send + Sync + 'static,
     {
         let mut stream = self;
-        while let Some(event) = stream.try_next().await? {
+        while let Some(event) = stream.next_event().await? {
             log::debug!("Processing event: {:?}", event);
             let mut cnt = 0;
             // try to handle it
@@ -87,13 +324,13 @@ impl<'s> Deref for KafkaEventStream<'s> {
     type Target = EventStream<'s, CustomAck>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.stream
     }
 }
 
 impl<'s> DerefMut for KafkaEventStream<'s> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.stream
     }
 }
 
@@ -101,7 +338,7 @@ impl<'s> Stream for KafkaEventStream<'s> {
     type Item = Result<Handle<'s, Event>, KafkaStreamError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match self.0.poll_next_unpin(cx) {
+        match self.stream.poll_next_unpin(cx) {
             Poll::Pending => Poll::Pending,
             Poll::Ready(next) => Poll::Ready(match next {
                 None => None,
@@ -111,3 +348,69 @@ impl<'s> Stream for KafkaEventStream<'s> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn stream_config(dead_letter: serde_json::Value) -> KafkaStreamConfig {
+        let mut config = json!({
+            "bootstrap_servers": "localhost:9092",
+            "topic": "registry",
+            "consumer_group": "operator",
+        });
+        config
+            .as_object_mut()
+            .unwrap()
+            .extend(dead_letter.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    #[test]
+    fn test_filter_applications() {
+        let filter = ApplicationFilter::default();
+        assert!(filter.matches("app1"));
+
+        let filter = ApplicationFilter {
+            include: vec!["tenant1-".into(), "tenant2-".into()],
+            exclude: vec!["tenant2-internal".into()],
+        };
+        assert!(filter.matches("tenant1-app"));
+        assert!(filter.matches("tenant2-app"));
+        assert!(!filter.matches("tenant2-internal-app"));
+        assert!(!filter.matches("tenant3-app"));
+
+        let filter = ApplicationFilter {
+            include: vec![],
+            exclude: vec!["test-".into()],
+        };
+        assert!(filter.matches("app1"));
+        assert!(!filter.matches("test-app1"));
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let config = stream_config(json!({}));
+        assert_eq!(config.dead_letter, DeadLetterPolicy::Skip);
+        assert!(matches!(DeadLetter::new(&config), Ok(DeadLetter::Skip)));
+    }
+
+    #[test]
+    fn test_dead_letter_requires_topic() {
+        let config = stream_config(json!({"dead_letter": "deadLetter"}));
+        assert!(matches!(
+            DeadLetter::new(&config),
+            Err(KafkaStreamError::Config(_))
+        ));
+
+        let config = stream_config(json!({
+            "dead_letter": "deadLetter",
+            "dead_letter_topic": "registry-dlq",
+        }));
+        assert!(matches!(
+            DeadLetter::new(&config),
+            Ok(DeadLetter::Forward { topic, .. }) if topic == "registry-dlq"
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+use async_trait::async_trait;
+use drogue_cloud_service_api::health::{HealthCheckError, HealthChecked};
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Reconnecting the event stream, after it failed.
+///
+/// The delay between two attempts doubles with each failed attempt, up to the maximum delay. A
+/// connection which stayed up for longer than the maximum delay counts as recovered, and resets
+/// the number of attempts.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReconnectConfig {
+    /// The number of consecutive failures, after which the stream fails.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// The delay before the first reconnect.
+    #[serde(default = "default_base_delay", with = "humantime_serde")]
+    pub base_delay: Duration,
+    /// The maximum delay between two reconnects.
+    #[serde(default = "default_max_delay", with = "humantime_serde")]
+    pub max_delay: Duration,
+}
+
+const fn default_max_attempts() -> u32 {
+    10
+}
+
+const fn default_base_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+const fn default_max_delay() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            base_delay: default_base_delay(),
+            max_delay: default_max_delay(),
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// The delay before reconnecting, after `failures` consecutive failures.
+    ///
+    /// Returns [`None`] if there should be no further attempt.
+    pub fn delay(&self, failures: u32) -> Option<Duration> {
+        if failures >= self.max_attempts {
+            return None;
+        }
+
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)));
+        Some(delay.min(self.max_delay))
+    }
+}
+
+/// Whether the event stream is currently connected.
+///
+/// A disconnected stream reports as not ready.
+#[derive(Clone, Debug, Default)]
+pub struct StreamHealth(Arc<AtomicBool>);
+
+impl StreamHealth {
+    pub fn is_connected(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, connected: bool) {
+        self.0.store(connected, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl HealthChecked for StreamHealth {
+    async fn is_ready(&self) -> Result<(), HealthCheckError> {
+        match self.is_connected() {
+            true => Ok(()),
+            false => HealthCheckError::nok("Event stream is disconnected"),
+        }
+    }
+}
+
+/// Run sessions of a stream, reconnecting with a backoff when they fail.
+///
+/// A session must not end. Only returns once reconnecting failed too often.
+pub async fn reconnect<S, C, R, Fut>(
+    config: &ReconnectConfig,
+    health: &StreamHealth,
+    mut connect: C,
+    mut run: R,
+) -> anyhow::Result<()>
+where
+    C: FnMut() -> anyhow::Result<S>,
+    R: FnMut(S) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let mut failures = 0;
+
+    loop {
+        let started = Instant::now();
+        let result = match connect() {
+            Ok(session) => {
+                health.set(true);
+                run(session).await
+            }
+            Err(err) => Err(err),
+        };
+        health.set(false);
+
+        let err = result
+            .err()
+            .unwrap_or_else(|| anyhow::anyhow!("Stream must not end"));
+
+        if started.elapsed() > config.max_delay {
+            failures = 0;
+        }
+        failures += 1;
+
+        match config.delay(failures) {
+            Some(delay) => {
+                log::warn!(
+                    "Event stream failed (attempt {} of {}), reconnecting in {:?}: {}",
+                    failures,
+                    config.max_attempts,
+                    delay,
+                    err
+                );
+                tokio::time::sleep(delay).await;
+            }
+            None => {
+                return Err(err.context(format!("Event stream failed {failures} times in a row")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+    use tokio::sync::Notify;
+
+    fn config(max_attempts: u32) -> ReconnectConfig {
+        ReconnectConfig {
+            max_attempts,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = ReconnectConfig {
+            max_attempts: 10,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+        };
+
+        assert_eq!(config.delay(1), Some(Duration::from_secs(1)));
+        assert_eq!(config.delay(2), Some(Duration::from_secs(2)));
+        assert_eq!(config.delay(3), Some(Duration::from_secs(4)));
+        assert_eq!(config.delay(4), Some(Duration::from_secs(5)));
+        assert_eq!(config.delay(9), Some(Duration::from_secs(5)));
+        assert_eq!(config.delay(10), None);
+    }
+
+    #[tokio::test]
+    async fn test_recover() {
+        let health = StreamHealth::default();
+        let connects = AtomicU32::new(0);
+        let recovered = Notify::new();
+
+        // the first session fails, the second keeps running
+        let stream = reconnect(
+            &config(3),
+            &health,
+            || Ok(connects.fetch_add(1, Ordering::Relaxed) + 1),
+            |session| {
+                let recovered = &recovered;
+                async move {
+                    if session == 1 {
+                        anyhow::bail!("Broker transport failure");
+                    }
+                    recovered.notify_one();
+                    futures::future::pending().await
+                }
+            },
+        );
+
+        tokio::select! {
+            result = stream => panic!("Stream must not end: {result:?}"),
+            _ = recovered.notified() => {}
+        }
+
+        assert_eq!(connects.load(Ordering::Relaxed), 2);
+        assert!(health.is_ready().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_give_up() {
+        let health = StreamHealth::default();
+        let connects = AtomicU32::new(0);
+
+        let result = reconnect(
+            &config(3),
+            &health,
+            || {
+                connects.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            },
+            |_| async { anyhow::bail!("Broker transport failure") },
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(connects.load(Ordering::Relaxed), 3);
+        assert!(health.is_ready().await.is_err());
+    }
+}
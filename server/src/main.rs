@@ -304,7 +304,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
     };
 
     let command_source = |consumer_group: &str| KafkaCommandSourceConfig {
-        topic: "iot-commands".to_string(),
+        topic: None,
         consumer_group: consumer_group.to_string(),
     };
 
@@ -445,6 +445,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             kafka_downstream_config: kafka,
             endpoint_pool: Default::default(),
             registry: registry.clone(),
+            topic_naming: Default::default(),
         };
 
         drogue_cloud_device_state_service::run(config, &mut main).await?;
@@ -529,6 +530,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
                 command_kafka_sink: kafka,
                 user_auth,
                 endpoint_pool: Default::default(),
+                topic_naming: Default::default(),
             }
         };
 
@@ -588,6 +590,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             kafka_command_config: kafka,
             check_kafka_topic_ready: false,
             endpoint_pool: Default::default(),
+            ..Default::default()
         };
 
         drogue_cloud_http_endpoint::run(config, &mut main).await?;
@@ -615,6 +618,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             registry: registry.clone(),
             kafka,
             user_auth,
+            topic_naming: Default::default(),
         };
 
         // The websocket integration uses the actix actors, so for now, that must run
@@ -685,6 +689,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
                 check_kafka_topic_ready: false,
                 endpoint_pool: Default::default(),
                 state: state.clone(),
+                topic_naming: Default::default(),
             };
 
             mqtt_endpoints.push(config.clone());
@@ -725,6 +730,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
                     kafka: kafka.clone(),
                     enable_username_password_auth: false,
                     disable_api_keys: false,
+                    topic_naming: Default::default(),
                 },
                 check_kafka_topic_ready: false,
                 user_auth,
@@ -800,6 +806,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             dtls_session_timeout: None,
             cert_bundle_file,
             key_file,
+            topic_naming: Default::default(),
         };
 
         drogue_cloud_coap_endpoint::run(config, &mut main).await?;
@@ -1,7 +1,7 @@
 use core::fmt::{self, Formatter};
 use drogue_client::{
     metrics::{AsPassFail, PassFail},
-    registry,
+    registry, Dialect, Translator,
 };
 use serde::{Deserialize, Serialize};
 
@@ -82,6 +82,44 @@ pub enum Outcome {
     Fail,
 }
 
+/// Validate if an application is "ok" to be used for authentication.
+pub fn validate_app(app: &registry::v1::Application) -> bool {
+    if app.metadata.deletion_timestamp.is_some() {
+        log::debug!("Application is about being deleted");
+        return false;
+    }
+
+    match app.section::<registry::v1::DeviceSpecCore>() {
+        // found "core", decoded successfully -> check
+        Some(Ok(core)) => {
+            if core.disabled {
+                return false;
+            }
+        }
+        // found "core", but could not decode -> fail
+        Some(Err(_)) => {
+            return false;
+        }
+        // no "core" section
+        _ => {}
+    };
+
+    // done
+    true
+}
+
+/// Strip the credentials from the device information, so that we do not leak them.
+pub fn strip_credentials(mut device: registry::v1::Device) -> registry::v1::Device {
+    // FIXME: we need to do a better job here, maybe add a "secrets" section instead
+    device
+        .spec
+        .remove(registry::v1::DeviceSpecAuthentication::key());
+    device
+        .spec
+        .remove(registry::v1::DeviceSpecCredentials::key());
+    device
+}
+
 /// The result of an authentication request.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AuthenticationResponse {
@@ -6,11 +6,14 @@ use std::convert::Infallible;
 use drogue_client::registry;
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug)]
 pub enum ResourceType<'a> {
     Events(&'a str),
     Commands(&'a str),
+    /// The dedicated commands topic of an application.
+    AppCommands(&'a str),
     Users(&'a str),
     Passwords(&'a str),
 }
@@ -19,6 +22,7 @@ impl<'a> ResourceType<'a> {
     pub fn app_name(&self) -> &str {
         match self {
             Self::Commands(app) => app,
+            Self::AppCommands(app) => app,
             Self::Events(app) => app,
             Self::Users(app) => app,
             Self::Passwords(app) => app,
@@ -33,8 +37,8 @@ pub enum KafkaEventType {
 }
 
 impl KafkaEventType {
-    fn make_topic(&self, name: &str) -> String {
-        make_kafka_resource_name(match self {
+    fn make_topic(&self, naming: &TopicNaming, name: &str) -> String {
+        naming.resource_name(match self {
             Self::Commands => ResourceType::Commands(name),
             Self::Events => ResourceType::Events(name),
         })
@@ -59,11 +63,15 @@ impl<'a> From<KafkaTarget<'a>> for KafkaConfig {
 pub trait KafkaConfigExt {
     type Error;
 
-    /// Get a Kafka topic..
+    /// Get a Kafka topic, named by the provided naming.
     ///
     /// This method must only return an Internal topic from a trusted source. Otherwise the user
     /// could internally redirect traffic.
-    fn kafka_topic(&self, event_type: KafkaEventType) -> Result<String, Self::Error>;
+    fn kafka_topic(
+        &self,
+        naming: &TopicNaming,
+        event_type: KafkaEventType,
+    ) -> Result<String, Self::Error>;
 
     /// Get a Kafka config, this can be either internal or external.
     ///
@@ -71,12 +79,13 @@ pub trait KafkaConfigExt {
     /// could internally redirect traffic.
     fn kafka_target<'a>(
         &self,
+        naming: &TopicNaming,
         event_type: KafkaEventType,
         default_kafka: &'a KafkaClientConfig,
     ) -> Result<KafkaTarget<'a>, Self::Error> {
         Ok(KafkaTarget {
             client: default_kafka,
-            topic: self.kafka_topic(event_type)?,
+            topic: self.kafka_topic(naming, event_type)?,
         })
     }
 }
@@ -84,51 +93,162 @@ pub trait KafkaConfigExt {
 impl KafkaConfigExt for registry::v1::Application {
     type Error = Infallible;
 
-    fn kafka_topic(&self, event_type: KafkaEventType) -> Result<String, Self::Error> {
-        Ok(event_type.make_topic(&self.metadata.name))
+    fn kafka_topic(
+        &self,
+        naming: &TopicNaming,
+        event_type: KafkaEventType,
+    ) -> Result<String, Self::Error> {
+        Ok(event_type.make_topic(naming, &self.metadata.name))
     }
 }
 
 const MAX_NAME_LEN: usize = 63;
+/// The maximum length of the naming prefix, leaving enough room for hashed names.
+const MAX_PREFIX_LEN: usize = 20;
 
 const NAME_REGEXP: &str = r#"^[a-z0-9]([-a-z0-9]*[a-z0-9])?(\\.[a-z0-9]([-a-z0-9]*[a-z0-9])?)*$"#;
 lazy_static! {
     static ref NAME_PATTERN: Regex = Regex::new(NAME_REGEXP).expect("Regexp must compile");
 }
 
-fn resource_name(prefix: &str, hashed_prefix: &str, resource: &str) -> String {
-    let name = format!("{}-{}", prefix, resource);
-    // try the simple route, if that works ...
-    if name.len() < MAX_NAME_LEN && NAME_PATTERN.is_match(resource) {
-        // ... simply return
-        name
-    } else {
-        // otherwise we need to clean up the name, and ensure we don't generate duplicates
-        // use a different prefix to prevent clashes with the simple names
-        let hash = md5::compute(resource);
-        format!("{}-{:x}-{}", hashed_prefix, hash, resource)
-    }
+const PLACEHOLDER_APP: &str = "{app}";
+const PLACEHOLDER_HASH: &str = "{hash}";
+
+/// The leading parts of the names of the other resources, which custom names must not clash with.
+const RESERVED_PREFIXES: &[&str] = &[
+    "evt-",
+    "cmd-",
+    "usr-",
+    "pwd-",
+    "commands-",
+    "user-",
+    "password-",
+    "iot-commands",
+];
+
+/// The naming of the Kafka resources of applications.
+///
+/// All processes which derive the resource names of an application must use the same naming,
+/// passed in through their configuration: the operators creating the resources, the endpoints
+/// publishing to the topics, and the integrations and command sources consuming them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TopicNaming {
+    /// A prefix of all resource names, e.g. `staging-`.
+    ///
+    /// This allows multiple instances to share one Kafka cluster.
+    #[serde(default)]
+    pub prefix: String,
+    /// The template of the events topic name, following the prefix, defaults to `events-{app}`.
+    ///
+    /// `{app}` is replaced with the name of the application, `{hash}` with its MD5 hash. Names
+    /// which are too long, or invalid, fall back to a hashed name.
+    #[serde(default)]
+    pub template: Option<String>,
 }
 
-pub fn make_kafka_resource_name(target: ResourceType) -> String {
-    let name = match target {
-        ResourceType::Events(app) => resource_name("events", "evt", app),
-        ResourceType::Users(app) => resource_name("user", "usr", app),
-        ResourceType::Passwords(app) => resource_name("password", "pwd", app),
-        ResourceType::Commands(_) => return "iot-commands".to_string(),
-    };
-
-    let name: String = name
-        .to_lowercase()
-        .chars()
-        .map(|c| match c {
-            '-' | 'a'..='z' | '0'..='9' => c,
-            _ => '-',
-        })
-        .take(MAX_NAME_LEN)
-        .collect();
+impl TopicNaming {
+    /// Validate the naming, so that it creates valid and unique names.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.prefix.len() > MAX_PREFIX_LEN {
+            return Err(format!(
+                "Prefix must not be longer than {MAX_PREFIX_LEN} characters"
+            ));
+        }
+        if !self
+            .prefix
+            .chars()
+            .all(|c| matches!(c, '-' | 'a'..='z' | '0'..='9'))
+            || self.prefix.starts_with('-')
+        {
+            return Err(format!("Invalid prefix: '{}'", self.prefix));
+        }
+
+        if let Some(template) = &self.template {
+            let literal = template
+                .replace(PLACEHOLDER_APP, "")
+                .replace(PLACEHOLDER_HASH, "");
+            if literal.len() == template.len() {
+                return Err(format!(
+                    "Template must contain '{PLACEHOLDER_APP}' or '{PLACEHOLDER_HASH}': '{template}'"
+                ));
+            }
+            if !literal
+                .chars()
+                .all(|c| matches!(c, '-' | 'a'..='z' | '0'..='9'))
+            {
+                return Err(format!("Invalid template: '{template}'"));
+            }
 
-    name.trim_end_matches('-').to_string()
+            // the names must not clash with other names, a hash never starts like one of them
+            if !template.starts_with(PLACEHOLDER_HASH) {
+                let leading = &template[..template.find('{').unwrap_or(template.len())];
+                if let Some(reserved) = RESERVED_PREFIXES.iter().find(|reserved| {
+                    leading.starts_with(*reserved) || reserved.starts_with(leading)
+                }) {
+                    return Err(format!(
+                        "Template must not start like other resource names ('{reserved}'): '{template}'"
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The name of the shared commands topic.
+    pub fn commands_topic(&self) -> String {
+        self.resource_name(ResourceType::Commands(""))
+    }
+
+    /// Create the name of a resource.
+    pub fn resource_name(&self, target: ResourceType) -> String {
+        let name = match target {
+            ResourceType::Events(app) => self.name(
+                self.template.as_deref().unwrap_or("events-{app}"),
+                "evt",
+                app,
+            ),
+            ResourceType::AppCommands(app) => self.name("commands-{app}", "cmd", app),
+            ResourceType::Users(app) => self.name("user-{app}", "usr", app),
+            ResourceType::Passwords(app) => self.name("password-{app}", "pwd", app),
+            ResourceType::Commands(_) => format!("{}iot-commands", self.prefix),
+        };
+
+        let name: String = name
+            .to_lowercase()
+            .chars()
+            .map(|c| match c {
+                '-' | 'a'..='z' | '0'..='9' => c,
+                _ => '-',
+            })
+            .take(MAX_NAME_LEN)
+            .collect();
+
+        name.trim_end_matches('-').to_string()
+    }
+
+    fn name(&self, template: &str, hashed_prefix: &str, resource: &str) -> String {
+        let hash = format!("{:x}", md5::compute(resource));
+        let name = format!(
+            "{}{}",
+            self.prefix,
+            template
+                .replace(PLACEHOLDER_APP, resource)
+                .replace(PLACEHOLDER_HASH, &hash)
+        );
+        // try the simple route, if that works ...
+        if name.len() < MAX_NAME_LEN && NAME_PATTERN.is_match(resource) {
+            // ... simply return
+            name
+        } else {
+            // otherwise we need to clean up the name, and ensure we don't generate duplicates
+            // use a different prefix to prevent clashes with the simple names
+            let name = format!("{}{}-{}-", self.prefix, hashed_prefix, hash);
+            // only the readable part may be truncated, the hash must be kept to stay unique
+            let remaining = MAX_NAME_LEN.saturating_sub(name.len());
+            name + &resource.chars().take(remaining).collect::<String>()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -147,7 +267,137 @@ mod test {
             ("FOO", "evt-901890a8e9c8cf6d5a1a542b229febff-foo"),
             ("foo-", "evt-03f19ca8da08c40c2d036c8915d383e2-foo"),
         ] {
-            assert_eq!(i.1, make_kafka_resource_name(ResourceType::Events(i.0)))
+            assert_eq!(
+                i.1,
+                TopicNaming::default().resource_name(ResourceType::Events(i.0))
+            )
+        }
+
+        assert_eq!(
+            "commands-foo",
+            TopicNaming::default().resource_name(ResourceType::AppCommands("foo"))
+        );
+        assert_eq!(
+            "cmd-901890a8e9c8cf6d5a1a542b229febff-foo",
+            TopicNaming::default().resource_name(ResourceType::AppCommands("FOO"))
+        );
+
+        // long names, only differing after the truncated part
+        let prefix = "a".repeat(69);
+        let first =
+            TopicNaming::default().resource_name(ResourceType::Events(&format!("{prefix}1")));
+        let second =
+            TopicNaming::default().resource_name(ResourceType::Events(&format!("{prefix}2")));
+
+        assert_ne!(first, second);
+        for name in [first, second] {
+            assert_eq!(name.len(), MAX_NAME_LEN);
+            assert!(name.starts_with("evt-"));
+        }
+
+        // a custom prefix
+
+        let naming = TopicNaming {
+            prefix: "staging-".into(),
+            template: None,
+        };
+        assert_eq!(
+            "staging-events-foo",
+            naming.resource_name(ResourceType::Events("foo"))
+        );
+        assert_eq!(
+            "staging-evt-901890a8e9c8cf6d5a1a542b229febff-foo",
+            naming.resource_name(ResourceType::Events("FOO"))
+        );
+        assert_eq!(
+            "staging-iot-commands",
+            naming.resource_name(ResourceType::Commands("foo"))
+        );
+
+        let first = naming.resource_name(ResourceType::Events(&format!("{prefix}1")));
+        let second = naming.resource_name(ResourceType::Events(&format!("{prefix}2")));
+        assert_ne!(first, second);
+        for name in [first, second] {
+            assert_eq!(name.len(), MAX_NAME_LEN);
+            assert!(name.starts_with("staging-evt-"));
         }
+
+        // a custom template
+
+        let naming = TopicNaming {
+            prefix: "staging-".into(),
+            template: Some("{app}-events".into()),
+        };
+        assert_eq!(
+            "staging-foo-events",
+            naming.resource_name(ResourceType::Events("foo"))
+        );
+        assert_eq!(
+            "staging-commands-foo",
+            naming.resource_name(ResourceType::AppCommands("foo"))
+        );
+
+        let naming = TopicNaming {
+            prefix: "".into(),
+            template: Some("app-{hash}".into()),
+        };
+        assert_eq!(
+            "app-acbd18db4cc2f85cedef654fccc4a4d8",
+            naming.resource_name(ResourceType::Events("foo"))
+        );
+    }
+
+    #[test]
+    fn kafka_topic_naming() {
+        let mut app = registry::v1::Application::default();
+        app.metadata.name = "foo".into();
+
+        let topic = |naming: &TopicNaming, event_type| app.kafka_topic(naming, event_type).unwrap();
+
+        let naming = TopicNaming::default();
+        assert_eq!(topic(&naming, KafkaEventType::Events), "events-foo");
+        assert_eq!(topic(&naming, KafkaEventType::Commands), "iot-commands");
+        assert_eq!(naming.commands_topic(), "iot-commands");
+
+        let naming = TopicNaming {
+            prefix: "staging-".into(),
+            template: Some("{app}-events".into()),
+        };
+        assert_eq!(topic(&naming, KafkaEventType::Events), "staging-foo-events");
+        assert_eq!(
+            topic(&naming, KafkaEventType::Commands),
+            "staging-iot-commands"
+        );
+        assert_eq!(naming.commands_topic(), "staging-iot-commands");
+
+        let kafka = KafkaClientConfig::default();
+        let target = app
+            .kafka_target(&naming, KafkaEventType::Events, &kafka)
+            .unwrap();
+        assert_eq!(target.topic, "staging-foo-events");
+    }
+
+    #[test]
+    fn topic_naming_validation() {
+        let naming = |prefix: &str, template: Option<&str>| TopicNaming {
+            prefix: prefix.into(),
+            template: template.map(Into::into),
+        };
+
+        assert!(naming("", None).validate().is_ok());
+        assert!(naming("staging-", None).validate().is_ok());
+        assert!(naming("staging-", Some("drogue-{app}")).validate().is_ok());
+        assert!(naming("", Some("{hash}")).validate().is_ok());
+
+        assert!(naming("Staging-", None).validate().is_err());
+        assert!(naming("-staging", None).validate().is_err());
+        assert!(naming(&"a".repeat(21), None).validate().is_err());
+        assert!(naming("", Some("events")).validate().is_err());
+        assert!(naming("", Some("events_{app}")).validate().is_err());
+        // may clash with hashed names
+        assert!(naming("", Some("{app}")).validate().is_err());
+        assert!(naming("", Some("evt-{app}")).validate().is_err());
+        assert!(naming("", Some("ev{app}")).validate().is_err());
+        assert!(naming("", Some("commands-{app}")).validate().is_err());
     }
 }
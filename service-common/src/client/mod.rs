@@ -2,9 +2,11 @@
 
 mod device_auth;
 mod device_state;
+mod secondary;
 
 pub use device_auth::*;
 pub use device_state::*;
+pub use secondary::*;
 
 use drogue_client::error::{ClientError, ErrorInformation};
 use http::StatusCode;
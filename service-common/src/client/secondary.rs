@@ -0,0 +1,198 @@
+use drogue_bazaar::client::ClientConfig;
+use drogue_client::{error::ClientError, registry};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How the sections of the secondary registry are combined with the ones of the primary registry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SecondaryMode {
+    /// Only use the section of the secondary registry, if the primary registry has none.
+    #[default]
+    Fallback,
+    /// Merge the fields of both sections, the registry taking precedence wins on conflicts.
+    Overlay,
+}
+
+/// Which registry wins, if both provide the same field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SecondaryPrecedence {
+    #[default]
+    Primary,
+    Secondary,
+}
+
+/// Reading application configuration from a secondary registry.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SecondaryRegistryConfig {
+    /// The client for the secondary registry.
+    pub client: ClientConfig,
+    #[serde(default)]
+    pub mode: SecondaryMode,
+    /// Only used in overlay mode, in fallback mode the primary registry always wins.
+    #[serde(default)]
+    pub precedence: SecondaryPrecedence,
+}
+
+/// A secondary registry, providing sections of applications the primary registry lacks.
+pub struct SecondaryRegistry {
+    mode: SecondaryMode,
+    precedence: SecondaryPrecedence,
+    client: registry::v1::Client,
+}
+
+impl SecondaryRegistry {
+    pub async fn new(config: SecondaryRegistryConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            mode: config.mode,
+            precedence: config.precedence,
+            client: config.client.into_client().await?,
+        })
+    }
+
+    /// Resolve a spec section of an application, read from the primary registry.
+    ///
+    /// In fallback mode, the secondary registry is only consulted if the section is missing.
+    pub async fn spec_section(
+        &self,
+        app: &registry::v1::Application,
+        name: &str,
+    ) -> Result<Option<Value>, ClientError> {
+        let primary = app.spec.get(name);
+        if self.mode == SecondaryMode::Fallback && primary.is_some() {
+            return Ok(primary.cloned());
+        }
+
+        let secondary = self.client.get_app(&app.metadata.name).await?;
+        let secondary = secondary.as_ref().and_then(|app| app.spec.get(name));
+
+        Ok(resolve_section(
+            self.mode,
+            self.precedence,
+            primary,
+            secondary,
+        ))
+    }
+}
+
+/// Resolve a section from the primary and the secondary registry.
+pub fn resolve_section(
+    mode: SecondaryMode,
+    precedence: SecondaryPrecedence,
+    primary: Option<&Value>,
+    secondary: Option<&Value>,
+) -> Option<Value> {
+    match (mode, primary, secondary) {
+        (_, None, None) => None,
+        (_, Some(value), None) | (_, None, Some(value)) => Some(value.clone()),
+        (SecondaryMode::Fallback, Some(primary), Some(_)) => Some(primary.clone()),
+        (SecondaryMode::Overlay, Some(primary), Some(secondary)) => {
+            let (mut base, top) = match precedence {
+                SecondaryPrecedence::Primary => (secondary.clone(), primary),
+                SecondaryPrecedence::Secondary => (primary.clone(), secondary),
+            };
+            overlay(&mut base, top);
+            Some(base)
+        }
+    }
+}
+
+/// Merge `top` into `base`, recursing into objects. Any other value of `top` replaces the one
+/// of `base`.
+fn overlay(base: &mut Value, top: &Value) {
+    match (base, top) {
+        (Value::Object(base), Value::Object(top)) => {
+            for (key, value) in top {
+                match base.get_mut(key) {
+                    Some(existing) => overlay(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, top) => *base = top.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_primary_only() {
+        let primary = json!({"partitions": 3});
+
+        for mode in [SecondaryMode::Fallback, SecondaryMode::Overlay] {
+            assert_eq!(
+                resolve_section(mode, Default::default(), Some(&primary), None),
+                Some(primary.clone())
+            );
+        }
+        assert_eq!(
+            resolve_section(Default::default(), Default::default(), None, None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_secondary_fallback() {
+        let primary = json!({"partitions": 3});
+        let secondary = json!({"partitions": 5, "replicas": 2});
+
+        assert_eq!(
+            resolve_section(
+                SecondaryMode::Fallback,
+                Default::default(),
+                None,
+                Some(&secondary)
+            ),
+            Some(secondary.clone())
+        );
+        // the primary section is used as a whole, even if the secondary one has more fields
+        assert_eq!(
+            resolve_section(
+                SecondaryMode::Fallback,
+                SecondaryPrecedence::Secondary,
+                Some(&primary),
+                Some(&secondary)
+            ),
+            Some(primary)
+        );
+    }
+
+    #[test]
+    fn test_overlay_merge() {
+        let primary = json!({"partitions": 3, "config": {"retention.ms": "1000"}});
+        let secondary = json!({
+            "partitions": 5,
+            "replicas": 2,
+            "config": {"retention.ms": "2000", "cleanup.policy": "compact"}
+        });
+
+        assert_eq!(
+            resolve_section(
+                SecondaryMode::Overlay,
+                SecondaryPrecedence::Primary,
+                Some(&primary),
+                Some(&secondary)
+            ),
+            Some(json!({
+                "partitions": 3,
+                "replicas": 2,
+                "config": {"retention.ms": "1000", "cleanup.policy": "compact"}
+            }))
+        );
+        assert_eq!(
+            resolve_section(
+                SecondaryMode::Overlay,
+                SecondaryPrecedence::Secondary,
+                Some(&primary),
+                Some(&secondary)
+            ),
+            Some(secondary)
+        );
+    }
+}
@@ -1,6 +1,7 @@
 pub mod auth;
 pub mod client;
 pub mod defaults;
+pub mod effective_config;
 pub mod endpoints;
 pub mod error;
 pub mod id;
@@ -43,7 +43,7 @@ impl Sink for MockSink {
         event: cloudevents::event::Event,
     ) -> Result<PublishOutcome, SinkError> {
         match target {
-            SinkTarget::Events(_) => {
+            SinkTarget::Events(_) | SinkTarget::Topic(..) => {
                 self.events.write().unwrap().push(event);
             }
             SinkTarget::Commands(_) => {
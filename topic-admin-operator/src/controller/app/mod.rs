@@ -18,20 +18,20 @@ use drogue_cloud_operator_common::controller::{
         ReconcileError, ReconcileProcessor, ReconcileState, Reconciler,
     },
 };
-use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
+use drogue_cloud_service_api::kafka::ResourceType;
 use rdkafka::{
     admin::{AdminClient, AdminOptions},
     client::DefaultClientContext,
     error::{KafkaError, RDKafkaErrorCode},
 };
-use std::ops::Deref;
+use std::{ops::Deref, sync::Arc};
 
 const FINALIZER: &str = "kafka-topic";
 
 pub struct ApplicationController {
     config: ControllerConfig,
     registry: registry::v1::Client,
-    admin: AdminClient<DefaultClientContext>,
+    admin: Arc<AdminClient<DefaultClientContext>>,
 }
 
 impl ApplicationController {
@@ -43,7 +43,7 @@ impl ApplicationController {
         Self {
             config: config.translate(),
             registry,
-            admin,
+            admin: Arc::new(admin),
         }
     }
 }
@@ -105,7 +105,7 @@ pub struct DeconstructContext {
 pub struct ApplicationReconciler<'a> {
     pub config: &'a ControllerConfig,
     pub registry: &'a registry::v1::Client,
-    pub admin: &'a AdminClient<DefaultClientContext>,
+    pub admin: &'a Arc<AdminClient<DefaultClientContext>>,
 }
 
 #[async_trait]
@@ -146,6 +146,10 @@ impl<'a> Reconciler for ApplicationReconciler<'a> {
                 config: self.config,
                 admin: self.admin,
             }),
+            Box::new(TopicReady {
+                config: self.config,
+                admin: self.admin,
+            }),
         ])
         .run_with::<KafkaAppStatus>(ctx)
         .await
@@ -157,7 +161,10 @@ impl<'a> Reconciler for ApplicationReconciler<'a> {
     ) -> Result<ProcessOutcome<Self::Output>, ReconcileError> {
         // delete
 
-        let topic_name = make_kafka_resource_name(ResourceType::Events(&ctx.app.metadata.name));
+        let topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Events(&ctx.app.metadata.name));
 
         match self
             .admin
@@ -2,15 +2,16 @@ use super::ConstructContext;
 use crate::{controller::ControllerConfig, kafka::TopicErrorConverter};
 use async_trait::async_trait;
 use drogue_cloud_operator_common::controller::reconciler::{
-    progress::{OperationOutcome, ProgressOperation},
+    progress::{self, OperationOutcome, ProgressOperation},
     ReconcileError,
 };
-use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
+use drogue_cloud_service_api::kafka::ResourceType;
 use rdkafka::{
     admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
     client::DefaultClientContext,
     error::{KafkaError, RDKafkaErrorCode},
 };
+use std::{num::NonZeroU32, sync::Arc, time::Duration};
 
 pub struct CreateTopic<'o> {
     pub config: &'o ControllerConfig,
@@ -28,7 +29,10 @@ impl<'o> ProgressOperation<ConstructContext> for CreateTopic<'o> {
         ctx: ConstructContext,
     ) -> drogue_cloud_operator_common::controller::reconciler::progress::Result<ConstructContext>
     {
-        let topic_name = make_kafka_resource_name(ResourceType::Events(&ctx.app.metadata.name));
+        let topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Events(&ctx.app.metadata.name));
 
         let mut config = Vec::with_capacity(self.config.properties.len());
         for (k, v) in &self.config.properties {
@@ -89,3 +93,118 @@ impl<'o> ProgressOperation<ConstructContext> for CreateTopic<'o> {
         Ok(OperationOutcome::Continue(ctx))
     }
 }
+
+/// The state of a topic, as reported by the metadata of the cluster.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TopicState {
+    Missing,
+    Failed(String),
+    Partitions(usize),
+}
+
+impl TopicState {
+    /// Evaluate if the topic is ready, returning the reason if it is not.
+    pub fn not_ready(&self, expected_partitions: NonZeroU32) -> Option<String> {
+        match self {
+            Self::Missing => Some("Topic not found".into()),
+            Self::Failed(err) => Some(format!("Topic reported error: {}", err)),
+            Self::Partitions(partitions)
+                if *partitions < expected_partitions.get().try_into().unwrap_or(usize::MAX) =>
+            {
+                Some(format!(
+                    "Topic has {} of {} partitions",
+                    partitions, expected_partitions
+                ))
+            }
+            Self::Partitions(_) => None,
+        }
+    }
+}
+
+pub struct TopicReady<'o> {
+    pub config: &'o ControllerConfig,
+    pub admin: &'o Arc<AdminClient<DefaultClientContext>>,
+}
+
+impl<'o> TopicReady<'o> {
+    async fn topic_state(&self, topic_name: String) -> Result<TopicState, ReconcileError> {
+        let admin = self.admin.clone();
+        let timeout = self.config.metadata_timeout;
+
+        // fetching metadata is blocking
+        tokio::task::spawn_blocking(move || {
+            let metadata = admin
+                .inner()
+                .fetch_metadata(Some(&topic_name), timeout)
+                .map_err(|err| {
+                    ReconcileError::temporary(format!("Failed to fetch topic metadata: {}", err))
+                })?;
+
+            Ok(
+                match metadata.topics().iter().find(|t| t.name() == topic_name) {
+                    None => TopicState::Missing,
+                    Some(topic) => match topic.error() {
+                        Some(err) => {
+                            TopicState::Failed(format!("{:?}", RDKafkaErrorCode::from(err)))
+                        }
+                        None => TopicState::Partitions(topic.partitions().len()),
+                    },
+                },
+            )
+        })
+        .await
+        .map_err(|err| {
+            ReconcileError::temporary(format!("Failed to fetch topic metadata: {}", err))
+        })?
+    }
+}
+
+#[async_trait]
+impl<'o> ProgressOperation<ConstructContext> for TopicReady<'o> {
+    fn type_name(&self) -> String {
+        "TopicsReady".into()
+    }
+
+    async fn run(&self, ctx: ConstructContext) -> progress::Result<ConstructContext> {
+        let topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Events(&ctx.app.metadata.name));
+
+        let state = self.topic_state(topic_name.clone()).await?;
+
+        match state.not_ready(self.config.num_partitions) {
+            None => Ok(OperationOutcome::Continue(ctx)),
+            Some(reason) => {
+                log::debug!("Topic {} not ready: {}", topic_name, reason);
+                Ok(OperationOutcome::Retry(ctx, Some(Duration::from_secs(15))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const THREE: NonZeroU32 = unsafe { NonZeroU32::new_unchecked(3) };
+
+    #[test]
+    fn test_ready() {
+        assert_eq!(TopicState::Partitions(3).not_ready(THREE), None);
+        // partitions may be increased manually
+        assert_eq!(TopicState::Partitions(5).not_ready(THREE), None);
+    }
+
+    #[test]
+    fn test_not_ready() {
+        assert!(TopicState::Missing.not_ready(THREE).is_some());
+        assert!(TopicState::Failed("LeaderNotAvailable".into())
+            .not_ready(THREE)
+            .is_some());
+        assert_eq!(
+            TopicState::Partitions(1).not_ready(THREE).as_deref(),
+            Some("Topic has 1 of 3 partitions")
+        );
+    }
+}
@@ -1,7 +1,8 @@
 pub mod app;
 
+use drogue_cloud_service_api::kafka::TopicNaming;
 use serde::Deserialize;
-use std::{collections::HashMap, num::NonZeroU32};
+use std::{collections::HashMap, num::NonZeroU32, time::Duration};
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct ControllerConfig {
@@ -11,6 +12,14 @@ pub struct ControllerConfig {
     pub num_replicas: NonZeroU32,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub properties: HashMap<String, String>,
+    /// Timeout when fetching the metadata of a topic, checking its readiness.
+    #[serde(default = "default::metadata_timeout", with = "humantime_serde")]
+    pub metadata_timeout: Duration,
+    /// The naming of the topics of applications.
+    ///
+    /// Services which derive the topic names, like the endpoints, must use the same naming.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
 
 impl ControllerConfig {
@@ -26,7 +35,7 @@ impl ControllerConfig {
 }
 
 mod default {
-    use std::num::NonZeroU32;
+    use std::{num::NonZeroU32, time::Duration};
 
     const ONE: NonZeroU32 = unsafe { NonZeroU32::new_unchecked(1) };
 
@@ -37,4 +46,8 @@ mod default {
     pub(crate) const fn num_replicas() -> NonZeroU32 {
         ONE
     }
+
+    pub(crate) const fn metadata_timeout() -> Duration {
+        Duration::from_secs(5)
+    }
 }
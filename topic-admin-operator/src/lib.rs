@@ -19,7 +19,6 @@ use futures::TryFutureExt;
 use serde::Deserialize;
 use std::fmt::Debug;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
@@ -60,6 +59,12 @@ fn is_relevant(event: &Event) -> Option<String> {
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
     log::debug!("Config: {:#?}", config);
 
+    config
+        .controller
+        .topic_naming
+        .validate()
+        .map_err(|err| anyhow::anyhow!("Invalid topic naming: {err}"))?;
+
     // client
 
     let registry = config.registry.into_client().await?;
@@ -67,16 +72,17 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
     // controller
 
     let client_config: rdkafka::ClientConfig = config.kafka_admin.into();
-    let controller = Arc::new(Mutex::new(BaseController::new(
+    let controller = Arc::new(BaseController::new(
         config.work_queue,
         "app",
         ApplicationController::new(config.controller, registry, client_config.create()?),
-    )?));
+    )?);
 
     // event source - device registry
 
     let registry_dispatcher = EventDispatcher::one(FnEventProcessor::new(controller, is_relevant));
     let registry = KafkaEventStream::new(config.kafka_source)?;
+    startup.check(registry.health());
     let registry = registry.run(registry_dispatcher);
 
     // run
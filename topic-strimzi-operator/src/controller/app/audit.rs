@@ -0,0 +1,273 @@
+use crate::controller::AuditSinkConfig;
+use chrono::{DateTime, Utc};
+use drogue_client::registry;
+use drogue_cloud_operator_common::controller::reconciler::ReconcileError;
+use drogue_cloud_service_api::kafka::KafkaConfig;
+use rdkafka::{
+    producer::{FutureProducer, FutureRecord},
+    ClientConfig,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// The action taken on a topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/// A record of a change to a topic, taken by the operator.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub application: String,
+    /// The generation of the application, which was reconciled.
+    pub generation: u64,
+    pub topic: String,
+    pub action: AuditAction,
+    /// The fields of the topic spec, which got changed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<String>,
+    pub outcome: AuditOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// Create the record of a change to the events topic of an application.
+    pub fn new<T>(
+        app: &registry::v1::Application,
+        topic: String,
+        action: AuditAction,
+        changes: Vec<String>,
+        result: Result<T, &ReconcileError>,
+    ) -> Self {
+        let (outcome, error) = match result {
+            Ok(_) => (AuditOutcome::Success, None),
+            Err(err) => (AuditOutcome::Failure, Some(err.to_string())),
+        };
+
+        Self {
+            timestamp: Utc::now(),
+            application: app.metadata.name.clone(),
+            generation: app.metadata.generation,
+            topic,
+            action,
+            changes,
+            outcome,
+            error,
+        }
+    }
+}
+
+/// A sink for audit records.
+///
+/// Recording is best effort, and must not block the reconciliation.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord);
+}
+
+/// Logging the records as JSON, to a dedicated log target.
+pub struct LogAuditSink {
+    target: String,
+}
+
+impl AuditSink for LogAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        match serde_json::to_string(record) {
+            Ok(record) => log::info!(target: self.target.as_str(), "{}", record),
+            Err(err) => log::warn!("Failed to encode audit record: {err}"),
+        }
+    }
+}
+
+/// Sending the records to a Kafka topic, keyed by the application.
+pub struct KafkaAuditSink {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl KafkaAuditSink {
+    pub fn new(config: KafkaConfig) -> anyhow::Result<Self> {
+        let client_config: ClientConfig = config.client.into();
+        Ok(Self {
+            producer: client_config.create()?,
+            topic: config.topic,
+        })
+    }
+}
+
+impl AuditSink for KafkaAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        let payload = match serde_json::to_vec(record) {
+            Ok(payload) => payload,
+            Err(err) => {
+                log::warn!("Failed to encode audit record: {err}");
+                return;
+            }
+        };
+
+        // only enqueue, without waiting for the delivery
+        let message = FutureRecord::to(&self.topic)
+            .key(&record.application)
+            .payload(&payload);
+        if let Err((err, _)) = self.producer.send_result(message) {
+            log::warn!(
+                "Failed to send audit record of application '{}': {}",
+                record.application,
+                err
+            );
+        }
+    }
+}
+
+/// Create the configured audit sink.
+pub fn create_audit_sink(config: &AuditSinkConfig) -> anyhow::Result<Arc<dyn AuditSink>> {
+    Ok(match config {
+        AuditSinkConfig::Log { target } => Arc::new(LogAuditSink {
+            target: target.clone(),
+        }),
+        AuditSinkConfig::Kafka(kafka) => Arc::new(KafkaAuditSink::new(kafka.clone())?),
+    })
+}
+
+/// Evaluate the change of a topic spec.
+///
+/// Returns [`None`] if the spec didn't change.
+pub fn topic_change(before: &Value, after: &Value) -> Option<(AuditAction, Vec<String>)> {
+    let before = match before.as_object() {
+        Some(before) => before,
+        None => return Some((AuditAction::Create, vec![])),
+    };
+
+    let empty = Default::default();
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut changes: Vec<String> = before
+        .keys()
+        .chain(after.keys())
+        .filter(|field| before.get(*field) != after.get(*field))
+        .cloned()
+        .collect();
+    changes.sort();
+    changes.dedup();
+
+    (!changes.is_empty()).then_some((AuditAction::Update, changes))
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    pub struct MockAuditSink(pub Mutex<Vec<AuditRecord>>);
+
+    impl AuditSink for MockAuditSink {
+        fn record(&self, record: &AuditRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    fn spec(partitions: u32) -> Value {
+        json!({
+            "config": { "retention.ms": 1000 },
+            "partitions": partitions,
+            "replicas": 1,
+            "topicName": "events-app1",
+        })
+    }
+
+    fn app() -> registry::v1::Application {
+        let mut app = registry::v1::Application::default();
+        app.metadata.name = "app1".into();
+        app.metadata.generation = 2;
+        app
+    }
+
+    fn record(
+        action: AuditAction,
+        changes: Vec<String>,
+        result: Result<(), &ReconcileError>,
+    ) -> Value {
+        let sink = MockAuditSink::default();
+        sink.record(&AuditRecord::new(
+            &app(),
+            "events-app1".into(),
+            action,
+            changes,
+            result,
+        ));
+
+        let mut records = sink.0.into_inner().unwrap();
+        assert_eq!(records.len(), 1);
+        let mut value = serde_json::to_value(records.remove(0)).unwrap();
+        assert!(value.as_object_mut().unwrap().remove("timestamp").is_some());
+        value
+    }
+
+    #[test]
+    fn test_create() {
+        let (action, changes) = topic_change(&Value::Null, &spec(3)).unwrap();
+
+        assert_eq!(
+            record(action, changes, Ok(())),
+            json!({
+                "application": "app1",
+                "generation": 2,
+                "topic": "events-app1",
+                "action": "create",
+                "outcome": "success",
+            })
+        );
+    }
+
+    #[test]
+    fn test_update() {
+        assert_eq!(topic_change(&spec(3), &spec(3)), None);
+
+        let (action, changes) = topic_change(&spec(3), &spec(5)).unwrap();
+
+        assert_eq!(
+            record(action, changes, Ok(())),
+            json!({
+                "application": "app1",
+                "generation": 2,
+                "topic": "events-app1",
+                "action": "update",
+                "changes": ["partitions"],
+                "outcome": "success",
+            })
+        );
+    }
+
+    #[test]
+    fn test_delete() {
+        let err = ReconcileError::temporary("Forbidden");
+
+        assert_eq!(
+            record(AuditAction::Delete, vec![], Err(&err)),
+            json!({
+                "application": "app1",
+                "generation": 2,
+                "topic": "events-app1",
+                "action": "delete",
+                "outcome": "failure",
+                "error": err.to_string(),
+            })
+        );
+    }
+}
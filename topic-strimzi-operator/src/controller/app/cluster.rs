@@ -0,0 +1,223 @@
+use crate::data::KafkaAppStatus;
+use async_trait::async_trait;
+use drogue_client::{core::v1::ConditionStatus, registry, Translator};
+use drogue_cloud_operator_common::controller::{
+    base::{ConditionExt, ProcessOutcome},
+    reconciler::ReconcileError,
+};
+use kube::{api::DynamicObject, Api};
+use std::time::Duration;
+
+const CONDITION_KAFKA_CLUSTER_READY: &str = "KafkaClusterReady";
+/// Annotation on the `Kafka` resource, pausing its reconciliation by Strimzi.
+const ANNOTATION_PAUSE_RECONCILIATION: &str = "strimzi.io/pause-reconciliation";
+
+/// The state of the Kafka cluster.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClusterState {
+    Ready,
+    Unavailable(String),
+}
+
+/// A source of the Kafka cluster state.
+#[async_trait]
+pub trait ClusterStateSource: Send + Sync {
+    async fn state(&self) -> ClusterState;
+}
+
+/// Evaluate the cluster state from the Strimzi `Kafka` resource.
+pub struct KafkaClusterSource {
+    api: Api<DynamicObject>,
+    name: String,
+}
+
+impl KafkaClusterSource {
+    pub fn new(api: Api<DynamicObject>, name: String) -> Self {
+        Self { api, name }
+    }
+}
+
+#[async_trait]
+impl ClusterStateSource for KafkaClusterSource {
+    async fn state(&self) -> ClusterState {
+        match self.api.get_opt(&self.name).await {
+            Ok(Some(kafka)) => cluster_state(&kafka),
+            Ok(None) => ClusterState::Unavailable(format!("Kafka '{}' not found", self.name)),
+            Err(err) => {
+                ClusterState::Unavailable(format!("Failed to get Kafka '{}': {}", self.name, err))
+            }
+        }
+    }
+}
+
+/// Evaluate the state of a `Kafka` resource.
+fn cluster_state(kafka: &DynamicObject) -> ClusterState {
+    let paused = kafka
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(ANNOTATION_PAUSE_RECONCILIATION))
+        .map(|value| value == "true")
+        .unwrap_or_default();
+
+    if paused {
+        return ClusterState::Unavailable("Reconciliation of the Kafka cluster is paused".into());
+    }
+
+    match super::condition_ready("Ready", kafka) {
+        Some(true) => ClusterState::Ready,
+        _ => ClusterState::Unavailable("Kafka cluster is not ready".into()),
+    }
+}
+
+/// Check if the Kafka cluster is available.
+///
+/// The result is recorded as a condition of the application. If the cluster is unavailable, this
+/// returns the outcome of the reconciliation, re-checking after the provided delay.
+pub async fn check_cluster(
+    source: &dyn ClusterStateSource,
+    app: &mut registry::v1::Application,
+    delay: Duration,
+) -> Result<Option<ProcessOutcome<registry::v1::Application>>, ReconcileError> {
+    let state = source.state().await;
+
+    let mut conditions = app
+        .section::<KafkaAppStatus>()
+        .and_then(|s| s.ok())
+        .map(|s| s.status.conditions)
+        .unwrap_or_default();
+
+    match state {
+        ClusterState::Ready => {
+            if conditions
+                .0
+                .iter()
+                .any(|c| c.r#type == CONDITION_KAFKA_CLUSTER_READY && c.status != "True")
+            {
+                log::info!("Kafka cluster is available again");
+            }
+            conditions.update(
+                CONDITION_KAFKA_CLUSTER_READY,
+                ConditionStatus {
+                    status: Some(true),
+                    ..Default::default()
+                },
+            );
+            app.set_status::<KafkaAppStatus>(conditions, app.metadata.generation)?;
+            Ok(None)
+        }
+        ClusterState::Unavailable(reason) => {
+            log::info!(
+                "Waiting for Kafka cluster, skipping application '{}': {}",
+                app.metadata.name,
+                reason
+            );
+            conditions.update(
+                CONDITION_KAFKA_CLUSTER_READY,
+                ConditionStatus {
+                    status: Some(false),
+                    reason: Some("WaitingForKafkaCluster".into()),
+                    message: Some(format!("Waiting for Kafka cluster: {reason}")),
+                },
+            );
+            app.finish_ready::<KafkaAppStatus>(conditions, app.metadata.generation)?;
+            Ok(Some(ProcessOutcome::Retry(app.clone(), Some(delay))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockSource(Mutex<Option<ClusterState>>);
+
+    #[async_trait]
+    impl ClusterStateSource for MockSource {
+        async fn state(&self) -> ClusterState {
+            self.0.lock().unwrap().clone().unwrap()
+        }
+    }
+
+    fn kafka(annotations: serde_json::Value, ready: &str) -> DynamicObject {
+        serde_json::from_value(json!({
+            "apiVersion": "kafka.strimzi.io/v1beta2",
+            "kind": "Kafka",
+            "metadata": {
+                "name": "drogue",
+                "annotations": annotations,
+            },
+            "status": {
+                "conditions": [
+                    {
+                        "type": "Ready",
+                        "status": ready,
+                    }
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn condition(app: &registry::v1::Application) -> (String, Option<String>) {
+        let status = app.section::<KafkaAppStatus>().unwrap().unwrap();
+        let condition = status
+            .status
+            .conditions
+            .0
+            .iter()
+            .find(|c| c.r#type == CONDITION_KAFKA_CLUSTER_READY)
+            .unwrap();
+        (condition.status.clone(), condition.message.clone())
+    }
+
+    #[test]
+    fn test_cluster_state() {
+        assert_eq!(
+            cluster_state(&kafka(json!({}), "True")),
+            ClusterState::Ready
+        );
+        assert!(matches!(
+            cluster_state(&kafka(json!({}), "False")),
+            ClusterState::Unavailable(_)
+        ));
+        assert!(matches!(
+            cluster_state(&kafka(
+                json!({ANNOTATION_PAUSE_RECONCILIATION: "true"}),
+                "True"
+            )),
+            ClusterState::Unavailable(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_then_available() {
+        let source = MockSource::default();
+        let delay = Duration::from_secs(120);
+        let mut app = registry::v1::Application::default();
+
+        // unavailable
+
+        *source.0.lock().unwrap() = Some(ClusterState::Unavailable("paused".into()));
+        let outcome = check_cluster(&source, &mut app, delay).await.unwrap();
+
+        assert!(matches!(outcome, Some(ProcessOutcome::Retry(_, Some(d))) if d == delay));
+        let (status, message) = condition(&app);
+        assert_eq!(status, "False");
+        assert_eq!(
+            message.as_deref(),
+            Some("Waiting for Kafka cluster: paused")
+        );
+
+        // available again
+
+        *source.0.lock().unwrap() = Some(ClusterState::Ready);
+        let outcome = check_cluster(&source, &mut app, delay).await.unwrap();
+
+        assert!(outcome.is_none());
+        assert_eq!(condition(&app).0, "True");
+    }
+}
@@ -0,0 +1,224 @@
+use crate::data::KafkaAppStatus;
+use async_trait::async_trait;
+use drogue_client::{registry, Translator};
+use drogue_cloud_operator_common::controller::{
+    base::CONDITION_RECONCILED, reconciler::ReconcileError,
+};
+use k8s_openapi::api::core::v1::ObjectReference;
+use kube::api::ApiResource;
+use kube_runtime::events::{Event, EventType, Recorder, Reporter};
+
+/// A transition of a topic, reported as Kubernetes event.
+///
+/// The reasons are stable, so that they can be used for selecting events.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TopicEvent {
+    Created,
+    NotReady,
+    Deleted,
+    ReconcileFailed(String),
+}
+
+impl TopicEvent {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Created => "TopicCreated",
+            Self::NotReady => "TopicNotReady",
+            Self::Deleted => "TopicDeleted",
+            Self::ReconcileFailed(_) => "ReconcileFailed",
+        }
+    }
+
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Self::Created | Self::Deleted => EventType::Normal,
+            Self::NotReady | Self::ReconcileFailed(_) => EventType::Warning,
+        }
+    }
+
+    fn action(&self) -> &'static str {
+        match self {
+            Self::Created => "Create",
+            Self::Deleted => "Delete",
+            Self::NotReady | Self::ReconcileFailed(_) => "Reconcile",
+        }
+    }
+
+    pub fn note(&self, application: &str) -> String {
+        match self {
+            Self::Created => format!("Created topic of application '{application}'"),
+            Self::NotReady => {
+                format!("Waiting for topic of application '{application}' to become ready")
+            }
+            Self::Deleted => format!("Deleted topic of application '{application}'"),
+            Self::ReconcileFailed(message) => {
+                format!("Failed to reconcile application '{application}': {message}")
+            }
+        }
+    }
+}
+
+/// A sink for the events of topics.
+#[async_trait]
+pub trait TopicEventSink: Send + Sync {
+    async fn publish(
+        &self,
+        namespace: &str,
+        topic: &str,
+        application: &str,
+        event: TopicEvent,
+    ) -> Result<(), ReconcileError>;
+}
+
+/// Publishing the events as Kubernetes events, regarding the `KafkaTopic`.
+pub struct KubeEventSink {
+    client: kube::Client,
+    reporter: Reporter,
+    resource: ApiResource,
+}
+
+impl KubeEventSink {
+    pub fn new(client: kube::Client, resource: ApiResource) -> Self {
+        Self {
+            client,
+            reporter: Reporter {
+                controller: "drogue-topic-operator".into(),
+                instance: std::env::var("HOSTNAME").ok(),
+            },
+            resource,
+        }
+    }
+}
+
+#[async_trait]
+impl TopicEventSink for KubeEventSink {
+    async fn publish(
+        &self,
+        namespace: &str,
+        topic: &str,
+        application: &str,
+        event: TopicEvent,
+    ) -> Result<(), ReconcileError> {
+        let reference = ObjectReference {
+            api_version: Some(self.resource.api_version.clone()),
+            kind: Some(self.resource.kind.clone()),
+            name: Some(topic.into()),
+            namespace: Some(namespace.into()),
+            ..Default::default()
+        };
+
+        Recorder::new(self.client.clone(), self.reporter.clone(), reference)
+            .publish(Event {
+                type_: event.event_type(),
+                reason: event.reason().into(),
+                note: Some(event.note(application)),
+                action: event.action().into(),
+                secondary: None,
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Emit the event of a topic.
+///
+/// Events are informational only, so failures are logged, but don't fail the reconciliation.
+pub async fn emit_event(
+    sink: &dyn TopicEventSink,
+    namespace: &str,
+    topic: &str,
+    application: &str,
+    event: TopicEvent,
+) {
+    let reason = event.reason();
+    if let Err(err) = sink.publish(namespace, topic, application, event).await {
+        log::warn!("Failed to publish event '{reason}' of topic '{topic}': {err}");
+    }
+}
+
+/// Get the message of a failed reconciliation, from the status of the application.
+pub fn reconcile_failure(app: &registry::v1::Application) -> Option<String> {
+    app.section::<KafkaAppStatus>()
+        .and_then(|s| s.ok())?
+        .status
+        .conditions
+        .0
+        .into_iter()
+        .find(|c| {
+            c.r#type == CONDITION_RECONCILED
+                && c.status == "False"
+                && c.reason.as_deref() == Some("Failed")
+        })
+        .map(|c| c.message.unwrap_or_default())
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use super::*;
+    use drogue_cloud_operator_common::controller::base::{ConditionExt, ReadyState};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    pub struct MockEventSink(pub Mutex<Vec<(String, TopicEvent)>>);
+
+    #[async_trait]
+    impl TopicEventSink for MockEventSink {
+        async fn publish(
+            &self,
+            _namespace: &str,
+            topic: &str,
+            _application: &str,
+            event: TopicEvent,
+        ) -> Result<(), ReconcileError> {
+            self.0.lock().unwrap().push((topic.into(), event));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_reasons() {
+        assert_eq!(TopicEvent::Created.reason(), "TopicCreated");
+        assert_eq!(TopicEvent::NotReady.reason(), "TopicNotReady");
+        assert_eq!(TopicEvent::Deleted.reason(), "TopicDeleted");
+        assert_eq!(
+            TopicEvent::ReconcileFailed("Forbidden".into()).event_type(),
+            EventType::Warning
+        );
+    }
+
+    #[test]
+    fn test_reconcile_failure() {
+        let mut app = registry::v1::Application::default();
+        assert_eq!(reconcile_failure(&app), None);
+
+        let mut status = KafkaAppStatus::default();
+        status
+            .status
+            .conditions
+            .update(CONDITION_RECONCILED, ReadyState::Progressing);
+        app.set_section(status.clone()).unwrap();
+        assert_eq!(reconcile_failure(&app), None);
+
+        status.status.conditions.update(
+            CONDITION_RECONCILED,
+            ReadyState::Failed("Unknown Kafka cluster 'gold'".into()),
+        );
+        app.set_section(status).unwrap();
+        assert_eq!(
+            reconcile_failure(&app).as_deref(),
+            Some("Unknown Kafka cluster 'gold'")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_emit() {
+        let sink = MockEventSink::default();
+        emit_event(&sink, "kafka", "events-app1", "app1", TopicEvent::Created).await;
+
+        assert_eq!(
+            *sink.0.lock().unwrap(),
+            vec![("events-app1".to_string(), TopicEvent::Created)]
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use super::{ConstructContext, ANNOTATION_APP_NAME, LABEL_MARKER};
+use async_trait::async_trait;
+use drogue_cloud_operator_common::controller::reconciler::{
+    progress::{self, OperationOutcome, ProgressOperation},
+    ReconcileError,
+};
+use drogue_cloud_service_api::kafka::{ResourceType, TopicNaming};
+use kube::{
+    api::{DynamicObject, ListParams},
+    Api,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// An index of topic names to the applications owning them.
+///
+/// This allows detecting two applications claiming the same topic, either due to a (rare)
+/// collision of the generated names, or a manually created topic.
+#[derive(Clone, Debug, Default)]
+pub struct TopicIndex {
+    owners: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl TopicIndex {
+    /// Build the index from the existing topics, using the application name annotation.
+    pub fn from_topics<'t>(topics: impl IntoIterator<Item = &'t DynamicObject>) -> Self {
+        let owners = topics
+            .into_iter()
+            .filter_map(|topic| {
+                let name = topic.metadata.name.clone()?;
+                let app = topic
+                    .metadata
+                    .annotations
+                    .as_ref()?
+                    .get(ANNOTATION_APP_NAME)?;
+                Some((name, app.clone()))
+            })
+            .collect();
+
+        Self {
+            owners: Arc::new(Mutex::new(owners)),
+        }
+    }
+
+    /// Load the index from the topics managed by the operator.
+    pub async fn load(api: &Api<DynamicObject>) -> anyhow::Result<Self> {
+        let topics = api
+            .list(&ListParams::default().labels(&format!("{LABEL_MARKER}=true")))
+            .await?;
+
+        let index = Self::from_topics(&topics.items);
+        log::info!("Loaded topic index: {} topics", index.len());
+
+        Ok(index)
+    }
+
+    fn len(&self) -> usize {
+        self.owners.lock().unwrap().len()
+    }
+
+    /// Claim a topic for an application.
+    ///
+    /// If the topic is already owned by a different application, the name of that application
+    /// is returned as error.
+    pub fn claim(&self, topic: &str, app: &str) -> Result<(), String> {
+        let mut owners = self.owners.lock().unwrap();
+        match owners.get(topic) {
+            Some(owner) if owner != app => Err(owner.clone()),
+            Some(_) => Ok(()),
+            None => {
+                owners.insert(topic.to_string(), app.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Release a topic, if it is owned by the application.
+    pub fn release(&self, topic: &str, app: &str) {
+        let mut owners = self.owners.lock().unwrap();
+        if owners.get(topic).map(String::as_str) == Some(app) {
+            owners.remove(topic);
+        }
+    }
+}
+
+/// Ensure that the topic of the application isn't owned by another application.
+pub struct ClaimTopic<'o> {
+    pub index: &'o TopicIndex,
+    pub naming: &'o TopicNaming,
+}
+
+#[async_trait]
+impl<'o> ProgressOperation<ConstructContext> for ClaimTopic<'o> {
+    fn type_name(&self) -> String {
+        "TopicOwned".into()
+    }
+
+    async fn run(&self, ctx: ConstructContext) -> progress::Result<ConstructContext> {
+        let app = &ctx.app.metadata.name;
+        let topic_name = self.naming.resource_name(ResourceType::Events(app));
+
+        match self.index.claim(&topic_name, app) {
+            Ok(()) => Ok(OperationOutcome::Continue(ctx)),
+            Err(owner) => Err(ReconcileError::permanent(format!(
+                "Topic '{topic_name}' is already owned by application '{owner}'"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_client::registry;
+    use serde_json::json;
+
+    fn topic(name: &str, app: &str) -> DynamicObject {
+        serde_json::from_value(json!({
+            "apiVersion": "kafka.strimzi.io/v1beta2",
+            "kind": "KafkaTopic",
+            "metadata": {
+                "name": name,
+                "annotations": {
+                    ANNOTATION_APP_NAME: app,
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    fn context(app: &str) -> ConstructContext {
+        let mut application = registry::v1::Application::default();
+        application.metadata.name = app.into();
+        ConstructContext {
+            app: application,
+            events_topic: None,
+            events_topic_name: None,
+            events_topic_partitions: None,
+            events_topic_drift: vec![],
+            events_topic_deferred: vec![],
+            events_topic_ignored_config: vec![],
+            commands_topic: None,
+            app_user: None,
+            app_user_name: None,
+        }
+    }
+
+    #[test]
+    fn test_claim() {
+        let index = TopicIndex::default();
+
+        assert_eq!(index.claim("events-app1", "app1"), Ok(()));
+        assert_eq!(index.claim("events-app1", "app1"), Ok(()));
+        assert_eq!(index.claim("events-app1", "app2"), Err("app1".into()));
+
+        // releasing by a different app must not change the owner
+
+        index.release("events-app1", "app2");
+        assert_eq!(index.claim("events-app1", "app2"), Err("app1".into()));
+
+        index.release("events-app1", "app1");
+        assert_eq!(index.claim("events-app1", "app2"), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_same_topic_name() {
+        // "app2" already owns the topic, which "app1" resolves to
+        let index = TopicIndex::from_topics(&[topic("events-app1", "app2")]);
+        let op = ClaimTopic {
+            index: &index,
+            naming: &TopicNaming::default(),
+        };
+
+        let result = op.run(context("app1")).await;
+        assert!(
+            matches!(&result, Err(ReconcileError::Permanent(msg)) if msg.contains("'app2'")),
+            "Result: {result:?}"
+        );
+
+        // other apps are not affected
+
+        assert!(matches!(
+            op.run(context("app3")).await,
+            Ok(OperationOutcome::Continue(_))
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::data::KafkaAppStatus;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use prometheus::{register_histogram_vec, HistogramVec};
+use std::time::Duration;
+
+lazy_static! {
+    pub static ref TOPIC_PROVISIONING_LATENCY: HistogramVec = register_histogram_vec!(
+        "drogue_topic_provisioning_seconds",
+        "Time from first reconciling an application, until its topic is ready",
+        &["outcome"],
+        vec![1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0]
+    )
+    .unwrap();
+}
+
+/// Record the start of the provisioning, unless it was already started.
+pub fn provisioning_started(status: &mut KafkaAppStatus, now: DateTime<Utc>) {
+    status.provisioning_started.get_or_insert(now);
+}
+
+/// Record the topic being ready.
+///
+/// The latency is only observed the first time the topic is ready, and only if the start of the
+/// provisioning was recorded. Returns the observed latency.
+pub fn topic_provisioned(status: &mut KafkaAppStatus, now: DateTime<Utc>) -> Option<Duration> {
+    if status.provisioned.is_some() {
+        return None;
+    }
+
+    let started = status.provisioning_started?;
+    status.provisioned = Some(now);
+
+    let latency = observe(started, now, "Ready");
+    log::debug!("Provisioned topic in {:?}", latency);
+    Some(latency)
+}
+
+/// Record the application being deleted, before its topic was ready.
+pub fn provisioning_abandoned(status: &KafkaAppStatus, now: DateTime<Utc>) -> Option<Duration> {
+    match (status.provisioning_started, status.provisioned) {
+        (Some(started), None) => Some(observe(started, now, "Deleted")),
+        _ => None,
+    }
+}
+
+fn observe(started: DateTime<Utc>, now: DateTime<Utc>, outcome: &str) -> Duration {
+    let latency = (now - started).to_std().unwrap_or_default();
+    TOPIC_PROVISIONING_LATENCY
+        .with_label_values(&[outcome])
+        .observe(latency.as_secs_f64());
+    latency
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn count(outcome: &str) -> u64 {
+        TOPIC_PROVISIONING_LATENCY
+            .with_label_values(&[outcome])
+            .get_sample_count()
+    }
+
+    #[test]
+    fn test_recorded_once_ready() {
+        let start = Utc::now();
+        let mut status = KafkaAppStatus::default();
+
+        provisioning_started(&mut status, start);
+        // later reconciliations keep the start
+        provisioning_started(&mut status, start + chrono::Duration::seconds(5));
+
+        let before = count("Ready");
+        assert_eq!(
+            topic_provisioned(&mut status, start + chrono::Duration::seconds(42)),
+            Some(Duration::from_secs(42))
+        );
+        assert_eq!(count("Ready"), before + 1);
+
+        // only the first transition is recorded
+        assert_eq!(
+            topic_provisioned(&mut status, start + chrono::Duration::seconds(60)),
+            None
+        );
+        assert_eq!(
+            provisioning_abandoned(&status, start + chrono::Duration::seconds(90)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_not_started() {
+        let mut status = KafkaAppStatus::default();
+
+        assert_eq!(topic_provisioned(&mut status, Utc::now()), None);
+        assert_eq!(status.provisioned, None);
+    }
+
+    #[test]
+    fn test_abandoned() {
+        let start = Utc::now();
+        let mut status = KafkaAppStatus::default();
+        provisioning_started(&mut status, start);
+
+        assert_eq!(
+            provisioning_abandoned(&status, start + chrono::Duration::seconds(10)),
+            Some(Duration::from_secs(10))
+        );
+    }
+}
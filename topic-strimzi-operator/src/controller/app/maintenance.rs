@@ -0,0 +1,242 @@
+use crate::{
+    controller::{MaintenanceConfig, MaintenanceWindow},
+    data::KafkaAppStatus,
+};
+use chrono::{DateTime, Datelike, TimeZone, Utc, Weekday};
+use drogue_client::{registry, Translator};
+use drogue_cloud_operator_common::controller::base::ProcessOutcome;
+use kube::api::DynamicObject;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Fields of the topic spec, whose changes may disrupt consumers.
+const DISRUPTIVE_FIELDS: &[&str] = &["config", "partitions"];
+
+fn starts_on(window: &MaintenanceWindow, day: Weekday) -> bool {
+    window.days.is_empty() || window.days.contains(&day)
+}
+
+fn is_open(window: &MaintenanceWindow, now: DateTime<Utc>) -> bool {
+    let time = now.time();
+    let today = now.weekday();
+
+    if window.start < window.end {
+        starts_on(window, today) && window.start <= time && time < window.end
+    } else {
+        // spanning midnight, possibly started yesterday
+        (starts_on(window, today) && time >= window.start)
+            || (starts_on(window, today.pred()) && time < window.end)
+    }
+}
+
+/// Evaluate the time until the next maintenance window opens.
+///
+/// Returns [`None`] if disruptive changes may be applied now, because a window is open, or no
+/// windows are configured.
+pub fn until_maintenance(config: &MaintenanceConfig, now: DateTime<Utc>) -> Option<Duration> {
+    if config.windows.is_empty() || config.windows.iter().any(|w| is_open(w, now)) {
+        return None;
+    }
+
+    let next = (0..=7)
+        .flat_map(|days| {
+            let date = now.date_naive() + chrono::Duration::days(days);
+            config
+                .windows
+                .iter()
+                .map(move |window| (window, Utc.from_utc_datetime(&date.and_time(window.start))))
+        })
+        .filter(|(window, start)| *start > now && starts_on(window, start.weekday()))
+        .filter_map(|(_, start)| (start - now).to_std().ok())
+        .min();
+
+    // every window starts at least once a week, so this is only a safeguard
+    Some(next.unwrap_or(Duration::from_secs(24 * 60 * 60)))
+}
+
+/// Keep the disruptive fields of an existing topic, returning the fields whose change got
+/// deferred.
+pub fn defer_changes(topic: &DynamicObject, declared: &mut Value) -> Vec<String> {
+    let live = match topic.data["spec"].as_object() {
+        Some(live) => live,
+        // new topic, nothing to disrupt
+        None => return vec![],
+    };
+
+    let mut deferred = vec![];
+    for field in DISRUPTIVE_FIELDS {
+        if let Some(value) = live.get(*field) {
+            if declared[*field] != *value {
+                declared[*field] = value.clone();
+                deferred.push(field.to_string());
+            }
+        }
+    }
+
+    deferred
+}
+
+/// Re-schedule an application with deferred changes, for when the maintenance window opens.
+pub fn reschedule_deferred(
+    outcome: ProcessOutcome<registry::v1::Application>,
+    delay: Option<Duration>,
+) -> ProcessOutcome<registry::v1::Application> {
+    let deferred = |app: &registry::v1::Application| {
+        app.section::<KafkaAppStatus>()
+            .and_then(|s| s.ok())
+            .map(|s| !s.deferred_changes.is_empty())
+            .unwrap_or_default()
+    };
+
+    match (outcome, delay) {
+        (ProcessOutcome::Complete(app), Some(delay)) if deferred(&app) => {
+            ProcessOutcome::Retry(app, Some(delay))
+        }
+        (ProcessOutcome::Retry(app, Some(when)), Some(delay)) if deferred(&app) => {
+            ProcessOutcome::Retry(app, Some(when.min(delay)))
+        }
+        (outcome, _) => outcome,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::NaiveTime;
+    use serde_json::json;
+
+    fn window(days: Vec<Weekday>, start: u32, end: u32) -> MaintenanceWindow {
+        MaintenanceWindow {
+            days,
+            start: NaiveTime::from_hms_opt(start, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(end, 0, 0).unwrap(),
+        }
+    }
+
+    fn config(windows: Vec<MaintenanceWindow>) -> MaintenanceConfig {
+        MaintenanceConfig { windows }
+    }
+
+    /// A Monday.
+    fn at(time: &str) -> DateTime<Utc> {
+        format!("2022-01-03T{time}Z").parse().unwrap()
+    }
+
+    fn hours(hours: u64) -> Option<Duration> {
+        Some(Duration::from_secs(hours * 60 * 60))
+    }
+
+    #[test]
+    fn test_no_windows() {
+        assert_eq!(until_maintenance(&config(vec![]), at("12:00:00")), None);
+    }
+
+    #[test]
+    fn test_window() {
+        let config = config(vec![window(vec![], 2, 4)]);
+
+        assert_eq!(until_maintenance(&config, at("02:00:00")), None);
+        assert_eq!(until_maintenance(&config, at("03:59:59")), None);
+        assert_eq!(until_maintenance(&config, at("04:00:00")), hours(22));
+        assert_eq!(until_maintenance(&config, at("01:00:00")), hours(1));
+    }
+
+    #[test]
+    fn test_window_spanning_midnight() {
+        let config = config(vec![window(vec![Weekday::Sun], 22, 2)]);
+
+        // started on Sunday
+        assert_eq!(until_maintenance(&config, at("01:00:00")), None);
+        // next Sunday
+        assert_eq!(
+            until_maintenance(&config, at("02:00:00")),
+            hours(6 * 24 + 20)
+        );
+    }
+
+    #[test]
+    fn test_window_days() {
+        let config = config(vec![
+            window(vec![Weekday::Wed], 2, 4),
+            window(vec![Weekday::Tue], 20, 21),
+        ]);
+
+        assert_eq!(until_maintenance(&config, at("02:00:00")), hours(24 + 18));
+    }
+
+    fn topic() -> DynamicObject {
+        serde_json::from_value(json!({
+            "apiVersion": "kafka.strimzi.io/v1beta2",
+            "kind": "KafkaTopic",
+            "metadata": { "name": "events-app1" },
+            "spec": {
+                "config": { "retention.ms": 1000 },
+                "partitions": 3,
+                "replicas": 1,
+                "topicName": "events-app1",
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_defer_changes() {
+        let mut declared = json!({
+            "config": { "retention.ms": 2000 },
+            "partitions": 5,
+            "replicas": 1,
+            "topicName": "events-app1",
+        });
+
+        assert_eq!(
+            defer_changes(&topic(), &mut declared),
+            vec!["config".to_string(), "partitions".to_string()]
+        );
+        assert_eq!(declared, topic().data["spec"]);
+    }
+
+    #[test]
+    fn test_defer_nothing() {
+        let mut declared = topic().data["spec"].clone();
+        assert!(defer_changes(&topic(), &mut declared).is_empty());
+
+        // new topics are always created
+        let mut new = topic();
+        new.data = json!({});
+        let mut declared = json!({ "config": {}, "partitions": 5 });
+        assert!(defer_changes(&new, &mut declared).is_empty());
+        assert_eq!(declared, json!({ "config": {}, "partitions": 5 }));
+    }
+
+    #[test]
+    fn test_reschedule() {
+        let mut app = registry::v1::Application::default();
+        let complete = |app: &registry::v1::Application| ProcessOutcome::Complete(app.clone());
+
+        // nothing deferred
+        assert!(matches!(
+            reschedule_deferred(complete(&app), hours(1)),
+            ProcessOutcome::Complete(_)
+        ));
+
+        app.update_section(|mut status: KafkaAppStatus| {
+            status.deferred_changes = vec!["partitions".into()];
+            status
+        })
+        .unwrap();
+
+        assert!(matches!(
+            reschedule_deferred(complete(&app), hours(1)),
+            ProcessOutcome::Retry(_, Some(delay)) if Some(delay) == hours(1)
+        ));
+        assert!(matches!(
+            reschedule_deferred(ProcessOutcome::Retry(app.clone(), hours(3)), hours(1)),
+            ProcessOutcome::Retry(_, Some(delay)) if Some(delay) == hours(1)
+        ));
+        // inside the window
+        assert!(matches!(
+            reschedule_deferred(complete(&app), None),
+            ProcessOutcome::Complete(_)
+        ));
+    }
+}
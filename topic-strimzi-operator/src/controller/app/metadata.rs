@@ -0,0 +1,252 @@
+use crate::{
+    controller::TopicMetadataConfig,
+    data::{KafkaAppStatus, PartitionOffsets, TopicMetadata},
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use drogue_client::{registry, Translator};
+use drogue_cloud_operator_common::controller::reconciler::ReconcileError;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use std::{sync::Arc, time::Duration};
+
+/// A source of topic metadata.
+#[async_trait]
+pub trait TopicMetadataSource: Send + Sync {
+    /// Fetch the offsets of all partitions of a topic.
+    async fn fetch(&self, topic: &str) -> anyhow::Result<Vec<PartitionOffsets>>;
+}
+
+/// Fetch topic metadata from the Kafka cluster.
+pub struct KafkaMetadataSource {
+    consumer: Arc<BaseConsumer>,
+    timeout: Duration,
+}
+
+impl KafkaMetadataSource {
+    pub fn new(config: rdkafka::ClientConfig, timeout: Duration) -> anyhow::Result<Self> {
+        Ok(Self {
+            consumer: Arc::new(config.create()?),
+            timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl TopicMetadataSource for KafkaMetadataSource {
+    async fn fetch(&self, topic: &str) -> anyhow::Result<Vec<PartitionOffsets>> {
+        let consumer = self.consumer.clone();
+        let timeout = self.timeout;
+        let topic = topic.to_string();
+
+        // the client calls are blocking
+        tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<PartitionOffsets>> {
+            let metadata = consumer.fetch_metadata(Some(&topic), timeout)?;
+            let partitions = metadata
+                .topics()
+                .iter()
+                .find(|t| t.name() == topic)
+                .map(|t| t.partitions())
+                .unwrap_or_default();
+
+            partitions
+                .iter()
+                .map(|partition| {
+                    let (low, high) = consumer.fetch_watermarks(&topic, partition.id(), timeout)?;
+                    Ok(PartitionOffsets {
+                        partition: partition.id(),
+                        low,
+                        high,
+                    })
+                })
+                .collect()
+        })
+        .await
+        .context("Failed to join metadata task")?
+    }
+}
+
+/// Discover the number of brokers of the Kafka cluster.
+pub async fn discover_broker_count(
+    config: rdkafka::ClientConfig,
+    timeout: Duration,
+) -> anyhow::Result<u32> {
+    let consumer: BaseConsumer = config.create()?;
+
+    // the client calls are blocking
+    tokio::task::spawn_blocking(move || -> anyhow::Result<u32> {
+        let metadata = consumer.fetch_metadata(None, timeout)?;
+        let brokers = metadata.brokers().len() as u32;
+        anyhow::ensure!(brokers > 0, "Cluster metadata contains no brokers");
+        Ok(brokers)
+    })
+    .await
+    .context("Failed to join metadata task")?
+}
+
+/// Update the topic metadata in the status of the application.
+///
+/// This is a best-effort operation, failing to fetch the metadata will keep the previous state.
+/// The metadata is only fetched if the last update is older than the configured interval.
+pub async fn update_topic_metadata(
+    config: &TopicMetadataConfig,
+    source: &dyn TopicMetadataSource,
+    app: &mut registry::v1::Application,
+    topic: &str,
+    now: DateTime<Utc>,
+) -> Result<(), ReconcileError> {
+    let last_update = app
+        .section::<KafkaAppStatus>()
+        .and_then(|s| s.ok())
+        .and_then(|s| s.topic_metadata)
+        .map(|metadata| metadata.last_update);
+
+    if let Some(last_update) = last_update {
+        if (now - last_update).to_std().unwrap_or_default() < config.interval {
+            return Ok(());
+        }
+    }
+
+    let mut offsets = match source.fetch(topic).await {
+        Ok(offsets) => offsets,
+        Err(err) => {
+            log::info!("Failed to fetch metadata of topic '{}': {}", topic, err);
+            return Ok(());
+        }
+    };
+
+    offsets.sort_unstable_by_key(|offsets| offsets.partition);
+
+    let metadata = TopicMetadata {
+        last_update: now,
+        partitions: offsets.len() as u32,
+        records: offsets.iter().map(PartitionOffsets::records).sum(),
+        truncated: offsets.len() > config.max_partitions,
+        offsets: offsets.into_iter().take(config.max_partitions).collect(),
+    };
+
+    app.update_section(|mut status: KafkaAppStatus| {
+        status.topic_metadata = Some(metadata);
+        status
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct MockSource {
+        fetches: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TopicMetadataSource for MockSource {
+        async fn fetch(&self, topic: &str) -> anyhow::Result<Vec<PartitionOffsets>> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(topic, "events-app1");
+            Ok(vec![
+                PartitionOffsets {
+                    partition: 1,
+                    low: 10,
+                    high: 15,
+                },
+                PartitionOffsets {
+                    partition: 0,
+                    low: 0,
+                    high: 100,
+                },
+                PartitionOffsets {
+                    partition: 2,
+                    low: 5,
+                    high: 5,
+                },
+            ])
+        }
+    }
+
+    fn metadata(app: &registry::v1::Application) -> Option<TopicMetadata> {
+        app.section::<KafkaAppStatus>()
+            .and_then(|s| s.ok())
+            .and_then(|s| s.topic_metadata)
+    }
+
+    #[tokio::test]
+    async fn test_metadata_in_status() {
+        let config = TopicMetadataConfig {
+            enabled: true,
+            max_partitions: 2,
+            ..Default::default()
+        };
+        let source = MockSource::default();
+        let mut app = registry::v1::Application::default();
+        let now = Utc::now();
+
+        update_topic_metadata(&config, &source, &mut app, "events-app1", now)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            metadata(&app),
+            Some(TopicMetadata {
+                last_update: now,
+                partitions: 3,
+                records: 105,
+                offsets: vec![
+                    PartitionOffsets {
+                        partition: 0,
+                        low: 0,
+                        high: 100,
+                    },
+                    PartitionOffsets {
+                        partition: 1,
+                        low: 10,
+                        high: 15,
+                    },
+                ],
+                truncated: true,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metadata_interval() {
+        let config = TopicMetadataConfig {
+            enabled: true,
+            interval: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let source = MockSource::default();
+        let mut app = registry::v1::Application::default();
+        let now = Utc::now();
+
+        update_topic_metadata(&config, &source, &mut app, "events-app1", now)
+            .await
+            .unwrap();
+
+        // within the interval, don't poll again
+
+        update_topic_metadata(
+            &config,
+            &source,
+            &mut app,
+            "events-app1",
+            now + chrono::Duration::seconds(30),
+        )
+        .await
+        .unwrap();
+        assert_eq!(source.fetches.load(Ordering::SeqCst), 1);
+
+        // after the interval, poll again
+
+        let later = now + chrono::Duration::seconds(90);
+        update_topic_metadata(&config, &source, &mut app, "events-app1", later)
+            .await
+            .unwrap();
+        assert_eq!(source.fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(metadata(&app).unwrap().last_update, later);
+    }
+}
@@ -0,0 +1,120 @@
+use drogue_cloud_operator_common::controller::{base::ProcessOutcome, reconciler::ReconcileError};
+use lazy_static::lazy_static;
+use prometheus::{register_histogram_vec, register_int_counter_vec, HistogramVec, IntCounterVec};
+use std::time::Instant;
+
+lazy_static! {
+    pub static ref RECONCILES: IntCounterVec = register_int_counter_vec!(
+        "drogue_topic_reconciles",
+        "Reconciliations of applications, by operation and outcome",
+        &["operation", "outcome"]
+    )
+    .unwrap();
+    pub static ref RECONCILE_DURATION: HistogramVec = register_histogram_vec!(
+        "drogue_topic_reconcile_duration_seconds",
+        "Duration of reconciling an application",
+        &["operation"],
+        vec![0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]
+    )
+    .unwrap();
+    // the number of applications is bounded, and topics only change rarely
+    pub static ref TOPIC_OPERATIONS: IntCounterVec = register_int_counter_vec!(
+        "drogue_topic_operations",
+        "Topics created or deleted, by application",
+        &["action", "application"]
+    )
+    .unwrap();
+}
+
+/// An operation of the reconciler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconcileOperation {
+    Construct,
+    Deconstruct,
+}
+
+impl ReconcileOperation {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Construct => "construct",
+            Self::Deconstruct => "deconstruct",
+        }
+    }
+}
+
+/// The outcome of a reconciliation, as metrics label.
+pub fn outcome_label<T>(result: &Result<ProcessOutcome<T>, ReconcileError>) -> &'static str {
+    match result {
+        Ok(ProcessOutcome::Complete(_)) => "Complete",
+        Ok(ProcessOutcome::Retry(..)) => "Retry",
+        Err(ReconcileError::Permanent(_)) => "Permanent",
+        Err(ReconcileError::Temporary(_)) => "Temporary",
+    }
+}
+
+/// Record a reconciliation, which started at `started`.
+pub fn observe_reconcile<T>(
+    operation: ReconcileOperation,
+    started: Instant,
+    result: &Result<ProcessOutcome<T>, ReconcileError>,
+) {
+    RECONCILES
+        .with_label_values(&[operation.label(), outcome_label(result)])
+        .inc();
+    RECONCILE_DURATION
+        .with_label_values(&[operation.label()])
+        .observe(started.elapsed().as_secs_f64());
+}
+
+/// Record the creation or deletion of a topic.
+pub fn observe_topic_operation(action: &str, application: &str) {
+    TOPIC_OPERATIONS
+        .with_label_values(&[action, application])
+        .inc();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn count(operation: &str, outcome: &str) -> u64 {
+        RECONCILES.with_label_values(&[operation, outcome]).get()
+    }
+
+    #[test]
+    fn test_outcome() {
+        assert_eq!(outcome_label(&Ok(ProcessOutcome::Complete(()))), "Complete");
+        assert_eq!(outcome_label(&Ok(ProcessOutcome::Retry((), None))), "Retry");
+        assert_eq!(
+            outcome_label::<()>(&Err(ReconcileError::permanent("Invalid"))),
+            "Permanent"
+        );
+        assert_eq!(
+            outcome_label::<()>(&Err(ReconcileError::temporary("Timeout"))),
+            "Temporary"
+        );
+    }
+
+    #[test]
+    fn test_observe() {
+        let before = count("deconstruct", "Temporary");
+        let samples = RECONCILE_DURATION
+            .with_label_values(&["deconstruct"])
+            .get_sample_count();
+
+        observe_reconcile::<()>(
+            ReconcileOperation::Deconstruct,
+            Instant::now(),
+            &Err(ReconcileError::temporary("Timeout")),
+        );
+
+        // other tests may reconcile concurrently
+        assert!(count("deconstruct", "Temporary") > before);
+        assert!(
+            RECONCILE_DURATION
+                .with_label_values(&["deconstruct"])
+                .get_sample_count()
+                > samples
+        );
+    }
+}
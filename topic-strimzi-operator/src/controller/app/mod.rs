@@ -1,48 +1,105 @@
+mod audit;
+mod cluster;
+mod events;
+mod index;
+mod latency;
+mod maintenance;
+mod metadata;
+mod metrics;
+mod namespace;
+mod placement;
+mod provision;
+mod status;
 mod topic;
 mod user;
 
+pub use audit::{create_audit_sink, AuditSink};
+use audit::{AuditAction, AuditRecord};
+use cluster::check_cluster;
+pub use cluster::{ClusterStateSource, KafkaClusterSource};
+use events::{emit_event, reconcile_failure, TopicEvent};
+pub use events::{KubeEventSink, TopicEventSink};
+use index::ClaimTopic;
+pub use index::TopicIndex;
+use latency::*;
+use maintenance::*;
+pub use metadata::{discover_broker_count, KafkaMetadataSource, TopicMetadataSource};
+use metrics::*;
+use provision::adopt;
+pub use provision::PreProvisioner;
+pub use status::{KubeStatusResourceSink, StatusResourceSink};
 use topic::*;
 use user::*;
 
-use crate::controller::ControllerConfig;
+use crate::{
+    controller::{ControllerConfig, DeletionConfig, TenantFilterConfig},
+    data::KafkaAppStatus,
+};
 use async_trait::async_trait;
+use chrono::Utc;
 use drogue_client::{
-    core::v1::Conditions,
+    core::v1::{ConditionStatus, Conditions},
     meta::v1::CommonMetadataMut,
-    registry::{self, v1::KafkaAppStatus},
-    Translator,
+    registry, Translator,
 };
 use drogue_cloud_operator_common::controller::{
     base::{ConditionExt, ControllerOperation, ProcessOutcome, ReadyState, CONDITION_RECONCILED},
     reconciler::{
         operation::HasFinalizer,
-        progress::{self, OperationOutcome, Progressor, ResourceAccessor, RunConstructor},
+        progress::{
+            self, OperationOutcome, ProgressOperation, Progressor, ResourceAccessor, RunConstructor,
+        },
         ReconcileError, ReconcileProcessor, ReconcileState, Reconciler,
     },
 };
-use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
+use drogue_cloud_service_api::kafka::ResourceType;
+use drogue_cloud_service_common::client::SecondaryRegistry;
 use k8s_openapi::api::core::v1::Secret;
 use kube::{
     api::{ApiResource, DynamicObject},
     Api,
 };
+use metadata::update_topic_metadata;
+use namespace::check_namespace;
+pub use namespace::{KubeNamespaceSource, NamespaceStateSource};
 use operator_framework::install::Delete;
-use std::{ops::Deref, time::Duration};
+use placement::{select_cluster, TopicClusters};
+use std::{
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 const FINALIZER: &str = "kafka";
 const LABEL_KAFKA_CLUSTER: &str = "strimzi.io/cluster";
 const LABEL_MARKER: &str = "drogue.io/auto-created";
 pub const ANNOTATION_APP_NAME: &str = "drogue.io/application-name";
+/// Annotation on the application, preventing the deletion of its Kafka resources.
+pub const ANNOTATION_DELETE_PROTECTION: &str = "drogue.io/delete-protection";
+const CONDITION_DELETE_PROTECTED: &str = "DeleteProtected";
+/// Annotation on the application, pausing its reconciliation, except for its deletion.
+pub const ANNOTATION_RECONCILE_PAUSED: &str = "drogue.io/reconcile-paused";
+const CONDITION_PAUSED: &str = "Paused";
+const CONDITION_KAFKA_RESOURCES_DELETED: &str = "KafkaResourcesDeleted";
+/// Delay until re-checking an application which is protected from deletion.
+const DELETE_PROTECTION_RECHECK: Duration = Duration::from_secs(60);
 
 pub struct ApplicationController {
     config: ControllerConfig,
     registry: registry::v1::Client,
 
     kafka_topic_resource: ApiResource,
-    kafka_topics: Api<DynamicObject>,
-    kafka_user_resource: ApiResource,
-    kafka_users: Api<DynamicObject>,
+    kafka_topics: TopicClusters,
+    kafka_users: Option<KafkaUsers>,
     secrets: Api<Secret>,
+    metadata: Option<Arc<dyn TopicMetadataSource>>,
+    topic_index: Option<TopicIndex>,
+    cluster: Option<Arc<dyn ClusterStateSource>>,
+    namespace: Option<Arc<dyn NamespaceStateSource>>,
+    secondary: Option<Arc<SecondaryRegistry>>,
+    status_resource: Option<Arc<dyn StatusResourceSink>>,
+    audit: Option<Arc<dyn AuditSink>>,
+    events: Option<Arc<dyn TopicEventSink>>,
 }
 
 impl ApplicationController {
@@ -51,20 +108,95 @@ impl ApplicationController {
         registry: registry::v1::Client,
         kafka_topic_resource: ApiResource,
         kafka_topics: Api<DynamicObject>,
-        kafka_user_resource: ApiResource,
-        kafka_users: Api<DynamicObject>,
         secrets: Api<Secret>,
     ) -> Self {
         Self {
             config,
             registry,
             kafka_topic_resource,
-            kafka_topics,
-            kafka_user_resource,
-            kafka_users,
+            kafka_topics: TopicClusters {
+                default: kafka_topics,
+                additional: Default::default(),
+            },
+            kafka_users: None,
             secrets,
+            metadata: None,
+            topic_index: None,
+            cluster: None,
+            namespace: None,
+            secondary: None,
+            status_resource: None,
+            audit: None,
+            events: None,
         }
     }
+
+    /// Set the source for polling the topic metadata.
+    pub fn with_metadata_source(mut self, metadata: Arc<dyn TopicMetadataSource>) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Set the index for validating the ownership of topics.
+    pub fn with_topic_index(mut self, topic_index: TopicIndex) -> Self {
+        self.topic_index = Some(topic_index);
+        self
+    }
+
+    /// Set the source for checking the availability of the Kafka cluster.
+    pub fn with_cluster_source(mut self, cluster: Arc<dyn ClusterStateSource>) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Set the source for checking if the topic namespace is terminating.
+    pub fn with_namespace_source(mut self, namespace: Arc<dyn NamespaceStateSource>) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    /// Set the secondary registry, for resolving the Kafka spec of applications.
+    pub fn with_secondary_registry(mut self, secondary: Arc<SecondaryRegistry>) -> Self {
+        self.secondary = Some(secondary);
+        self
+    }
+
+    /// Set the sink for the status resources of topics.
+    pub fn with_status_resource(mut self, status_resource: Arc<dyn StatusResourceSink>) -> Self {
+        self.status_resource = Some(status_resource);
+        self
+    }
+
+    /// Set the sink for the events of topics.
+    pub fn with_event_sink(mut self, events: Arc<dyn TopicEventSink>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Add an additional Kafka cluster, selectable by applications.
+    pub fn with_cluster(mut self, name: String, kafka_topics: Api<DynamicObject>) -> Self {
+        self.kafka_topics.additional.insert(name, kafka_topics);
+        self
+    }
+
+    /// Provision a Kafka user for each application, using the Strimzi `KafkaUser` resources.
+    pub fn with_kafka_users(
+        mut self,
+        kafka_user_resource: ApiResource,
+        kafka_users: Api<DynamicObject>,
+    ) -> Self {
+        self.kafka_users = Some(KafkaUsers {
+            resource: kafka_user_resource,
+            api: kafka_users,
+        });
+        self
+    }
+
+    /// Set the sink for the audit records of topic changes.
+    pub fn with_audit_sink(mut self, audit: Arc<dyn AuditSink>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
 }
 
 #[async_trait]
@@ -80,9 +212,16 @@ impl ControllerOperation<String, registry::v1::Application, registry::v1::Applic
             registry: &self.registry,
             kafka_topic_resource: &self.kafka_topic_resource,
             kafka_topics: &self.kafka_topics,
-            kafka_user_resource: &self.kafka_user_resource,
-            kafka_users: &self.kafka_users,
+            kafka_users: self.kafka_users.as_ref(),
             secrets: &self.secrets,
+            metadata: self.metadata.as_deref(),
+            topic_index: self.topic_index.as_ref(),
+            cluster: self.cluster.as_deref(),
+            namespace: self.namespace.as_deref(),
+            secondary: self.secondary.as_deref(),
+            status_resource: self.status_resource.as_deref(),
+            audit: self.audit.as_deref(),
+            events: self.events.as_deref(),
         })
         .reconcile(application)
         .await
@@ -95,7 +234,7 @@ impl ControllerOperation<String, registry::v1::Application, registry::v1::Applic
     ) -> Result<registry::v1::Application, ()> {
         let mut conditions = app
             .section::<KafkaAppStatus>()
-            .and_then(|s| s.ok().map(|s| s.conditions))
+            .and_then(|s| s.ok().map(|s| s.status.conditions))
             .unwrap_or_default();
 
         conditions.update(CONDITION_RECONCILED, ReadyState::Failed(message.into()));
@@ -119,6 +258,15 @@ pub struct ConstructContext {
     pub app: registry::v1::Application,
     pub events_topic: Option<DynamicObject>,
     pub events_topic_name: Option<String>,
+    pub events_topic_partitions: Option<Partitions>,
+    /// Fields of the topic spec, which differ from the declared spec.
+    pub events_topic_drift: Vec<String>,
+    /// Fields of the topic spec, whose change is deferred until the next maintenance window.
+    pub events_topic_deferred: Vec<String>,
+    /// Keys of the topic config, which the cluster reported as ignored.
+    pub events_topic_ignored_config: Vec<String>,
+    /// The commands topic, if the application enabled commands.
+    pub commands_topic: Option<DynamicObject>,
     pub app_user: Option<DynamicObject>,
     pub app_user_name: Option<String>,
 }
@@ -132,10 +280,23 @@ pub struct ApplicationReconciler<'a> {
     pub config: &'a ControllerConfig,
     pub registry: &'a registry::v1::Client,
     pub kafka_topic_resource: &'a ApiResource,
-    pub kafka_topics: &'a Api<DynamicObject>,
-    pub kafka_user_resource: &'a ApiResource,
-    pub kafka_users: &'a Api<DynamicObject>,
+    pub kafka_topics: &'a TopicClusters,
+    pub kafka_users: Option<&'a KafkaUsers>,
     pub secrets: &'a Api<Secret>,
+    pub metadata: Option<&'a dyn TopicMetadataSource>,
+    pub topic_index: Option<&'a TopicIndex>,
+    pub cluster: Option<&'a dyn ClusterStateSource>,
+    pub namespace: Option<&'a dyn NamespaceStateSource>,
+    pub secondary: Option<&'a SecondaryRegistry>,
+    pub status_resource: Option<&'a dyn StatusResourceSink>,
+    pub audit: Option<&'a dyn AuditSink>,
+    pub events: Option<&'a dyn TopicEventSink>,
+}
+
+/// Check if the tenant of the application, taken from its label, is managed by this operator.
+fn is_managed(filter: &TenantFilterConfig, app: &registry::v1::Application) -> bool {
+    let tenant = app.metadata.labels.get(&filter.label).map(String::as_str);
+    filter.is_managed(tenant)
 }
 
 #[async_trait]
@@ -147,9 +308,22 @@ impl<'a> Reconciler for ApplicationReconciler<'a> {
 
     async fn eval_state(
         &self,
-        app: Self::Input,
+        mut app: Self::Input,
     ) -> Result<ReconcileState<Self::Output, Self::Construct, Self::Deconstruct>, ReconcileError>
     {
+        // applications of other tenants are not ours, not even to clean up
+        if !is_managed(&self.config.tenant_filter, &app) {
+            log::debug!(
+                "Ignoring application '{}' of another tenant",
+                app.metadata.name
+            );
+            return Ok(ReconcileState::Ignore(app));
+        }
+
+        if check_paused(&mut app)? {
+            return Ok(ReconcileState::Ignore(app));
+        }
+
         Self::eval_by_finalizer(
             true,
             app,
@@ -158,6 +332,11 @@ impl<'a> Reconciler for ApplicationReconciler<'a> {
                 app,
                 events_topic: None,
                 events_topic_name: None,
+                events_topic_partitions: None,
+                events_topic_drift: vec![],
+                events_topic_deferred: vec![],
+                events_topic_ignored_config: vec![],
+                commands_topic: None,
                 app_user: None,
                 app_user_name: None,
             },
@@ -173,55 +352,288 @@ impl<'a> Reconciler for ApplicationReconciler<'a> {
         &self,
         ctx: Self::Construct,
     ) -> Result<ProcessOutcome<Self::Output>, ReconcileError> {
-        Progressor::<Self::Construct>::new(vec![
-            Box::new(HasFinalizer(FINALIZER)),
-            Box::new(CreateTopic {
-                api: self.kafka_topics,
-                resource: self.kafka_topic_resource,
-                config: self.config,
-            }),
-            Box::new(TopicReady {
-                config: self.config,
-            }),
-            Box::new(CreateUser {
-                users_api: self.kafka_users,
-                users_resource: self.kafka_user_resource,
+        let started = Instant::now();
+        let result = self.construct_app(ctx).await;
+        observe_reconcile(ReconcileOperation::Construct, started, &result);
+        result
+    }
+
+    async fn deconstruct(
+        &self,
+        ctx: Self::Deconstruct,
+    ) -> Result<ProcessOutcome<Self::Output>, ReconcileError> {
+        let started = Instant::now();
+        let result = self.deconstruct_app(ctx).await;
+        observe_reconcile(ReconcileOperation::Deconstruct, started, &result);
+        result
+    }
+}
+
+impl ResourceAccessor for ConstructContext {
+    type Resource = registry::v1::Application;
+
+    fn resource(&self) -> &registry::v1::Application {
+        &self.app
+    }
+
+    fn resource_mut(&mut self) -> &mut registry::v1::Application {
+        &mut self.app
+    }
+
+    fn into(self) -> registry::v1::Application {
+        self.app
+    }
+
+    fn conditions(&self) -> Conditions {
+        self.app
+            .section::<KafkaAppStatus>()
+            .and_then(|s| s.ok())
+            .unwrap_or_default()
+            .status
+            .conditions
+    }
+}
+
+/// Check if the application is protected from deletion.
+fn is_delete_protected(app: &registry::v1::Application) -> bool {
+    app.metadata
+        .annotations
+        .get(ANNOTATION_DELETE_PROTECTION)
+        .map(|value| value == "true")
+        .unwrap_or_default()
+}
+
+/// Check if the reconciliation of the application is paused.
+fn is_paused(app: &registry::v1::Application) -> bool {
+    app.metadata
+        .annotations
+        .get(ANNOTATION_RECONCILE_PAUSED)
+        .map(|value| value == "true")
+        .unwrap_or_default()
+}
+
+/// Check if the reconciliation of the application is paused.
+///
+/// If the application is paused, this sets a condition, explaining why it isn't reconciled, and
+/// returns `true`. Otherwise, a previously set condition gets removed. Deleting an application
+/// is never paused, so that the finalizer can be removed.
+fn check_paused(app: &mut registry::v1::Application) -> Result<bool, ReconcileError> {
+    let paused = is_paused(app) && app.metadata.deletion_timestamp.is_none();
+
+    if paused {
+        log::info!(
+            "Reconciliation of application '{}' is paused",
+            app.metadata.name
+        );
+
+        app.update_section(|mut status: KafkaAppStatus| {
+            status.status.conditions.update(
+                CONDITION_PAUSED,
+                ConditionStatus {
+                    status: Some(true),
+                    reason: Some("ReconcilePaused".into()),
+                    message: Some(format!(
+                        "Reconciliation is paused, remove the annotation '{ANNOTATION_RECONCILE_PAUSED}' to resume"
+                    )),
+                },
+            );
+            status
+        })?;
+    } else if let Some(Ok(mut status)) = app.section::<KafkaAppStatus>() {
+        let conditions = &mut status.status.conditions.0;
+        if conditions.iter().any(|c| c.r#type == CONDITION_PAUSED) {
+            log::info!(
+                "Reconciliation of application '{}' is resumed",
+                app.metadata.name
+            );
+            conditions.retain(|c| c.r#type != CONDITION_PAUSED);
+            app.set_section(status)?;
+        }
+    }
+
+    Ok(paused)
+}
+
+impl ApplicationReconciler<'_> {
+    /// Create the Kafka resources of an application.
+    async fn construct_app(
+        &self,
+        mut ctx: ConstructContext,
+    ) -> Result<ProcessOutcome<registry::v1::Application>, ReconcileError> {
+        if self.config.provisioning_metrics.enabled {
+            ctx.app.update_section(|mut status: KafkaAppStatus| {
+                provisioning_started(&mut status, Utc::now());
+                status
+            })?;
+        }
+
+        // skip, if the namespace is going away
+
+        if let Some(namespace) = self.namespace {
+            if let Some(outcome) = check_namespace(namespace, &mut ctx.app).await? {
+                return Ok(outcome);
+            }
+        }
+
+        // wait for the Kafka cluster, instead of failing all steps
+
+        if let Some(cluster) = self.cluster {
+            if let Some(outcome) =
+                check_cluster(cluster, &mut ctx.app, self.config.cluster_check.retry_delay).await?
+            {
+                return Ok(outcome);
+            }
+        }
+
+        // disruptive changes must wait for the maintenance window
+
+        let deferral = until_maintenance(&self.config.maintenance, Utc::now());
+
+        let mut steps: Vec<Box<dyn ProgressOperation<ConstructContext> + '_>> =
+            vec![Box::new(HasFinalizer(FINALIZER))];
+        if let Some(index) = self.topic_index {
+            steps.push(Box::new(ClaimTopic {
+                index,
+                naming: &self.config.topic_naming,
+            }));
+        }
+        steps.push(Box::new(CreateTopic {
+            clusters: self.kafka_topics,
+            resource: self.kafka_topic_resource,
+            config: self.config,
+            secondary: self.secondary,
+            defer_disruptive: deferral.is_some(),
+            audit: self.audit,
+            events: self.events,
+        }));
+        steps.push(Box::new(CreateCommandsTopic {
+            clusters: self.kafka_topics,
+            resource: self.kafka_topic_resource,
+            config: self.config,
+        }));
+        steps.push(Box::new(TopicReady {
+            config: self.config,
+            status_resource: self.status_resource,
+            events: self.events,
+        }));
+        if let Some(users) = self.kafka_users {
+            steps.push(Box::new(CreateUser {
+                users,
                 secrets_api: self.secrets,
                 config: self.config,
-            }),
-            Box::new(UserReady {
+            }));
+            steps.push(Box::new(UserReady {
                 config: self.config,
                 secrets: self.secrets,
-            }),
-        ])
-        .run_with::<KafkaAppStatus>(ctx)
-        .await
+            }));
+        }
+
+        let app_name = ctx.app.metadata.name.clone();
+        let topic_namespace = select_cluster(self.config, &ctx.app)
+            .and_then(|cluster| self.kafka_topics.placement(self.config, cluster))
+            .map(|placement| placement.topic_namespace)
+            .unwrap_or(&self.config.topic_namespace);
+
+        let outcome = Progressor::<ConstructContext>::new(steps)
+            .run_with::<KafkaAppStatus>(ctx)
+            .await?;
+
+        // report failures, which are only recorded in the status otherwise
+
+        if let Some(events) = self.events {
+            if let Some(message) = reconcile_failure(outcome.deref()) {
+                emit_event(
+                    events,
+                    topic_namespace,
+                    &self
+                        .config
+                        .topic_naming
+                        .resource_name(ResourceType::Events(&app_name)),
+                    &app_name,
+                    TopicEvent::ReconcileFailed(message),
+                )
+                .await;
+            }
+        }
+
+        // poll the topic metadata, once everything is ready
+
+        let outcome = match (outcome, self.metadata) {
+            (ProcessOutcome::Complete(mut app), Some(metadata))
+                if self.config.topic_metadata.enabled =>
+            {
+                let topic_name = self
+                    .config
+                    .topic_naming
+                    .resource_name(ResourceType::Events(&app.metadata.name));
+                update_topic_metadata(
+                    &self.config.topic_metadata,
+                    metadata,
+                    &mut app,
+                    &topic_name,
+                    Utc::now(),
+                )
+                .await?;
+                // re-schedule, to keep the metadata up to date
+                ProcessOutcome::Retry(app, Some(self.config.topic_metadata.interval))
+            }
+            (outcome, _) => outcome,
+        };
+
+        // re-schedule, to apply the deferred changes once the maintenance window opens
+
+        Ok(reschedule_deferred(outcome, deferral))
     }
 
-    async fn deconstruct(
+    /// Delete the Kafka resources of an application, and remove the finalizer.
+    async fn deconstruct_app(
         &self,
-        mut ctx: Self::Deconstruct,
-    ) -> Result<ProcessOutcome<Self::Output>, ReconcileError> {
+        mut ctx: DeconstructContext,
+    ) -> Result<ProcessOutcome<registry::v1::Application>, ReconcileError> {
+        // check for protection, keeping the finalizer
+
+        if block_deletion(&mut ctx)? {
+            return Ok(ProcessOutcome::Retry(
+                ctx.app,
+                Some(DELETE_PROTECTION_RECHECK),
+            ));
+        }
+
+        if self.config.provisioning_metrics.enabled {
+            // only observed once, not again when retrying a failed deletion
+            if let Some(status) = ctx.status.as_ref().filter(|s| s.deletion_attempts == 0) {
+                provisioning_abandoned(status, Utc::now());
+            }
+        }
+
         // delete
 
-        let topic_name = make_kafka_resource_name(ResourceType::Events(&ctx.app.metadata.name));
+        let topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Events(&ctx.app.metadata.name));
 
-        let user_name = make_kafka_resource_name(ResourceType::Users(&ctx.app.metadata.name));
+        let result = self.delete_resources(&ctx).await;
 
-        let password_name =
-            make_kafka_resource_name(ResourceType::Passwords(&ctx.app.metadata.name));
+        if let Some(audit) = self.audit {
+            audit.record(&AuditRecord::new(
+                &ctx.app,
+                topic_name.clone(),
+                AuditAction::Delete,
+                vec![],
+                result.as_ref(),
+            ));
+        }
 
-        // remove topic
+        if let Err(err) = result {
+            if let Some(outcome) = deletion_failed(&self.config.deletion, &mut ctx, err)? {
+                return Ok(outcome);
+            }
+        }
 
-        self.kafka_topics
-            .delete_optionally(&topic_name, &Default::default())
-            .await?;
-        self.kafka_users
-            .delete_optionally(&user_name, &Default::default())
-            .await?;
-        self.secrets
-            .delete_optionally(&password_name, &Default::default())
-            .await?;
+        if let Some(index) = self.topic_index {
+            index.release(&topic_name, &ctx.app.metadata.name);
+        }
 
         // TODO: wait for resources to be actually deleted, then remove the finalizer
 
@@ -233,30 +645,179 @@ impl<'a> Reconciler for ApplicationReconciler<'a> {
 
         Ok(ProcessOutcome::Complete(ctx.app))
     }
-}
 
-impl ResourceAccessor for ConstructContext {
-    type Resource = registry::v1::Application;
+    /// Delete the Kafka resources of an application.
+    ///
+    /// The topic is deleted from the cluster it was placed on.
+    async fn delete_resources(&self, ctx: &DeconstructContext) -> Result<(), ReconcileError> {
+        let app = ctx.app.metadata.name.as_str();
+        let cluster = ctx.status.as_ref().and_then(|s| s.cluster.as_deref());
+        let placement = self.kafka_topics.placement(self.config, cluster)?;
 
-    fn resource(&self) -> &registry::v1::Application {
-        &self.app
-    }
+        let topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Events(app));
 
-    fn resource_mut(&mut self) -> &mut registry::v1::Application {
-        &mut self.app
+        let commands_topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::AppCommands(app));
+
+        let user_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Users(app));
+
+        let password_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Passwords(app));
+
+        // remove topic
+
+        match self.config.dry_run {
+            true => log::info!(
+                "Dry run, not deleting topics '{topic_name}' and '{commands_topic_name}' from namespace '{}'",
+                placement.topic_namespace
+            ),
+            false => {
+                placement
+                    .api
+                    .delete_optionally(&topic_name, &Default::default())
+                    .await?;
+                placement
+                    .api
+                    .delete_optionally(&commands_topic_name, &Default::default())
+                    .await?;
+                observe_topic_operation("Delete", app);
+            }
+        }
+        if let Some(events) = self.events {
+            emit_event(
+                events,
+                placement.topic_namespace,
+                &topic_name,
+                app,
+                TopicEvent::Deleted,
+            )
+            .await;
+        }
+        if let Some(users) = self.kafka_users {
+            users
+                .api
+                .delete_optionally(&user_name, &Default::default())
+                .await?;
+        }
+        self.secrets
+            .delete_optionally(&password_name, &Default::default())
+            .await?;
+
+        if let Some(status_resource) = self.status_resource {
+            status_resource.delete(&topic_name).await?;
+        }
+
+        Ok(())
     }
+}
 
-    fn into(self) -> registry::v1::Application {
-        self.app
+/// Handle a failed attempt to delete the Kafka resources.
+///
+/// Without a maximum number of attempts, the error is returned. Otherwise, the failed attempt
+/// is recorded and the deletion gets retried. Once the maximum number of attempts is reached, the
+/// finalizer is removed anyway, and this returns [`None`] to proceed with the deletion.
+fn deletion_failed(
+    config: &DeletionConfig,
+    ctx: &mut DeconstructContext,
+    err: ReconcileError,
+) -> Result<Option<ProcessOutcome<registry::v1::Application>>, ReconcileError> {
+    let max_attempts = match config.max_attempts {
+        Some(max_attempts) => max_attempts,
+        None => return Err(err),
+    };
+
+    let mut status = ctx.status.take().unwrap_or_default();
+    status.deletion_attempts += 1;
+    let attempts = status.deletion_attempts;
+
+    if attempts >= max_attempts {
+        log::warn!(
+            "Failed to delete Kafka resources of application '{}' after {} attempts, removing finalizer anyway. Kafka resources may be orphaned: {}",
+            ctx.app.metadata.name,
+            attempts,
+            err
+        );
+        status.status.conditions.update(
+            CONDITION_KAFKA_RESOURCES_DELETED,
+            ConditionStatus {
+                status: Some(false),
+                reason: Some("FinalizerRemoved".into()),
+                message: Some(format!(
+                    "Removed finalizer after {attempts} failed attempts, Kafka resources may be orphaned: {err}"
+                )),
+            },
+        );
+        ctx.app.set_section(status)?;
+        ctx.app.metadata.remove_finalizer(FINALIZER);
+        return Ok(None);
     }
 
-    fn conditions(&self) -> Conditions {
-        self.app
-            .section::<KafkaAppStatus>()
-            .and_then(|s| s.ok())
-            .unwrap_or_default()
-            .conditions
+    log::info!(
+        "Failed to delete Kafka resources of application '{}' (attempt {} of {}): {}",
+        ctx.app.metadata.name,
+        attempts,
+        max_attempts,
+        err
+    );
+    status.status.conditions.update(
+        CONDITION_KAFKA_RESOURCES_DELETED,
+        ConditionStatus {
+            status: Some(false),
+            reason: Some("DeletionFailed".into()),
+            message: Some(format!(
+                "Failed to delete Kafka resources (attempt {attempts} of {max_attempts}): {err}"
+            )),
+        },
+    );
+    ctx.app.set_section(status)?;
+
+    Ok(Some(ProcessOutcome::Retry(
+        ctx.app.clone(),
+        Some(config.retry_delay),
+    )))
+}
+
+/// Block the deletion of a protected application.
+///
+/// If the application is protected, this sets a condition, explaining why the deletion is blocked,
+/// and returns `true`. The deletion can only proceed once the annotation was removed.
+fn block_deletion(ctx: &mut DeconstructContext) -> Result<bool, ReconcileError> {
+    if !is_delete_protected(&ctx.app) {
+        return Ok(false);
     }
+
+    log::info!(
+        "Deletion of application '{}' is blocked by delete protection",
+        ctx.app.metadata.name
+    );
+
+    let mut conditions = ctx
+        .status
+        .take()
+        .map(|status| status.status.conditions)
+        .unwrap_or_default();
+
+    conditions.update(
+        CONDITION_DELETE_PROTECTED,
+        ReadyState::Failed(format!(
+            "Deletion is blocked, remove the annotation '{ANNOTATION_DELETE_PROTECTION}' to proceed"
+        )),
+    );
+
+    ctx.app
+        .finish_ready::<KafkaAppStatus>(conditions, ctx.app.metadata.generation)?;
+
+    Ok(true)
 }
 
 fn retry<C>(ctx: C) -> progress::Result<C>
@@ -287,3 +848,223 @@ fn condition_ready(condition: &str, resource: &DynamicObject) -> Option<bool> {
                 .next()
         })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_client::meta::v1::NonScopedMetadata;
+
+    fn context(annotations: &[(&str, &str)]) -> DeconstructContext {
+        let app = registry::v1::Application {
+            metadata: NonScopedMetadata {
+                name: "app1".into(),
+                finalizers: vec![FINALIZER.into()],
+                annotations: annotations
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let status = app.section::<KafkaAppStatus>().and_then(|s| s.ok());
+        DeconstructContext { app, status }
+    }
+
+    #[test]
+    fn test_delete_blocked() {
+        let mut ctx = context(&[(ANNOTATION_DELETE_PROTECTION, "true")]);
+
+        assert!(block_deletion(&mut ctx).unwrap());
+
+        // the finalizer must be kept
+
+        assert_eq!(ctx.app.metadata.finalizers, vec![FINALIZER.to_string()]);
+
+        // the reason must be reported
+
+        let status = ctx.app.section::<KafkaAppStatus>().unwrap().unwrap();
+        let condition = status
+            .status
+            .conditions
+            .0
+            .iter()
+            .find(|c| c.r#type == CONDITION_DELETE_PROTECTED)
+            .unwrap();
+        assert_eq!(condition.status, "False");
+        assert!(condition
+            .message
+            .as_deref()
+            .unwrap_or_default()
+            .contains(ANNOTATION_DELETE_PROTECTION));
+    }
+
+    fn paused_condition(app: &registry::v1::Application) -> Option<String> {
+        app.section::<KafkaAppStatus>()?
+            .ok()?
+            .status
+            .conditions
+            .0
+            .into_iter()
+            .find(|c| c.r#type == CONDITION_PAUSED)
+            .map(|c| c.status)
+    }
+
+    #[test]
+    fn test_paused() {
+        let mut app = context(&[(ANNOTATION_RECONCILE_PAUSED, "true")]).app;
+
+        assert!(check_paused(&mut app).unwrap());
+        assert_eq!(paused_condition(&app).as_deref(), Some("True"));
+        assert_eq!(app.metadata.finalizers, vec![FINALIZER.to_string()]);
+
+        // resuming removes the condition
+
+        app.metadata.annotations.remove(ANNOTATION_RECONCILE_PAUSED);
+        assert!(!check_paused(&mut app).unwrap());
+        assert_eq!(paused_condition(&app), None);
+    }
+
+    #[test]
+    fn test_paused_deleted() {
+        let mut app = context(&[(ANNOTATION_RECONCILE_PAUSED, "true")]).app;
+        app.metadata.deletion_timestamp = Some(Utc::now());
+
+        // deletion wins, and proceeds by finalizer
+
+        assert!(!check_paused(&mut app).unwrap());
+        assert_eq!(paused_condition(&app), None);
+        assert!(matches!(
+            ApplicationReconciler::eval_by_finalizer(
+                true,
+                app,
+                FINALIZER,
+                |_| unreachable!("must not construct"),
+                |app| DeconstructContext { app, status: None },
+                |_| unreachable!("must not ignore"),
+            ),
+            Ok(ReconcileState::Deconstruct(_))
+        ));
+    }
+
+    #[test]
+    fn test_delete_not_blocked() {
+        assert!(!block_deletion(&mut context(&[])).unwrap());
+        assert!(!block_deletion(&mut context(&[(ANNOTATION_DELETE_PROTECTION, "false")])).unwrap());
+    }
+
+    fn app(labels: &[(&str, &str)]) -> registry::v1::Application {
+        registry::v1::Application {
+            metadata: NonScopedMetadata {
+                name: "app1".into(),
+                labels: labels
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn filter(tenants: &[&str]) -> TenantFilterConfig {
+        TenantFilterConfig {
+            tenants: Some(tenants.iter().map(ToString::to_string).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tenant_matching() {
+        let filter = filter(&["tenant1", "tenant2"]);
+
+        assert!(is_managed(
+            &filter,
+            &app(&[("drogue.io/tenant", "tenant1")])
+        ));
+        assert!(is_managed(
+            &filter,
+            &app(&[("drogue.io/tenant", "tenant2")])
+        ));
+    }
+
+    #[test]
+    fn test_tenant_not_matching() {
+        let filter = filter(&["tenant1"]);
+
+        assert!(!is_managed(
+            &filter,
+            &app(&[("drogue.io/tenant", "tenant3")])
+        ));
+        // no tenant
+        assert!(!is_managed(&filter, &app(&[])));
+        // a different label
+        assert!(!is_managed(&filter, &app(&[("tenant", "tenant1")])));
+    }
+
+    #[test]
+    fn test_no_tenant_filter() {
+        let filter = TenantFilterConfig::default();
+
+        assert!(is_managed(&filter, &app(&[])));
+        assert!(is_managed(
+            &filter,
+            &app(&[("drogue.io/tenant", "tenant3")])
+        ));
+    }
+
+    #[test]
+    fn test_forced_finalizer_removal() {
+        let config = DeletionConfig {
+            max_attempts: Some(3),
+            ..Default::default()
+        };
+        let mut ctx = context(&[]);
+
+        for attempt in 1..3 {
+            let err = ReconcileError::permanent("Failed to delete topic");
+            let outcome = deletion_failed(&config, &mut ctx, err).unwrap();
+
+            // retried, keeping the finalizer
+            let app = match outcome {
+                Some(ProcessOutcome::Retry(app, Some(_))) => app,
+                outcome => panic!("Unexpected outcome: {outcome:?}"),
+            };
+            assert_eq!(app.metadata.finalizers, vec![FINALIZER.to_string()]);
+
+            // the next reconciliation starts with the stored application
+            let status = app.section::<KafkaAppStatus>().unwrap().unwrap();
+            assert_eq!(status.deletion_attempts, attempt);
+            ctx = DeconstructContext {
+                app,
+                status: Some(status),
+            };
+        }
+
+        let err = ReconcileError::permanent("Failed to delete topic");
+        assert!(deletion_failed(&config, &mut ctx, err).unwrap().is_none());
+        assert!(ctx.app.metadata.finalizers.is_empty());
+
+        let status = ctx.app.section::<KafkaAppStatus>().unwrap().unwrap();
+        let condition = status
+            .status
+            .conditions
+            .0
+            .iter()
+            .find(|c| c.r#type == CONDITION_KAFKA_RESOURCES_DELETED)
+            .unwrap();
+        assert_eq!(condition.reason.as_deref(), Some("FinalizerRemoved"));
+    }
+
+    #[test]
+    fn test_deletion_failed_unlimited() {
+        let mut ctx = context(&[]);
+        let err = ReconcileError::permanent("Failed to delete topic");
+
+        assert_eq!(
+            deletion_failed(&Default::default(), &mut ctx, err.clone()).unwrap_err(),
+            err
+        );
+        assert_eq!(ctx.app.metadata.finalizers, vec![FINALIZER.to_string()]);
+    }
+}
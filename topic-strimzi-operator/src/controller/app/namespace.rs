@@ -0,0 +1,185 @@
+use crate::data::KafkaAppStatus;
+use async_trait::async_trait;
+use drogue_client::{core::v1::ConditionStatus, registry, Translator};
+use drogue_cloud_operator_common::controller::{
+    base::{ConditionExt, ProcessOutcome},
+    reconciler::ReconcileError,
+};
+use k8s_openapi::api::core::v1::Namespace;
+use kube::Api;
+
+const CONDITION_NAMESPACE_ACTIVE: &str = "NamespaceActive";
+
+/// A source of the state of the namespace, in which the topics get created.
+#[async_trait]
+pub trait NamespaceStateSource: Send + Sync {
+    /// The name of the namespace.
+    fn name(&self) -> &str;
+
+    /// Check if the namespace is being deleted.
+    async fn is_terminating(&self) -> bool;
+}
+
+/// Evaluate the state from the `Namespace` resource.
+pub struct KubeNamespaceSource {
+    api: Api<Namespace>,
+    name: String,
+}
+
+impl KubeNamespaceSource {
+    pub fn new(api: Api<Namespace>, name: String) -> Self {
+        Self { api, name }
+    }
+}
+
+#[async_trait]
+impl NamespaceStateSource for KubeNamespaceSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn is_terminating(&self) -> bool {
+        match self.api.get_opt(&self.name).await {
+            Ok(Some(namespace)) => is_terminating(&namespace),
+            Ok(None) => false,
+            Err(err) => {
+                // we can't tell, so try to reconcile
+                log::info!("Failed to get namespace '{}': {}", self.name, err);
+                false
+            }
+        }
+    }
+}
+
+fn is_terminating(namespace: &Namespace) -> bool {
+    namespace.metadata.deletion_timestamp.is_some()
+        || namespace
+            .status
+            .as_ref()
+            .and_then(|status| status.phase.as_deref())
+            == Some("Terminating")
+}
+
+/// Check if the namespace of the topics is terminating.
+///
+/// The result is recorded as a condition of the application. If the namespace is terminating,
+/// creating resources would fail anyway, and the resources get deleted with the namespace. In this
+/// case, this returns the outcome of the reconciliation, completing it without a retry.
+pub async fn check_namespace(
+    source: &dyn NamespaceStateSource,
+    app: &mut registry::v1::Application,
+) -> Result<Option<ProcessOutcome<registry::v1::Application>>, ReconcileError> {
+    let terminating = source.is_terminating().await;
+
+    let mut conditions = app
+        .section::<KafkaAppStatus>()
+        .and_then(|s| s.ok())
+        .map(|s| s.status.conditions)
+        .unwrap_or_default();
+
+    match terminating {
+        false => {
+            conditions.update(
+                CONDITION_NAMESPACE_ACTIVE,
+                ConditionStatus {
+                    status: Some(true),
+                    ..Default::default()
+                },
+            );
+            app.set_status::<KafkaAppStatus>(conditions, app.metadata.generation)?;
+            Ok(None)
+        }
+        true => {
+            log::info!(
+                "Namespace '{}' is terminating, skipping application '{}'",
+                source.name(),
+                app.metadata.name
+            );
+            conditions.update(
+                CONDITION_NAMESPACE_ACTIVE,
+                ConditionStatus {
+                    status: Some(false),
+                    reason: Some("NamespaceTerminating".into()),
+                    message: Some(format!(
+                        "Namespace '{}' is terminating, skipping reconciliation",
+                        source.name()
+                    )),
+                },
+            );
+            app.finish_ready::<KafkaAppStatus>(conditions, app.metadata.generation)?;
+            Ok(Some(ProcessOutcome::Complete(app.clone())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+    use k8s_openapi::{api::core::v1::NamespaceStatus, apimachinery::pkg::apis::meta::v1::Time};
+
+    struct MockSource(bool);
+
+    #[async_trait]
+    impl NamespaceStateSource for MockSource {
+        fn name(&self) -> &str {
+            "kafka"
+        }
+
+        async fn is_terminating(&self) -> bool {
+            self.0
+        }
+    }
+
+    fn condition(app: &registry::v1::Application) -> (String, Option<String>) {
+        let status = app.section::<KafkaAppStatus>().unwrap().unwrap();
+        let condition = status
+            .status
+            .conditions
+            .0
+            .iter()
+            .find(|c| c.r#type == CONDITION_NAMESPACE_ACTIVE)
+            .unwrap();
+        (condition.status.clone(), condition.reason.clone())
+    }
+
+    #[test]
+    fn test_is_terminating() {
+        let mut namespace = Namespace::default();
+        assert!(!is_terminating(&namespace));
+
+        namespace.status = Some(NamespaceStatus {
+            phase: Some("Terminating".into()),
+            ..Default::default()
+        });
+        assert!(is_terminating(&namespace));
+
+        let mut namespace = Namespace::default();
+        namespace.metadata.deletion_timestamp = Some(Time(Utc::now()));
+        assert!(is_terminating(&namespace));
+    }
+
+    #[tokio::test]
+    async fn test_terminating_ignored() {
+        let mut app = registry::v1::Application::default();
+
+        let outcome = check_namespace(&MockSource(true), &mut app).await.unwrap();
+
+        // complete, not failed or retried
+        assert!(matches!(outcome, Some(ProcessOutcome::Complete(_))));
+        assert_eq!(
+            condition(&app),
+            ("False".into(), Some("NamespaceTerminating".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_active() {
+        let mut app = registry::v1::Application::default();
+
+        let outcome = check_namespace(&MockSource(false), &mut app).await.unwrap();
+
+        assert!(outcome.is_none());
+        assert_eq!(condition(&app).0, "True");
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{controller::ControllerConfig, data::KafkaAppStatus};
+use drogue_client::{registry, Translator};
+use drogue_cloud_operator_common::controller::reconciler::ReconcileError;
+use kube::{api::DynamicObject, Api};
+use std::collections::HashMap;
+
+/// Annotation on the application, selecting the Kafka cluster of its topic.
+pub const ANNOTATION_KAFKA_CLUSTER: &str = "drogue.io/kafka-cluster";
+
+/// The Kafka cluster, which a topic gets placed on.
+pub struct Placement<'a> {
+    /// The name of the additional cluster, [`None`] for the default one.
+    pub name: Option<&'a str>,
+    pub cluster_name: &'a str,
+    pub topic_namespace: &'a str,
+    pub api: &'a Api<DynamicObject>,
+}
+
+/// The APIs of the topics of all Kafka clusters.
+#[derive(Clone)]
+pub struct TopicClusters {
+    /// The default cluster.
+    pub default: Api<DynamicObject>,
+    /// The additional clusters, by name.
+    pub additional: HashMap<String, Api<DynamicObject>>,
+}
+
+impl TopicClusters {
+    /// Resolve a cluster, as selected by [`select_cluster`].
+    pub fn placement<'a>(
+        &'a self,
+        config: &'a ControllerConfig,
+        name: Option<&'a str>,
+    ) -> Result<Placement<'a>, ReconcileError> {
+        let name = match name {
+            Some(name) => name,
+            None => {
+                return Ok(Placement {
+                    name: None,
+                    cluster_name: &config.cluster_name,
+                    topic_namespace: &config.topic_namespace,
+                    api: &self.default,
+                })
+            }
+        };
+
+        match (config.clusters.get(name), self.additional.get(name)) {
+            (Some(cluster), Some(api)) => Ok(Placement {
+                name: Some(name),
+                cluster_name: &cluster.cluster_name,
+                topic_namespace: &cluster.topic_namespace,
+                api,
+            }),
+            _ => Err(ReconcileError::permanent(format!(
+                "Unknown Kafka cluster '{name}'"
+            ))),
+        }
+    }
+}
+
+/// Select the Kafka cluster of an application, by its annotation or the configured default.
+///
+/// Once the topic of the application was created, the application stays on the cluster recorded
+/// in its status, as changing the selection would orphan the existing topic.
+///
+/// Returns [`None`] for the default cluster.
+pub fn select_cluster<'a>(
+    config: &'a ControllerConfig,
+    app: &registry::v1::Application,
+) -> Result<Option<&'a str>, ReconcileError> {
+    let selected = select_new_cluster(config, app);
+
+    let status = match app.section::<KafkaAppStatus>().and_then(|s| s.ok()) {
+        Some(status) if status.topic_name.is_some() => status,
+        _ => return selected,
+    };
+
+    let recorded = match status.cluster.as_deref() {
+        Some(name) => match config.clusters.get_key_value(name) {
+            Some((name, _)) => Some(name.as_str()),
+            None => {
+                return Err(ReconcileError::permanent(format!(
+                    "Kafka cluster '{name}' of the existing topic is no longer configured"
+                )))
+            }
+        },
+        None => None,
+    };
+
+    if !matches!(&selected, Ok(selected) if *selected == recorded) {
+        log::info!(
+            "Application '{}' stays on its Kafka cluster ({}), ignoring a changed selection",
+            app.metadata.name,
+            recorded.unwrap_or("default")
+        );
+    }
+
+    Ok(recorded)
+}
+
+/// Select the Kafka cluster of an application, which has no topic yet.
+fn select_new_cluster<'a>(
+    config: &'a ControllerConfig,
+    app: &registry::v1::Application,
+) -> Result<Option<&'a str>, ReconcileError> {
+    let name = app
+        .metadata
+        .annotations
+        .get(ANNOTATION_KAFKA_CLUSTER)
+        .map(String::as_str)
+        .filter(|name| !name.is_empty())
+        .or(config.default_cluster.as_deref());
+
+    match name {
+        Some(name) => match config.clusters.get_key_value(name) {
+            Some((name, _)) => Ok(Some(name.as_str())),
+            None => Err(ReconcileError::permanent(format!(
+                "Unknown Kafka cluster '{name}'"
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::KafkaClusterConfig;
+    use serde_json::json;
+
+    fn config() -> ControllerConfig {
+        let mut config: ControllerConfig = serde_json::from_value(json!({
+            "topic_namespace": "kafka",
+            "cluster_name": "drogue",
+        }))
+        .unwrap();
+        for name in ["bronze", "gold"] {
+            config.clusters.insert(
+                name.into(),
+                KafkaClusterConfig {
+                    topic_namespace: format!("kafka-{name}"),
+                    cluster_name: name.into(),
+                },
+            );
+        }
+        config
+    }
+
+    fn app(cluster: Option<&str>) -> registry::v1::Application {
+        let mut app = registry::v1::Application::default();
+        if let Some(cluster) = cluster {
+            app.metadata
+                .annotations
+                .insert(ANNOTATION_KAFKA_CLUSTER.into(), cluster.into());
+        }
+        app
+    }
+
+    #[test]
+    fn test_select_default() {
+        let mut config = config();
+
+        assert_eq!(select_cluster(&config, &app(None)), Ok(None));
+        assert_eq!(select_cluster(&config, &app(Some(""))), Ok(None));
+
+        config.default_cluster = Some("bronze".into());
+        assert_eq!(select_cluster(&config, &app(None)), Ok(Some("bronze")));
+    }
+
+    #[test]
+    fn test_select_annotated() {
+        let mut config = config();
+        config.default_cluster = Some("bronze".into());
+
+        assert_eq!(
+            select_cluster(&config, &app(Some("gold"))),
+            Ok(Some("gold"))
+        );
+    }
+
+    #[test]
+    fn test_select_recorded() {
+        let mut config = config();
+        let provisioned = |cluster: Option<&str>, recorded: Option<&str>| {
+            let mut app = app(cluster);
+            app.update_section(|mut status: KafkaAppStatus| {
+                status.topic_name = Some("events-app1".into());
+                status.cluster = recorded.map(Into::into);
+                status
+            })
+            .unwrap();
+            app
+        };
+
+        // changed annotation
+        assert_eq!(
+            select_cluster(&config, &provisioned(Some("gold"), Some("bronze"))),
+            Ok(Some("bronze"))
+        );
+        assert_eq!(
+            select_cluster(&config, &provisioned(Some("gold"), None)),
+            Ok(None)
+        );
+
+        // changed default
+        config.default_cluster = Some("bronze".into());
+        assert_eq!(select_cluster(&config, &provisioned(None, None)), Ok(None));
+
+        // removed cluster
+        config.clusters.remove("gold");
+        assert!(matches!(
+            select_cluster(&config, &provisioned(None, Some("gold"))),
+            Err(ReconcileError::Permanent(_))
+        ));
+
+        // not yet provisioned
+        assert_eq!(select_cluster(&config, &app(None)), Ok(Some("bronze")));
+    }
+
+    #[test]
+    fn test_select_unknown() {
+        let config = config();
+
+        assert!(matches!(
+            select_cluster(&config, &app(Some("platinum"))),
+            Err(ReconcileError::Permanent(_))
+        ));
+    }
+}
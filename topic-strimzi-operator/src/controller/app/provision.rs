@@ -0,0 +1,266 @@
+use super::{topic::limit_partitions, ANNOTATION_APP_NAME, LABEL_KAFKA_CLUSTER, LABEL_MARKER};
+use crate::controller::ControllerConfig;
+use drogue_cloud_service_api::kafka::ResourceType;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::{ApiResource, DynamicObject, ListParams, PostParams},
+    Api, Resource,
+};
+use operator_framework::install::Delete;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Label of topics, which were created ahead of their application.
+pub const LABEL_PRE_PROVISIONED: &str = "drogue.io/pre-provisioned";
+/// Key of the config map, listing the anticipated applications.
+const CONFIG_MAP_KEY: &str = "applications";
+
+/// Adopt a topic for its application.
+///
+/// If the topic was pre-provisioned, the marker is removed and the spec is reset, so that the
+/// declared spec of the application gets applied without being considered drift. Returns `true`
+/// if the topic was pre-provisioned.
+pub fn adopt(topic: &mut DynamicObject) -> bool {
+    let pre_provisioned = topic
+        .metadata
+        .labels
+        .as_mut()
+        .and_then(|labels| labels.remove(LABEL_PRE_PROVISIONED))
+        .is_some();
+
+    if pre_provisioned {
+        topic.data["spec"] = Value::Null;
+    }
+
+    pre_provisioned
+}
+
+/// Create the topic for an anticipated application, using the defaults.
+fn pre_provisioned_topic(
+    config: &ControllerConfig,
+    resource: &ApiResource,
+    app: &str,
+) -> DynamicObject {
+    let topic_name = config.topic_naming.resource_name(ResourceType::Events(app));
+    let partitions = limit_partitions(config, config.default_partitions)
+        .map(|partitions| partitions.count())
+        .unwrap_or(config.default_partitions);
+
+    let mut topic = DynamicObject::new(&topic_name, resource).within(&config.topic_namespace);
+    topic.meta_mut().labels = Some(BTreeMap::from([
+        (LABEL_KAFKA_CLUSTER.into(), config.cluster_name.clone()),
+        (LABEL_MARKER.into(), "true".into()),
+        (LABEL_PRE_PROVISIONED.into(), "true".into()),
+    ]));
+    topic.meta_mut().annotations = Some(BTreeMap::from([(
+        ANNOTATION_APP_NAME.into(),
+        app.to_string(),
+    )]));
+    topic.data["spec"] = json!({
+        "config": {},
+        "partitions": partitions,
+        "replicas": config.default_replicas,
+        "topicName": topic_name,
+    });
+
+    topic
+}
+
+/// Find the pre-provisioned topics, which are no longer anticipated.
+///
+/// Topics which got adopted by their application are not pre-provisioned anymore.
+fn unclaimed<'t>(
+    topics: impl IntoIterator<Item = &'t DynamicObject>,
+    anticipated: &BTreeSet<String>,
+) -> Vec<String> {
+    topics
+        .into_iter()
+        .filter(|topic| {
+            topic
+                .metadata
+                .labels
+                .as_ref()
+                .map_or(false, |labels| labels.contains_key(LABEL_PRE_PROVISIONED))
+        })
+        .filter(|topic| {
+            topic
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.get(ANNOTATION_APP_NAME))
+                .map_or(true, |app| !anticipated.contains(app))
+        })
+        .filter_map(|topic| topic.metadata.name.clone())
+        .collect()
+}
+
+/// Parse the application names from the content of the config map.
+fn parse_names(value: &str) -> impl Iterator<Item = String> + '_ {
+    value.split_whitespace().map(String::from)
+}
+
+/// Pre-creates the topics of anticipated applications.
+pub struct PreProvisioner {
+    config: ControllerConfig,
+    resource: ApiResource,
+    topics: Api<DynamicObject>,
+    config_maps: Api<ConfigMap>,
+}
+
+impl PreProvisioner {
+    pub fn new(
+        config: ControllerConfig,
+        resource: ApiResource,
+        topics: Api<DynamicObject>,
+        config_maps: Api<ConfigMap>,
+    ) -> Self {
+        Self {
+            config,
+            resource,
+            topics,
+            config_maps,
+        }
+    }
+
+    /// Load the names of the anticipated applications.
+    async fn anticipated(&self) -> anyhow::Result<BTreeSet<String>> {
+        let mut names = self
+            .config
+            .pre_provision
+            .applications
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        if let Some(name) = &self.config.pre_provision.config_map {
+            if let Some(config_map) = self.config_maps.get_opt(name).await? {
+                names.extend(
+                    config_map
+                        .data
+                        .as_ref()
+                        .and_then(|data| data.get(CONFIG_MAP_KEY))
+                        .map(|value| parse_names(value).collect::<Vec<_>>())
+                        .unwrap_or_default(),
+                );
+            }
+        }
+
+        Ok(names)
+    }
+
+    /// Create the missing topics, and delete the ones which are no longer anticipated.
+    async fn reconcile(&self) -> anyhow::Result<()> {
+        let anticipated = self.anticipated().await?;
+
+        for app in &anticipated {
+            let topic = pre_provisioned_topic(&self.config, &self.resource, app);
+            let name = topic.metadata.name.clone().unwrap_or_default();
+            if self.topics.get_opt(&name).await?.is_none() {
+                log::info!("Pre-provisioning topic '{name}' for application '{app}'");
+                self.topics.create(&PostParams::default(), &topic).await?;
+            }
+        }
+
+        let topics = self
+            .topics
+            .list(&ListParams::default().labels(&format!("{LABEL_PRE_PROVISIONED}=true")))
+            .await?;
+        for name in unclaimed(&topics.items, &anticipated) {
+            log::info!("Deleting unclaimed pre-provisioned topic '{name}'");
+            self.topics
+                .delete_optionally(&name, &Default::default())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Periodically reconcile the pre-provisioned topics.
+    pub async fn run(self) -> anyhow::Result<()> {
+        loop {
+            if let Err(err) = self.reconcile().await {
+                log::warn!("Failed to reconcile pre-provisioned topics: {err}");
+            }
+            tokio::time::sleep(self.config.pre_provision.interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> ControllerConfig {
+        serde_json::from_value(json!({
+            "topic_namespace": "kafka",
+            "cluster_name": "drogue",
+        }))
+        .unwrap()
+    }
+
+    fn resource() -> ApiResource {
+        ApiResource {
+            group: "kafka.strimzi.io".into(),
+            version: "v1beta2".into(),
+            api_version: "kafka.strimzi.io/v1beta2".into(),
+            kind: "KafkaTopic".into(),
+            plural: "kafkatopics".into(),
+        }
+    }
+
+    fn label(topic: &DynamicObject, name: &str) -> Option<String> {
+        topic.metadata.labels.as_ref()?.get(name).cloned()
+    }
+
+    #[test]
+    fn test_pre_create_then_adopt() {
+        let mut topic = pre_provisioned_topic(&config(), &resource(), "app1");
+
+        assert_eq!(topic.metadata.name.as_deref(), Some("events-app1"));
+        assert_eq!(
+            label(&topic, LABEL_PRE_PROVISIONED).as_deref(),
+            Some("true")
+        );
+        assert_eq!(label(&topic, LABEL_MARKER).as_deref(), Some("true"));
+        assert_eq!(topic.data["spec"]["partitions"], json!(3));
+
+        assert!(adopt(&mut topic));
+
+        assert_eq!(label(&topic, LABEL_PRE_PROVISIONED), None);
+        assert_eq!(label(&topic, LABEL_MARKER).as_deref(), Some("true"));
+        // the spec of the application gets applied
+        assert_eq!(topic.data["spec"], Value::Null);
+
+        // adopting again is a no-op
+        topic.data["spec"] = json!({"partitions": 5});
+        assert!(!adopt(&mut topic));
+        assert_eq!(topic.data["spec"], json!({"partitions": 5}));
+    }
+
+    #[test]
+    fn test_unclaimed_cleanup() {
+        let config = config();
+        let mut adopted = pre_provisioned_topic(&config, &resource(), "app1");
+        adopt(&mut adopted);
+        let topics = [
+            adopted,
+            pre_provisioned_topic(&config, &resource(), "app2"),
+            pre_provisioned_topic(&config, &resource(), "app3"),
+        ];
+
+        // "app2" is still anticipated, "app3" never got claimed
+        let anticipated = BTreeSet::from(["app2".to_string()]);
+        assert_eq!(
+            unclaimed(&topics, &anticipated),
+            vec!["events-app3".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(
+            parse_names("app1\napp2  app3\n").collect::<Vec<_>>(),
+            vec!["app1", "app2", "app3"]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use super::ANNOTATION_APP_NAME;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use drogue_cloud_operator_common::controller::reconciler::ReconcileError;
+use kube::{
+    api::{ApiResource, DynamicObject},
+    Api, Resource,
+};
+use operator_framework::{install::Delete, process::create_or_update_by, utils::UseOrCreate};
+use serde::{Deserialize, Serialize};
+
+/// The state of the topic of an application, reflected by the status resource.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicStatusSummary {
+    pub application: String,
+    pub topic: String,
+    pub ready: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitions: Option<u32>,
+    pub last_reconcile: DateTime<Utc>,
+}
+
+/// A sink for the status resources of topics.
+#[async_trait]
+pub trait StatusResourceSink: Send + Sync {
+    /// Create or update the status resource of a topic.
+    async fn apply(&self, status: &TopicStatusSummary) -> Result<(), ReconcileError>;
+    /// Delete the status resource of a topic, if it exists.
+    async fn delete(&self, topic: &str) -> Result<(), ReconcileError>;
+}
+
+/// Storing the status as custom resources.
+///
+/// The state is stored in the `status` field of the resource, so the custom resource definition
+/// must not enable the status subresource.
+pub struct KubeStatusResourceSink {
+    api: Api<DynamicObject>,
+    resource: ApiResource,
+    namespace: String,
+}
+
+impl KubeStatusResourceSink {
+    pub fn new(api: Api<DynamicObject>, resource: ApiResource, namespace: String) -> Self {
+        Self {
+            api,
+            resource,
+            namespace,
+        }
+    }
+}
+
+#[async_trait]
+impl StatusResourceSink for KubeStatusResourceSink {
+    async fn apply(&self, status: &TopicStatusSummary) -> Result<(), ReconcileError> {
+        let value = serde_json::to_value(status)?;
+
+        create_or_update_by(
+            &self.api,
+            Some(self.namespace.clone()),
+            &status.topic,
+            |meta| {
+                let mut resource =
+                    DynamicObject::new(&status.topic, &self.resource).within(&self.namespace);
+                *resource.meta_mut() = meta;
+                resource
+            },
+            |this, that| this.metadata == that.metadata && this.data == that.data,
+            |mut resource| {
+                resource.metadata.annotations.use_or_create(|annotations| {
+                    annotations.insert(ANNOTATION_APP_NAME.into(), status.application.clone());
+                });
+                resource.data["status"] = value;
+                Ok::<_, ReconcileError>(resource)
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, topic: &str) -> Result<(), ReconcileError> {
+        self.api
+            .delete_optionally(topic, &Default::default())
+            .await?;
+        Ok(())
+    }
+}
+
+/// Emit the status resource of a topic.
+///
+/// The status resource is informational only, so failures are logged, but don't fail the
+/// reconciliation.
+pub async fn emit_status(sink: &dyn StatusResourceSink, status: TopicStatusSummary) {
+    if let Err(err) = sink.apply(&status).await {
+        log::warn!(
+            "Failed to update status resource of topic '{}': {}",
+            status.topic,
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use super::*;
+    use std::{collections::HashMap, sync::Mutex};
+
+    #[derive(Default)]
+    pub struct MockSink(pub Mutex<HashMap<String, TopicStatusSummary>>);
+
+    #[async_trait]
+    impl StatusResourceSink for MockSink {
+        async fn apply(&self, status: &TopicStatusSummary) -> Result<(), ReconcileError> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(status.topic.clone(), status.clone());
+            Ok(())
+        }
+
+        async fn delete(&self, topic: &str) -> Result<(), ReconcileError> {
+            self.0.lock().unwrap().remove(topic);
+            Ok(())
+        }
+    }
+
+    fn summary(ready: bool, now: DateTime<Utc>) -> TopicStatusSummary {
+        TopicStatusSummary {
+            application: "app1".into(),
+            topic: "events-app1".into(),
+            ready,
+            partitions: Some(3),
+            last_reconcile: now,
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let now = "2022-01-01T00:00:00Z".parse().unwrap();
+        assert_eq!(
+            serde_json::to_value(summary(true, now)).unwrap(),
+            serde_json::json!({
+                "application": "app1",
+                "topic": "events-app1",
+                "ready": true,
+                "partitions": 3,
+                "lastReconcile": "2022-01-01T00:00:00Z",
+            })
+        );
+    }
+}
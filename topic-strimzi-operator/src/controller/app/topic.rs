@@ -1,77 +1,482 @@
 use super::{
-    condition_ready, retry, ConstructContext, ANNOTATION_APP_NAME, LABEL_KAFKA_CLUSTER,
-    LABEL_MARKER,
+    adopt,
+    audit::{topic_change, AuditAction, AuditRecord, AuditSink},
+    condition_ready, defer_changes,
+    events::{emit_event, TopicEvent, TopicEventSink},
+    metrics::observe_topic_operation,
+    placement::{select_cluster, Placement, TopicClusters},
+    status::{emit_status, StatusResourceSink, TopicStatusSummary},
+    topic_provisioned, ConstructContext, ANNOTATION_APP_NAME, LABEL_KAFKA_CLUSTER, LABEL_MARKER,
+};
+use crate::{
+    controller::{
+        ControllerConfig, DriftMode, IgnoredConfigDetection, LimitMode, SchemaPolicy,
+        TopicStatusConfig,
+    },
+    data::{AppCommandsSpec, KafkaAppSpec, KafkaAppStatus, Retention, TopicCondition, TopicStatus},
 };
-use crate::controller::ControllerConfig;
 use async_trait::async_trait;
-use drogue_client::{registry::v1::KafkaAppStatus, Translator};
+use chrono::Utc;
+use drogue_client::{core::v1::ConditionStatus, registry, Translator};
 use drogue_cloud_operator_common::controller::reconciler::{
     progress::{self, OperationOutcome, ProgressOperation},
     ReconcileError,
 };
-use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
+use drogue_cloud_service_api::kafka::ResourceType;
+use drogue_cloud_service_common::client::SecondaryRegistry;
 use kube::{
-    api::{ApiResource, DynamicObject},
-    Api, Resource,
+    api::{ApiResource, DynamicObject, ObjectMeta},
+    Resource,
 };
-use operator_framework::{process::create_or_update_by, utils::UseOrCreate};
-use serde_json::json;
+use operator_framework::{install::Delete, process::create_or_update_by, utils::UseOrCreate};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+const RETENTION_MS: &str = "retention.ms";
+const RETENTION_BYTES: &str = "retention.bytes";
+/// The latest schema version of the Kafka spec, supported by this operator.
+const SUPPORTED_SPEC_SCHEMA: u32 = 2;
+
+/// The effective number of partitions, after applying the limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partitions {
+    Accepted(u32),
+    Clamped { requested: u32, partitions: u32 },
+}
+
+impl Partitions {
+    pub fn count(&self) -> u32 {
+        match self {
+            Self::Accepted(partitions) | Self::Clamped { partitions, .. } => *partitions,
+        }
+    }
+}
+
+/// Apply the configured partition limits to a requested partition count.
+pub(super) fn limit_partitions(
+    config: &ControllerConfig,
+    requested: u32,
+) -> Result<Partitions, ReconcileError> {
+    let min = config.min_partitions.unwrap_or(1);
+    let max = config.max_partitions.unwrap_or(u32::MAX);
+    let partitions = requested.clamp(min, max);
+
+    if partitions == requested {
+        return Ok(Partitions::Accepted(requested));
+    }
+
+    match config.partition_limit_mode {
+        LimitMode::Clamp => Ok(Partitions::Clamped {
+            requested,
+            partitions,
+        }),
+        LimitMode::Reject => Err(ReconcileError::permanent(format!(
+            "Requested number of partitions ({requested}) is outside the allowed range ({min}..={max})"
+        ))),
+    }
+}
+
+/// Check the schema version of the Kafka spec.
+///
+/// A newer version may contain fields this operator doesn't know about. Depending on the
+/// configuration, those fields are ignored, or the application isn't reconciled at all.
+fn check_spec_schema(config: &ControllerConfig, spec: &KafkaAppSpec) -> Result<(), ReconcileError> {
+    let schema = match spec.schema {
+        Some(schema) if schema > SUPPORTED_SPEC_SCHEMA => schema,
+        _ => return Ok(()),
+    };
+
+    match config.spec_schema {
+        SchemaPolicy::Lenient => {
+            log::warn!(
+                "Kafka spec uses schema version {schema}, newer than the supported version ({SUPPORTED_SPEC_SCHEMA}), ignoring unknown fields"
+            );
+            Ok(())
+        }
+        SchemaPolicy::Strict => Err(ReconcileError::permanent(format!(
+            "Kafka spec uses schema version {schema}, but only up to version {SUPPORTED_SPEC_SCHEMA} is supported"
+        ))),
+    }
+}
+
+/// Validate the requested number of replicas against the number of brokers.
+///
+/// Kafka can't place more replicas than there are brokers, such a topic would never become ready.
+/// If the number of brokers is unknown, any positive number is accepted.
+fn validate_replicas(config: &ControllerConfig, replicas: u32) -> Result<u32, ReconcileError> {
+    if replicas == 0 {
+        return Err(ReconcileError::permanent(
+            "Requested number of replicas must be at least 1",
+        ));
+    }
+
+    match config.broker_count {
+        Some(brokers) if replicas > brokers => Err(ReconcileError::permanent(format!(
+            "Requested number of replicas ({replicas}) exceeds the number of brokers ({brokers})"
+        ))),
+        _ => Ok(replicas),
+    }
+}
+
+/// Reject degenerate topic names.
+///
+/// Characters which are not valid in a topic name get replaced, so an application name without
+/// any valid character would result in a topic name consisting only of the hash, which can't be
+/// related to the application anymore.
+fn check_topic_name(app: &str, topic_name: &str) -> Result<(), ReconcileError> {
+    if !app.chars().any(|c| c.is_ascii_alphanumeric()) {
+        return Err(ReconcileError::permanent(format!(
+            "Application name '{app}' doesn't contain any characters valid in a topic name (ASCII letters and digits)"
+        )));
+    }
+
+    if topic_name.is_empty()
+        || topic_name.len() > 63
+        || topic_name.starts_with('-')
+        || topic_name.ends_with('-')
+    {
+        return Err(ReconcileError::permanent(format!(
+            "Invalid topic name '{topic_name}' for application '{app}'"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Expand the topic config of an application, starting with the selected preset.
+///
+/// Explicitly configured keys override the ones of the preset, which override the defaults of
+/// the controller.
+fn expand_config(
+    config: &ControllerConfig,
+    spec: &KafkaAppSpec,
+) -> Result<BTreeMap<String, Value>, ReconcileError> {
+    let mut result: BTreeMap<String, Value> = config
+        .topic_config
+        .iter()
+        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+        .collect();
+
+    if let Some(preset) = &spec.preset {
+        result.extend(config.topic_presets.get(preset).cloned().ok_or_else(|| {
+            ReconcileError::permanent(format!("Unknown topic preset '{preset}'"))
+        })?);
+    }
+
+    result.extend(spec.config.clone());
+
+    if let Some(retention) = &spec.retention {
+        for (key, value) in [
+            (RETENTION_MS, retention.ms),
+            (RETENTION_BYTES, retention.bytes),
+        ] {
+            match value {
+                Some(value) if value < 0 => {
+                    return Err(ReconcileError::permanent(format!(
+                        "Retention '{key}' must not be negative, but is {value}"
+                    )))
+                }
+                Some(value) => {
+                    result.insert(key.into(), value.into());
+                }
+                None => {}
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Evaluate the effective retention from the expanded topic config.
+///
+/// Returns [`None`] if neither limit is configured, inheriting the defaults of the cluster.
+fn effective_retention(config: &BTreeMap<String, Value>) -> Option<Retention> {
+    let limit = |key: &str| {
+        config.get(key).and_then(|value| match value {
+            Value::Number(value) => value.as_i64(),
+            Value::String(value) => value.parse().ok(),
+            _ => None,
+        })
+    };
+
+    let retention = Retention {
+        ms: limit(RETENTION_MS),
+        bytes: limit(RETENTION_BYTES),
+    };
+
+    (retention != Retention::default()).then_some(retention)
+}
+
+/// Translate the declared topic config keys to the names used by the cluster.
+///
+/// Keys which are neither an alias, nor the target of one, are passed through with a warning. If
+/// both an alias and its target are declared, the target wins.
+fn translate_config(
+    aliases: &HashMap<String, String>,
+    declared: &BTreeMap<String, Value>,
+) -> Map<String, Value> {
+    let mut result = Map::new();
+
+    for (key, value) in declared {
+        match aliases.get(key) {
+            Some(target) => {
+                if declared.contains_key(target) {
+                    log::warn!("Topic config key '{key}' is overridden by '{target}'");
+                    continue;
+                }
+                log::debug!("Translating topic config key '{key}' to '{target}'");
+                result.insert(target.clone(), value.clone());
+            }
+            None => {
+                if !aliases.values().any(|target| target == key) {
+                    log::warn!("Passing through unknown topic config key '{key}'");
+                }
+                result.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    result
+}
+
+/// The declared spec of a topic.
+fn declared_spec(
+    topic_name: &str,
+    partitions: u32,
+    replicas: u32,
+    topic_config: Map<String, Value>,
+) -> Value {
+    json!({
+        "config": topic_config,
+        "partitions": partitions,
+        "replicas": replicas,
+        "topicName": topic_name,
+    })
+}
+
+/// Log a topic instead of applying it, reporting it as ready.
+fn dry_run_topic(mut topic: DynamicObject) -> DynamicObject {
+    log::info!(
+        "Dry run, not applying topic '{}': {}",
+        topic.metadata.name.as_deref().unwrap_or_default(),
+        serde_json::to_string(&topic).unwrap_or_default()
+    );
+
+    topic.data["status"] = json!({
+        "conditions": [{
+            "type": "Ready",
+            "status": "True",
+            "reason": "DryRun",
+        }]
+    });
+    topic
+}
+
+/// Check that the declared spec doesn't decrease the partitions of an existing topic.
+///
+/// Kafka can't reduce the number of partitions of a topic, so this can't be fixed by retrying.
+fn check_partitions(topic: &DynamicObject, declared: &Value) -> Result<(), ReconcileError> {
+    let current = topic.data["spec"]["partitions"].as_u64();
+    let wanted = declared["partitions"].as_u64();
+
+    match (current, wanted) {
+        (Some(current), Some(wanted)) if wanted < current => Err(ReconcileError::permanent(
+            format!("Number of partitions can't be decreased (from {current} to {wanted})"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Apply the declared spec to a topic, returning the fields of the existing spec which differ.
+///
+/// The partitions are not considered drift, as they may only grow and are always applied. When
+/// only warning, the existing spec is kept as it is.
+fn apply_spec(mode: DriftMode, topic: &mut DynamicObject, declared: Value) -> Vec<String> {
+    let live = match topic.data["spec"].as_object() {
+        Some(live) => live,
+        None => {
+            // new topic, or no spec at all
+            topic.data["spec"] = declared;
+            return vec![];
+        }
+    };
+
+    let empty = Default::default();
+    let wanted = declared.as_object().unwrap_or(&empty);
+
+    let mut drift: Vec<String> = live
+        .keys()
+        .chain(wanted.keys())
+        .filter(|field| *field != "partitions" && live.get(*field) != wanted.get(*field))
+        .cloned()
+        .collect();
+    drift.sort();
+    drift.dedup();
+
+    match mode {
+        DriftMode::Correct => {
+            if !drift.is_empty() {
+                log::info!(
+                    "Correcting drift of topic '{}': {}",
+                    topic.metadata.name.as_deref().unwrap_or_default(),
+                    drift.join(", ")
+                );
+            }
+            topic.data["spec"] = declared;
+            vec![]
+        }
+        DriftMode::Warn => {
+            topic.data["spec"]["partitions"] = wanted.get("partitions").cloned().into();
+            drift
+        }
+    }
+}
 
 pub struct CreateTopic<'o> {
-    pub api: &'o Api<DynamicObject>,
+    pub clusters: &'o TopicClusters,
     pub resource: &'o ApiResource,
     pub config: &'o ControllerConfig,
+    pub secondary: Option<&'o SecondaryRegistry>,
+    /// Defer disruptive changes, as the maintenance window is closed.
+    pub defer_disruptive: bool,
+    pub audit: Option<&'o dyn AuditSink>,
+    pub events: Option<&'o dyn TopicEventSink>,
 }
 
 impl CreateTopic<'_> {
+    /// Get the Kafka spec of the application, consulting the secondary registry if present.
+    async fn spec(&self, app: &registry::v1::Application) -> Result<KafkaAppSpec, ReconcileError> {
+        let secondary = match self.secondary {
+            Some(secondary) => secondary,
+            None => {
+                return Ok(app
+                    .section::<KafkaAppSpec>()
+                    .and_then(|s| s.ok())
+                    .unwrap_or_default())
+            }
+        };
+
+        let spec = secondary
+            .spec_section(app, "kafka")
+            .await
+            .map_err(ReconcileError::temporary)?;
+
+        Ok(spec
+            .and_then(|spec| serde_json::from_value(spec).ok())
+            .unwrap_or_default())
+    }
+
     async fn ensure_kafka_topic(
-        kafka_topics: &Api<DynamicObject>,
-        kafka_topic_resource: &ApiResource,
-        config: &ControllerConfig,
-        target: ResourceType<'_>,
-    ) -> Result<(DynamicObject, String), ReconcileError> {
-        let topic_name = make_kafka_resource_name(target.clone());
-
-        let topic = create_or_update_by(
-            kafka_topics,
-            Some(config.topic_namespace.clone()),
-            &topic_name,
-            |meta| {
-                let mut topic = DynamicObject::new(&topic_name, kafka_topic_resource)
-                    .within(&config.topic_namespace);
-                *topic.meta_mut() = meta;
-                topic
-            },
-            |this, that| this.metadata == that.metadata && this.data == that.data,
-            |mut topic| {
-                // set target cluster
-                topic.metadata.labels.use_or_create(|labels| {
-                    labels.insert(LABEL_KAFKA_CLUSTER.into(), config.cluster_name.clone());
-                    // set marker
-                    labels.insert(LABEL_MARKER.into(), "true".to_string());
-                });
+        &self,
+        app: &registry::v1::Application,
+        placement: &Placement<'_>,
+        partitions: u32,
+        replicas: u32,
+        topic_config: Map<String, Value>,
+    ) -> Result<(DynamicObject, String, Vec<String>, Vec<String>), ReconcileError> {
+        let config = self.config;
+        let kafka_topic_resource = self.resource;
+        let target = ResourceType::Events(&app.metadata.name);
+        let topic_name = config.topic_naming.resource_name(target.clone());
+        check_topic_name(&app.metadata.name, &topic_name)?;
+        let mut drift = vec![];
+        let mut deferred = vec![];
+        let mut change = None;
 
-                topic.metadata.annotations.use_or_create(|annotations| {
-                    annotations.insert(ANNOTATION_APP_NAME.into(), target.app_name().into());
-                });
+        let creator = |meta| {
+            let mut topic = DynamicObject::new(&topic_name, kafka_topic_resource)
+                .within(placement.topic_namespace);
+            *topic.meta_mut() = meta;
+            topic
+        };
+        let mutator = |mut topic: DynamicObject| {
+            let before = topic.data["spec"].clone();
 
-                // set config
-                topic.data["spec"] = json!({
-                    "config": {},
-                    "partitions": 3,
-                    "replicas": 1,
-                    "topicName": topic_name,
-                });
+            // take over a pre-provisioned topic
+            if adopt(&mut topic) {
+                log::info!("Adopting pre-provisioned topic '{topic_name}'");
+            }
 
-                Ok::<_, ReconcileError>(topic)
-            },
-        )
-        .await?
-        .resource();
+            // set target cluster
+            topic.metadata.labels.use_or_create(|labels| {
+                labels.insert(LABEL_KAFKA_CLUSTER.into(), placement.cluster_name.into());
+                // set marker
+                labels.insert(LABEL_MARKER.into(), "true".to_string());
+            });
+
+            topic.metadata.annotations.use_or_create(|annotations| {
+                annotations.insert(ANNOTATION_APP_NAME.into(), target.app_name().into());
+            });
+
+            // set config
+            let mut declared = declared_spec(&topic_name, partitions, replicas, topic_config);
+            if self.defer_disruptive {
+                deferred = defer_changes(&topic, &mut declared);
+                if !deferred.is_empty() {
+                    log::info!(
+                        "Deferring changes of topic '{topic_name}' until the next maintenance window: {}",
+                        deferred.join(", ")
+                    );
+                }
+            }
+            check_partitions(&topic, &declared)?;
+            drift = apply_spec(config.topic_drift, &mut topic, declared);
+            change = topic_change(&before, &topic.data["spec"]);
+
+            Ok::<_, ReconcileError>(topic)
+        };
+
+        let result = match config.dry_run {
+            true => mutator(creator(ObjectMeta {
+                name: Some(topic_name.clone()),
+                namespace: Some(placement.topic_namespace.into()),
+                ..Default::default()
+            }))
+            .map(dry_run_topic),
+            false => create_or_update_by(
+                placement.api,
+                Some(placement.topic_namespace.to_string()),
+                &topic_name,
+                creator,
+                |this, that| this.metadata == that.metadata && this.data == that.data,
+                mutator,
+            )
+            .await
+            .map(|outcome| outcome.resource()),
+        };
+
+        if let (false, Ok(_), Some((AuditAction::Create, _))) = (config.dry_run, &result, &change) {
+            observe_topic_operation("Create", &app.metadata.name);
+        }
+
+        if let (Some(events), Ok(_), Some((AuditAction::Create, _))) =
+            (self.events, &result, &change)
+        {
+            emit_event(
+                events,
+                placement.topic_namespace,
+                &topic_name,
+                &app.metadata.name,
+                TopicEvent::Created,
+            )
+            .await;
+        }
+
+        if let (Some(audit), Some((action, changes))) = (self.audit, change) {
+            audit.record(&AuditRecord::new(
+                app,
+                topic_name.clone(),
+                action,
+                changes,
+                result.as_ref(),
+            ));
+        }
+
+        let topic = result?;
 
         // done
 
-        Ok((topic, topic_name))
+        Ok((topic, topic_name, drift, deferred))
     }
 }
 
@@ -86,25 +491,227 @@ impl<'o> ProgressOperation<ConstructContext> for CreateTopic<'o> {
         mut ctx: ConstructContext,
     ) -> drogue_cloud_operator_common::controller::reconciler::progress::Result<ConstructContext>
     {
-        let (topic, topic_name) = Self::ensure_kafka_topic(
-            self.api,
-            self.resource,
+        let spec = self.spec(&ctx.app).await?;
+        check_spec_schema(self.config, &spec)?;
+        let partitions = limit_partitions(
+            self.config,
+            spec.partitions.unwrap_or(self.config.default_partitions),
+        )?;
+        let replicas = validate_replicas(
             self.config,
-            ResourceType::Events(&ctx.app.metadata.name),
-        )
-        .await?;
+            spec.replicas.unwrap_or(self.config.default_replicas),
+        )?;
+        let topic_config = expand_config(self.config, &spec)?;
+        let retention = effective_retention(&topic_config);
+        let placement = self
+            .clusters
+            .placement(self.config, select_cluster(self.config, &ctx.app)?)?;
+
+        let (topic, topic_name, drift, deferred) = self
+            .ensure_kafka_topic(
+                &ctx.app,
+                &placement,
+                partitions.count(),
+                replicas,
+                translate_config(&self.config.topic_config_aliases, &topic_config),
+            )
+            .await?;
 
         ctx.events_topic = Some(topic);
         ctx.events_topic_name = Some(topic_name);
+        ctx.events_topic_partitions = Some(partitions);
+        ctx.events_topic_drift = drift;
+        ctx.events_topic_deferred = deferred.clone();
+
+        ctx.app.update_section(|mut status: KafkaAppStatus| {
+            status.retention = retention;
+            status.deferred_changes = deferred;
+            status.cluster = placement.name.map(Into::into);
+            status.topic_name = ctx.events_topic_name.clone();
+            status
+        })?;
 
         // done
 
         Ok(OperationOutcome::Continue(ctx))
     }
+
+    fn when_continued(&self, ctx: &ConstructContext) -> ConditionStatus {
+        let mut warnings = vec![];
+
+        if !ctx.events_topic_deferred.is_empty() {
+            warnings.push((
+                "ChangesDeferred",
+                format!(
+                    "Disruptive changes deferred until the next maintenance window: {}",
+                    ctx.events_topic_deferred.join(", ")
+                ),
+            ));
+        }
+
+        if let Some(Partitions::Clamped {
+            requested,
+            partitions,
+        }) = ctx.events_topic_partitions
+        {
+            warnings.push((
+                "PartitionsClamped",
+                format!("Requested number of partitions ({requested}) was limited to {partitions}"),
+            ));
+        }
+
+        if !ctx.events_topic_drift.is_empty() {
+            warnings.push((
+                "ConfigDrift",
+                format!(
+                    "Topic configuration differs from the declared one: {}",
+                    ctx.events_topic_drift.join(", ")
+                ),
+            ));
+        }
+
+        match warnings.first() {
+            Some((reason, _)) => ConditionStatus {
+                status: Some(true),
+                reason: Some(reason.to_string()),
+                message: Some(
+                    warnings
+                        .iter()
+                        .map(|(_, message)| message.as_str())
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                ),
+            },
+            None => ConditionStatus {
+                status: Some(true),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Creating the dedicated commands topic of an application, if it enabled commands.
+///
+/// The topic is placed on the same cluster as the events topic, using the defaults of the
+/// controller. Once commands get disabled, the topic is deleted.
+pub struct CreateCommandsTopic<'o> {
+    pub clusters: &'o TopicClusters,
+    pub resource: &'o ApiResource,
+    pub config: &'o ControllerConfig,
+}
+
+#[async_trait]
+impl<'o> ProgressOperation<ConstructContext> for CreateCommandsTopic<'o> {
+    fn type_name(&self) -> String {
+        "CreateCommandsTopic".into()
+    }
+
+    async fn run(&self, mut ctx: ConstructContext) -> progress::Result<ConstructContext> {
+        let enabled = ctx
+            .app
+            .section::<AppCommandsSpec>()
+            .and_then(|s| s.ok())
+            .map(|s| s.enabled)
+            .unwrap_or_default();
+        let placement = self
+            .clusters
+            .placement(self.config, select_cluster(self.config, &ctx.app)?)?;
+        let topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::AppCommands(&ctx.app.metadata.name));
+        check_topic_name(&ctx.app.metadata.name, &topic_name)?;
+
+        if !enabled {
+            let exists = ctx
+                .app
+                .section::<KafkaAppStatus>()
+                .and_then(|s| s.ok())
+                .and_then(|s| s.commands_topic)
+                .is_some();
+            match (exists, self.config.dry_run) {
+                (false, _) => {}
+                (true, true) => log::debug!("Dry run, not deleting topic '{topic_name}'"),
+                (true, false) => {
+                    placement
+                        .api
+                        .delete_optionally(&topic_name, &Default::default())
+                        .await?;
+                }
+            }
+            ctx.commands_topic = None;
+            ctx.app.update_section(|mut status: KafkaAppStatus| {
+                status.commands_topic = None;
+                status
+            })?;
+            return Ok(OperationOutcome::Continue(ctx));
+        }
+
+        let partitions = limit_partitions(self.config, self.config.default_partitions)?.count();
+        let topic_config = self
+            .config
+            .topic_config
+            .iter()
+            .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+            .collect();
+        let declared = declared_spec(
+            &topic_name,
+            partitions,
+            self.config.default_replicas,
+            topic_config,
+        );
+
+        let creator = |meta| {
+            let mut topic =
+                DynamicObject::new(&topic_name, self.resource).within(placement.topic_namespace);
+            *topic.meta_mut() = meta;
+            topic
+        };
+        let mutator = |mut topic: DynamicObject| {
+            topic.metadata.labels.use_or_create(|labels| {
+                labels.insert(LABEL_KAFKA_CLUSTER.into(), placement.cluster_name.into());
+                labels.insert(LABEL_MARKER.into(), "true".to_string());
+            });
+            topic.metadata.annotations.use_or_create(|annotations| {
+                annotations.insert(ANNOTATION_APP_NAME.into(), ctx.app.metadata.name.clone());
+            });
+            check_partitions(&topic, &declared)?;
+            topic.data["spec"] = declared;
+            Ok::<_, ReconcileError>(topic)
+        };
+
+        let topic = match self.config.dry_run {
+            true => dry_run_topic(mutator(creator(ObjectMeta {
+                name: Some(topic_name.clone()),
+                namespace: Some(placement.topic_namespace.into()),
+                ..Default::default()
+            }))?),
+            false => create_or_update_by(
+                placement.api,
+                Some(placement.topic_namespace.to_string()),
+                &topic_name,
+                creator,
+                |this, that| this.metadata == that.metadata && this.data == that.data,
+                mutator,
+            )
+            .await?
+            .resource(),
+        };
+
+        ctx.commands_topic = Some(topic);
+        ctx.app.update_section(|mut status: KafkaAppStatus| {
+            status.commands_topic = Some(topic_name);
+            status
+        })?;
+
+        Ok(OperationOutcome::Continue(ctx))
+    }
 }
 
 pub struct TopicReady<'o> {
     pub config: &'o ControllerConfig,
+    pub status_resource: Option<&'o dyn StatusResourceSink>,
+    pub events: Option<&'o dyn TopicEventSink>,
 }
 
 #[async_trait]
@@ -120,15 +727,1280 @@ impl<'o> ProgressOperation<ConstructContext> for TopicReady<'o> {
             .and_then(|topic| condition_ready("Ready", topic))
             .unwrap_or_default();
 
+        if let (Some(sink), Some(topic)) = (self.status_resource, &ctx.events_topic_name) {
+            let status = TopicStatusSummary {
+                application: ctx.app.metadata.name.clone(),
+                topic: topic.clone(),
+                ready: events_ready,
+                partitions: ctx.events_topic_partitions.map(|p| p.count()),
+                last_reconcile: Utc::now(),
+            };
+            emit_status(sink, status).await;
+        }
+
+        let topic_status = match self.config.topic_status.enabled {
+            true => ctx
+                .events_topic
+                .as_ref()
+                .map(|topic| topic_status(&self.config.topic_status, topic)),
+            false => None,
+        };
+
+        ctx.events_topic_ignored_config = match (&ctx.events_topic, events_ready) {
+            (Some(topic), true) if self.config.ignored_config.enabled => {
+                ignored_config(&self.config.ignored_config, topic)
+            }
+            _ => vec![],
+        };
+
+        // the commands topic is only present if enabled
+        let commands_ready = ctx
+            .commands_topic
+            .as_ref()
+            .map(|topic| condition_ready("Ready", topic).unwrap_or_default())
+            .unwrap_or(true);
+        let ready = events_ready && commands_ready;
+
+        let provisioned = events_ready && self.config.provisioning_metrics.enabled;
+        let mut attempts = 0;
         ctx.app.update_section(|mut status: KafkaAppStatus| {
             // using the internal model only for now
             status.downstream = None;
+            status.topic = topic_status;
+            if provisioned {
+                topic_provisioned(&mut status, Utc::now());
+            }
+            // back off, while not ready
+            attempts = status.ready_poll_attempts;
+            status.ready_poll_attempts = match ready {
+                true => 0,
+                false => attempts.saturating_add(1),
+            };
             status
         })?;
 
-        match events_ready {
+        if let Some(events) = self.events {
+            for (topic, ready) in [
+                (&ctx.events_topic, events_ready),
+                (&ctx.commands_topic, commands_ready),
+            ] {
+                if let (Some(topic), false) = (topic, ready) {
+                    emit_event(
+                        events,
+                        topic.metadata.namespace.as_deref().unwrap_or_default(),
+                        topic.metadata.name.as_deref().unwrap_or_default(),
+                        &ctx.app.metadata.name,
+                        TopicEvent::NotReady,
+                    )
+                    .await;
+                }
+            }
+        }
+
+        match ready {
             true => Ok(OperationOutcome::Continue(ctx)),
-            false => retry(ctx),
+            false => Ok(OperationOutcome::Retry(
+                ctx,
+                Some(self.config.ready_poll.delay(attempts)),
+            )),
+        }
+    }
+
+    fn when_continued(&self, ctx: &ConstructContext) -> ConditionStatus {
+        match ctx.events_topic_ignored_config.is_empty() {
+            true => ConditionStatus {
+                status: Some(true),
+                ..Default::default()
+            },
+            false => ConditionStatus {
+                status: Some(true),
+                reason: Some("KafkaConfigPartiallyApplied".into()),
+                message: Some(format!(
+                    "Topic configuration was ignored by the cluster: {}",
+                    ctx.events_topic_ignored_config.join(", ")
+                )),
+            },
+        }
+    }
+}
+
+/// Find the keys of the topic config, which the cluster reported as ignored.
+///
+/// The keys are taken from the `Warning` conditions with a matching reason. Strimzi lists the keys
+/// in brackets (e.g. `These .spec.config properties are not configurable: [a, b]`). Otherwise,
+/// the declared keys mentioned in the message are used.
+fn ignored_config(config: &IgnoredConfigDetection, topic: &DynamicObject) -> Vec<String> {
+    let declared = topic.data["spec"]["config"]
+        .as_object()
+        .map(|config| config.keys().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let mut ignored = topic.data["status"]["conditions"]
+        .as_array()
+        .map(|conditions| conditions.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter(|cond| cond["type"] == "Warning" && cond["status"] != "False")
+        .filter(|cond| {
+            cond["reason"]
+                .as_str()
+                .map_or(false, |reason| config.reasons.iter().any(|r| r == reason))
+        })
+        .filter_map(|cond| cond["message"].as_str())
+        .flat_map(|message| {
+            let listed = message
+                .rfind('[')
+                .and_then(|start| {
+                    message[start + 1..]
+                        .find(']')
+                        .map(|end| &message[start + 1..start + 1 + end])
+                })
+                .map(|list| {
+                    list.split(',')
+                        .map(str::trim)
+                        .filter(|key| !key.is_empty())
+                        .map(String::from)
+                        .collect::<Vec<_>>()
+                });
+            listed.unwrap_or_else(|| {
+                declared
+                    .iter()
+                    .filter(|key| message.contains(key.as_str()))
+                    .cloned()
+                    .collect()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    ignored.sort();
+    ignored.dedup();
+    ignored
+}
+
+/// Extract the status of a topic, limited by the configuration.
+fn topic_status(config: &TopicStatusConfig, topic: &DynamicObject) -> TopicStatus {
+    let conditions = topic.data["status"]["conditions"]
+        .as_array()
+        .map(|conditions| conditions.as_slice())
+        .unwrap_or_default();
+
+    let mut truncated = conditions.len() > config.max_conditions;
+
+    let conditions = conditions
+        .iter()
+        .take(config.max_conditions)
+        .filter_map(|cond| cond.as_object())
+        .map(|cond| {
+            let text = |name: &str| cond.get(name).and_then(|v| v.as_str()).map(String::from);
+            let message = text("message").map(|mut message| {
+                let end = message.char_indices().nth(config.max_message_length);
+                if let Some((idx, _)) = end {
+                    truncated = true;
+                    message.truncate(idx);
+                }
+                message
+            });
+            TopicCondition {
+                r#type: text("type").unwrap_or_default(),
+                status: text("status").unwrap_or_default(),
+                reason: text("reason"),
+                message,
+            }
+        })
+        .collect();
+
+    TopicStatus {
+        conditions,
+        truncated,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::app::status::test::MockSink;
+    use drogue_client::registry;
+    use drogue_cloud_operator_common::controller::base::ConditionExt;
+    use drogue_cloud_service_api::kafka::{KafkaConfigExt, KafkaEventType, TopicNaming};
+    use futures::FutureExt;
+    use kube::Api;
+    use std::time::Duration;
+
+    fn topic() -> DynamicObject {
+        serde_json::from_value(json!({
+            "apiVersion": "kafka.strimzi.io/v1beta2",
+            "kind": "KafkaTopic",
+            "metadata": {
+                "name": "events-app1",
+            },
+            "status": {
+                "conditions": [
+                    {
+                        "type": "NotReady",
+                        "status": "True",
+                        "reason": "InvalidResourceException",
+                        "message": "Number of partitions cannot be decreased",
+                    },
+                    {
+                        "type": "Ready",
+                        "status": "False",
+                    }
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn config(min: Option<u32>, max: Option<u32>, mode: LimitMode) -> ControllerConfig {
+        let mut config: ControllerConfig = serde_json::from_value(json!({
+            "topic_namespace": "kafka",
+            "cluster_name": "drogue",
+        }))
+        .unwrap();
+        config.min_partitions = min;
+        config.max_partitions = max;
+        config.partition_limit_mode = mode;
+        config.emit_events = false;
+        config.provision_users = false;
+        config
+    }
+
+    /// The context of a new application, before any operation ran.
+    fn context(app: registry::v1::Application) -> ConstructContext {
+        ConstructContext {
+            app,
+            events_topic: None,
+            events_topic_name: None,
+            events_topic_partitions: None,
+            events_topic_drift: vec![],
+            events_topic_deferred: vec![],
+            events_topic_ignored_config: vec![],
+            commands_topic: None,
+            app_user: None,
+            app_user_name: None,
+        }
+    }
+
+    #[test]
+    fn test_partitions_unlimited() {
+        let config = config(None, None, LimitMode::Reject);
+        assert_eq!(
+            limit_partitions(&config, 3).unwrap(),
+            Partitions::Accepted(3)
+        );
+        assert_eq!(
+            limit_partitions(&config, 1000).unwrap(),
+            Partitions::Accepted(1000)
+        );
+    }
+
+    #[test]
+    fn test_partitions_in_range() {
+        let config = config(Some(2), Some(10), LimitMode::Reject);
+        assert_eq!(
+            limit_partitions(&config, 2).unwrap(),
+            Partitions::Accepted(2)
+        );
+        assert_eq!(
+            limit_partitions(&config, 10).unwrap(),
+            Partitions::Accepted(10)
+        );
+    }
+
+    #[test]
+    fn test_partitions_clamp() {
+        let config = config(Some(2), Some(10), LimitMode::Clamp);
+        assert_eq!(
+            limit_partitions(&config, 1).unwrap(),
+            Partitions::Clamped {
+                requested: 1,
+                partitions: 2
+            }
+        );
+        assert_eq!(
+            limit_partitions(&config, 1000).unwrap(),
+            Partitions::Clamped {
+                requested: 1000,
+                partitions: 10
+            }
+        );
+    }
+
+    #[test]
+    fn test_partitions_reject() {
+        let config = config(Some(2), Some(10), LimitMode::Reject);
+        assert!(matches!(
+            limit_partitions(&config, 1),
+            Err(ReconcileError::Permanent(_))
+        ));
+        assert!(matches!(
+            limit_partitions(&config, 1000),
+            Err(ReconcileError::Permanent(_))
+        ));
+    }
+
+    #[test]
+    fn test_partitions_validate() {
+        assert!(config(None, None, LimitMode::Reject).validate().is_ok());
+        assert!(config(Some(2), None, LimitMode::Reject).validate().is_ok());
+        assert!(config(Some(2), Some(2), LimitMode::Reject)
+            .validate()
+            .is_ok());
+        assert!(config(Some(10), Some(2), LimitMode::Reject)
+            .validate()
+            .is_err());
+        // the minimum defaults to 1
+        assert!(config(None, Some(1), LimitMode::Reject).validate().is_ok());
+        assert!(config(None, Some(0), LimitMode::Reject).validate().is_err());
+        assert!(config(Some(0), None, LimitMode::Reject).validate().is_err());
+    }
+
+    #[test]
+    fn test_replicas_within_brokers() {
+        let mut config = config(None, None, LimitMode::Reject);
+        config.broker_count = Some(3);
+
+        assert_eq!(validate_replicas(&config, 1).unwrap(), 1);
+        assert_eq!(validate_replicas(&config, 3).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_replicas_exceed_brokers() {
+        let mut config = config(None, None, LimitMode::Reject);
+        config.broker_count = Some(3);
+
+        let result = validate_replicas(&config, 4);
+        assert_eq!(
+            result,
+            Err(ReconcileError::permanent(
+                "Requested number of replicas (4) exceeds the number of brokers (3)"
+            ))
+        );
+        assert!(matches!(
+            validate_replicas(&config, 0),
+            Err(ReconcileError::Permanent(_))
+        ));
+    }
+
+    #[test]
+    fn test_replicas_unknown_brokers() {
+        let config = config(None, None, LimitMode::Reject);
+        assert_eq!(validate_replicas(&config, 5).unwrap(), 5);
+    }
+
+    fn spec(schema: Option<u32>) -> KafkaAppSpec {
+        KafkaAppSpec {
+            schema,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_topic_name_valid() {
+        assert!(check_topic_name("app1", "events-app1").is_ok());
+
+        // all uppercase, falls back to a hashed, but readable name
+        let app = "A".repeat(100);
+        let topic_name = TopicNaming::default().resource_name(ResourceType::Events(&app));
+        assert!(topic_name.starts_with("evt-") && topic_name.ends_with("aaaa"));
+        assert!(check_topic_name(&app, &topic_name).is_ok());
+    }
+
+    #[test]
+    fn test_topic_name_degenerate() {
+        for app in ["___", "", "...", "äöü"] {
+            let topic_name = TopicNaming::default().resource_name(ResourceType::Events(app));
+            assert!(
+                matches!(
+                    check_topic_name(app, &topic_name),
+                    Err(ReconcileError::Permanent(msg)) if msg.contains("doesn't contain any characters")
+                ),
+                "{app}"
+            );
+        }
+
+        assert!(matches!(
+            check_topic_name("app1", ""),
+            Err(ReconcileError::Permanent(_))
+        ));
+        assert!(matches!(
+            check_topic_name("app1", "events-"),
+            Err(ReconcileError::Permanent(_))
+        ));
+    }
+
+    #[test]
+    fn test_schema_supported() {
+        let mut config = config(None, None, LimitMode::Reject);
+        config.spec_schema = SchemaPolicy::Strict;
+
+        assert_eq!(check_spec_schema(&config, &spec(None)), Ok(()));
+        assert_eq!(
+            check_spec_schema(&config, &spec(Some(SUPPORTED_SPEC_SCHEMA))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_schema_newer_lenient() {
+        let mut config = config(None, None, LimitMode::Reject);
+        config.spec_schema = SchemaPolicy::Lenient;
+
+        assert_eq!(
+            check_spec_schema(&config, &spec(Some(SUPPORTED_SPEC_SCHEMA + 1))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_schema_newer_strict() {
+        let mut config = config(None, None, LimitMode::Reject);
+        config.spec_schema = SchemaPolicy::Strict;
+
+        assert_eq!(
+            check_spec_schema(&config, &spec(Some(3))),
+            Err(ReconcileError::permanent(
+                "Kafka spec uses schema version 3, but only up to version 2 is supported"
+            ))
+        );
+    }
+
+    fn preset(name: &str) -> Result<Value, ReconcileError> {
+        let config = config(None, None, LimitMode::Reject);
+        let spec = KafkaAppSpec {
+            preset: Some(name.into()),
+            ..Default::default()
+        };
+        expand_config(&config, &spec).map(|config| json!(config))
+    }
+
+    fn retention(ms: Option<i64>, bytes: Option<i64>) -> Result<Value, ReconcileError> {
+        let config = config(None, None, LimitMode::Reject);
+        let spec = KafkaAppSpec {
+            retention: Some(Retention { ms, bytes }),
+            ..Default::default()
+        };
+        expand_config(&config, &spec).map(|config| json!(config))
+    }
+
+    #[test]
+    fn test_retention_ms_only() {
+        let config = retention(Some(604800000), None).unwrap();
+        assert_eq!(config, json!({"retention.ms": 604800000}));
+    }
+
+    #[test]
+    fn test_retention_bytes_only() {
+        let config = retention(None, Some(10737418240)).unwrap();
+        assert_eq!(config, json!({"retention.bytes": 10737418240i64}));
+    }
+
+    #[test]
+    fn test_retention_both() {
+        let config = retention(Some(604800000), Some(10737418240)).unwrap();
+        assert_eq!(
+            config,
+            json!({"retention.ms": 604800000, "retention.bytes": 10737418240i64})
+        );
+    }
+
+    #[test]
+    fn test_retention_neither() {
+        assert_eq!(retention(None, None).unwrap(), json!({}));
+
+        let config = config(None, None, LimitMode::Reject);
+        assert_eq!(
+            effective_retention(&expand_config(&config, &Default::default()).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_retention_negative() {
+        assert_eq!(
+            retention(Some(-1), None),
+            Err(ReconcileError::permanent(
+                "Retention 'retention.ms' must not be negative, but is -1"
+            ))
+        );
+        assert!(retention(None, Some(-1)).is_err());
+    }
+
+    #[test]
+    fn test_retention_overrides_config() {
+        let config = config(None, None, LimitMode::Reject);
+        let spec = KafkaAppSpec {
+            config: [
+                ("retention.ms".to_string(), json!("1000")),
+                ("retention.bytes".to_string(), json!("2000")),
+            ]
+            .into(),
+            retention: Some(Retention {
+                ms: Some(3000),
+                bytes: None,
+            }),
+            ..Default::default()
+        };
+        let expanded = expand_config(&config, &spec).unwrap();
+
+        // the bytes are still inherited from the config
+        assert_eq!(
+            effective_retention(&expanded),
+            Some(Retention {
+                ms: Some(3000),
+                bytes: Some(2000),
+            })
+        );
+    }
+
+    #[test]
+    fn test_preset_high_throughput() {
+        assert_eq!(
+            preset("high-throughput").unwrap(),
+            json!({
+                "cleanup.policy": "delete",
+                "compression.type": "lz4",
+                "segment.bytes": 1073741824,
+                "segment.ms": 604800000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_preset_small_messages() {
+        assert_eq!(
+            preset("small-messages").unwrap(),
+            json!({
+                "cleanup.policy": "delete",
+                "max.message.bytes": 65536,
+                "segment.bytes": 104857600,
+                "segment.ms": 86400000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_preset_compacted_state() {
+        assert_eq!(
+            preset("compacted-state").unwrap(),
+            json!({
+                "cleanup.policy": "compact",
+                "delete.retention.ms": 86400000,
+                "min.cleanable.dirty.ratio": 0.1,
+                "segment.bytes": 104857600,
+                "segment.ms": 3600000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_preset_unknown() {
+        assert_eq!(
+            preset("unknown"),
+            Err(ReconcileError::permanent("Unknown topic preset 'unknown'"))
+        );
+    }
+
+    #[test]
+    fn test_preset_override() {
+        let config = config(None, None, LimitMode::Reject);
+        let spec = KafkaAppSpec {
+            preset: Some("compacted-state".into()),
+            config: BTreeMap::from([("segment.ms".to_string(), json!(60000))]),
+            ..Default::default()
+        };
+
+        let expanded = expand_config(&config, &spec).unwrap();
+        assert_eq!(expanded["cleanup.policy"], json!("compact"));
+        assert_eq!(expanded["segment.ms"], json!(60000));
+    }
+
+    #[test]
+    fn test_controller_defaults() {
+        let mut config = config(None, None, LimitMode::Reject);
+
+        // nothing configured at all
+        assert!(expand_config(&config, &KafkaAppSpec::default())
+            .unwrap()
+            .is_empty());
+
+        config.topic_config = BTreeMap::from([
+            ("cleanup.policy".to_string(), "delete".to_string()),
+            ("retention.ms".to_string(), "86400000".to_string()),
+        ]);
+        let spec = KafkaAppSpec {
+            config: BTreeMap::from([("retention.ms".to_string(), json!(60000))]),
+            ..Default::default()
+        };
+
+        let expanded = expand_config(&config, &spec).unwrap();
+        assert_eq!(expanded["cleanup.policy"], json!("delete"));
+        // the application wins
+        assert_eq!(expanded["retention.ms"], json!(60000));
+
+        // and so does the preset
+        let spec = KafkaAppSpec {
+            preset: Some("compacted-state".into()),
+            ..Default::default()
+        };
+        let expanded = expand_config(&config, &spec).unwrap();
+        assert_eq!(expanded["cleanup.policy"], json!("compact"));
+        assert_eq!(expanded["retention.ms"], json!("86400000"));
+    }
+
+    fn aliases() -> HashMap<String, String> {
+        HashMap::from([(
+            "message.timestamp.difference.max.ms".to_string(),
+            "message.timestamp.before.max.ms".to_string(),
+        )])
+    }
+
+    #[test]
+    fn test_config_aliased() {
+        let declared = BTreeMap::from([(
+            "message.timestamp.difference.max.ms".to_string(),
+            json!(1000),
+        )]);
+
+        assert_eq!(
+            Value::Object(translate_config(&aliases(), &declared)),
+            json!({ "message.timestamp.before.max.ms": 1000 })
+        );
+    }
+
+    #[test]
+    fn test_config_not_aliased() {
+        let declared = BTreeMap::from([
+            ("message.timestamp.before.max.ms".to_string(), json!(1000)),
+            ("cleanup.policy".to_string(), json!("compact")),
+        ]);
+
+        assert_eq!(
+            Value::Object(translate_config(&aliases(), &declared)),
+            json!({ "message.timestamp.before.max.ms": 1000, "cleanup.policy": "compact" })
+        );
+    }
+
+    #[test]
+    fn test_config_alias_overridden() {
+        let declared = BTreeMap::from([
+            ("message.timestamp.difference.max.ms".to_string(), json!(1)),
+            ("message.timestamp.before.max.ms".to_string(), json!(1000)),
+        ]);
+
+        assert_eq!(
+            Value::Object(translate_config(&aliases(), &declared)),
+            json!({ "message.timestamp.before.max.ms": 1000 })
+        );
+    }
+
+    fn declared() -> Value {
+        json!({
+            "config": {},
+            "partitions": 5,
+            "replicas": 1,
+            "topicName": "events-app1",
+        })
+    }
+
+    /// A topic, which was edited by hand.
+    fn drifted_topic() -> DynamicObject {
+        let mut topic = topic();
+        topic.data["spec"] = json!({
+            "config": { "retention.ms": 1000 },
+            "partitions": 3,
+            "replicas": 1,
+            "topicName": "events-app1",
+        });
+        topic
+    }
+
+    #[test]
+    fn test_spec_requested_counts() {
+        let spec = declared_spec("events-app1", 12, 3, Map::new());
+
+        assert_eq!(spec["partitions"], json!(12));
+        assert_eq!(spec["replicas"], json!(3));
+        assert_eq!(spec["topicName"], json!("events-app1"));
+    }
+
+    #[test]
+    fn test_partitions_increase() {
+        let mut topic = topic();
+        assert!(check_partitions(&topic, &declared()).is_ok());
+
+        topic.data["spec"] = json!({ "partitions": 3 });
+        assert!(check_partitions(&topic, &declared()).is_ok());
+        topic.data["spec"] = json!({ "partitions": 5 });
+        assert!(check_partitions(&topic, &declared()).is_ok());
+    }
+
+    #[test]
+    fn test_partitions_decrease() {
+        let mut topic = topic();
+        topic.data["spec"] = json!({ "partitions": 8 });
+
+        assert!(matches!(
+            check_partitions(&topic, &declared()),
+            Err(ReconcileError::Permanent(_))
+        ));
+    }
+
+    #[test]
+    fn test_spec_new_topic() {
+        let mut topic = topic();
+        let drift = apply_spec(DriftMode::Warn, &mut topic, declared());
+
+        assert!(drift.is_empty());
+        assert_eq!(topic.data["spec"], declared());
+    }
+
+    #[test]
+    fn test_spec_no_drift() {
+        let mut topic = topic();
+        topic.data["spec"] = declared();
+        // growing the partitions is no drift
+        topic.data["spec"]["partitions"] = json!(3);
+
+        let drift = apply_spec(DriftMode::Warn, &mut topic, declared());
+
+        assert!(drift.is_empty());
+        assert_eq!(topic.data["spec"], declared());
+    }
+
+    #[test]
+    fn test_spec_drift_correct() {
+        let mut topic = drifted_topic();
+        let drift = apply_spec(DriftMode::Correct, &mut topic, declared());
+
+        assert!(drift.is_empty());
+        assert_eq!(topic.data["spec"], declared());
+    }
+
+    #[test]
+    fn test_spec_drift_warn() {
+        let mut topic = drifted_topic();
+        let drift = apply_spec(DriftMode::Warn, &mut topic, declared());
+
+        assert_eq!(drift, vec!["config".to_string()]);
+        assert_eq!(
+            topic.data["spec"],
+            json!({
+                "config": { "retention.ms": 1000 },
+                "partitions": 5,
+                "replicas": 1,
+                "topicName": "events-app1",
+            })
+        );
+    }
+
+    #[test]
+    fn test_copy_conditions() {
+        let mut app = registry::v1::Application::default();
+        let status = topic_status(&Default::default(), &topic());
+
+        app.update_section(|mut section: KafkaAppStatus| {
+            section.topic = Some(status);
+            section
+        })
+        .unwrap();
+
+        let status = app
+            .section::<KafkaAppStatus>()
+            .and_then(|s| s.ok())
+            .and_then(|s| s.topic)
+            .unwrap();
+
+        assert_eq!(
+            status,
+            TopicStatus {
+                conditions: vec![
+                    TopicCondition {
+                        r#type: "NotReady".into(),
+                        status: "True".into(),
+                        reason: Some("InvalidResourceException".into()),
+                        message: Some("Number of partitions cannot be decreased".into()),
+                    },
+                    TopicCondition {
+                        r#type: "Ready".into(),
+                        status: "False".into(),
+                        reason: None,
+                        message: None,
+                    }
+                ],
+                truncated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_copy_bounded() {
+        let config = TopicStatusConfig {
+            enabled: true,
+            max_conditions: 1,
+            max_message_length: 9,
+        };
+        let status = topic_status(&config, &topic());
+
+        assert!(status.truncated);
+        assert_eq!(status.conditions.len(), 1);
+        assert_eq!(status.conditions[0].message.as_deref(), Some("Number of"));
+    }
+
+    fn warned_topic(message: &str) -> DynamicObject {
+        serde_json::from_value(json!({
+            "apiVersion": "kafka.strimzi.io/v1beta2",
+            "kind": "KafkaTopic",
+            "metadata": {
+                "name": "events-app1",
+            },
+            "spec": {
+                "config": {
+                    "cleanup.policy": "compact",
+                    "min.insync.replicas": 2,
+                    "retention.ms": 3600000,
+                },
+            },
+            "status": {
+                "conditions": [
+                    {
+                        "type": "Ready",
+                        "status": "True",
+                    },
+                    {
+                        "type": "Warning",
+                        "status": "True",
+                        "reason": "NotConfigurable",
+                        "message": message,
+                    }
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_ignored_config_listed() {
+        let topic = warned_topic(
+            "These .spec.config properties are not configurable: [min.insync.replicas, cleanup.policy]",
+        );
+
+        assert_eq!(
+            ignored_config(&Default::default(), &topic),
+            vec!["cleanup.policy", "min.insync.replicas"]
+        );
+    }
+
+    #[test]
+    fn test_ignored_config_mentioned() {
+        let topic = warned_topic("Property min.insync.replicas is not supported by the broker");
+
+        assert_eq!(
+            ignored_config(&Default::default(), &topic),
+            vec!["min.insync.replicas"]
+        );
+    }
+
+    #[test]
+    fn test_ignored_config_other_reason() {
+        let topic = warned_topic("These .spec.config properties are not configurable: [a]");
+        let config = IgnoredConfigDetection {
+            reasons: vec!["Other".into()],
+            ..Default::default()
+        };
+
+        assert!(ignored_config(&config, &topic).is_empty());
+        assert!(ignored_config(&Default::default(), &topic()).is_empty());
+    }
+
+    #[test]
+    fn test_ignored_config_condition() {
+        let config = config(None, None, LimitMode::Clamp);
+        let topic = warned_topic(
+            "These .spec.config properties are not configurable: [min.insync.replicas]",
+        );
+        let ready = TopicReady {
+            config: &config,
+            status_resource: None,
+            events: None,
+        };
+
+        let mut ctx = context(registry::v1::Application::default());
+        let condition = ready.when_continued(&ctx);
+        assert_eq!(condition.status, Some(true));
+        assert_eq!(condition.reason, None);
+
+        ctx.events_topic_ignored_config = ignored_config(&config.ignored_config, &topic);
+        let condition = ready.when_continued(&ctx);
+
+        // still ready, but with a warning
+        assert_eq!(condition.status, Some(true));
+        assert_eq!(
+            condition.reason.as_deref(),
+            Some("KafkaConfigPartiallyApplied")
+        );
+        assert_eq!(
+            condition.message.as_deref(),
+            Some("Topic configuration was ignored by the cluster: min.insync.replicas")
+        );
+    }
+
+    #[test]
+    fn test_topic_name_kept() {
+        let mut app = registry::v1::Application::default();
+        app.update_section(|mut status: KafkaAppStatus| {
+            status.topic_name = Some("events-app1".into());
+            status
+        })
+        .unwrap();
+
+        let conditions = app
+            .section::<KafkaAppStatus>()
+            .unwrap()
+            .unwrap()
+            .status
+            .conditions;
+        app.finish_ready::<KafkaAppStatus>(conditions, 1).unwrap();
+
+        let status = app.section::<KafkaAppStatus>().unwrap().unwrap();
+        assert_eq!(status.topic_name.as_deref(), Some("events-app1"));
+        assert_eq!(
+            serde_json::to_value(&status).unwrap()["topicName"],
+            json!("events-app1")
+        );
+    }
+
+    fn ready_topic(name: &str, ready: &str) -> DynamicObject {
+        serde_json::from_value(json!({
+            "apiVersion": "kafka.strimzi.io/v1beta2",
+            "kind": "KafkaTopic",
+            "metadata": { "name": name },
+            "status": {
+                "conditions": [{ "type": "Ready", "status": ready }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_commands_topic_ready() {
+        let config = config(None, None, LimitMode::Clamp);
+        let ready = TopicReady {
+            config: &config,
+            status_resource: None,
+            events: None,
+        };
+        let ctx = |commands: Option<&str>| ConstructContext {
+            events_topic: Some(ready_topic("events-app1", "True")),
+            events_topic_name: Some("events-app1".into()),
+            commands_topic: commands.map(|ready| ready_topic("commands-app1", ready)),
+            ..context(registry::v1::Application::default())
+        };
+
+        // commands not enabled
+        assert!(matches!(
+            ready.run(ctx(None)).await,
+            Ok(OperationOutcome::Continue(_))
+        ));
+        assert!(matches!(
+            ready.run(ctx(Some("True"))).await,
+            Ok(OperationOutcome::Continue(_))
+        ));
+        // waiting for the commands topic
+        assert!(matches!(
+            ready.run(ctx(Some("False"))).await,
+            Ok(OperationOutcome::Retry(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ready_backoff() {
+        let mut config = config(None, None, LimitMode::Clamp);
+        config.ready_poll.interval = Duration::from_secs(5);
+        config.ready_poll.max_interval = Duration::from_secs(15);
+        let ready = TopicReady {
+            config: &config,
+            status_resource: None,
+            events: None,
+        };
+        let ctx = |app: registry::v1::Application, events: &str| ConstructContext {
+            events_topic: Some(ready_topic("events-app1", events)),
+            events_topic_name: Some("events-app1".into()),
+            ..context(app)
+        };
+        let attempts = |app: &registry::v1::Application| {
+            app.section::<KafkaAppStatus>()
+                .and_then(|s| s.ok())
+                .unwrap()
+                .ready_poll_attempts
+        };
+
+        let mut app = registry::v1::Application::default();
+        for expected in [5, 10, 15, 15] {
+            match ready.run(ctx(app, "False")).await {
+                Ok(OperationOutcome::Retry(ctx, delay)) => {
+                    assert_eq!(delay, Some(Duration::from_secs(expected)));
+                    app = ctx.app;
+                }
+                _ => panic!("Topic must not be ready"),
+            }
+        }
+        assert_eq!(attempts(&app), 4);
+
+        // reset, once ready
+        match ready.run(ctx(app, "True")).await {
+            Ok(OperationOutcome::Continue(ctx)) => assert_eq!(attempts(&ctx.app), 0),
+            _ => panic!("Topic must be ready"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_resource_lifecycle() {
+        let config = config(None, None, LimitMode::Clamp);
+        let sink = MockSink::default();
+        let ready = TopicReady {
+            config: &config,
+            status_resource: Some(&sink),
+            events: None,
+        };
+
+        let mut app = registry::v1::Application::default();
+        app.metadata.name = "app1".into();
+        let ctx = |topic: DynamicObject| ConstructContext {
+            events_topic: Some(topic),
+            events_topic_name: Some("events-app1".into()),
+            events_topic_partitions: Some(Partitions::Accepted(3)),
+            ..context(app.clone())
+        };
+        let status = || sink.0.lock().unwrap().get("events-app1").cloned().unwrap();
+
+        // created, while the topic is not ready
+
+        ready.run(ctx(topic())).await.unwrap();
+        let created = status();
+        assert_eq!(created.application, "app1");
+        assert!(!created.ready);
+        assert_eq!(created.partitions, Some(3));
+
+        // updated, once the topic is ready
+
+        let mut topic = topic();
+        topic.data["status"]["conditions"] = json!([{"type": "Ready", "status": "True"}]);
+        ready.run(ctx(topic)).await.unwrap();
+        let updated = status();
+        assert!(updated.ready);
+        assert!(updated.last_reconcile >= created.last_reconcile);
+        assert_eq!(sink.0.lock().unwrap().len(), 1);
+
+        // deleted with the application
+
+        sink.delete("events-app1").await.unwrap();
+        assert!(sink.0.lock().unwrap().is_empty());
+    }
+
+    fn resource() -> ApiResource {
+        ApiResource {
+            group: "kafka.strimzi.io".into(),
+            version: "v1beta2".into(),
+            api_version: "kafka.strimzi.io/v1beta2".into(),
+            kind: "KafkaTopic".into(),
+            plural: "kafkatopics".into(),
+        }
+    }
+
+    /// Create clusters with a mock client, which allows checking for API calls.
+    fn mock_clusters(
+        resource: &ApiResource,
+    ) -> (
+        TopicClusters,
+        tower_test::mock::Handle<http::Request<hyper::Body>, http::Response<hyper::Body>>,
+    ) {
+        let (service, handle) =
+            tower_test::mock::pair::<http::Request<hyper::Body>, http::Response<hyper::Body>>();
+        let client = kube::Client::new(service, "default");
+        let clusters = TopicClusters {
+            default: Api::namespaced_with(client, "kafka", resource),
+            additional: Default::default(),
+        };
+        (clusters, handle)
+    }
+
+    fn create_topic<'o>(
+        clusters: &'o TopicClusters,
+        resource: &'o ApiResource,
+        config: &'o ControllerConfig,
+    ) -> CreateTopic<'o> {
+        CreateTopic {
+            clusters,
+            resource,
+            config,
+            secondary: None,
+            defer_disruptive: false,
+            audit: None,
+            events: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let mut config = config(None, None, LimitMode::Reject);
+        config.dry_run = true;
+        let resource = resource();
+        let (clusters, mut handle) = mock_clusters(&resource);
+        let create = create_topic(&clusters, &resource, &config);
+
+        let mut app = registry::v1::Application::default();
+        app.metadata.name = "app1".into();
+        let placement = clusters.placement(&config, None).unwrap();
+
+        let (topic, topic_name, _, _) = create
+            .ensure_kafka_topic(&app, &placement, 5, 1, Map::new())
+            .await
+            .unwrap();
+
+        assert_eq!(topic_name, "events-app1");
+        assert_eq!(topic.metadata.namespace.as_deref(), Some("kafka"));
+        assert_eq!(topic.data["spec"]["partitions"], json!(5));
+        // reported as ready, to continue the reconciliation
+        assert_eq!(condition_ready("Ready", &topic), Some(true));
+
+        // no API call was attempted
+        assert!(handle.next_request().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_custom_naming() {
+        let mut config = config(None, None, LimitMode::Reject);
+        config.dry_run = true;
+        config.topic_naming = TopicNaming {
+            prefix: "staging-".into(),
+            template: Some("{app}-events".into()),
+        };
+        let resource = resource();
+        let (clusters, _handle) = mock_clusters(&resource);
+        let create = create_topic(&clusters, &resource, &config);
+
+        let mut app = registry::v1::Application::default();
+        app.metadata.name = "app1".into();
+        let placement = clusters.placement(&config, None).unwrap();
+
+        let (topic, topic_name, _, _) = create
+            .ensure_kafka_topic(&app, &placement, 5, 1, Map::new())
+            .await
+            .unwrap();
+
+        assert_eq!(topic_name, "staging-app1-events");
+        assert_eq!(topic.metadata.name.as_deref(), Some("staging-app1-events"));
+        // the endpoints and integrations derive the same name, from the same naming
+        assert_eq!(
+            app.kafka_topic(&config.topic_naming, KafkaEventType::Events)
+                .unwrap(),
+            topic_name
+        );
+    }
+
+    #[tokio::test]
+    async fn test_degenerate_topic_name() {
+        let config = config(None, None, LimitMode::Reject);
+        let resource = resource();
+        let (clusters, mut handle) = mock_clusters(&resource);
+        let create = create_topic(&clusters, &resource, &config);
+
+        let mut app = registry::v1::Application::default();
+        app.metadata.name = "___".into();
+        let placement = clusters.placement(&config, None).unwrap();
+
+        let result = create
+            .ensure_kafka_topic(&app, &placement, 5, 1, Map::new())
+            .await;
+        assert!(matches!(result, Err(ReconcileError::Permanent(_))));
+
+        // no topic was created
+        assert!(handle.next_request().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_degenerate_commands_topic_name() {
+        let config = config(None, None, LimitMode::Reject);
+        let resource = resource();
+        let (clusters, mut handle) = mock_clusters(&resource);
+        let create = CreateCommandsTopic {
+            clusters: &clusters,
+            resource: &resource,
+            config: &config,
+        };
+
+        let mut app = registry::v1::Application::default();
+        app.metadata.name = "___".into();
+        app.spec.insert("commands".into(), json!({"enabled": true}));
+        let ctx = context(app);
+
+        assert!(matches!(
+            create.run(ctx).await,
+            Err(ReconcileError::Permanent(_))
+        ));
+
+        // no topic was created
+        assert!(handle.next_request().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_commands_topic_disabled() {
+        let config = config(None, None, LimitMode::Reject);
+        let resource = resource();
+        let (clusters, mut handle) = mock_clusters(&resource);
+        let create = CreateCommandsTopic {
+            clusters: &clusters,
+            resource: &resource,
+            config: &config,
+        };
+        let ctx = |commands_topic: Option<&str>| {
+            let mut app = registry::v1::Application::default();
+            app.metadata.name = "app1".into();
+            app.update_section(|mut status: KafkaAppStatus| {
+                status.commands_topic = commands_topic.map(Into::into);
+                status
+            })
+            .unwrap();
+            context(app)
+        };
+
+        // never had a commands topic
+        assert!(matches!(
+            create.run(ctx(None)).await,
+            Ok(OperationOutcome::Continue(_))
+        ));
+        assert!(handle.next_request().now_or_never().is_none());
+
+        // the commands topic got disabled
+        let responder = async {
+            let (request, send) = handle.next_request().await.unwrap();
+            assert_eq!(request.method(), http::Method::DELETE);
+            assert!(request.uri().path().ends_with("/kafkatopics/commands-app1"));
+            send.send_response(
+                http::Response::builder()
+                    .status(404)
+                    .body(hyper::Body::from(
+                        json!({
+                            "kind": "Status",
+                            "apiVersion": "v1",
+                            "status": "Failure",
+                            "reason": "NotFound",
+                            "message": "not found",
+                            "code": 404,
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            );
+        };
+        let (outcome, _) = tokio::join!(create.run(ctx(Some("commands-app1"))), responder);
+        match outcome {
+            Ok(OperationOutcome::Continue(ctx)) => {
+                let status = ctx.app.section::<KafkaAppStatus>().unwrap().unwrap();
+                assert_eq!(status.commands_topic, None);
+            }
+            _ => panic!("Must continue"),
         }
     }
 }
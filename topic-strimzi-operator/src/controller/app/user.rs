@@ -2,17 +2,17 @@ use super::{
     condition_ready, retry, ConstructContext, ANNOTATION_APP_NAME, LABEL_KAFKA_CLUSTER,
     LABEL_MARKER,
 };
-use crate::controller::ControllerConfig;
+use crate::{controller::ControllerConfig, data::KafkaAppStatus};
 use async_trait::async_trait;
 use drogue_client::{
-    registry::v1::{Application, DownstreamSpec, KafkaAppStatus, KafkaUserStatus},
+    registry::v1::{Application, DownstreamSpec, KafkaUserStatus},
     Translator,
 };
 use drogue_cloud_operator_common::controller::reconciler::{
     progress::{self, OperationOutcome, ProgressOperation},
     ReconcileError,
 };
-use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
+use drogue_cloud_service_api::kafka::ResourceType;
 use k8s_openapi::{api::core::v1::Secret, ByteString};
 use kube::{
     api::{ApiResource, DynamicObject},
@@ -23,13 +23,52 @@ use operator_framework::{
     process::{create_or_update, create_or_update_by},
     utils::UseOrCreate,
 };
-use serde_json::json;
+use serde_json::{json, Value};
 
 const KEY_PASSWORD: &str = "password";
 
+/// The Strimzi `KafkaUser` resources, if users get provisioned.
+#[derive(Clone)]
+pub struct KafkaUsers {
+    pub resource: ApiResource,
+    pub api: Api<DynamicObject>,
+}
+
+/// The ACLs of the user of an application.
+///
+/// The user may only read the topics of its own application.
+fn acls(topic_name: &str, commands_topic_name: Option<&str>) -> Value {
+    let topic = |name: &str| {
+        json!({
+            "host": "*",
+            "operation": "Read",
+            "resource": {
+                "type": "topic",
+                "name": name,
+                "patternType": "literal",
+            },
+        })
+    };
+
+    let mut acls = vec![topic(topic_name)];
+    if let Some(commands_topic_name) = commands_topic_name {
+        acls.push(topic(commands_topic_name));
+    }
+    acls.push(json!({
+        "host": "*",
+        "operation": "Read",
+        "resource": {
+            "type": "group",
+            "name": "*",
+            "patternType": "literal",
+        }
+    }));
+
+    Value::Array(acls)
+}
+
 pub struct CreateUser<'o> {
-    pub users_api: &'o Api<DynamicObject>,
-    pub users_resource: &'o ApiResource,
+    pub users: &'o KafkaUsers,
     pub secrets_api: &'o Api<Secret>,
     pub config: &'o ControllerConfig,
 }
@@ -40,17 +79,36 @@ impl CreateUser<'_> {
         app: String,
         // a user provided password to apply
         password: Option<String>,
+        // grant access to the commands topic too
+        commands: bool,
     ) -> Result<(DynamicObject, String), ReconcileError> {
-        let user_name = make_kafka_resource_name(ResourceType::Users(&app));
-        let topic_name = make_kafka_resource_name(ResourceType::Events(&app));
-        let password_name = make_kafka_resource_name(ResourceType::Passwords(&app));
+        let user_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Users(&app));
+        let topic_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Events(&app));
+        let commands_topic_name = match commands {
+            true => Some(
+                self.config
+                    .topic_naming
+                    .resource_name(ResourceType::AppCommands(&app)),
+            ),
+            false => None,
+        };
+        let password_name = self
+            .config
+            .topic_naming
+            .resource_name(ResourceType::Passwords(&app));
 
         let user = create_or_update_by(
-            self.users_api,
+            &self.users.api,
             Some(self.config.topic_namespace.clone()),
             &user_name,
             |meta| {
-                let mut user = DynamicObject::new(&topic_name, self.users_resource)
+                let mut user = DynamicObject::new(&topic_name, &self.users.resource)
                     .within(&self.config.topic_namespace);
                 *user.meta_mut() = meta;
                 user
@@ -85,26 +143,7 @@ impl CreateUser<'_> {
                         "type": "scram-sha-512",
                     },
                     "authorization": {
-                        "acls": [
-                            {
-                                "host": "*",
-                                "operation": "Read",
-                                "resource": {
-                                    "type": "topic",
-                                    "name": topic_name,
-                                    "patternType": "literal",
-                                },
-                            },
-                            {
-                                "host": "*",
-                                "operation": "Read",
-                                "resource": {
-                                    "type": "group",
-                                    "name": "*",
-                                    "patternType": "literal",
-                                }
-                            }
-                        ],
+                        "acls": acls(&topic_name, commands_topic_name.as_deref()),
                         "type": "simple",
                     },
                     "template": {
@@ -174,7 +213,11 @@ impl<'o> ProgressOperation<ConstructContext> for CreateUser<'o> {
     async fn run(&self, mut ctx: ConstructContext) -> progress::Result<ConstructContext> {
         let password = find_user_password(&ctx.app);
         let (user, user_name) = self
-            .ensure_kafka_user(ctx.app.metadata.name.clone(), password)
+            .ensure_kafka_user(
+                ctx.app.metadata.name.clone(),
+                password,
+                ctx.commands_topic.is_some(),
+            )
             .await?;
 
         ctx.app_user = Some(user);
@@ -232,11 +275,17 @@ impl<'o> ProgressOperation<ConstructContext> for UserReady<'o> {
         };
 
         let user_status = user.is_some();
+        // Strimzi stores the credentials in a secret, named after the user
+        let user_secret = match user_status {
+            true => ctx.app_user_name.clone(),
+            false => None,
+        };
 
         // update the user section
 
         ctx.app.update_section(|mut status: KafkaAppStatus| {
             status.user = user;
+            status.user_secret = user_secret;
             status
         })?;
 
@@ -255,3 +304,34 @@ fn find_user_password(app: &Application) -> Option<String> {
         .and_then(|s| s.ok())
         .and_then(|spec| spec.password)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_acls() {
+        let topics = |acls: Value| -> Vec<String> {
+            acls.as_array()
+                .unwrap()
+                .iter()
+                .filter(|acl| acl["resource"]["type"] == "topic")
+                .map(|acl| acl["resource"]["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        assert_eq!(topics(acls("events-app1", None)), vec!["events-app1"]);
+        assert_eq!(
+            topics(acls("events-app1", Some("commands-app1"))),
+            vec!["events-app1", "commands-app1"]
+        );
+
+        // all access is read only
+        for acl in acls("events-app1", Some("commands-app1"))
+            .as_array()
+            .unwrap()
+        {
+            assert_eq!(acl["operation"], "Read");
+        }
+    }
+}
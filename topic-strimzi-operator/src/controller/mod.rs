@@ -1,8 +1,15 @@
 pub mod app;
 
-use serde::Deserialize;
+use chrono::{NaiveTime, Weekday};
+use drogue_cloud_service_api::kafka::{KafkaConfig, TopicNaming};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::Duration,
+};
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ControllerConfig {
     /// The namespace in which the topics get created
     pub topic_namespace: String,
@@ -10,4 +17,597 @@ pub struct ControllerConfig {
     ///
     /// This will be used as the `strimzi.io/cluster` label value.
     pub cluster_name: String,
+    /// Additional Kafka clusters, selectable by applications using the
+    /// `drogue.io/kafka-cluster` annotation.
+    #[serde(default)]
+    pub clusters: HashMap<String, KafkaClusterConfig>,
+    /// The additional cluster to use, for applications which don't select one.
+    ///
+    /// By default, those use the cluster configured by the `cluster_name` and `topic_namespace`.
+    #[serde(default)]
+    pub default_cluster: Option<String>,
+    /// The number of partitions of a topic, if the application doesn't request one.
+    #[serde(default = "default_partitions")]
+    pub default_partitions: u32,
+    /// The number of replicas of a topic, if the application doesn't request one.
+    #[serde(default = "default_replicas")]
+    pub default_replicas: u32,
+    /// The minimum number of partitions of a topic.
+    #[serde(default)]
+    pub min_partitions: Option<u32>,
+    /// The maximum number of partitions of a topic.
+    #[serde(default)]
+    pub max_partitions: Option<u32>,
+    /// How to handle partition counts outside of the limits.
+    #[serde(default)]
+    pub partition_limit_mode: LimitMode,
+    /// The number of brokers of the Kafka cluster, used for validating the replicas of a topic.
+    ///
+    /// If the Kafka admin configuration is present, the number is discovered at startup instead.
+    /// This value is only used as a fallback, in case the discovery fails.
+    #[serde(default)]
+    pub broker_count: Option<u32>,
+    /// Copying the status of the topic into the application status.
+    #[serde(default)]
+    pub topic_status: TopicStatusConfig,
+    /// Polling the metadata of the topic into the application status.
+    #[serde(default)]
+    pub topic_metadata: TopicMetadataConfig,
+    /// Validate that no two applications claim the same topic.
+    ///
+    /// The owners of the existing topics are loaded at startup, from their annotations.
+    #[serde(default)]
+    pub validate_topic_ownership: bool,
+    /// Check the availability of the Kafka cluster before reconciling.
+    #[serde(default)]
+    pub cluster_check: ClusterCheckConfig,
+    /// Polling topics until they are ready.
+    #[serde(default)]
+    pub ready_poll: ReadyPollConfig,
+    /// The default config of all topics, overridden by the preset and the config of the
+    /// application.
+    #[serde(default)]
+    pub topic_config: BTreeMap<String, String>,
+    /// Aliases of topic config keys, mapping the declared name to the name used by the cluster.
+    ///
+    /// This allows using the same application spec with different Kafka versions.
+    #[serde(default)]
+    pub topic_config_aliases: HashMap<String, String>,
+    /// Named bundles of topic config, selectable by applications.
+    #[serde(default = "default_topic_presets")]
+    pub topic_presets: HashMap<String, BTreeMap<String, Value>>,
+    /// How to handle applications with a Kafka spec, newer than supported by this operator.
+    #[serde(default)]
+    pub spec_schema: SchemaPolicy,
+    /// How to handle changes of the topic spec, which were not made by the operator.
+    #[serde(default)]
+    pub topic_drift: DriftMode,
+    /// How to handle applications while the topic namespace is terminating.
+    #[serde(default)]
+    pub terminating_namespace: TerminatingNamespacePolicy,
+    /// Pre-create the topics of applications, which are expected to be created soon.
+    #[serde(default)]
+    pub pre_provision: PreProvisionConfig,
+    /// Only manage the applications of certain tenants.
+    ///
+    /// This allows multiple operator instances to partition the applications by tenant.
+    #[serde(default)]
+    pub tenant_filter: TenantFilterConfig,
+    /// Detecting topic config, which the cluster accepted but didn't apply.
+    #[serde(default)]
+    pub ignored_config: IgnoredConfigDetection,
+    /// Recording the time it takes to provision the topic of an application.
+    #[serde(default)]
+    pub provisioning_metrics: ProvisioningMetricsConfig,
+    /// Reflecting the state of the topic in a dedicated resource.
+    #[serde(default)]
+    pub status_resource: StatusResourceConfig,
+    /// Handling applications, whose Kafka resources fail to be deleted.
+    #[serde(default)]
+    pub deletion: DeletionConfig,
+    /// Restricting disruptive changes of topics to maintenance windows.
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// Emit Kubernetes events for transitions of topics, regarding the `KafkaTopic` resources.
+    #[serde(default = "default_emit_events")]
+    pub emit_events: bool,
+    /// Only log the topics, instead of creating, updating, or deleting them.
+    ///
+    /// Topics are reported as ready, so that the rest of the reconciliation can be observed.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Recording the changes of topics, made by the operator.
+    #[serde(default)]
+    pub audit: Option<AuditSinkConfig>,
+    /// Provision a Kafka user for each application, which may only read the topics of the
+    /// application.
+    ///
+    /// Requires the Strimzi `KafkaUser` resource to be installed.
+    #[serde(default = "default_provision_users")]
+    pub provision_users: bool,
+    /// The naming of the topics and users of applications.
+    ///
+    /// Services which derive the topic names, like the endpoints, must use the same naming.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
+}
+
+impl ControllerConfig {
+    /// Validate the configuration, for values which can't be checked by the type system.
+    pub fn validate(&self) -> Result<(), String> {
+        // the effective limits, as applied to the requested number of partitions
+        let min = self.min_partitions.unwrap_or(1);
+        let max = self.max_partitions.unwrap_or(u32::MAX);
+
+        if min < 1 {
+            return Err("Minimum number of partitions must be at least 1".into());
+        }
+        if min > max {
+            return Err(format!(
+                "Minimum number of partitions ({min}) must not be greater than the maximum ({max})"
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+const fn default_emit_events() -> bool {
+    true
+}
+
+const fn default_provision_users() -> bool {
+    true
+}
+
+const fn default_partitions() -> u32 {
+    3
+}
+
+const fn default_replicas() -> u32 {
+    1
+}
+
+/// The default topic presets.
+pub fn default_topic_presets() -> HashMap<String, BTreeMap<String, Value>> {
+    let preset = |config: Value| serde_json::from_value(config).unwrap_or_default();
+
+    HashMap::from([
+        (
+            "high-throughput".to_string(),
+            preset(json!({
+                "cleanup.policy": "delete",
+                "compression.type": "lz4",
+                "segment.bytes": 1073741824,
+                "segment.ms": 604800000,
+            })),
+        ),
+        (
+            "small-messages".to_string(),
+            preset(json!({
+                "cleanup.policy": "delete",
+                "max.message.bytes": 65536,
+                "segment.bytes": 104857600,
+                "segment.ms": 86400000,
+            })),
+        ),
+        (
+            "compacted-state".to_string(),
+            preset(json!({
+                "cleanup.policy": "compact",
+                "delete.retention.ms": 86400000,
+                "min.cleanable.dirty.ratio": 0.1,
+                "segment.bytes": 104857600,
+                "segment.ms": 3600000,
+            })),
+        ),
+    ])
+}
+
+/// An additional Kafka cluster.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KafkaClusterConfig {
+    /// The namespace in which the topics get created.
+    pub topic_namespace: String,
+    /// The resource name of the Kafka cluster.
+    pub cluster_name: String,
+}
+
+/// How to handle values outside of a configured limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LimitMode {
+    /// Use the closest value inside the limits.
+    #[default]
+    Clamp,
+    /// Fail the reconciliation.
+    Reject,
+}
+
+/// How to handle a Kafka spec with an unsupported schema version.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SchemaPolicy {
+    /// Reconcile anyway, ignoring unknown fields.
+    #[default]
+    Lenient,
+    /// Refuse to reconcile the application.
+    Strict,
+}
+
+/// How to handle a topic spec, which differs from the declared spec.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DriftMode {
+    /// Overwrite the topic spec with the declared spec.
+    #[default]
+    Correct,
+    /// Keep the topic spec, and report the drift in the condition of the topic.
+    Warn,
+}
+
+/// How to handle applications while the topic namespace is terminating.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TerminatingNamespacePolicy {
+    /// Reconcile as usual, which will fail until the namespace is gone.
+    #[default]
+    Attempt,
+    /// Skip the reconciliation, as the resources get deleted with the namespace anyway.
+    Skip,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TopicStatusConfig {
+    /// Copy the conditions of the `KafkaTopic` into the application status.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The maximum number of conditions to copy.
+    #[serde(default = "default_max_conditions")]
+    pub max_conditions: usize,
+    /// The maximum length of a condition message, longer messages get truncated.
+    #[serde(default = "default_max_message_length")]
+    pub max_message_length: usize,
+}
+
+const fn default_max_conditions() -> usize {
+    5
+}
+
+const fn default_max_message_length() -> usize {
+    256
+}
+
+impl Default for TopicStatusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_conditions: default_max_conditions(),
+            max_message_length: default_max_message_length(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TopicMetadataConfig {
+    /// Periodically poll the metadata of the topic.
+    ///
+    /// This requires access to the Kafka cluster, using the `kafka_admin` configuration.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The interval to poll the metadata in.
+    #[serde(default = "default_metadata_interval", with = "humantime_serde")]
+    pub interval: Duration,
+    /// The timeout of a single metadata request.
+    #[serde(default = "default_metadata_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+    /// The maximum number of partitions to report the offsets for.
+    #[serde(default = "default_max_partitions")]
+    pub max_partitions: usize,
+}
+
+const fn default_metadata_interval() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+const fn default_metadata_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+const fn default_max_partitions() -> usize {
+    16
+}
+
+impl Default for TopicMetadataConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_metadata_interval(),
+            timeout: default_metadata_timeout(),
+            max_partitions: default_max_partitions(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClusterCheckConfig {
+    /// Skip reconciling applications while the Kafka cluster is paused or not ready.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The name of the `Kafka` resource, defaults to the `cluster_name`.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The namespace of the `Kafka` resource, defaults to the `topic_namespace`.
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// The delay until re-checking an application while the cluster is unavailable.
+    #[serde(default = "default_cluster_retry_delay", with = "humantime_serde")]
+    pub retry_delay: Duration,
+}
+
+const fn default_cluster_retry_delay() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl Default for ClusterCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: None,
+            namespace: None,
+            retry_delay: default_cluster_retry_delay(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReadyPollConfig {
+    /// The delay until re-checking a topic, which is not ready yet.
+    ///
+    /// The delay doubles with every attempt, until the topic is ready.
+    #[serde(default = "default_ready_poll_interval", with = "humantime_serde")]
+    pub interval: Duration,
+    /// The maximum delay until re-checking a topic.
+    #[serde(default = "default_ready_poll_max_interval", with = "humantime_serde")]
+    pub max_interval: Duration,
+}
+
+const fn default_ready_poll_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+const fn default_ready_poll_max_interval() -> Duration {
+    Duration::from_secs(300)
+}
+
+impl Default for ReadyPollConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_ready_poll_interval(),
+            max_interval: default_ready_poll_max_interval(),
+        }
+    }
+}
+
+impl ReadyPollConfig {
+    /// The delay after the given number of attempts, which found the topic not ready.
+    pub fn delay(&self, attempts: u32) -> Duration {
+        self.interval
+            .saturating_mul(2u32.saturating_pow(attempts))
+            .min(self.max_interval)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PreProvisionConfig {
+    /// The names of the anticipated applications.
+    #[serde(default)]
+    pub applications: Vec<String>,
+    /// The name of a `ConfigMap` in the topic namespace, listing additional applications.
+    ///
+    /// The names are read from the `applications` key, separated by whitespace.
+    #[serde(default)]
+    pub config_map: Option<String>,
+    /// The interval to reconcile the pre-provisioned topics in.
+    #[serde(default = "default_pre_provision_interval", with = "humantime_serde")]
+    pub interval: Duration,
+}
+
+const fn default_pre_provision_interval() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+impl Default for PreProvisionConfig {
+    fn default() -> Self {
+        Self {
+            applications: Default::default(),
+            config_map: None,
+            interval: default_pre_provision_interval(),
+        }
+    }
+}
+
+impl PreProvisionConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.applications.is_empty() || self.config_map.is_some()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TenantFilterConfig {
+    /// The label of the application, carrying the name of its tenant.
+    #[serde(default = "default_tenant_label")]
+    pub label: String,
+    /// The tenants to manage the applications of.
+    ///
+    /// If not set, all applications are managed. Otherwise, applications of other tenants, or
+    /// without a tenant, are ignored entirely.
+    #[serde(default)]
+    pub tenants: Option<BTreeSet<String>>,
+}
+
+fn default_tenant_label() -> String {
+    "drogue.io/tenant".into()
+}
+
+impl Default for TenantFilterConfig {
+    fn default() -> Self {
+        Self {
+            label: default_tenant_label(),
+            tenants: None,
+        }
+    }
+}
+
+impl TenantFilterConfig {
+    /// Check if the applications of a tenant are managed.
+    pub fn is_managed(&self, tenant: Option<&str>) -> bool {
+        match (&self.tenants, tenant) {
+            (None, _) => true,
+            (Some(tenants), Some(tenant)) => tenants.contains(tenant),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct IgnoredConfigDetection {
+    /// Report topic config, which the cluster ignored, in the condition of the topic.
+    ///
+    /// The topic is still considered ready.
+    #[serde(default = "default_ignored_config_enabled")]
+    pub enabled: bool,
+    /// The reasons of the `Warning` conditions of a topic, which report ignored config keys.
+    #[serde(default = "default_ignored_config_reasons")]
+    pub reasons: Vec<String>,
+}
+
+const fn default_ignored_config_enabled() -> bool {
+    true
+}
+
+fn default_ignored_config_reasons() -> Vec<String> {
+    vec!["NotConfigurable".into()]
+}
+
+impl Default for IgnoredConfigDetection {
+    fn default() -> Self {
+        Self {
+            enabled: default_ignored_config_enabled(),
+            reasons: default_ignored_config_reasons(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ProvisioningMetricsConfig {
+    /// Record the time from first reconciling an application, until its topic is ready.
+    ///
+    /// The start of the provisioning is stored in the status of the application. The latency is
+    /// exposed as the histogram `drogue_topic_provisioning_seconds`, labeled by the outcome.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Emitting a custom resource per application, reflecting the state of its topic.
+///
+/// This is intended for tools, which want to watch the topics without reading the applications
+/// from the registry. The resource is created in the topic namespace, using the name of the topic.
+/// The custom resource definition must be installed, otherwise the resource isn't emitted.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StatusResourceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// The API group of the custom resource.
+    #[serde(default = "default_status_resource_group")]
+    pub group: String,
+    /// The kind of the custom resource.
+    #[serde(default = "default_status_resource_kind")]
+    pub kind: String,
+}
+
+fn default_status_resource_group() -> String {
+    "drogue.io".into()
+}
+
+fn default_status_resource_kind() -> String {
+    "DrogueTopicStatus".into()
+}
+
+impl Default for StatusResourceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            group: default_status_resource_group(),
+            kind: default_status_resource_kind(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeletionConfig {
+    /// The number of failed attempts to delete the Kafka resources, before the finalizer gets
+    /// removed anyway.
+    ///
+    /// This allows deleting the application, but may leave orphaned Kafka resources behind. By
+    /// default, the finalizer is kept until the resources could be deleted.
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    /// The delay until the next attempt, after a failed attempt.
+    #[serde(default = "default_deletion_retry_delay", with = "humantime_serde")]
+    pub retry_delay: Duration,
+}
+
+const fn default_deletion_retry_delay() -> Duration {
+    Duration::from_secs(30)
+}
+
+impl Default for DeletionConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: None,
+            retry_delay: default_deletion_retry_delay(),
+        }
+    }
+}
+
+/// Restricting disruptive changes of topics to maintenance windows.
+///
+/// Increasing the partitions, or changing the config of an existing topic, may disrupt its
+/// consumers. Outside of the windows, those changes are deferred, while creating topics and
+/// updating the status continues as usual.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MaintenanceConfig {
+    /// The maintenance windows. If empty, changes are applied at any time.
+    #[serde(default)]
+    pub windows: Vec<MaintenanceWindow>,
+}
+
+/// A recurring time range, in UTC.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MaintenanceWindow {
+    /// The days of the week the window starts on. If empty, the window starts on every day.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// The start of the window (e.g. `22:00:00`).
+    pub start: NaiveTime,
+    /// The end of the window. If not after the start, the window ends on the following day.
+    pub end: NaiveTime,
+}
+
+/// Where to send the audit records of topic changes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditSinkConfig {
+    /// Log the records as JSON, using a dedicated log target.
+    Log {
+        #[serde(default = "default_audit_target")]
+        target: String,
+    },
+    /// Send the records to a Kafka topic.
+    Kafka(KafkaConfig),
+}
+
+fn default_audit_target() -> String {
+    "audit".into()
 }
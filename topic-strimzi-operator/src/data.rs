@@ -0,0 +1,219 @@
+use chrono::{DateTime, Utc};
+use drogue_client::{core::v1::Conditions, dialect, registry, Section};
+use drogue_cloud_operator_common::controller::base::StatusSection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    ops::{Deref, DerefMut},
+};
+
+/// The Kafka spec section of an application.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KafkaAppSpec {
+    /// The schema version of this section.
+    ///
+    /// Only needs to be set when using fields, which were introduced by a later version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<u32>,
+    /// The requested number of partitions of the events topic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitions: Option<u32>,
+    /// The requested number of replicas of the events topic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<u32>,
+    /// The name of a topic preset, providing the defaults of the topic config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Configuration of the events topic, overriding the preset.
+    ///
+    /// Keys may use names of older Kafka versions, which get translated using the configured
+    /// aliases.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub config: BTreeMap<String, Value>,
+    /// The retention of the events topic, overriding the config and the preset.
+    ///
+    /// Introduced with schema version 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<Retention>,
+}
+
+dialect!(KafkaAppSpec[Section::Spec => "kafka"]);
+
+/// The commands spec section of an application.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppCommandsSpec {
+    /// Provision a dedicated commands topic for the application.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+dialect!(AppCommandsSpec[Section::Spec => "commands"]);
+
+/// The retention of a topic, by age and by size.
+///
+/// Both limits are independent. If both are set, records are deleted once either limit is
+/// reached. A limit which isn't set is inherited from the config of the topic, or the cluster.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Retention {
+    /// The maximum age of records in milliseconds (`retention.ms`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ms: Option<i64>,
+    /// The maximum size of a partition in bytes (`retention.bytes`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<i64>,
+}
+
+/// The Kafka status section of an application.
+///
+/// This extends the standard [`registry::v1::KafkaAppStatus`] with information only provided by
+/// this operator.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KafkaAppStatus {
+    #[serde(flatten)]
+    pub status: registry::v1::KafkaAppStatus,
+
+    /// The name of the events topic in Kafka.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic_name: Option<String>,
+
+    /// The status of the events topic, copied from the `KafkaTopic` resource.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<TopicStatus>,
+
+    /// The effective retention of the events topic, if configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<Retention>,
+
+    /// Metadata of the events topic, periodically polled from Kafka.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic_metadata: Option<TopicMetadata>,
+
+    /// The time the operator started provisioning the topic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provisioning_started: Option<DateTime<Utc>>,
+    /// The time the topic was first reported ready.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provisioned: Option<DateTime<Utc>>,
+
+    /// The number of failed attempts to delete the Kafka resources.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub deletion_attempts: u32,
+
+    /// The number of consecutive checks, which found the topics not ready.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub ready_poll_attempts: u32,
+
+    /// Fields of the topic spec, whose change is deferred until the next maintenance window.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deferred_changes: Vec<String>,
+
+    /// The name of the commands topic, if commands are enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commands_topic: Option<String>,
+
+    /// The name of the secret, holding the credentials of the Kafka user.
+    ///
+    /// Only present if users get provisioned, once the user is ready.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_secret: Option<String>,
+
+    /// The additional Kafka cluster, the events topic was placed on.
+    ///
+    /// If absent, the topic is placed on the default cluster.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+}
+
+dialect!(KafkaAppStatus[Section::Status => "kafka"]);
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+impl Deref for KafkaAppStatus {
+    type Target = registry::v1::KafkaAppStatus;
+
+    fn deref(&self) -> &Self::Target {
+        &self.status
+    }
+}
+
+impl DerefMut for KafkaAppStatus {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.status
+    }
+}
+
+impl StatusSection for KafkaAppStatus {
+    fn ready_name() -> &'static str {
+        registry::v1::KafkaAppStatus::ready_name()
+    }
+
+    fn update_status(&mut self, conditions: Conditions, observed_generation: u64) {
+        self.status.update_status(conditions, observed_generation);
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicStatus {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<TopicCondition>,
+    /// Set when conditions were dropped or shortened.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicCondition {
+    pub r#type: String,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Approximate size information of a topic.
+///
+/// This is collected on a best-effort basis, and may be outdated.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicMetadata {
+    pub last_update: DateTime<Utc>,
+    /// The number of partitions of the topic.
+    pub partitions: u32,
+    /// The approximate number of records retained, across all partitions.
+    pub records: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub offsets: Vec<PartitionOffsets>,
+    /// Set when not all partitions are listed in the offsets.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// The offsets (watermarks) of a partition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionOffsets {
+    pub partition: i32,
+    /// The earliest offset still retained.
+    pub low: i64,
+    /// The offset of the next record.
+    pub high: i64,
+}
+
+impl PartitionOffsets {
+    /// The approximate number of records retained.
+    ///
+    /// For compacted topics, this is an upper bound, as offsets may have been compacted away.
+    pub fn records(&self) -> u64 {
+        (self.high - self.low).max(0) as u64
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+/// Waiting for the Strimzi resources to become available.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DiscoveryConfig {
+    /// The maximum time to wait for the discovery to succeed.
+    #[serde(default = "default_max_wait", with = "humantime_serde")]
+    pub max_wait: Duration,
+    /// The delay before the first retry, doubled with every retry.
+    #[serde(default = "default_initial_backoff", with = "humantime_serde")]
+    pub initial_backoff: Duration,
+    /// The maximum delay between two attempts.
+    #[serde(default = "default_max_backoff", with = "humantime_serde")]
+    pub max_backoff: Duration,
+}
+
+const fn default_max_wait() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+const fn default_initial_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+const fn default_max_backoff() -> Duration {
+    Duration::from_secs(30)
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            max_wait: default_max_wait(),
+            initial_backoff: default_initial_backoff(),
+            max_backoff: default_max_backoff(),
+        }
+    }
+}
+
+/// Run the discovery, retrying until it succeeds or the maximum wait time has expired.
+///
+/// As the operator is only started after the discovery, it will report as not ready until then.
+pub async fn discover_with_retry<F, Fut, T>(config: &DiscoveryConfig, f: F) -> anyhow::Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let start = Instant::now();
+    let mut backoff = config.initial_backoff;
+
+    loop {
+        match f().await {
+            Ok(result) => break Ok(result),
+            Err(err) if start.elapsed() + backoff <= config.max_wait => {
+                log::info!("Discovery failed, retrying in {:?}: {}", backoff, err);
+                tokio::time::sleep(backoff).await;
+                backoff = std::cmp::min(backoff * 2, config.max_backoff);
+            }
+            Err(err) => {
+                log::warn!("Discovery failed, giving up after {:?}", start.elapsed());
+                break Err(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn config(max_wait: Duration) -> DiscoveryConfig {
+        DiscoveryConfig {
+            max_wait,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        }
+    }
+
+    /// A mocked discovery, failing a number of times before succeeding.
+    async fn discovery(calls: &AtomicUsize, failures: usize) -> anyhow::Result<&'static str> {
+        match calls.fetch_add(1, Ordering::SeqCst) < failures {
+            true => anyhow::bail!("Unable to discover 'KafkaTopic'"),
+            false => Ok("KafkaTopic"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_then_success() {
+        let calls = AtomicUsize::default();
+        let result =
+            discover_with_retry(&config(Duration::from_secs(10)), || discovery(&calls, 3)).await;
+
+        assert_eq!(result.unwrap(), "KafkaTopic");
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_give_up() {
+        let calls = AtomicUsize::default();
+        let result = discover_with_retry(&config(Duration::from_millis(50)), || {
+            discovery(&calls, usize::MAX)
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert!(calls.load(Ordering::SeqCst) > 1);
+    }
+}
@@ -1,35 +1,54 @@
 mod controller;
+mod data;
+mod discover;
 
-use crate::controller::{
-    app::{ApplicationController, ANNOTATION_APP_NAME},
-    ControllerConfig,
+use crate::{
+    controller::{
+        app::{
+            create_audit_sink, discover_broker_count, ApplicationController, KafkaClusterSource,
+            KafkaMetadataSource, KubeEventSink, KubeNamespaceSource, KubeStatusResourceSink,
+            PreProvisioner, TopicIndex, ANNOTATION_APP_NAME,
+        },
+        ControllerConfig, StatusResourceConfig, TerminatingNamespacePolicy,
+    },
+    discover::{discover_with_retry, DiscoveryConfig},
 };
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use drogue_cloud_operator_common::{
     controller::base::{
-        queue::WorkQueueConfig, BaseController, EventDispatcher, FnEventProcessor, NameSource,
-        ResourceProcessor,
+        queue::WorkQueueConfig, BaseController, EventDispatcher, EventDispatcherConfig,
+        EventSenderDeadLetterSink, FnEventProcessor, NameSource, ResourceProcessor,
     },
+    leader::{LeaderElection, LeaderElectionConfig},
     watcher::RunStream,
 };
 use drogue_cloud_registry_events::{
-    stream::{KafkaEventStream, KafkaStreamConfig},
+    sender::{KafkaEventSender, KafkaSenderConfig},
+    stream::{KafkaEventStream, KafkaStreamConfig, StreamHealth},
     Event,
 };
+use drogue_cloud_service_api::kafka::KafkaClientConfig;
 use drogue_cloud_service_common::{
     app::{Startup, StartupExt},
-    client::ClientConfig,
+    client::{ClientConfig, SecondaryRegistry, SecondaryRegistryConfig},
     defaults,
+    effective_config::log_effective_config,
+};
+use futures::{
+    future::{select_all, LocalBoxFuture},
+    FutureExt,
+};
+use k8s_openapi::api::core::v1::{ConfigMap, Namespace, Secret};
+use kube::{
+    api::{ApiResource, ListParams},
+    core::DynamicObject,
+    discovery, Api,
 };
-use futures::FutureExt;
-use k8s_openapi::api::core::v1::Secret;
-use kube::{api::ListParams, core::DynamicObject, discovery, Api};
 use kube_runtime::watcher;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{fmt::Debug, sync::Arc};
-use tokio::sync::Mutex;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Config {
     #[serde(default = "defaults::max_json_payload_size")]
     pub max_json_payload_size: usize,
@@ -39,13 +58,44 @@ pub struct Config {
 
     pub registry: ClientConfig,
 
+    /// A secondary registry, consulted for the Kafka spec of applications.
+    #[serde(default)]
+    pub secondary_registry: Option<SecondaryRegistryConfig>,
+
     pub controller: ControllerConfig,
 
     pub work_queue: WorkQueueConfig,
 
     pub kafka_source: KafkaStreamConfig,
+
+    /// Retry settings for processing registry events.
+    #[serde(default)]
+    pub dispatcher: EventDispatcherConfig,
+
+    /// Waiting for the Strimzi resources at startup.
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+
+    /// Forward registry events, which failed processing, to this topic.
+    #[serde(default)]
+    pub dead_letter: Option<KafkaSenderConfig>,
+
+    /// The Kafka client for polling topic metadata.
+    #[serde(default)]
+    pub kafka_admin: Option<KafkaClientConfig>,
+
+    /// Electing the replica which reconciles, when running more than one.
+    #[serde(default)]
+    pub leader_election: LeaderElectionConfig,
 }
 
+/// The default name of the lease, used for electing the leader.
+const LEASE_NAME: &str = "drogue-cloud-topic-strimzi-operator";
+
+/// Check if a registry event is relevant to the operator.
+///
+/// Events don't carry the labels of the application, so the tenant filter is applied when
+/// reconciling the application.
 fn is_relevant(event: &Event) -> Option<String> {
     match event {
         Event::Application {
@@ -62,62 +112,340 @@ fn is_relevant(event: &Event) -> Option<String> {
 }
 
 const GROUP_KAFKA_STRIMZI_IO: &str = "kafka.strimzi.io";
+const KIND_KAFKA: &str = "Kafka";
 const KIND_KAFKA_TOPIC: &str = "KafkaTopic";
 const KIND_KAFKA_USER: &str = "KafkaUser";
 
+/// Discover the Strimzi resources for topics, and for users if `users` get provisioned.
+async fn discover_resources(
+    kube: &kube::Client,
+    users: bool,
+) -> anyhow::Result<(ApiResource, Option<ApiResource>)> {
+    let group = discovery::group(kube, GROUP_KAFKA_STRIMZI_IO).await?;
+    let (kafka_topic_resource, _caps) = group
+        .recommended_kind(KIND_KAFKA_TOPIC)
+        .ok_or_else(|| anyhow!("Unable to discover '{}'", KIND_KAFKA_TOPIC))?;
+    let kafka_user_resource = match users {
+        true => {
+            let (kafka_user_resource, _caps) = group
+                .recommended_kind(KIND_KAFKA_USER)
+                .ok_or_else(|| anyhow!("Unable to discover '{}'", KIND_KAFKA_USER))?;
+            Some(kafka_user_resource)
+        }
+        false => None,
+    };
+
+    Ok((kafka_topic_resource, kafka_user_resource))
+}
+
+/// Discover the Strimzi resource for the Kafka cluster.
+async fn discover_cluster_resource(kube: &kube::Client) -> anyhow::Result<ApiResource> {
+    let group = discovery::group(kube, GROUP_KAFKA_STRIMZI_IO).await?;
+    let (kafka_resource, _caps) = group
+        .recommended_kind(KIND_KAFKA)
+        .ok_or_else(|| anyhow!("Unable to discover '{}'", KIND_KAFKA))?;
+
+    Ok(kafka_resource)
+}
+
+/// Discover the custom resource for the status of topics.
+///
+/// Returns [`None`] if the custom resource definition is not installed.
+async fn discover_status_resource(
+    kube: &kube::Client,
+    config: &StatusResourceConfig,
+) -> Option<ApiResource> {
+    let group = match discovery::group(kube, &config.group).await {
+        Ok(group) => group,
+        Err(err) => {
+            log::warn!(
+                "Unable to discover group '{}', not emitting the status of topics: {}",
+                config.group,
+                err
+            );
+            return None;
+        }
+    };
+
+    match group.recommended_kind(&config.kind) {
+        Some((resource, _caps)) => Some(resource),
+        None => {
+            log::warn!(
+                "Unable to discover '{}', not emitting the status of topics",
+                config.kind
+            );
+            None
+        }
+    }
+}
+
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
-    log::debug!("Config: {:#?}", config);
+    log_effective_config(&config);
+
+    config
+        .controller
+        .topic_naming
+        .validate()
+        .map_err(|err| anyhow!("Invalid topic naming: {err}"))?;
+    config
+        .controller
+        .validate()
+        .map_err(|err| anyhow!("Invalid controller configuration: {err}"))?;
 
     let kube = kube::client::Client::try_default()
         .await
         .context("Failed to create Kubernetes client")?;
 
+    let health = StreamHealth::default();
+    startup.check(health.clone());
+
+    if !config.leader_election.enabled {
+        startup.spawn_iter(operate(config, kube, health).await?);
+        return Ok(());
+    }
+
+    // only the leader operates, standbys keep serving the health endpoints
+
+    let election = LeaderElection::new(kube.clone(), config.leader_election.clone(), LEASE_NAME)?;
+    startup.check(election.leadership());
+    startup.spawn_iter([election
+        .run(move || async move {
+            let (result, _, _) = select_all(operate(config, kube, health).await?).await;
+            result
+        })
+        .boxed_local()]);
+
+    Ok(())
+}
+
+/// Set up the controller, returning its event sources to run.
+async fn operate(
+    mut config: Config,
+    kube: kube::Client,
+    health: StreamHealth,
+) -> anyhow::Result<Vec<LocalBoxFuture<'static, anyhow::Result<()>>>> {
     // k8s resources
 
-    let group = discovery::group(&kube, GROUP_KAFKA_STRIMZI_IO).await?;
-    let (kafka_topic_resource, _caps) = group
-        .recommended_kind(KIND_KAFKA_TOPIC)
-        .ok_or_else(|| anyhow!("Unable to discover '{}'", KIND_KAFKA_TOPIC))?;
+    let (kafka_topic_resource, kafka_user_resource) =
+        discover_with_retry(&config.discovery, || {
+            discover_resources(&kube, config.controller.provision_users)
+        })
+        .await?;
     let kafka_topics = Api::<DynamicObject>::namespaced_with(
         kube.clone(),
         &config.controller.topic_namespace,
         &kafka_topic_resource,
     );
-    let (kafka_user_resource, _caps) = group
-        .recommended_kind(KIND_KAFKA_USER)
-        .ok_or_else(|| anyhow!("Unable to discover '{}'", KIND_KAFKA_USER))?;
-    let kafka_users = Api::<DynamicObject>::namespaced_with(
-        kube.clone(),
-        &config.controller.topic_namespace,
-        &kafka_user_resource,
-    );
+    let kafka_users = kafka_user_resource.map(|kafka_user_resource| {
+        let kafka_users = Api::<DynamicObject>::namespaced_with(
+            kube.clone(),
+            &config.controller.topic_namespace,
+            &kafka_user_resource,
+        );
+        (kafka_user_resource, kafka_users)
+    });
     let secrets = Api::<Secret>::namespaced(kube.clone(), &config.controller.topic_namespace);
+    let cluster_topics: Vec<(String, Api<DynamicObject>)> = config
+        .controller
+        .clusters
+        .iter()
+        .map(|(name, cluster)| {
+            (
+                name.clone(),
+                Api::<DynamicObject>::namespaced_with(
+                    kube.clone(),
+                    &cluster.topic_namespace,
+                    &kafka_topic_resource,
+                ),
+            )
+        })
+        .collect();
 
     // client
 
     let registry = config.registry.into_client().await?;
 
-    // controller
+    // broker count
 
-    let controller = Arc::new(Mutex::new(BaseController::new(
-        config.work_queue,
-        "app",
-        ApplicationController::new(
-            config.controller,
-            registry,
-            kafka_topic_resource,
-            kafka_topics.clone(),
-            kafka_user_resource,
-            kafka_users.clone(),
-            secrets.clone(),
+    if let Some(kafka_admin) = &config.kafka_admin {
+        match discover_broker_count(
+            kafka_admin.clone().into(),
+            config.controller.topic_metadata.timeout,
+        )
+        .await
+        {
+            Ok(brokers) => {
+                log::info!("Discovered {} brokers", brokers);
+                config.controller.broker_count = Some(brokers);
+            }
+            Err(err) => log::info!(
+                "Failed to discover the number of brokers, using configured value ({:?}): {}",
+                config.controller.broker_count,
+                err
+            ),
+        }
+    }
+
+    // topic metadata
+
+    let metadata = match (config.controller.topic_metadata.enabled, config.kafka_admin) {
+        (true, Some(kafka_admin)) => Some(KafkaMetadataSource::new(
+            kafka_admin.into(),
+            config.controller.topic_metadata.timeout,
+        )?),
+        (true, None) => bail!("Polling topic metadata requires the Kafka admin configuration"),
+        (false, _) => None,
+    };
+
+    // topic index
+
+    let topic_index = match config.controller.validate_topic_ownership {
+        true => Some(
+            TopicIndex::load(&kafka_topics)
+                .await
+                .context("Failed to load topic index")?,
         ),
-    )?));
+        false => None,
+    };
+
+    // cluster check
+
+    let cluster = match config.controller.cluster_check.enabled {
+        true => {
+            let check = &config.controller.cluster_check;
+            let kafka_resource =
+                discover_with_retry(&config.discovery, || discover_cluster_resource(&kube)).await?;
+            let api = Api::<DynamicObject>::namespaced_with(
+                kube.clone(),
+                check
+                    .namespace
+                    .as_ref()
+                    .unwrap_or(&config.controller.topic_namespace),
+                &kafka_resource,
+            );
+            let name = check
+                .name
+                .clone()
+                .unwrap_or_else(|| config.controller.cluster_name.clone());
+            Some(KafkaClusterSource::new(api, name))
+        }
+        false => None,
+    };
+
+    // namespace check
+
+    let namespace = match config.controller.terminating_namespace {
+        TerminatingNamespacePolicy::Skip => Some(KubeNamespaceSource::new(
+            Api::<Namespace>::all(kube.clone()),
+            config.controller.topic_namespace.clone(),
+        )),
+        TerminatingNamespacePolicy::Attempt => None,
+    };
+
+    // status resource
+
+    let status_resource = match config.controller.status_resource.enabled {
+        true => discover_status_resource(&kube, &config.controller.status_resource)
+            .await
+            .map(|resource| {
+                KubeStatusResourceSink::new(
+                    Api::<DynamicObject>::namespaced_with(
+                        kube.clone(),
+                        &config.controller.topic_namespace,
+                        &resource,
+                    ),
+                    resource,
+                    config.controller.topic_namespace.clone(),
+                )
+            }),
+        false => None,
+    };
+
+    // audit
+
+    let audit = config
+        .controller
+        .audit
+        .as_ref()
+        .map(create_audit_sink)
+        .transpose()
+        .context("Failed to create audit sink")?;
+
+    // events
+
+    let events = match config.controller.emit_events {
+        true => Some(KubeEventSink::new(
+            kube.clone(),
+            kafka_topic_resource.clone(),
+        )),
+        false => None,
+    };
+
+    // pre-provisioning
+
+    let provisioner = match config.controller.pre_provision.is_enabled() {
+        true => Some(PreProvisioner::new(
+            config.controller.clone(),
+            kafka_topic_resource.clone(),
+            kafka_topics.clone(),
+            Api::<ConfigMap>::namespaced(kube.clone(), &config.controller.topic_namespace),
+        )),
+        false => None,
+    };
+
+    // controller
+
+    let mut controller = ApplicationController::new(
+        config.controller,
+        registry,
+        kafka_topic_resource,
+        kafka_topics.clone(),
+        secrets.clone(),
+    );
+    if let Some((kafka_user_resource, kafka_users)) = &kafka_users {
+        controller = controller.with_kafka_users(kafka_user_resource.clone(), kafka_users.clone());
+    }
+    if let Some(metadata) = metadata {
+        controller = controller.with_metadata_source(Arc::new(metadata));
+    }
+    if let Some(topic_index) = topic_index {
+        controller = controller.with_topic_index(topic_index);
+    }
+    if let Some(cluster) = cluster {
+        controller = controller.with_cluster_source(Arc::new(cluster));
+    }
+    if let Some(namespace) = namespace {
+        controller = controller.with_namespace_source(Arc::new(namespace));
+    }
+    if let Some(status_resource) = status_resource {
+        controller = controller.with_status_resource(Arc::new(status_resource));
+    }
+    if let Some(audit) = audit {
+        controller = controller.with_audit_sink(audit);
+    }
+    if let Some(events) = events {
+        controller = controller.with_event_sink(Arc::new(events));
+    }
+    for (name, kafka_topics) in &cluster_topics {
+        controller = controller.with_cluster(name.clone(), kafka_topics.clone());
+    }
+    if let Some(secondary) = config.secondary_registry {
+        controller =
+            controller.with_secondary_registry(Arc::new(SecondaryRegistry::new(secondary).await?));
+    }
+    let controller = Arc::new(BaseController::new(config.work_queue, "app", controller)?);
 
     // event source - device registry
 
-    let registry_dispatcher =
-        EventDispatcher::one(FnEventProcessor::new(controller.clone(), is_relevant));
-    let registry = KafkaEventStream::new(config.kafka_source)?;
+    let mut registry_dispatcher =
+        EventDispatcher::one(FnEventProcessor::new(controller.clone(), is_relevant))
+            .with_config(config.dispatcher);
+    if let Some(dead_letter) = config.dead_letter {
+        registry_dispatcher = registry_dispatcher.dead_letter(EventSenderDeadLetterSink(
+            KafkaEventSender::new(dead_letter)?,
+        ));
+    }
+    let registry = KafkaEventStream::new(config.kafka_source)?.with_health(health);
     let registry = registry.run(registry_dispatcher);
 
     // event source - KafkaTopic
@@ -128,13 +456,27 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         NameSource::Annotation(ANNOTATION_APP_NAME.into()),
     )));
 
+    // event source - KafkaTopic, of additional clusters
+
+    let watcher_cluster_topics = cluster_topics.into_iter().map(|(_, kafka_topics)| {
+        watcher(kafka_topics, ListParams::default())
+            .run_stream(EventDispatcher::one(ResourceProcessor::new(
+                controller.clone(),
+                NameSource::Annotation(ANNOTATION_APP_NAME.into()),
+            )))
+            .boxed_local()
+    });
+
     // event source - KafkaUser
 
-    let watcher_users = watcher(kafka_users, ListParams::default());
-    let watcher_users = watcher_users.run_stream(EventDispatcher::one(ResourceProcessor::new(
-        controller.clone(),
-        NameSource::Annotation(ANNOTATION_APP_NAME.into()),
-    )));
+    let watcher_users = kafka_users.map(|(_, kafka_users)| {
+        watcher(kafka_users, ListParams::default()).run_stream(EventDispatcher::one(
+            ResourceProcessor::new(
+                controller.clone(),
+                NameSource::Annotation(ANNOTATION_APP_NAME.into()),
+            ),
+        ))
+    });
 
     // event source - Secret
 
@@ -146,14 +488,18 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     // run
 
-    startup.spawn_iter([
+    let mut tasks = vec![
         registry.boxed_local(),
         watcher_topics.boxed_local(),
-        watcher_users.boxed_local(),
         watcher_secret.boxed_local(),
-    ]);
-
-    // exiting
+    ];
+    if let Some(watcher_users) = watcher_users {
+        tasks.push(watcher_users.boxed_local());
+    }
+    tasks.extend(watcher_cluster_topics);
+    if let Some(provisioner) = provisioner {
+        tasks.push(provisioner.run().boxed_local());
+    }
 
-    Ok(())
+    Ok(tasks)
 }
@@ -8,7 +8,7 @@ use actix::Actor;
 use actix_web::web;
 use drogue_client::user::v1::authz::Permission;
 use drogue_cloud_service_api::{
-    kafka::KafkaClientConfig,
+    kafka::{KafkaClientConfig, TopicNaming},
     webapp::{self as actix_web},
 };
 use drogue_cloud_service_common::{
@@ -50,6 +50,10 @@ pub struct Config {
 
     #[serde(default)]
     pub http: HttpConfig,
+
+    /// The naming of the topics, must match the naming of the topic operator.
+    #[serde(default)]
+    pub topic_naming: TopicNaming,
 }
 
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
@@ -71,6 +75,7 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
     let service_addr = Service {
         clients: HashMap::default(),
         kafka_config: config.kafka,
+        topic_naming: config.topic_naming,
         registry,
     }
     .start();
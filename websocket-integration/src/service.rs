@@ -3,7 +3,9 @@ use actix::{prelude::*, AsyncContext, SpawnHandle, WrapFuture};
 use anyhow::{anyhow, Result};
 use drogue_client::registry::v1::Client;
 use drogue_cloud_integration_common::stream::{EventStream, EventStreamConfig};
-use drogue_cloud_service_api::kafka::{KafkaClientConfig, KafkaConfigExt, KafkaEventType};
+use drogue_cloud_service_api::kafka::{
+    KafkaClientConfig, KafkaConfigExt, KafkaEventType, TopicNaming,
+};
 use drogue_cloud_service_common::error::ServiceError;
 use futures::StreamExt;
 use std::collections::HashMap;
@@ -14,6 +16,7 @@ use uuid::Uuid;
 pub struct Service {
     pub clients: HashMap<Uuid, Stream>,
     pub kafka_config: KafkaClientConfig,
+    pub topic_naming: TopicNaming,
     pub registry: Client,
 }
 
@@ -36,12 +39,19 @@ impl Handler<Subscribe> for Service {
         let addr = msg.addr.clone();
         let registry_client = self.registry.clone();
         let kafka = self.kafka_config.clone();
+        let naming = self.topic_naming.clone();
         let consumer_group = msg.consumer_group.clone();
 
         let fut = async move {
             // set up a stream
-            let stream =
-                Service::get_stream(registry_client, &kafka, app.clone(), consumer_group).await;
+            let stream = Service::get_stream(
+                registry_client,
+                &kafka,
+                &naming,
+                app.clone(),
+                consumer_group,
+            )
+            .await;
             // run the stream
             let _ = match stream {
                 Ok(s) => Service::run_stream(s, addr.clone(), app.clone().as_str()).await,
@@ -125,6 +135,7 @@ impl Service {
     async fn get_stream(
         registry: Client,
         kafka_config: &KafkaClientConfig,
+        naming: &TopicNaming,
         application: String,
         group_id: Option<String>,
     ) -> Result<EventStream<'static>, ServiceError> {
@@ -144,7 +155,7 @@ impl Service {
         // create stream
         let stream = EventStream::new(EventStreamConfig {
             kafka: app_res
-                .kafka_target(KafkaEventType::Events, kafka_config)
+                .kafka_target(naming, KafkaEventType::Events, kafka_config)
                 .map_err(|_| ServiceError::InternalError("This should be infallible".into()))?
                 .into(),
             consumer_group: group_id.map(|group_id| format!("{application}.{group_id}")),